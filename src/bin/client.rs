@@ -36,22 +36,32 @@ impl Client {
             if val == RESPONSE_END {
                 break;
             }
-            // 解析事务命令
-            if val.starts_with("TRANSACTION") {
-                let args = val.split(" ").collect::<Vec<_>>();
-                if args[2] == "COMMIT" || args[2] == "ROLLBACK" {
-                    self.txn_version = None;
-                } else if args[2] == "BEGIN" {
-                    let version = args[1].parse::<u64>().unwrap();
-                    self.txn_version = Some(version);
-                }
+            // 解析开启事务的结果，如 BEGIN (version 7)
+            if let Some(version) = val
+                .strip_prefix("BEGIN (version ")
+                .and_then(|v| v.strip_suffix(')'))
+            {
+                self.txn_version = Some(version.parse::<u64>()?);
             }
             println!("{}", val);
         }
+        // COMMIT 和 ROLLBACK 执行失败时服务端的事务同样已经结束，如可串行化校验失败，不依赖结果判断
+        if ends_transaction(sql_cmd) {
+            self.txn_version = None;
+        }
         Ok(())
     }
 }
 
+// 命令是否为 COMMIT 或 ROLLBACK
+fn ends_transaction(sql_cmd: &str) -> bool {
+    let word = sql_cmd
+        .split(|c: char| c.is_ascii_whitespace() || c == ';')
+        .next()
+        .unwrap_or_default();
+    word.eq_ignore_ascii_case("COMMIT") || word.eq_ignore_ascii_case("ROLLBACK")
+}
+
 impl Drop for Client {
     fn drop(&mut self) {
         if self.txn_version.is_some() {
@@ -62,17 +72,38 @@ impl Drop for Client {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let addr = env::args()
-        .nth(1)
-        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    // client [addr] [--no-quote] [--null TEXT] [--precision N] [--width N] [--types] [--raw]
+    let mut addr = "127.0.0.1:8080".to_string();
+    let mut format_opts = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-quote" => format_opts.push("quote=off".to_string()),
+            "--types" => format_opts.push("types=on".to_string()),
+            "--raw" => format_opts.push("raw=on".to_string()),
+            "--width" => format_opts.push(format!("width={}", args.next().unwrap_or_default())),
+            "--null" => format_opts.push(format!("null={}", args.next().unwrap_or_default())),
+            "--precision" => {
+                format_opts.push(format!("precision={}", args.next().unwrap_or_default()))
+            }
+            _ => addr = arg,
+        }
+    }
     let addr = addr.parse::<SocketAddr>()?;
     let mut client = Client::new(addr).await?;
+    // 设置结果的展示方式，也可以在命令行中执行 \format
+    if !format_opts.is_empty() {
+        client
+            .execute_sql(&format!("\\format {}", format_opts.join(" ")))
+            .await?;
+    }
 
     let mut editor = DefaultEditor::new()?;
     loop {
+        // 在事务中时提示符为 rsdb*>，事务的版本号可以通过 show transaction 查看
         let prompt = match client.txn_version {
-            Some(version) => format!("rsdb#{}> ", version),
-            None => "rsdb> ".to_string(),
+            Some(_) => "rsdb*> ",
+            None => "rsdb> ",
         };
         let readline = editor.readline(&prompt);
         match readline {
@@ -97,3 +128,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ends_transaction;
+
+    #[test]
+    fn test_ends_transaction() {
+        assert!(ends_transaction("commit;"));
+        assert!(ends_transaction("COMMIT"));
+        assert!(ends_transaction("rollback ;"));
+        assert!(!ends_transaction("begin;"));
+        assert!(!ends_transaction("select * from commits;"));
+        assert!(!ends_transaction("committed;"));
+    }
+}
@@ -2,49 +2,82 @@ use futures::SinkExt;
 use rsdb::error::RSDBResult;
 use rsdb::sql;
 use rsdb::sql::engine::kv::KVEngine;
-use rsdb::storage::disk::DiskEngine;
+use rsdb::storage::disk::{DiskEngine, DiskOptions};
+use rsdb::storage::engine::{Durability, Engine as StorageEngine};
+use rsdb::storage::memory::MemoryEngine;
 use tokio::net::{TcpListener, TcpStream};
 use tokio_stream::StreamExt;
 use tokio_util::codec::{Framed, LinesCodec};
 
 use std::env;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::Arc;
 
 // cargo run --bin server
 const DB_PATH: &str = "/tmp/rsdb-test/redb-log";
 const RESPONSE_END: &str = "!!!end!!!";
+// 数据落盘的方式：always、never，或者后台落盘的间隔如 100ms，默认每次提交时落盘
+const DURABILITY_ENV: &str = "RSDB_DURABILITY";
+// 设置时使用这个口令加密数据文件，已经加密的数据库必须设置相同的口令
+const PASSPHRASE_ENV: &str = "RSDB_PASSPHRASE";
+// 设置时使用内存存储引擎，启动时从这个快照文件加载数据，\checkpoint 命令将数据写回快照文件
+const SNAPSHOT_ENV: &str = "RSDB_SNAPSHOT";
+
+// 保存检查点，返回展示给客户端的结果，只有内存存储引擎支持
+type Checkpoint = Arc<dyn Fn() -> RSDBResult<String> + Send + Sync>;
 
 enum SqlRequest {
     SQL(String),
     ListTables,
     TableInfo(String),
+    // \format key=value ...，设置结果的展示方式
+    Format(Vec<(String, String)>),
+    // \checkpoint，将内存存储引擎的数据写入快照文件
+    Checkpoint,
 }
 
 impl SqlRequest {
     pub fn parse(cmd: &str) -> Self {
         let upper_cmd = cmd.to_uppercase();
+        if upper_cmd.starts_with("\\FORMAT") {
+            let opts = cmd
+                .split_ascii_whitespace()
+                .skip(1)
+                .map(|arg| match arg.split_once('=') {
+                    Some((key, value)) => (key.to_string(), value.to_string()),
+                    None => (arg.to_string(), String::new()),
+                })
+                .collect();
+            return SqlRequest::Format(opts);
+        }
+        if upper_cmd.trim() == "\\CHECKPOINT" {
+            return SqlRequest::Checkpoint;
+        }
         if upper_cmd == "SHOW TABLES" {
             return SqlRequest::ListTables;
         }
-        if upper_cmd.starts_with("SHOW TABLE") {
-            let args = cmd.split_ascii_whitespace().collect::<Vec<_>>();
-            if args.len() == 3 {
-                return SqlRequest::TableInfo(args[2].to_lowercase());
-            }
+        // 只有 SHOW TABLE <name> 是查看表结构，SHOW TABLES EXTENDED 等其他语句按照 SQL 执行
+        if let [show, table, name] = cmd.split_ascii_whitespace().collect::<Vec<_>>()[..]
+            && show.eq_ignore_ascii_case("SHOW")
+            && table.eq_ignore_ascii_case("TABLE")
+        {
+            return SqlRequest::TableInfo(name.to_lowercase());
         }
         SqlRequest::SQL(cmd.into())
     }
 }
 
+// 每个连接对应一个 session，SET 和 \format 的设置只对当前连接生效
 pub struct ServerSession<E: sql::engine::Engine> {
     session: sql::engine::Session<E>,
+    checkpoint: Option<Checkpoint>,
 }
 
 impl<E: sql::engine::Engine + 'static> ServerSession<E> {
-    pub fn new(eng: MutexGuard<E>) -> RSDBResult<Self> {
+    pub fn new(eng: &E, checkpoint: Option<Checkpoint>) -> RSDBResult<Self> {
         Ok(Self {
             session: eng.session()?,
+            checkpoint,
         })
     }
 
@@ -57,20 +90,52 @@ impl<E: sql::engine::Engine + 'static> ServerSession<E> {
                     let req = SqlRequest::parse(&line);
                     // 执行请求
                     let response = match req {
-                        SqlRequest::SQL(sql) => match self.session.execute(&sql) {
-                            Ok(rs) => rs.to_string(),
-                            Err(e) => e.to_string(),
-                        },
+                        // 一行中可以包含多条语句，依次执行，遇到错误后停止
+                        // 按照执行之后的设置展示结果，如 set output_format = 'json'; select ...
+                        SqlRequest::SQL(sql) => {
+                            let results = self.session.execute_script(&sql, true);
+                            let format = &self.session.settings().format;
+                            results
+                                .into_iter()
+                                .map(|(_, result)| match result {
+                                    Ok(rs) => rs.format(format),
+                                    Err(e) => e.to_string(),
+                                })
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        }
                         SqlRequest::ListTables => match self.session.get_table_names() {
-                            Ok(names) => names,
+                            Ok(names) => names.join("\n"),
                             Err(e) => e.to_string(),
                         },
                         SqlRequest::TableInfo(table_name) => {
-                            match self.session.get_table(table_name) {
-                                Ok(tbinfo) => tbinfo,
+                            match self.session.execute(&format!("describe {};", table_name)) {
+                                Ok(rs) => rs.format(&self.session.settings().format),
+                                Err(e) => e.to_string(),
+                            }
+                        }
+                        SqlRequest::Format(opts) => {
+                            // 先在副本上修改，全部合法后才生效
+                            let mut format = self.session.settings().format.clone();
+                            match opts
+                                .iter()
+                                .try_for_each(|(key, value)| format.set(key, value))
+                            {
+                                Ok(_) => {
+                                    let response = format!("{:?}", format);
+                                    self.session.settings_mut().format = format;
+                                    response
+                                }
                                 Err(e) => e.to_string(),
                             }
                         }
+                        SqlRequest::Checkpoint => match &self.checkpoint {
+                            Some(checkpoint) => checkpoint().unwrap_or_else(|e| e.to_string()),
+                            None => format!(
+                                "checkpoint is only supported by the memory engine, set {} to enable it",
+                                SNAPSHOT_ENV
+                            ),
+                        },
                     };
                     // 发送执行结果
                     if let Err(e) = lines.send(response.as_str()).await {
@@ -97,15 +162,54 @@ async fn main() -> RSDBResult<()> {
         .unwrap_or_else(|| "127.0.0.1:8080".to_string());
     let listener = TcpListener::bind(&addr).await?;
     println!("rsdb server started, listening on {}", addr);
+    // 设置了快照文件时使用内存存储引擎，否则使用磁盘存储引擎
+    if let Ok(path) = env::var(SNAPSHOT_ENV) {
+        let path = PathBuf::from(path);
+        let mut engine = MemoryEngine::new();
+        if path.exists() {
+            let keys = engine.load_from(&path, false)?;
+            println!("loaded {} keys from snapshot {}", keys, path.display());
+        }
+        let kvengine = KVEngine::new(engine);
+        let kv = kvengine.kv.clone();
+        let checkpoint: Checkpoint = Arc::new(move || {
+            let keys = kv.with_engine(|engine| engine.dump_to(&path))?;
+            Ok(format!(
+                "checkpoint saved {} keys to {}",
+                keys,
+                path.display()
+            ))
+        });
+        return serve(listener, kvengine, Some(checkpoint)).await;
+    }
     // 初始化 DB
     let p = PathBuf::from(DB_PATH);
-    let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
-    let shared_engine = Arc::new(Mutex::new(kvengine));
+    let durability = match env::var(DURABILITY_ENV) {
+        Ok(value) => value.parse()?,
+        Err(_) => Durability::Always,
+    };
+    println!("durability: {:?}", durability);
+    let options = DiskOptions {
+        durability,
+        passphrase: env::var(PASSPHRASE_ENV).ok(),
+        ..Default::default()
+    };
+    let kvengine = KVEngine::new(DiskEngine::new_with_options(p.clone(), options)?);
+    serve(listener, kvengine, None).await
+}
+
+// 存储引擎的锁只在每次读写时持有，每个连接的 session 各自持有一份引擎的拷贝即可
+async fn serve<E: StorageEngine + Send + 'static>(
+    listener: TcpListener,
+    kvengine: KVEngine<E>,
+    checkpoint: Option<Checkpoint>,
+) -> RSDBResult<()> {
+    // 旧版本的数据迁移到默认数据库
+    kvengine.migrate()?;
     loop {
         match listener.accept().await {
             Ok((socket, _)) => {
-                let db = shared_engine.clone();
-                let mut ss = ServerSession::new(db.lock()?)?;
+                let mut ss = ServerSession::new(&kvengine, checkpoint.clone())?;
                 tokio::spawn(async move {
                     match ss.handle_request(socket).await {
                         Ok(_) => {}
@@ -121,3 +225,28 @@ async fn main() -> RSDBResult<()> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SqlRequest;
+
+    #[test]
+    fn test_parse_request() {
+        assert!(matches!(
+            SqlRequest::parse("show tables"),
+            SqlRequest::ListTables
+        ));
+        assert!(matches!(
+            SqlRequest::parse("show table t1"),
+            SqlRequest::TableInfo(name) if name == "t1"
+        ));
+        assert!(matches!(
+            SqlRequest::parse("show tables extended;"),
+            SqlRequest::SQL(sql) if sql == "show tables extended;"
+        ));
+        assert!(matches!(
+            SqlRequest::parse("show tables like 'a%';"),
+            SqlRequest::SQL(_)
+        ));
+    }
+}
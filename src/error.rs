@@ -8,20 +8,97 @@ pub type RSDBResult<T> = std::result::Result<T, RSDBError>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RSDBError {
-    Parse(String),
+    Parse {
+        message: String,
+        location: Option<Location>,
+    },
     Internal(String),
     WriteConflict,
+    // 可串行化事务读取的数据在提交前被其他事务修改，事务已经回滚
+    SerializationFailure,
+    TableNotFound(String),
+    ReadOnlyTransaction,
+    ValueTooLong {
+        column: String,
+        length: usize,
+        max_length: usize,
+    },
+    ValueOutOfRange {
+        column: String,
+        value: String,
+        datatype: String,
+    },
+    TypeMismatch(String),
+    NotNullViolation {
+        column: String,
+    },
+    // INSERT 中显式给出的 NULL 违反了非空约束，row 为 VALUES 中从 1 开始的行号
+    InsertNullValue {
+        table: String,
+        column: String,
+        row: usize,
+    },
+    // 多行 INSERT 中某一行出错，row 为从 1 开始的行号，primary_key 为该行的主键（如果已经确定）
+    InsertRow {
+        row: usize,
+        primary_key: Option<String>,
+        source: Box<RSDBError>,
+    },
+    ColumnTypeMismatch {
+        column: String,
+        datatype: String,
+        value: String,
+    },
+    ColumnNotFound {
+        column: String,
+        table: String,
+    },
+    AmbiguousColumn(String),
+    // 语句的执行时间超过了 session 设置的 timeout，值为毫秒数
+    StatementTimeout(u64),
+}
+
+// 解析错误在 SQL 文本中的位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    // 出错所在行，以及用 ^ 标注出错位置的下一行
+    pub snippet: String,
+}
+
+impl RSDBError {
+    // 为 INSERT 中某一行的错误加上行号和主键，事务级别的错误以及已经带有行号的错误原样返回
+    pub fn at_insert_row(self, row: usize, primary_key: Option<String>) -> Self {
+        match self {
+            err @ (RSDBError::InsertNullValue { .. }
+            | RSDBError::InsertRow { .. }
+            | RSDBError::WriteConflict
+            | RSDBError::ReadOnlyTransaction) => err,
+            err => RSDBError::InsertRow {
+                row,
+                primary_key,
+                source: Box::new(err),
+            },
+        }
+    }
 }
 
 impl From<std::num::ParseIntError> for RSDBError {
     fn from(value: std::num::ParseIntError) -> Self {
-        RSDBError::Parse(value.to_string())
+        RSDBError::Parse {
+            message: value.to_string(),
+            location: None,
+        }
     }
 }
 
 impl From<std::num::ParseFloatError> for RSDBError {
     fn from(value: std::num::ParseFloatError) -> Self {
-        RSDBError::Parse(value.to_string())
+        RSDBError::Parse {
+            message: value.to_string(),
+            location: None,
+        }
     }
 }
 
@@ -72,9 +149,85 @@ impl From<FromUtf8Error> for RSDBError {
 impl Display for RSDBError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RSDBError::Parse(err) => write!(f, "parse error: {}", err),
+            RSDBError::Parse {
+                message,
+                location: None,
+            } => write!(f, "parse error: {}", message),
+            RSDBError::Parse {
+                message,
+                location: Some(loc),
+            } => write!(
+                f,
+                "parse error: {} at line {} column {}\n{}",
+                message, loc.line, loc.column, loc.snippet
+            ),
             RSDBError::Internal(err) => write!(f, "internal error: {}", err),
             RSDBError::WriteConflict => write!(f, "write conflict, try transaction again"),
+            RSDBError::SerializationFailure => write!(
+                f,
+                "could not serialize access due to concurrent update, try transaction again"
+            ),
+            RSDBError::TableNotFound(table_name) => {
+                write!(f, "table {} does not exist", table_name)
+            }
+            RSDBError::ReadOnlyTransaction => {
+                write!(f, "cannot write in a read-only transaction")
+            }
+            RSDBError::ValueTooLong {
+                column,
+                length,
+                max_length,
+            } => write!(
+                f,
+                "value too long for column {}: {} characters, max {}",
+                column, length, max_length
+            ),
+            RSDBError::ValueOutOfRange {
+                column,
+                value,
+                datatype,
+            } => write!(
+                f,
+                "value {} out of range for column {} of type {}",
+                value, column, datatype
+            ),
+            RSDBError::TypeMismatch(message) => write!(f, "type mismatch: {}", message),
+            RSDBError::NotNullViolation { column } => {
+                write!(f, "column {} cannot be null", column)
+            }
+            RSDBError::InsertNullValue { table, column, row } => write!(
+                f,
+                "null value in column {} of table {} violates not-null constraint (row {})",
+                column, table, row
+            ),
+            RSDBError::InsertRow {
+                row,
+                primary_key: Some(pk),
+                source,
+            } => write!(f, "insert row {} (primary key {}): {}", row, pk, source),
+            RSDBError::InsertRow {
+                row,
+                primary_key: None,
+                source,
+            } => write!(f, "insert row {}: {}", row, source),
+            RSDBError::ColumnTypeMismatch {
+                column,
+                datatype,
+                value,
+            } => write!(
+                f,
+                "value {} does not match type {} of column {}",
+                value, datatype, column
+            ),
+            RSDBError::ColumnNotFound { column, table } => {
+                write!(f, "column {} not found in table {}", column, table)
+            }
+            RSDBError::AmbiguousColumn(column) => {
+                write!(f, "column reference {} is ambiguous", column)
+            }
+            RSDBError::StatementTimeout(ms) => {
+                write!(f, "statement canceled after timeout of {}ms", ms)
+            }
         }
     }
 }
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use crate::sql::parser::ast::Statement;
+
+// 缓存解析后的语句，key 为 SQL 文本，超出容量时淘汰最久没有使用的语句
+// 执行计划依赖于当前事务，因此只缓存语法树
+pub struct StatementCache {
+    capacity: usize,
+    entries: HashMap<String, (Statement, u64)>,
+    // 逻辑时钟，每次访问递增，用于找出最久没有使用的语句
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+// 缓存命中统计
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub size: usize,
+}
+
+impl StatementCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    // 查找缓存的语句，没有命中时返回 None，由调用方解析后调用 insert
+    pub fn get(&mut self, sql: &str) -> Option<Statement> {
+        self.tick += 1;
+        match self.entries.get_mut(sql) {
+            Some((stmt, used)) => {
+                *used = self.tick;
+                self.hits += 1;
+                Some(stmt.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, sql: &str, stmt: Statement) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(sql) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = oldest {
+                self.entries.remove(&key);
+            }
+        }
+        self.entries.insert(sql.to_string(), (stmt, self.tick));
+    }
+
+    // 表结构变化时，删除引用了该表的语句
+    pub fn invalidate(&mut self, table_name: &str) {
+        self.entries
+            .retain(|_, (stmt, _)| !stmt.tables().iter().any(|t| t == table_name));
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            size: self.entries.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatementCache;
+    use crate::{error::RSDBResult, sql::parser::Parser};
+
+    #[test]
+    fn test_statement_cache_lru() -> RSDBResult<()> {
+        let mut cache = StatementCache::new(2);
+        for sql in ["select * from t1;", "select * from t2;"] {
+            assert!(cache.get(sql).is_none());
+            cache.insert(sql, Parser::new(sql).parse()?);
+        }
+        // 访问 t1 之后，t2 成为最久没有使用的语句
+        assert!(cache.get("select * from t1;").is_some());
+        let sql = "select * from t3;";
+        assert!(cache.get(sql).is_none());
+        cache.insert(sql, Parser::new(sql).parse()?);
+        assert!(cache.get("select * from t2;").is_none());
+        assert!(cache.get("select * from t1;").is_some());
+        assert!(cache.get("select * from t3;").is_some());
+
+        // 删除引用了 t1 的语句
+        let sql = "select * from t1 join t3 on a = b;";
+        cache.insert(sql, Parser::new(sql).parse()?);
+        cache.invalidate("t1");
+        assert!(cache.get(sql).is_none());
+        assert!(cache.get("select * from t1;").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.size), (3, 6, 1));
+        Ok(())
+    }
+}
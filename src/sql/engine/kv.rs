@@ -1,16 +1,24 @@
-use std::collections::HashSet;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{RSDBError, RSDBResult},
     sql::{
-        engine::{Engine, Transaction},
+        engine::{DEFAULT_DATABASE, Engine, SequenceValues, TEMP_TABLE_PREFIX, Transaction},
         parser::ast::{Expression, evaluate_expr},
-        schema::Table,
-        types::{Row, Value},
+        schema::{Column, Index, Table, TableSize, TableStats},
+        types::{DataType, Row, Rows, Value},
+    },
+    storage::{
+        self,
+        engine::Engine as StorageEngine,
+        keycode::{deserialize_key, serialize_key},
+        mvcc::{MvccStatus, TransactionOptions, VacuumStats},
     },
-    storage::{self, engine::Engine as StorageEngine, keycode::serialize_key},
 };
 
 // KV Engin 定义
@@ -32,28 +40,328 @@ impl<E: StorageEngine> KVEngine<E> {
             kv: storage::mvcc::Mvcc::new(engine),
         }
     }
+
+    // 将旧版本的数据迁移到当前的格式，启动时调用，已经迁移过的数据不会被修改
+    pub fn migrate(&self) -> RSDBResult<()> {
+        self.migrate_legacy_keys()?;
+        self.migrate_float_keys()
+    }
+
+    // 旧版本的 key 中没有数据库名，将其中的表以及表中的数据、索引和统计信息迁移到默认数据库
+    // 旧版本的表 key 有两种格式：
+    // - 最初版本中字符串直接保存为原始字节，没有结尾标记，如表 t 的 key 为 0 't'，表结构同样是最初的格式
+    // - 之后字符串以 0 0 结尾，如 0 't' 0 0，其他部分与当前的格式一致
+    fn migrate_legacy_keys(&self) -> RSDBResult<()> {
+        let txn = self.kv.begin()?;
+        // 最初格式的 key 可能与新的 key 相同，先删除再写入
+        let mut deletes = Vec::new();
+        let mut sets = Vec::new();
+        for result in txn.scan_prefix(LegacyKeyPrefix::Table.encode()?)? {
+            // 新版本的表 key 中有两个字符串，能够解析说明已经迁移过
+            if deserialize_key::<Key>(&result.key).is_ok() {
+                continue;
+            }
+            // 之后的格式中字符串都以 0 0 结尾，最初的表 key 中没有 0
+            if !result.key[1..].contains(&0) {
+                let name = String::from_utf8(result.key[1..].to_vec())?;
+                let table: LegacyTable = bincode::deserialize(&result.value)?;
+                Self::migrate_raw_table(&txn, table.into_table(name), &mut deletes, &mut sets)?;
+                deletes.push((result.key, None));
+                continue;
+            }
+            // 两种格式都不是的 key 保持不变，不影响其他表的迁移
+            let Ok(name) = deserialize_key::<String>(&result.key[1..]) else {
+                continue;
+            };
+            // 临时表的表名中带有 . 前缀，与新版本中的数据库名一致
+            let (db, table) = match name.split_once('.') {
+                Some((db, table)) => (db.to_string(), table.to_string()),
+                None => (DEFAULT_DATABASE.to_string(), name.clone()),
+            };
+            // 旧的 key 为 前缀 + 剩余部分，替换为新的前缀即可，不需要解析剩余部分
+            let moves = [
+                (
+                    LegacyKeyPrefix::Row(name.clone()).encode()?,
+                    KeyPrefix::Row(db.clone(), table.clone()).encode()?,
+                ),
+                (
+                    LegacyKeyPrefix::Index(name.clone()).encode()?,
+                    KeyPrefix::Index(db.clone(), table.clone()).encode()?,
+                ),
+                (
+                    LegacyKeyPrefix::Stats(name.clone()).encode()?,
+                    Key::Stats(db.clone(), table.clone()).encode()?,
+                ),
+            ];
+            for (old_prefix, new_prefix) in moves {
+                for row in txn.scan_prefix(old_prefix.clone())? {
+                    let key = [new_prefix.as_slice(), &row.key[old_prefix.len()..]].concat();
+                    sets.push((key, Some(row.value)));
+                    deletes.push((row.key, None));
+                }
+            }
+            sets.push((Key::Table(db, table).encode()?, Some(result.value)));
+            deletes.push((result.key, None));
+        }
+        if !deletes.is_empty() {
+            deletes.extend(sets);
+            txn.write_batch(deletes)?;
+        }
+        txn.commit()
+    }
+
+    // 旧的行 key 为 1 表名 主键值，索引 key 为 2 表名 列名 列值，表名和列名没有结尾标记，无法可靠地解析
+    // 值的编码以类型标记 0 ~ 4 开头，名称中不会出现这些字节，以此区分 t 和 tt 等前缀相同的表
+    // 之后格式的 key 中名称后面是结尾标记 0 0，NULL 的编码只有一个 0，因此 NULL 只能是 key 的最后一个字节
+    // 主键 key 根据行中保存的值重新计算，索引全部删除后根据行重新构造
+    fn migrate_raw_table(
+        txn: &storage::mvcc::MvccTransaction<E>,
+        table: Table,
+        deletes: &mut Vec<(Vec<u8>, Option<Vec<u8>>)>,
+        sets: &mut Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    ) -> RSDBResult<()> {
+        let db = DEFAULT_DATABASE.to_string();
+        let raw_prefix = |tag: u8, names: &[&str]| {
+            let mut prefix = vec![tag];
+            names.iter().for_each(|n| prefix.extend(n.as_bytes()));
+            prefix
+        };
+        let raw_value = |key: &[u8], prefix: &[u8]| match key.get(prefix.len()) {
+            Some(0) => key.len() == prefix.len() + 1,
+            Some(tag) => (1..=4).contains(tag),
+            None => false,
+        };
+        let row_prefix = raw_prefix(1, &[&table.name]);
+        let mut indexes: HashMap<Vec<u8>, HashSet<Value>> = HashMap::new();
+        for row in txn.scan_prefix(row_prefix.clone())? {
+            if !raw_value(&row.key, &row_prefix) {
+                continue;
+            }
+            let data: Row = bincode::deserialize(&row.value)?;
+            let pk = table.get_primary_key(&data)?;
+            for (index_name, values) in table.index_keys(&data)? {
+                let key =
+                    Key::Index(db.clone(), table.name.clone(), index_name, values).encode()?;
+                indexes.entry(key).or_default().insert(pk.clone());
+            }
+            let key = Key::Row(db.clone(), table.name.clone(), pk).encode()?;
+            deletes.push((row.key, None));
+            sets.push((key, Some(row.value)));
+        }
+        for col in table.columns.iter().filter(|c| c.index) {
+            let index_prefix = raw_prefix(2, &[&table.name, &col.name]);
+            for index in txn.scan_prefix(index_prefix.clone())? {
+                if raw_value(&index.key, &index_prefix) {
+                    deletes.push((index.key, None));
+                }
+            }
+        }
+        for (key, pks) in indexes {
+            sets.push((key, Some(bincode::serialize(&pks)?)));
+        }
+        sets.push((
+            Key::Table(db, table.name.clone()).encode()?,
+            Some(bincode::serialize(&table)?),
+        ));
+        Ok(())
+    }
+
+    // 旧版本的 key 中浮点数直接保存为大端字节，与现在的保序编码不同，按照旧编码写入的主键和索引无法被查找到
+    // 行中保存了完整的值，按照当前的编码重新计算主键 key 和索引，与存储中不一致时重写
+    // 已经是当前编码的 key 不会被修改，完成后记录下来，之后启动时不再扫描
+    fn migrate_float_keys(&self) -> RSDBResult<()> {
+        let txn = self.kv.begin()?;
+        let done = Key::Migration(FLOAT_KEYS_MIGRATION.to_string()).encode()?;
+        if txn.get(done.clone())?.is_some() {
+            return txn.commit();
+        }
+        // 一行旧的 key 可能是另一行新的 key，先删除再写入
+        let mut deletes = Vec::new();
+        let mut sets = Vec::new();
+        for result in txn.scan_prefix(LegacyKeyPrefix::Table.encode()?)? {
+            let Key::Table(db, name) = deserialize_key(&result.key)? else {
+                return Err(RSDBError::Internal(format!(
+                    "unexpected table key {:?}",
+                    result.key
+                )));
+            };
+            let table: Table = bincode::deserialize(&result.value)?;
+            let float_key = table.columns.iter().any(|col| {
+                col.datatype == DataType::Float
+                    && (col.primary_key
+                        || col.index
+                        || table.indexes.iter().any(|i| i.columns.contains(&col.name)))
+            });
+            if !float_key {
+                continue;
+            }
+            let mut indexes: HashMap<Vec<u8>, HashSet<Value>> = HashMap::new();
+            let mut pks = HashSet::new();
+            for row in txn.scan_prefix(KeyPrefix::Row(db.clone(), name.clone()).encode()?)? {
+                let data: Row = bincode::deserialize(&row.value)?;
+                let pk = table.get_primary_key(&data)?;
+                // 旧编码中 0.0 和 -0.0 等相等的值是不同的主键，现在无法同时保存
+                if !pks.insert(pk.clone()) {
+                    return Err(KVTransaction::<E>::duplicate_key(&table, &pk));
+                }
+                for (index_name, values) in table.index_keys(&data)? {
+                    let key = Key::Index(db.clone(), name.clone(), index_name, values).encode()?;
+                    indexes.entry(key).or_default().insert(pk.clone());
+                }
+                let key = Key::Row(db.clone(), name.clone(), pk).encode()?;
+                if key != row.key {
+                    deletes.push((row.key, None));
+                    sets.push((key, Some(row.value)));
+                }
+            }
+            for index in txn.scan_prefix(KeyPrefix::Index(db.clone(), name.clone()).encode()?)? {
+                match indexes.remove(&index.key) {
+                    Some(pks) if bincode::deserialize::<HashSet<Value>>(&index.value)? == pks => {}
+                    Some(pks) => sets.push((index.key, Some(bincode::serialize(&pks)?))),
+                    None => deletes.push((index.key, None)),
+                }
+            }
+            for (key, pks) in indexes {
+                sets.push((key, Some(bincode::serialize(&pks)?)));
+            }
+        }
+        deletes.extend(sets);
+        deletes.push((done, Some(vec![])));
+        txn.write_batch(deletes)?;
+        txn.commit()
+    }
 }
 
-impl<E: StorageEngine> Engine for KVEngine<E> {
+impl<E: StorageEngine + 'static> Engine for KVEngine<E> {
     type Transaction = KVTransaction<E>;
 
-    fn begin(&self) -> RSDBResult<Self::Transaction> {
-        Ok(Self::Transaction::new(self.kv.begin()?))
+    fn begin_with(&self, opts: TransactionOptions) -> RSDBResult<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_with(opts)?))
+    }
+
+    fn vacuum(&self) -> RSDBResult<VacuumStats> {
+        self.kv.vacuum()
     }
 }
 
 // KV Transaction 定义，实际上对存储引擎中 MvccTransaction 的封装
 pub struct KVTransaction<E: StorageEngine> {
     txn: storage::mvcc::MvccTransaction<E>,
+    // 事务内读取过的表结构，避免每次都从存储引擎读取并反序列化，None 表示表不存在
+    // 事务的快照是固定的，只有本事务的 DDL 会修改表结构，修改时删除对应的缓存
+    tables: RefCell<HashMap<String, Option<Table>>>,
+    // 当前数据库，不带数据库名的表名属于这个数据库
+    database: RefCell<String>,
+    // 所属 session 中序列最近一次分配的值
+    sequences: RefCell<SequenceValues>,
 }
 
 impl<E: StorageEngine> KVTransaction<E> {
     pub fn new(txn: storage::mvcc::MvccTransaction<E>) -> Self {
-        Self { txn }
+        Self {
+            txn,
+            tables: RefCell::new(HashMap::new()),
+            database: RefCell::new(DEFAULT_DATABASE.to_string()),
+            sequences: RefCell::new(SequenceValues::default()),
+        }
+    }
+
+    // 表名可以带有数据库名，如 app.t，否则属于当前数据库
+    fn resolve(&self, table_name: &str) -> (String, String) {
+        match table_name.split_once('.') {
+            Some((db, name)) => (db.to_string(), name.to_string()),
+            None => (self.database.borrow().clone(), table_name.to_string()),
+        }
+    }
+
+    // 由数据库名和表名构造表的 key 或者前缀
+    fn table_key<K: Serialize>(
+        &self,
+        table_name: &str,
+        key: impl FnOnce(String, String) -> K,
+    ) -> RSDBResult<Vec<u8>> {
+        let (db, name) = self.resolve(table_name);
+        serialize_key(&key(db, name))
+    }
+
+    // 缓存中以带有数据库名的完整表名作为 key，切换数据库后不会读到其他数据库的表
+    fn cache_key(&self, table_name: &str) -> String {
+        let (db, name) = self.resolve(table_name);
+        format!("{}.{}", db, name)
+    }
+
+    // 序列与表一样属于数据库，序列名可以带有数据库名
+    fn sequence_key(&self, name: &str) -> RSDBResult<Vec<u8>> {
+        let (db, name) = self.resolve(name);
+        Key::Sequence(db, name).encode()
+    }
+
+    // 数据库中所有的表名，不带数据库名
+    fn database_tables(&self, db: &str) -> RSDBResult<Vec<String>> {
+        let prefix = KeyPrefix::Table(db.to_string()).encode()?;
+        let mut names = Vec::new();
+        for result in self.txn.scan_prefix(prefix)? {
+            if let Key::Table(_, name) = deserialize_key(&result.key)? {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    // 保存表结构，并删除缓存
+    fn save_table(&self, table: &Table) -> RSDBResult<()> {
+        self.tables
+            .borrow_mut()
+            .remove(&self.cache_key(&table.name));
+        let key = self.table_key(&table.name, Key::Table)?;
+        self.txn.set(key, bincode::serialize(table)?)
+    }
+
+    // 校验新插入的行，并检查主键对应的数据是否已经存在
+    fn check_new_row(&self, table: &Table, row: &Row, pk: &Value, id: &[u8]) -> RSDBResult<()> {
+        table.validate_row(row)?;
+        if self.txn.get(id.to_vec())?.is_some() {
+            return Err(Self::duplicate_key(table, pk));
+        }
+        Ok(())
+    }
+
+    fn database_not_found(name: &str) -> RSDBError {
+        RSDBError::Internal(format!("database {} does not exist", name))
+    }
+
+    fn sequence_not_found(name: &str) -> RSDBError {
+        RSDBError::Internal(format!("sequence {} does not exist", name))
+    }
+
+    fn duplicate_key(table: &Table, pk: &Value) -> RSDBError {
+        RSDBError::Internal(format!(
+            "Duplicate data for primary key {:?} in table {}",
+            pk,
+            table.name.clone()
+        ))
+    }
+
+    // 索引 key 以及要写入的值，索引为空时删除这个 key
+    fn index_write(
+        &self,
+        table_name: &str,
+        index_name: &str,
+        values: &[Value],
+        index: HashSet<Value>,
+    ) -> RSDBResult<(Vec<u8>, Option<Vec<u8>>)> {
+        let key = self.table_key(table_name, |db, t| {
+            Key::Index(db, t, index_name.to_string(), values.to_vec())
+        })?;
+        if index.is_empty() {
+            Ok((key, None))
+        } else {
+            Ok((key, Some(bincode::serialize(&index)?)))
+        }
     }
 }
 
-impl<E: StorageEngine> Transaction for KVTransaction<E> {
+impl<E: StorageEngine + 'static> Transaction for KVTransaction<E> {
     fn commit(&self) -> RSDBResult<()> {
         self.txn.commit()
     }
@@ -66,56 +374,69 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
         self.txn.version()
     }
 
+    fn read_only(&self) -> bool {
+        self.txn.read_only()
+    }
+
+    fn write_count(&self) -> RSDBResult<usize> {
+        self.txn.write_count()
+    }
+
+    fn status(&self) -> RSDBResult<MvccStatus> {
+        self.txn.status()
+    }
+
     fn create_row(&self, table: &Table, row: Row) -> RSDBResult<()> {
-        // 校验行的有效性
-        for (i, col) in table.columns.iter().enumerate() {
-            match row[i].datatype() {
-                None if col.nullable => continue,
-                None => {
-                    return Err(RSDBError::Internal(format!(
-                        "column {} cannot be null",
-                        col.name
-                    )));
-                }
-                Some(dt) if dt != col.datatype => {
-                    return Err(RSDBError::Internal(format!(
-                        "column {} type mismatch",
-                        col.name
-                    )));
-                }
-                _ => continue,
-            }
-        }
         // 找到表中的主键作为一行数据的唯一标识
         let pk = table.get_primary_key(&row)?;
-        // 查看主键对应的数据是否已经存在了
-        let id = Key::Row(table.name.clone(), pk.clone()).encode()?;
-        if self.txn.get(id.clone())?.is_some() {
-            return Err(RSDBError::Internal(format!(
-                "Duplicate data for primary key {:?} in table {}",
-                pk,
-                table.name.clone()
-            )));
-        }
+        // 校验行的有效性，并查看主键对应的数据是否已经存在了
+        let id = self.table_key(&table.name, |db, t| Key::Row(db, t, pk.clone()))?;
+        self.check_new_row(table, &row, &pk, &id)?;
         // 存放数据
         let value = bincode::serialize(&row)?;
         self.txn.set(id, value)?;
 
         // 存放索引
-        let index_cols = table
-            .columns
-            .iter()
-            .enumerate()
-            .filter(|(_, col)| col.index)
-            .collect::<Vec<_>>();
-        for (i, index_col) in index_cols {
-            let mut index = self.load_index(&table.name, &index_col.name, &row[i])?;
+        for (index_name, values) in table.index_keys(&row)? {
+            let mut index = self.load_index(&table.name, &index_name, &values)?;
             index.insert(pk.clone());
-            self.save_index(&table.name, &index_col.name, &row[i], index)?;
+            self.save_index(&table.name, &index_name, &values, index)?;
         }
         Ok(())
     }
 
+    fn create_rows(&self, table: &Table, rows: Vec<Row>) -> RSDBResult<()> {
+        let has_index = table.columns.iter().any(|col| col.index) || !table.indexes.is_empty();
+        let mut writes = Vec::with_capacity(rows.len());
+        // 这一批中已经插入的主键
+        let mut pks = HashSet::new();
+        // 按照索引 key 分组，每个索引 key 只读写一次
+        let mut index_pks: HashMap<(String, Vec<Value>), Vec<Value>> = HashMap::new();
+        for (i, row) in rows.into_iter().enumerate() {
+            let pk = table.get_primary_key(&row)?;
+            let id = self.table_key(&table.name, |db, t| Key::Row(db, t, pk.clone()))?;
+            let checked = match pks.contains(&pk) {
+                true => Err(Self::duplicate_key(table, &pk)),
+                false => self.check_new_row(table, &row, &pk, &id),
+            };
+            checked.map_err(|e| e.at_insert_row(i + 1, Some(pk.to_string())))?;
+            if has_index {
+                for key in table.index_keys(&row)? {
+                    index_pks.entry(key).or_default().push(pk.clone());
+                }
+            }
+            writes.push((id, Some(bincode::serialize(&row)?)));
+            pks.insert(pk);
+        }
+        for ((index_name, values), new_pks) in index_pks {
+            let mut index = self.load_index(&table.name, &index_name, &values)?;
+            index.extend(new_pks);
+            writes.push(self.index_write(&table.name, &index_name, &values, index)?);
+        }
+        // 所有的行和索引在一次加锁中写入
+        self.txn.write_batch(writes)
+    }
+
     fn update_row(&self, table: &Table, old_pk: &Value, row: Row) -> RSDBResult<()> {
         let new_pk = table.get_primary_key(&row)?;
         // 更新了主键，则删除旧的数据，加一条新的数据
@@ -123,87 +444,113 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
             self.delete_row(table, old_pk)?;
             return self.create_row(table, row);
         }
-        // 维护索引
-        let index_cols = table
-            .columns
-            .iter()
-            .enumerate()
-            .filter(|(_, col)| col.index)
-            .collect::<Vec<_>>();
-        for (i, index_col) in index_cols {
-            if let Some(old_row) = self.read_by_pk(&table.name, old_pk)? {
+        table.validate_row(&row)?;
+        // 维护索引，新旧两行的索引 key 一一对应
+        if let Some(old_row) = self.read_by_pk(&table.name, old_pk)? {
+            let old_keys = table.index_keys(&old_row)?;
+            let new_keys = table.index_keys(&row)?;
+            for ((index_name, old_values), (_, new_values)) in old_keys.into_iter().zip(new_keys) {
                 // 索引列没有被更新
-                if old_row[i] == row[i] {
+                if old_values == new_values {
                     continue;
                 }
-                let mut old_index = self.load_index(&table.name, &index_col.name, &old_row[i])?;
+                let mut old_index = self.load_index(&table.name, &index_name, &old_values)?;
                 old_index.remove(old_pk);
-                self.save_index(&table.name, &index_col.name, &old_row[i], old_index)?;
+                self.save_index(&table.name, &index_name, &old_values, old_index)?;
 
-                let mut new_index = self.load_index(&table.name, &index_col.name, &row[i])?;
+                let mut new_index = self.load_index(&table.name, &index_name, &new_values)?;
                 new_index.insert(new_pk.clone());
-                self.save_index(&table.name, &index_col.name, &row[i], new_index)?;
+                self.save_index(&table.name, &index_name, &new_values, new_index)?;
             }
         }
 
-        let key = Key::Row(table.name.clone(), new_pk).encode()?;
+        let key = self.table_key(&table.name, |db, t| Key::Row(db, t, new_pk))?;
         let value = bincode::serialize(&row)?;
         self.txn.set(key, value)
     }
 
     fn delete_row(&self, table: &Table, pk: &Value) -> RSDBResult<()> {
-        // 删除索引
-        let index_cols = table
-            .columns
-            .iter()
-            .enumerate()
-            .filter(|(_, col)| col.index)
-            .collect::<Vec<_>>();
-        for (i, index_col) in index_cols {
-            if let Some(row) = self.read_by_pk(&table.name, pk)? {
-                let mut index = self.load_index(&table.name, &index_col.name, &row[i])?;
+        // 删除索引，原来的行只需要读取一次
+        let has_index = table.columns.iter().any(|col| col.index) || !table.indexes.is_empty();
+        if has_index && let Some(row) = self.read_by_pk(&table.name, pk)? {
+            for (index_name, values) in table.index_keys(&row)? {
+                let mut index = self.load_index(&table.name, &index_name, &values)?;
                 index.remove(pk);
-                self.save_index(&table.name, &index_col.name, &row[i], index)?;
+                self.save_index(&table.name, &index_name, &values, index)?;
             }
         }
 
-        let key = Key::Row(table.name.clone(), pk.clone()).encode()?;
+        let key = self.table_key(&table.name, |db, t| Key::Row(db, t, pk.clone()))?;
         self.txn.delete(key)
     }
 
-    fn scan_table(&self, table: &Table, filter: Option<Expression>) -> RSDBResult<Vec<Row>> {
-        let prefix = KeyPrefix::Row(table.name.clone()).encode()?;
-        let results = self.txn.scan_prefix(prefix)?;
-        let mut rows = Vec::new();
-        for result in results {
-            // 过滤数据
-            let row: Row = bincode::deserialize(&result.value)?;
-            if let Some(expr) = &filter {
-                let cols = table
-                    .columns
-                    .iter()
-                    .map(|c| c.name.clone())
-                    .collect::<Vec<_>>();
-                match evaluate_expr(expr, &cols, &row, &cols, &row)? {
-                    Value::Null => continue,
-                    Value::Boolean(false) => continue,
-                    Value::Boolean(true) => {
-                        rows.push(row);
-                    }
-                    _ => {
-                        return Err(RSDBError::Internal(
-                            "evaluate_expr must return a boolean".to_string(),
-                        ));
-                    }
-                }
-            } else {
-                rows.push(row);
+    fn scan_rows(
+        &self,
+        table: &Table,
+        filter: Option<Expression>,
+        limit: Option<usize>,
+        reverse: bool,
+    ) -> RSDBResult<Rows> {
+        let prefix = self.table_key(&table.name, KeyPrefix::Row)?;
+        let cols = table
+            .columns
+            .iter()
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+        // 过滤数据，不满足条件的行返回 None
+        let filter_row = move |value: &[u8]| -> RSDBResult<Option<Row>> {
+            let row: Row = bincode::deserialize(value)?;
+            let Some(expr) = &filter else {
+                return Ok(Some(row));
+            };
+            match evaluate_expr(expr, &cols, &row, &cols, &row)? {
+                Value::Null | Value::Boolean(false) => Ok(None),
+                Value::Boolean(true) => Ok(Some(row)),
+                _ => Err(RSDBError::Internal(
+                    "evaluate_expr must return a boolean".to_string(),
+                )),
             }
+        };
+        // 边扫描存储边过滤，执行器拉取时才读取下一批数据
+        // 有行数上限时，满足条件的行足够后停止扫描
+        let rows = self.txn.scan(prefix, reverse)?.filter_map(move |result| {
+            result
+                .and_then(|result| filter_row(&result.value))
+                .transpose()
+        });
+        match limit {
+            Some(limit) => Ok(Box::new(rows.take(limit))),
+            None => Ok(Box::new(rows)),
         }
-        Ok(rows)
+    }
+
+    fn count_rows(&self, table: &Table) -> RSDBResult<usize> {
+        // 只统计 key 的个数，不需要反序列化行数据
+        let prefix = self.table_key(&table.name, KeyPrefix::Row)?;
+        Ok(self.txn.scan_prefix(prefix)?.len())
+    }
+
+    fn table_size(&self, table: &Table) -> RSDBResult<TableSize> {
+        let mut size = TableSize::default();
+        self.txn
+            .scan_prefix_with(self.table_key(&table.name, KeyPrefix::Row)?, |result| {
+                size.rows += 1;
+                size.bytes += result.value.len();
+                Ok(true)
+            })?;
+        self.txn
+            .scan_prefix_with(self.table_key(&table.name, KeyPrefix::Index)?, |result| {
+                size.bytes += result.value.len();
+                Ok(true)
+            })?;
+        Ok(size)
     }
 
     fn create_table(&self, table: Table) -> RSDBResult<()> {
+        let (db, _) = self.resolve(&table.name);
+        if !self.database_exists(&db)? {
+            return Err(Self::database_not_found(&db));
+        }
         // 判断表是否存在
         if self.get_table(table.name.clone())?.is_some() {
             return Err(RSDBError::Internal(format!(
@@ -213,25 +560,79 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
         }
         // 判断表的有效性
         table.validate()?;
-        let key = Key::Table(table.name.clone()).encode()?;
-        let value = bincode::serialize(&table)?;
-        self.txn.set(key, value)
+        self.save_table(&table)
     }
 
     fn drop_table(&self, table_name: String) -> RSDBResult<()> {
-        let table = self.must_get_table(table_name.clone())?;
-        // 删除表中的所有数据
-        let rows = self.scan_table(&table, None)?;
-        for row in rows {
-            self.delete_row(&table, &table.get_primary_key(&row)?)?;
-        }
-        // 删除表的元信息
-        let key = Key::Table(table_name).encode()?;
-        self.txn.delete(key)
+        self.must_get_table(table_name.clone())?;
+        // 按前缀删除表中的所有数据和索引，不需要反序列化行
+        let mut writes = Vec::new();
+        for prefix in [
+            self.table_key(&table_name, KeyPrefix::Row)?,
+            self.table_key(&table_name, KeyPrefix::Index)?,
+        ] {
+            for result in self.txn.scan_prefix(prefix)? {
+                writes.push((result.key, None));
+            }
+        }
+        // 删除表的统计信息和元信息
+        writes.push((self.table_key(&table_name, Key::Stats)?, None));
+        writes.push((self.table_key(&table_name, Key::Table)?, None));
+        self.tables
+            .borrow_mut()
+            .remove(&self.cache_key(&table_name));
+        self.txn.write_batch(writes)
     }
 
-    fn get_table(&self, table_name: String) -> RSDBResult<Option<Table>> {
-        let key = Key::Table(table_name).encode()?;
+    fn create_index(&self, table_name: &str, index: Index) -> RSDBResult<()> {
+        let mut table = self.must_get_table(table_name.to_string())?;
+        // 单列索引以列名作为索引名，不能与之重名
+        if table.indexes.iter().any(|i| i.name == index.name)
+            || table
+                .columns
+                .iter()
+                .any(|c| c.index && c.name == index.name)
+        {
+            return Err(RSDBError::Internal(format!(
+                "index {} already exists in table {}",
+                index.name, table_name
+            )));
+        }
+        for (i, col_name) in index.columns.iter().enumerate() {
+            table.get_col_index(col_name)?;
+            if index.columns[..i].contains(col_name) {
+                return Err(RSDBError::Internal(format!(
+                    "column {} appears more than once in index {}",
+                    col_name, index.name
+                )));
+            }
+        }
+        let index_name = index.name.clone();
+        table.indexes.push(index);
+        self.save_table(&table)?;
+
+        // 为已有的数据建立索引
+        for row in self.scan_table(&table, None)? {
+            let pk = table.get_primary_key(&row)?;
+            for (name, values) in table.index_keys(&row)? {
+                if name != index_name {
+                    continue;
+                }
+                let mut index = self.load_index(&table.name, &name, &values)?;
+                index.insert(pk.clone());
+                self.save_index(&table.name, &name, &values, index)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn save_stats(&self, table_name: &str, stats: &TableStats) -> RSDBResult<()> {
+        let key = self.table_key(table_name, Key::Stats)?;
+        self.txn.set(key, bincode::serialize(stats)?)
+    }
+
+    fn get_stats(&self, table_name: &str) -> RSDBResult<Option<TableStats>> {
+        let key = self.table_key(table_name, Key::Stats)?;
         Ok(self
             .txn
             .get(key)?
@@ -239,29 +640,163 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
             .transpose()?)
     }
 
+    // 返回的表结构中的表名与参数一致，之后对这个表的读写使用相同的数据库
+    fn get_table(&self, table_name: String) -> RSDBResult<Option<Table>> {
+        let cache_key = self.cache_key(&table_name);
+        let cached = self.tables.borrow().get(&cache_key).cloned();
+        let table = match cached {
+            Some(table) => table,
+            None => {
+                let key = self.table_key(&table_name, Key::Table)?;
+                let table: Option<Table> = self
+                    .txn
+                    .get(key)?
+                    .map(|v| bincode::deserialize(&v))
+                    .transpose()?;
+                self.tables.borrow_mut().insert(cache_key, table.clone());
+                table
+            }
+        };
+        Ok(table.map(|table| Table {
+            name: table_name,
+            ..table
+        }))
+    }
+
+    // 临时表属于单独的数据库，不会出现在其中
     fn get_table_names(&self) -> RSDBResult<Vec<String>> {
-        let prefix = KeyPrefix::Table.encode()?;
-        let results = self.txn.scan_prefix(prefix)?;
-        let mut names = Vec::new();
-        for result in results {
-            let table: Table = bincode::deserialize(&result.value)?;
-            names.push(table.name);
+        let db = self.database.borrow().clone();
+        self.database_tables(&db)
+    }
+
+    fn create_database(&self, name: &str) -> RSDBResult<()> {
+        if self.database_exists(name)? {
+            return Err(RSDBError::Internal(format!(
+                "database {} already exists",
+                name
+            )));
         }
-        Ok(names)
+        self.txn
+            .set(Key::Database(name.to_string()).encode()?, vec![])
+    }
+
+    fn drop_database(&self, name: &str) -> RSDBResult<()> {
+        if name == DEFAULT_DATABASE {
+            return Err(RSDBError::Internal(
+                "can not drop the default database".to_string(),
+            ));
+        }
+        if !self.database_exists(name)? {
+            return Err(Self::database_not_found(name));
+        }
+        for table in self.database_tables(name)? {
+            self.drop_table(format!("{}.{}", name, table))?;
+        }
+        // 序列的计数器在提交时清除，与 DROP SEQUENCE 一致
+        for result in self
+            .txn
+            .scan_prefix(KeyPrefix::Sequence(name.to_string()).encode()?)?
+        {
+            self.txn.delete(result.key.clone())?;
+            self.txn.delete_unversioned_on_commit(result.key)?;
+        }
+        self.txn.delete(Key::Database(name.to_string()).encode()?)
+    }
+
+    // 临时表所在的数据库由 session 管理，没有单独的记录
+    fn database_exists(&self, name: &str) -> RSDBResult<bool> {
+        if name == DEFAULT_DATABASE || name.starts_with(TEMP_TABLE_PREFIX) {
+            return Ok(true);
+        }
+        let key = Key::Database(name.to_string()).encode()?;
+        Ok(self.txn.get(key)?.is_some())
+    }
+
+    fn set_database(&self, name: &str) {
+        *self.database.borrow_mut() = name.to_string();
+    }
+
+    fn set_sequence_values(&self, values: SequenceValues) {
+        *self.sequences.borrow_mut() = values;
+    }
+
+    fn create_sequence(&self, name: &str) -> RSDBResult<()> {
+        let (db, _) = self.resolve(name);
+        if !self.database_exists(&db)? {
+            return Err(Self::database_not_found(&db));
+        }
+        if self.sequence_exists(name)? {
+            return Err(RSDBError::Internal(format!(
+                "sequence {} already exists",
+                name
+            )));
+        }
+        // 序列是否存在由事务控制，计数器不受事务控制，创建时清除之前残留的计数器
+        let key = self.sequence_key(name)?;
+        self.txn.set(key.clone(), vec![])?;
+        self.sequences
+            .borrow()
+            .lock()?
+            .remove(&self.cache_key(name));
+        self.txn.delete_unversioned(key)
+    }
+
+    fn drop_sequence(&self, name: &str) -> RSDBResult<()> {
+        if !self.sequence_exists(name)? {
+            return Err(Self::sequence_not_found(name));
+        }
+        // 计数器在删除操作提交时才清除，回滚后序列的值不会重复
+        let key = self.sequence_key(name)?;
+        self.txn.delete(key.clone())?;
+        self.txn.delete_unversioned_on_commit(key)
+    }
+
+    fn sequence_exists(&self, name: &str) -> RSDBResult<bool> {
+        let key = self.sequence_key(name)?;
+        Ok(self.txn.get(key)?.is_some())
+    }
+
+    // 计数器的更新直接写入存储引擎，并发的事务不会因为同一个序列而写冲突
+    fn nextval(&self, name: &str) -> RSDBResult<i64> {
+        if !self.sequence_exists(name)? {
+            return Err(Self::sequence_not_found(name));
+        }
+        let key = self.sequence_key(name)?;
+        let value = self.txn.fetch_add_unversioned(key, 1)?;
+        self.sequences
+            .borrow()
+            .lock()?
+            .insert(self.cache_key(name), value);
+        Ok(value)
+    }
+
+    // 其他 session 分配的值不可见，避免并发时取到其他 session 刚刚分配的值
+    fn currval(&self, name: &str) -> RSDBResult<i64> {
+        if !self.sequence_exists(name)? {
+            return Err(Self::sequence_not_found(name));
+        }
+        self.sequences
+            .borrow()
+            .lock()?
+            .get(&self.cache_key(name))
+            .copied()
+            .ok_or_else(|| {
+                RSDBError::Internal(format!(
+                    "currval of sequence {} is not yet defined in this session",
+                    name
+                ))
+            })
     }
 
     fn load_index(
         &self,
         table_name: &str,
-        col_name: &str,
-        col_value: &Value,
+        index_name: &str,
+        values: &[Value],
     ) -> RSDBResult<HashSet<Value>> {
-        let key = Key::Index(
-            table_name.to_string(),
-            col_name.to_string(),
-            col_value.clone(),
-        )
-        .encode()?;
+        let key = self.table_key(table_name, |db, t| {
+            Key::Index(db, t, index_name.to_string(), values.to_vec())
+        })?;
         Ok(self
             .txn
             .get(key)?
@@ -273,25 +808,18 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
     fn save_index(
         &self,
         table_name: &str,
-        col_name: &str,
-        col_value: &Value,
+        index_name: &str,
+        values: &[Value],
         index: HashSet<Value>,
     ) -> RSDBResult<()> {
-        let key = Key::Index(
-            table_name.to_string(),
-            col_name.to_string(),
-            col_value.clone(),
-        )
-        .encode()?;
-        if index.is_empty() {
-            self.txn.delete(key)
-        } else {
-            self.txn.set(key, bincode::serialize(&index)?)
+        match self.index_write(table_name, index_name, values, index)? {
+            (key, Some(value)) => self.txn.set(key, value),
+            (key, None) => self.txn.delete(key),
         }
     }
 
     fn read_by_pk(&self, table_name: &str, pk: &Value) -> RSDBResult<Option<Row>> {
-        let key = Key::Row(table_name.to_string(), pk.clone()).encode()?;
+        let key = self.table_key(table_name, |db, t| Key::Row(db, t, pk.clone()))?;
         Ok(self
             .txn
             .get(key)?
@@ -302,41 +830,129 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
 
 #[derive(Debug, Serialize, Deserialize)]
 enum Key {
-    Table(String),                // 表名
-    Row(String, Value),           // 表名，主键值
-    Index(String, String, Value), // 表名，列名，列值
+    Table(String, String),                     // 数据库名，表名
+    Row(String, String, Value),                // 数据库名，表名，主键值
+    Index(String, String, String, Vec<Value>), // 数据库名，表名，索引名，索引列的值
+    Stats(String, String),                     // 数据库名，表名
+    Sequence(String, String),                  // 数据库名，序列名
+    Database(String),                          // 数据库名
+    Migration(String),                         // 已经完成的数据迁移
 }
 
+// 浮点数改为保序编码之后，重写主键和索引 key 的迁移
+const FLOAT_KEYS_MIGRATION: &str = "float_keys";
+
 impl Key {
     pub fn encode(&self) -> RSDBResult<Vec<u8>> {
         serialize_key(self)
     }
 }
 
+// 与 Key 中对应的变体顺序一致，编码后是 Key 的前缀
 #[derive(Debug, Serialize)]
 enum KeyPrefix {
+    Table(String),
+    Row(String, String),
+    Index(String, String),
+    // 只用于占位，统计信息不需要按前缀扫描
+    #[allow(dead_code)]
+    Stats(String),
+    Sequence(String),
+}
+
+impl KeyPrefix {
+    pub fn encode(&self) -> RSDBResult<Vec<u8>> {
+        serialize_key(self)
+    }
+}
+
+// 旧版本中没有数据库名的 key 前缀，只用于迁移，变体顺序与 Key 一致
+#[derive(Debug, Serialize)]
+enum LegacyKeyPrefix {
     Table,
     Row(String),
+    Index(String),
+    Stats(String),
 }
 
-impl KeyPrefix {
+impl LegacyKeyPrefix {
     pub fn encode(&self) -> RSDBResult<Vec<u8>> {
         serialize_key(self)
     }
 }
 
+// 最初版本的表结构，只用于迁移
+#[derive(Debug, Serialize, Deserialize)]
+struct LegacyTable {
+    name: String,
+    columns: Vec<LegacyColumn>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LegacyColumn {
+    name: String,
+    datatype: DataType,
+    nullable: bool,
+    default: Option<Value>,
+    primary_key: bool,
+    index: bool,
+}
+
+impl LegacyTable {
+    // 之后增加的属性都取默认值，表名以 key 中的为准
+    fn into_table(self, name: String) -> Table {
+        Table {
+            name,
+            columns: self
+                .columns
+                .into_iter()
+                .map(|col| Column {
+                    name: col.name,
+                    datatype: col.datatype,
+                    max_length: None,
+                    int_width: None,
+                    precision: None,
+                    nullable: col.nullable,
+                    default: col.default,
+                    default_sequence: None,
+                    primary_key: col.primary_key,
+                    index: col.index && !col.primary_key,
+                })
+                .collect(),
+            indexes: Vec::new(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::KVEngine;
-    use crate::storage::engine::Engine as StorageEngine;
+    use std::{
+        collections::{HashMap, HashSet},
+        ops::RangeBounds,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use super::{
+        KVEngine, KVTransaction, Key, KeyPrefix, LegacyColumn, LegacyKeyPrefix, LegacyTable,
+    };
+    use crate::storage::engine::{Engine as StorageEngine, EngineIterator, EngineStatus};
     use crate::{
-        error::RSDBResult,
+        error::{RSDBError, RSDBResult},
         sql::{
-            engine::{Engine, Session},
-            executor::ResultSet,
-            types::{Row, Value},
+            engine::{DEFAULT_DATABASE, Engine, Session, Transaction, settings::SessionSettings},
+            executor::{DEFAULT_MAX_WIDTH, FormatOptions, ResultSet},
+            schema::{ColumnStats, Index, TableStats},
+            types::{self, DataType, Row, Value},
+        },
+        storage::{
+            disk::DiskEngine,
+            memory::MemoryEngine,
+            mvcc::{MvccKey, TransactionOptions},
         },
-        storage::disk::DiskEngine,
     };
 
     fn setup_table<E: StorageEngine + 'static>(s: &mut Session<KVEngine<E>>) -> RSDBResult<()> {
@@ -386,7 +1002,7 @@ mod tests {
         expect: Vec<Row>,
     ) -> RSDBResult<()> {
         match s.execute(&format!("select * from {};", table_name))? {
-            ResultSet::Scan { columns: _, rows } => {
+            ResultSet::Scan { rows, .. } => {
                 assert_eq!(rows, expect);
             }
             _ => unreachable!(),
@@ -394,13 +1010,29 @@ mod tests {
         Ok(())
     }
 
+    // 执行查询并返回结果中的行
+    fn query_rows<E: Engine + 'static>(s: &mut Session<E>, sql: &str) -> RSDBResult<Vec<Row>> {
+        match s.execute(sql)? {
+            ResultSet::Scan { rows, .. } => Ok(rows),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    // 执行 EXPLAIN 并返回查询的执行计划
+    fn explain<E: Engine + 'static>(s: &mut Session<E>, sql: &str) -> RSDBResult<String> {
+        match s.execute(&format!("explain {}", sql))? {
+            ResultSet::Explain { plan } => Ok(plan),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
     #[allow(dead_code)]
     fn scan_table_and_print<E: StorageEngine + 'static>(
         s: &mut Session<KVEngine<E>>,
         table_name: &str,
     ) -> RSDBResult<()> {
         match s.execute(&format!("select * from {};", table_name))? {
-            ResultSet::Scan { columns: _, rows } => {
+            ResultSet::Scan { rows, .. } => {
                 for row in rows {
                     println!("{:?}", row);
                 }
@@ -456,20 +1088,44 @@ mod tests {
 
         // t2
         s.execute("insert into t2 (a) values (1);")?;
+        // 整数自动转换为浮点数
+        s.execute("insert into t2 (a, c) values (2, 2);")?;
         scan_table_and_compare(
             &mut s,
             "t2",
-            vec![vec![
-                Value::Integer(1),
-                Value::Integer(100),
-                Value::Float(1.1),
-                Value::Boolean(false),
-                Value::Boolean(true),
-                Value::String("v1".to_string()),
-                Value::String("v2".to_string()),
-                Value::String("v3".to_string()),
-            ]],
+            vec![
+                vec![
+                    Value::Integer(1),
+                    Value::Integer(100),
+                    Value::Float(1.1),
+                    Value::Boolean(false),
+                    Value::Boolean(true),
+                    Value::String("v1".to_string()),
+                    Value::String("v2".to_string()),
+                    Value::String("v3".to_string()),
+                ],
+                vec![
+                    Value::Integer(2),
+                    Value::Integer(100),
+                    Value::Float(2.0),
+                    Value::Boolean(false),
+                    Value::Boolean(true),
+                    Value::String("v1".to_string()),
+                    Value::String("v2".to_string()),
+                    Value::String("v3".to_string()),
+                ],
+            ],
         )?;
+        // 无法精确转换为浮点数的整数，以及类型不兼容的值报错
+        assert!(
+            s.execute("insert into t2 (a, c) values (3, 9007199254740993);")
+                .is_err()
+        );
+        assert!(
+            s.execute("insert into t2 (a, c) values (3, true);")
+                .is_err()
+        );
+        assert!(s.execute("insert into t2 (a, b) values (3, 1.5);").is_err());
 
         // t3
         s.execute("insert into t3 (a, d) values (1, 1.1);")?;
@@ -501,21 +1157,443 @@ mod tests {
     }
 
     #[test]
-    fn test_update() -> RSDBResult<()> {
-        let p = tempfile::tempdir()?.keep().join("rsdb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+    fn test_insert_explicit_null() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
         let mut s = kvengine.session()?;
-        setup_table(&mut s)?;
-
-        s.execute("insert into t2 values (1, 1, 1.1, true, true, 'v1', 'v2', 'v3');")?;
-        s.execute("insert into t2 values (2, 2, 2.2, false, false, 'v4', 'v5', 'v6');")?;
-        s.execute("insert into t2 values (3, 3, 3.3, true, false, 'v7', 'v8', 'v9');")?;
-        s.execute("insert into t2 values (4, 4, 4.4, false, true, 'v10', 'v11', 'v12');")?;
+        s.execute(
+            "create table t (a int primary key, b text default 'vv', c int not null default 100);",
+        )?;
 
-        let res = s.execute("update t2 set b = 100 where a = 1;")?;
-        assert_eq!(res, ResultSet::Update { count: 1 });
+        // 显式的 NULL 不会替换为默认值
+        s.execute("insert into t values (1, null, 1);")?;
+        s.execute("insert into t (a, b) values (2, null);")?;
+        s.execute("insert into t (a) values (3);")?;
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::Integer(1), Value::Null, Value::Integer(1)],
+                vec![Value::Integer(2), Value::Null, Value::Integer(100)],
+                vec![
+                    Value::Integer(3),
+                    Value::String("vv".to_string()),
+                    Value::Integer(100),
+                ],
+            ],
+        )?;
+
+        // 非空列上显式的 NULL 报错，错误中包含表名、列名和行号
+        assert_eq!(
+            s.execute("insert into t values (4, 'x', 4), (5, 'y', null);"),
+            Err(RSDBError::InsertNullValue {
+                table: "t".to_string(),
+                column: "c".to_string(),
+                row: 2,
+            })
+        );
+        assert_eq!(
+            s.execute("insert into t (c, a) values (null, 6);"),
+            Err(RSDBError::InsertNullValue {
+                table: "t".to_string(),
+                column: "c".to_string(),
+                row: 1,
+            })
+        );
+        match s.execute("select a from t where a > 3;")? {
+            ResultSet::Scan { rows, .. } => assert!(rows.is_empty()),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_too_many_values() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c int);")?;
+
+        assert_eq!(
+            s.execute("insert into t1 values (1, 'a', 2, 99);"),
+            Err(RSDBError::InsertRow {
+                row: 1,
+                primary_key: None,
+                source: Box::new(RSDBError::Internal(
+                    "table t1 has 3 columns but 4 values were supplied".to_string()
+                )),
+            })
+        );
+        s.execute("insert into t1 values (1, 'a', 2);")?;
+
+        // 之前写入的多出值的行仍然可以扫描，多出的值被忽略
+        let txn = kvengine.begin()?;
+        let row: Row = vec![
+            Value::Integer(2),
+            Value::String("b".to_string()),
+            Value::Integer(3),
+            Value::Integer(99),
+        ];
+        txn.txn.set(
+            Key::Row(
+                DEFAULT_DATABASE.to_string(),
+                "t1".to_string(),
+                Value::Integer(2),
+            )
+            .encode()?,
+            bincode::serialize(&row)?,
+        )?;
+        txn.commit()?;
+        match s.execute("select c, a from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(2), Value::Integer(1)],
+                    vec![Value::Integer(3), Value::Integer(2)],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_row_error() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+
+        // 错误中带有出错的行号和主键，整条语句回滚
+        assert_eq!(
+            s.execute("insert into t values (1, 'a'), (2, 'b'), (1, 'c'), (3, 'd');"),
+            Err(RSDBError::InsertRow {
+                row: 3,
+                primary_key: Some("1".to_string()),
+                source: Box::new(RSDBError::Internal(
+                    "Duplicate data for primary key Integer(1) in table t".to_string()
+                )),
+            })
+        );
+        assert_eq!(
+            s.execute("insert into t values (1, 'a'), (2, 3);"),
+            Err(RSDBError::InsertRow {
+                row: 2,
+                primary_key: Some("2".to_string()),
+                source: Box::new(RSDBError::ColumnTypeMismatch {
+                    column: "b".to_string(),
+                    datatype: "String".to_string(),
+                    value: "3".to_string(),
+                }),
+            })
+        );
+        match s.execute("select * from t;")? {
+            ResultSet::Scan { rows, .. } => assert!(rows.is_empty()),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    // 统计 set 调用次数、scan 调用次数和扫描读取的 key 个数的存储引擎
+    // MVCC 的每次 get 对应一次 scan
+    struct CountingEngine {
+        inner: MemoryEngine,
+        sets: Arc<AtomicUsize>,
+        scans: Arc<AtomicUsize>,
+        reads: Arc<AtomicUsize>,
+    }
+
+    struct CountingIterator<'a> {
+        inner: <MemoryEngine as StorageEngine>::EngineIterator<'a>,
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl Iterator for CountingIterator<'_> {
+        type Item = RSDBResult<(Vec<u8>, Vec<u8>)>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let item = self.inner.next();
+            if item.is_some() {
+                self.reads.fetch_add(1, Ordering::SeqCst);
+            }
+            item
+        }
+    }
+
+    impl DoubleEndedIterator for CountingIterator<'_> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            let item = self.inner.next_back();
+            if item.is_some() {
+                self.reads.fetch_add(1, Ordering::SeqCst);
+            }
+            item
+        }
+    }
+
+    impl StorageEngine for CountingEngine {
+        type EngineIterator<'a> = CountingIterator<'a>;
+
+        fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> RSDBResult<()> {
+            self.sets.fetch_add(1, Ordering::SeqCst);
+            self.inner.set(key, value)
+        }
+
+        fn get(&mut self, key: Vec<u8>) -> RSDBResult<Option<Vec<u8>>> {
+            self.inner.get(key)
+        }
+
+        fn delete(&mut self, key: Vec<u8>) -> RSDBResult<()> {
+            self.inner.delete(key)
+        }
+
+        fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+            self.scans.fetch_add(1, Ordering::SeqCst);
+            CountingIterator {
+                inner: self.inner.scan(range),
+                reads: self.reads.clone(),
+            }
+        }
+
+        fn status(&mut self) -> RSDBResult<EngineStatus> {
+            self.inner.status()
+        }
+    }
+
+    impl EngineIterator for CountingIterator<'_> {}
+
+    #[test]
+    fn test_bulk_insert() -> RSDBResult<()> {
+        let sets = Arc::new(AtomicUsize::new(0));
+        let kvengine = KVEngine::new(CountingEngine {
+            inner: MemoryEngine::new(),
+            sets: sets.clone(),
+            scans: Arc::default(),
+            reads: Arc::default(),
+        });
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index, c text);")?;
+        s.execute("insert into t values (0, 0, 'x');")?;
+
+        // 一条 INSERT 插入 10000 行，b 列只有 10 个不同的值
+        let n = 10000;
+        let values = (1..=n)
+            .map(|i| format!("({}, {}, 'v{}')", i, i % 10, i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let res = s.execute(&format!("insert into t values {};", values))?;
+        assert_eq!(res, ResultSet::Insert { count: n });
+
+        match s.execute("select count(a), max(a) from t;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(
+                    rows,
+                    vec![vec![Value::Integer(n as i64 + 1), Value::Integer(n as i64)]]
+                )
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select count(a) from t where b = 0;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(n as i64 / 10 + 1)]])
+            }
+            _ => unreachable!(),
+        }
+
+        // 每个索引 key 只写入一次，MVCC 的每次写入对应存储引擎的两次 set
+        let txn = kvengine.begin()?;
+        let table = txn.must_get_table("t".to_string())?;
+        let rows = (n + 1..=2 * n)
+            .map(|i| {
+                vec![
+                    Value::Integer(i as i64),
+                    Value::Integer(i as i64 % 10),
+                    Value::Null,
+                ]
+            })
+            .collect();
+        let before = sets.load(Ordering::SeqCst);
+        txn.create_rows(&table, rows)?;
+        assert_eq!(sets.load(Ordering::SeqCst) - before, 2 * (n + 10));
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_limit_pushdown() -> RSDBResult<()> {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let kvengine = KVEngine::new(CountingEngine {
+            inner: MemoryEngine::new(),
+            sets: Arc::default(),
+            scans: Arc::default(),
+            reads: reads.clone(),
+        });
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c text);")?;
+        let n = 1000;
+        let values = (1..=n)
+            .map(|i| format!("({}, {}, 'v{}')", i, i % 10, i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        s.execute(&format!("insert into t values {};", values))?;
+        let mut count_reads = |sql: &str| -> RSDBResult<(Vec<Row>, usize)> {
+            reads.store(0, Ordering::SeqCst);
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok((rows, reads.load(Ordering::SeqCst))),
+                _ => unreachable!(),
+            }
+        };
+
+        // LIMIT 下推到扫描，读取到足够的行后停止
+        let (rows, count) = count_reads("select a from t limit 3 offset 2;")?;
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Integer(3)],
+                vec![Value::Integer(4)],
+                vec![Value::Integer(5)]
+            ]
+        );
+        assert!(count < 50, "read {} keys", count);
+        let (rows, count) = count_reads("select a, c from t where b = 7 limit 2;")?;
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Integer(7), Value::String("v7".to_string())],
+                vec![Value::Integer(17), Value::String("v17".to_string())],
+            ]
+        );
+        assert!(count < 50, "read {} keys", count);
+
+        // 中间有排序或聚集时不能下推，需要读取所有的行
+        for sql in [
+            "select a from t order by c limit 1;",
+            "select b, count(a) from t group by b limit 1;",
+        ] {
+            let (rows, count) = count_reads(sql)?;
+            assert_eq!(rows.len(), 1);
+            assert!(count >= n, "{}: read {} keys", sql, count);
+        }
+
+        // 下推的行数包含 OFFSET 跳过的行
+        match s.execute("explain select a from t where b = 1 limit 5 offset 2;")? {
+            ResultSet::Explain { plan } => assert_eq!(
+                plan,
+                "Projection: a\n  Limit: 5\n    Offset: 2\n      Scan: t [a] (filter: b = 1) (limit 7)"
+            ),
+            _ => unreachable!(),
+        }
+        match s.execute("explain select a from t order by a limit 5;")? {
+            ResultSet::Explain { plan } => assert!(!plan.contains("(limit"), "{}", plan),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by_primary_key() -> RSDBResult<()> {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let kvengine = KVEngine::new(CountingEngine {
+            inner: MemoryEngine::new(),
+            sets: Arc::default(),
+            scans: Arc::default(),
+            reads: reads.clone(),
+        });
+        let mut s = kvengine.session()?;
+        s.execute("create table t (k text primary key, b int, c int);")?;
+        let n = 500;
+        let values = (0..n)
+            .map(|i| format!("('k{:03}', {}, {})", i, i % 3, i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        s.execute(&format!("insert into t values {};", values))?;
+        let mut query = |sql: &str| -> RSDBResult<(Vec<Row>, usize)> {
+            reads.store(0, Ordering::SeqCst);
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok((rows, reads.load(Ordering::SeqCst))),
+                _ => unreachable!(),
+            }
+        };
+
+        // 按主键降序，反向扫描并下推 LIMIT，不需要排序
+        let (rows, count) = query("select k, c from t order by k desc limit 3;")?;
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::String("k499".to_string()), Value::Integer(499)],
+                vec![Value::String("k498".to_string()), Value::Integer(498)],
+                vec![Value::String("k497".to_string()), Value::Integer(497)],
+            ]
+        );
+        assert!(count < 50, "read {} keys", count);
+        let (rows, count) = query("select c from t where b = 0 order by t.k limit 2 offset 1;")?;
+        assert_eq!(rows, vec![vec![Value::Integer(3)], vec![Value::Integer(6)]]);
+        assert!(count < 50, "read {} keys", count);
+
+        let mut s = kvengine.session()?;
+        assert_eq!(
+            explain(&mut s, "select k from t order by k desc limit 3;")?,
+            "Projection: k\n  Limit: 3\n    Scan: t [k] (reverse) (limit 3)"
+        );
+        assert_eq!(
+            explain(&mut s, "select * from t where b = 1 order by k;")?,
+            "Scan: t (filter: b = 1)"
+        );
+
+        // 多列排序、非主键列、与主键同名的别名、整数主键都需要排序
+        s.execute("create table u (a int primary key, b int);")?;
+        s.execute("insert into u values (-2, 1), (3, 1), (-7, 2), (4, 2);")?;
+        for (sql, expected) in [
+            (
+                "select b, c from t where c < 6 order by b desc, k asc;",
+                vec![(2, 2), (2, 5), (1, 1), (1, 4), (0, 0), (0, 3)],
+            ),
+            (
+                "select b, c from t where c < 6 order by b, k desc;",
+                vec![(0, 3), (0, 0), (1, 4), (1, 1), (2, 5), (2, 2)],
+            ),
+            (
+                "select b, c from t where c < 6 order by k desc, b;",
+                vec![(2, 5), (1, 4), (0, 3), (2, 2), (1, 1), (0, 0)],
+            ),
+            (
+                "select b, c as k from t where c < 4 order by k desc;",
+                vec![(0, 3), (2, 2), (1, 1), (0, 0)],
+            ),
+            (
+                "select a, b from u order by a desc;",
+                vec![(4, 2), (3, 1), (-2, 1), (-7, 2)],
+            ),
+            (
+                "select b, a from u order by b desc, a;",
+                vec![(2, -7), (2, 4), (1, -2), (1, 3)],
+            ),
+        ] {
+            assert!(explain(&mut s, sql)?.contains("Order:"), "{}", sql);
+            let rows = query_rows(&mut s, sql)?;
+            let expected = expected
+                .into_iter()
+                .map(|(x, y)| vec![Value::Integer(x), Value::Integer(y)])
+                .collect::<Vec<_>>();
+            assert_eq!(rows, expected, "{}", sql);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_update() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        setup_table(&mut s)?;
+
+        s.execute("insert into t2 values (1, 1, 1.1, true, true, 'v1', 'v2', 'v3');")?;
+        s.execute("insert into t2 values (2, 2, 2.2, false, false, 'v4', 'v5', 'v6');")?;
+        s.execute("insert into t2 values (3, 3, 3.3, true, false, 'v7', 'v8', 'v9');")?;
+        s.execute("insert into t2 values (4, 4, 4.4, false, true, 'v10', 'v11', 'v12');")?;
+
+        let res = s.execute("update t2 set b = 100 where a = 1;")?;
+        assert_eq!(res, ResultSet::Update { count: 1 });
         let res = s.execute("update t2 set d = false where d = true;")?;
         assert_eq!(res, ResultSet::Update { count: 2 });
+        let res = s.execute("update t2 set c = 5 where a = 2;")?;
+        assert_eq!(res, ResultSet::Update { count: 1 });
 
         scan_table_and_compare(
             &mut s,
@@ -534,7 +1612,7 @@ mod tests {
                 vec![
                     Value::Integer(2),
                     Value::Integer(2),
-                    Value::Float(2.2),
+                    Value::Float(5.0),
                     Value::Boolean(false),
                     Value::Boolean(false),
                     Value::String("v4".to_string()),
@@ -568,6 +1646,85 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_update_expression() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c int index, d text);")?;
+        s.execute("insert into t values (1, 1, 10, 'x'), (2, 2, 20, 'y'), (3, 3, 30, 'z');")?;
+
+        // SET 中的表达式基于更新前的行计算
+        let res = s.execute("update t set b = b + 1, c = b * 100 where a > 1;")?;
+        assert_eq!(res, ResultSet::Update { count: 2 });
+        // 主键扫描和索引扫描作为数据源
+        s.execute("update t set b = a + c where a = 1;")?;
+        s.execute("update t set a = a + 10 where c = 300;")?;
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![
+                    Value::Integer(1),
+                    Value::Integer(11),
+                    Value::Integer(10),
+                    Value::String("x".to_string()),
+                ],
+                vec![
+                    Value::Integer(2),
+                    Value::Integer(3),
+                    Value::Integer(200),
+                    Value::String("y".to_string()),
+                ],
+                vec![
+                    Value::Integer(13),
+                    Value::Integer(4),
+                    Value::Integer(300),
+                    Value::String("z".to_string()),
+                ],
+            ],
+        )?;
+
+        // 表达式计算出错时返回错误，而不是 panic
+        assert!(s.execute("update t set b = d + 1;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_validation() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, d float not null);")?;
+        s.execute("insert into t values (1, 'x', 1.5);")?;
+
+        // 更新后的行同样要校验类型和是否为空
+        assert_eq!(
+            s.execute("update t set b = 1;"),
+            Err(RSDBError::ColumnTypeMismatch {
+                column: "b".to_string(),
+                datatype: "String".to_string(),
+                value: "1".to_string(),
+            })
+        );
+        assert_eq!(
+            s.execute("update t set d = null where a = 1;"),
+            Err(RSDBError::NotNullViolation {
+                column: "d".to_string(),
+            })
+        );
+        // 更新主键时先删除再插入，同样会校验
+        assert!(s.execute("update t set a = 2, d = null;").is_err());
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![vec![
+                Value::Integer(1),
+                Value::String("x".to_string()),
+                Value::Float(1.5),
+            ]],
+        )?;
+        Ok(())
+    }
+
     #[test]
     fn test_delete() -> RSDBResult<()> {
         let p = tempfile::tempdir()?.keep().join("rsdb-log");
@@ -659,7 +1816,7 @@ mod tests {
         s.execute("insert into t3 values (7, 87, 82, 9.52);")?;
 
         match s.execute("select a, b as col2 from t3 order by b, c desc limit 3 offset 1;")? {
-            ResultSet::Scan { columns, rows } => {
+            ResultSet::Scan { columns, rows, .. } => {
                 for col in &columns {
                     print!("{} ", col);
                 }
@@ -689,7 +1846,7 @@ mod tests {
         s.execute("insert into t3 values (7), (8), (9);")?;
 
         match s.execute("select * from t1 cross join t2 cross join t3;")? {
-            ResultSet::Scan { columns, rows } => {
+            ResultSet::Scan { columns, rows, .. } => {
                 assert_eq!(3, columns.len());
                 assert_eq!(27, rows.len());
                 // for row in rows {
@@ -699,6 +1856,10 @@ mod tests {
             _ => unreachable!(),
         }
 
+        // 逗号分隔的表等价于 cross join
+        let res = s.execute("select * from t1 cross join t2 cross join t3;")?;
+        assert_eq!(s.execute("select * from t1, t2, t3;")?, res);
+
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
@@ -717,124 +1878,124 @@ mod tests {
         s.execute("insert into t3 values (3), (8), (9);")?;
 
         match s.execute("select * from t1 right join t2 on a = b join t3 on a = c;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(3, columns.len());
-                assert_eq!(1, rows.len());
-                for row in rows {
-                    println!("{:?}", row);
-                }
-            }
-            _ => unreachable!(),
-        }
-
-        std::fs::remove_dir_all(p.parent().unwrap())?;
-        Ok(())
-    }
-
-    #[test]
-    fn test_agg() -> RSDBResult<()> {
-        let p = tempfile::tempdir()?.keep().join("rsdb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
-        let mut s = kvengine.session()?;
-        s.execute("create table t1 (a int primary key, b text, c float);")?;
-
-        s.execute("insert into t1 values (1, 'aa', 3.1);")?;
-        s.execute("insert into t1 values (2, 'cc', 5.3);")?;
-        s.execute("insert into t1 values (3, null, NULL);")?;
-        s.execute("insert into t1 values (4, 'dd', 4.6);")?;
-
-        match s.execute("select count(a) as total, max(b), min(a), sum(c), avg(c) from t1;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(
-                    columns,
-                    vec!["total", "MAX(b)", "MIN(a)", "SUM(c)", "AVG(c)"]
-                );
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["t1.a", "t2.b", "t3.c"]);
                 assert_eq!(
                     rows,
                     vec![vec![
-                        Value::Integer(4),
-                        Value::String("dd".to_string()),
-                        Value::Integer(1),
-                        Value::Float(13.0),
-                        Value::Float(13.0 / 3.0)
+                        Value::Integer(3),
+                        Value::Integer(3),
+                        Value::Integer(3)
                     ]]
                 );
             }
             _ => unreachable!(),
         }
 
-        s.execute("create table t2 (a int primary key, b text, c float);")?;
-        s.execute("insert into t2 values (1, NULL, NULL);")?;
-        s.execute("insert into t2 values (2, NULL, NULL);")?;
-        match s.execute("select count(a) as total, max(b), min(a), sum(c), avg(c) from t2;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(
-                    columns,
-                    vec!["total", "MAX(b)", "MIN(a)", "SUM(c)", "AVG(c)"]
-                );
+        // right join 的列顺序与查询一致，左表一侧填充 NULL，与交换两侧的 left join 结果相同
+        let sorted = |rs: ResultSet| match rs {
+            ResultSet::Scan { mut rows, .. } => {
+                rows.sort_by(|x, y| {
+                    x.iter()
+                        .zip(y)
+                        .map(|(x, y)| x.total_cmp(y))
+                        .find(|o| o.is_ne())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                rows
+            }
+            _ => unreachable!(),
+        };
+        let (i, null) = (Value::Integer, Value::Null);
+        for (sql, mirror) in [
+            (
+                "select a, b from t1 right join t2 on a = b;",
+                "select a, b from t2 left join t1 on b = a;",
+            ),
+            (
+                "select a, b, c from t1 right join t2 on a = b join t3 on a = c;",
+                "select a, b, c from t2 left join t1 on b = a join t3 on a = c;",
+            ),
+            (
+                "select a, b, c from t1 right join t2 on a = b right join t3 on b = c;",
+                "select a, b, c from t3 left join t2 on c = b left join t1 on a = b;",
+            ),
+        ] {
+            assert_eq!(
+                sorted(s.execute(sql)?),
+                sorted(s.execute(mirror)?),
+                "{}",
+                sql
+            );
+        }
+        match s.execute("select * from t1 right join t2 on a = b;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["t1.a", "t2.b"]);
                 assert_eq!(
                     rows,
-                    vec![vec![
-                        Value::Integer(2),
-                        Value::Null,
-                        Value::Integer(1),
-                        Value::Null,
-                        Value::Null
-                    ]]
+                    vec![vec![i(2), i(2)], vec![i(3), i(3)], vec![null.clone(), i(4)]]
                 );
             }
             _ => unreachable!(),
         }
+        assert_eq!(
+            sorted(s.execute(
+                "select a, b, c from t1 right join t2 on a = b right join t3 on b = c;"
+            )?),
+            vec![
+                vec![null.clone(), null.clone(), i(8)],
+                vec![null.clone(), null, i(9)],
+                vec![i(3), i(3), i(3)],
+            ]
+        );
 
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
     #[test]
-    fn test_group_by() -> RSDBResult<()> {
+    fn test_full_join() -> RSDBResult<()> {
         let p = tempfile::tempdir()?.keep().join("rsdb-log");
         let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
         let mut s = kvengine.session()?;
-        s.execute("create table t1 (a int primary key, b text, c float);")?;
+        s.execute("create table t1 (a int primary key, x text);")?;
+        s.execute("create table t2 (b int primary key, y text);")?;
 
-        s.execute("insert into t1 values (1, 'aa', 3.1);")?;
-        s.execute("insert into t1 values (2, 'bb', 5.3);")?;
-        s.execute("insert into t1 values (3, null, NULL);")?;
-        s.execute("insert into t1 values (4, null, 4.6);")?;
-        s.execute("insert into t1 values (5, 'bb', 5.8);")?;
-        s.execute("insert into t1 values (6, 'dd', 1.4);")?;
+        s.execute("insert into t1 values (1, 'l1'), (2, 'l2'), (3, 'l3');")?;
+        s.execute("insert into t2 values (2, 'r2'), (3, 'r3'), (4, 'r4');")?;
 
-        match s.execute(
-            "select b, min(c), max(a), avg(c) as avg_c from t1 group by b order by avg_c;",
-        )? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns, vec!["b", "MIN(c)", "MAX(a)", "avg_c"]);
+        match s.execute("select * from t1 full join t2 on a = b;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["t1.a", "t1.x", "t2.b", "t2.y"]);
                 assert_eq!(
                     rows,
                     vec![
+                        // 只有左表有
                         vec![
-                            Value::String("dd".to_string()),
-                            Value::Float(1.4),
-                            Value::Integer(6),
-                            Value::Float(1.4)
+                            Value::Integer(1),
+                            Value::String("l1".to_string()),
+                            Value::Null,
+                            Value::Null,
                         ],
+                        // 左右都匹配
                         vec![
-                            Value::String("aa".to_string()),
-                            Value::Float(3.1),
-                            Value::Integer(1),
-                            Value::Float(3.1)
+                            Value::Integer(2),
+                            Value::String("l2".to_string()),
+                            Value::Integer(2),
+                            Value::String("r2".to_string()),
                         ],
                         vec![
-                            Value::Null,
-                            Value::Float(4.6),
-                            Value::Integer(4),
-                            Value::Float(4.6)
+                            Value::Integer(3),
+                            Value::String("l3".to_string()),
+                            Value::Integer(3),
+                            Value::String("r3".to_string()),
                         ],
+                        // 只有右表有
                         vec![
-                            Value::String("bb".to_string()),
-                            Value::Float(5.3),
-                            Value::Integer(5),
-                            Value::Float(5.55)
+                            Value::Null,
+                            Value::Null,
+                            Value::Integer(4),
+                            Value::String("r4".to_string()),
                         ],
                     ]
                 );
@@ -847,106 +2008,513 @@ mod tests {
     }
 
     #[test]
-    fn test_filter() -> RSDBResult<()> {
-        let p = tempfile::tempdir()?.keep().join("rsdb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+    fn test_full_join_duplicate_keys() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
         let mut s = kvengine.session()?;
-        s.execute("create table t1 (a int primary key, b text, c float, d bool);")?;
+        s.execute("create table t1 (a int primary key, x int);")?;
+        s.execute("create table t2 (b int primary key, y int);")?;
+        // 两侧的 join 列都有重复值和 NULL
+        s.execute("insert into t1 values (1, 1), (2, 1), (3, 2), (4, null), (5, 5);")?;
+        s.execute("insert into t2 values (1, 1), (2, 2), (3, 2), (4, null), (5, 7);")?;
 
-        s.execute("insert into t1 values (1, 'aa', 3.1, true);")?;
-        s.execute("insert into t1 values (2, 'bb', 5.3, true);")?;
-        s.execute("insert into t1 values (3, null, NULL, false);")?;
-        s.execute("insert into t1 values (4, null, 4.6, false);")?;
-        s.execute("insert into t1 values (5, 'bb', 5.8, true);")?;
-        s.execute("insert into t1 values (6, 'dd', 1.4, false);")?;
+        let (i, null) = (Value::Integer, || Value::Null);
+        let expected = vec![
+            vec![null(), null(), i(4), null()],
+            vec![null(), null(), i(5), i(7)],
+            vec![i(1), i(1), i(1), i(1)],
+            vec![i(2), i(1), i(1), i(1)],
+            vec![i(3), i(2), i(2), i(2)],
+            vec![i(3), i(2), i(3), i(2)],
+            vec![i(4), null(), null(), null()],
+            vec![i(5), i(5), null(), null()],
+        ];
+        // HashJoin 与 NestLoopJoin，以及交换两侧的顺序，结果都一致
+        for (sql, plan) in [
+            (
+                "select a, x, b, y from t1 full join t2 on x = y;",
+                "HashJoin",
+            ),
+            (
+                "select a, x, b, y from t2 full join t1 on y = x;",
+                "HashJoin",
+            ),
+            (
+                "select a, x, b, y from t1 full join t2 on x = y + 0;",
+                "NestLoopJoin",
+            ),
+            (
+                "select a, x, b, y from t2 full join t1 on y + 0 = x;",
+                "NestLoopJoin",
+            ),
+        ] {
+            match s.execute(&format!("explain {}", sql))? {
+                ResultSet::Explain { plan: p } => assert!(p.contains(plan), "{}", p),
+                _ => unreachable!(),
+            }
+            match s.execute(sql)? {
+                ResultSet::Scan { mut rows, .. } => {
+                    rows.sort_by(|x, y| {
+                        x.iter()
+                            .zip(y)
+                            .map(|(x, y)| x.total_cmp(y))
+                            .find(|o| o.is_ne())
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    });
+                    assert_eq!(rows, expected, "{}", sql);
+                }
+                _ => unreachable!(),
+            }
+        }
+        Ok(())
+    }
 
-        match s.execute("select * from t1 where d < true;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(4, columns.len());
-                assert_eq!(3, rows.len());
+    #[test]
+    fn test_join_using() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (id int primary key, k int, x text);")?;
+        s.execute("create table t2 (id int primary key, k int, y text);")?;
+
+        s.execute("insert into t1 values (1, 10, 'l1'), (2, 20, 'l2'), (3, 30, 'l3');")?;
+        s.execute("insert into t2 values (2, 20, 'r2'), (3, 31, 'r3'), (4, 40, 'r4');")?;
+
+        // on 条件的结果中 id 列出现两次，以表名区分，using 只保留一份
+        match s.execute("select * from t1 join t2 on id = id;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(
+                    columns,
+                    vec!["t1.id", "t1.k", "t1.x", "t2.id", "t2.k", "t2.y"]
+                );
+                assert_eq!(rows.len(), 2);
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select * from t1 join t2 using (id);")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["id", "t1.k", "t1.x", "t2.k", "t2.y"]);
+                assert_eq!(rows.len(), 2);
+            }
+            _ => unreachable!(),
+        }
+
+        // 多个列之间是 and 的关系
+        match s.execute("select * from t1 join t2 using (id, k);")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["id", "k", "t1.x", "t2.y"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(2),
+                        Value::Integer(20),
+                        Value::String("l2".to_string()),
+                        Value::String("r2".to_string()),
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // full join 时 using 列取两边非 NULL 的值
+        match s.execute("select * from t1 full join t2 using (id);")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["id", "t1.k", "t1.x", "t2.k", "t2.y"]);
+                let ids = rows.iter().map(|r| r[0].clone()).collect::<Vec<_>>();
+                assert_eq!(
+                    ids,
+                    vec![
+                        Value::Integer(1),
+                        Value::Integer(2),
+                        Value::Integer(3),
+                        Value::Integer(4)
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_qualified_columns() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (id int primary key, k int);")?;
+        s.execute("create table t2 (id int primary key, k int);")?;
+        s.execute("insert into t1 values (1, 10), (2, 20), (3, 30);")?;
+        s.execute("insert into t2 values (2, 21), (3, 31), (4, 41);")?;
+
+        // 同名的列以表名区分
+        let rs = s.execute("select * from t1 join t2 on t1.id = t2.id where t2.k > 30;")?;
+        assert_eq!(
+            rs.to_string(),
+            "t1.id |t1.k |t2.id |t2.k\n------+-----+------+-----\n    3 |  30 |    3 |  31\n1 ROWS"
+        );
+        match s.execute("select t2.id, t1.k, t2.k as k2 from t1 left join t2 on t2.id = t1.id order by t1.id desc;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["t2.id", "t1.k", "k2"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::Integer(3), Value::Integer(30), Value::Integer(31)],
+                        vec![Value::Integer(2), Value::Integer(20), Value::Integer(21)],
+                        vec![Value::Null, Value::Integer(10), Value::Null],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+        // 过滤条件在 Join 之后计算时也使用正确的列
+        match s.execute("select t1.id from t1 full join t2 on t1.id = t2.id where t1.k < t2.k;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(2)], vec![Value::Integer(3)]]);
+            }
+            _ => unreachable!(),
+        }
+        // 聚集函数
+        match s.execute("select count(t2.id), max(t1.k) from t1 left join t2 on t1.id = t2.id;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(2), Value::Integer(30)]]);
+            }
+            _ => unreachable!(),
+        }
+        // 没有限定的同名列有歧义，不存在的表名报错
+        assert_eq!(
+            s.execute("select k from t1 join t2 on t1.id = t2.id;"),
+            Err(RSDBError::AmbiguousColumn("k".to_string()))
+        );
+        assert!(
+            s.execute("select t3.k from t1 join t2 on t1.id = t2.id;")
+                .is_err()
+        );
+
+        // 单表查询也可以使用限定的列名，仍然可以使用主键和索引
+        match s.execute("explain select t1.k from t1 where t1.id = 2;")? {
+            ResultSet::Explain { plan } => {
+                assert_eq!(plan, "Projection: t1.k\n  PrimaryKeyScan: t1 (2)")
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select t1.k from t1 where t1.id = 2;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["t1.k"]);
+                assert_eq!(rows, vec![vec![Value::Integer(20)]]);
+            }
+            _ => unreachable!(),
+        }
+        s.execute("update t1 set k = 31 where t1.id = 3;")?;
+        match s.execute("select k from t1 where id = 3;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(31)]]),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_join_duplicate_columns() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+        s.execute("create table t2 (a int primary key, c int);")?;
+        s.execute("create table t3 (a int primary key);")?;
+        s.execute("insert into t1 values (1, 10), (2, 20);")?;
+        s.execute("insert into t2 values (3, 30), (4, 40);")?;
+        s.execute("insert into t3 values (5);")?;
+
+        // 同名的列以表名限定，不会重复
+        match s.execute("select * from t1 cross join t2 cross join t3;")? {
+            ResultSet::Scan { columns, .. } => {
+                assert_eq!(columns, vec!["t1.a", "t1.b", "t2.a", "t2.c", "t3.a"])
+            }
+            _ => unreachable!(),
+        }
+
+        // 在 SELECT 和 ORDER BY 中引用有歧义的列名报错，而不是使用第一个同名列
+        for sql in [
+            "select a from t1 cross join t2;",
+            "select b from t1 cross join t2 order by a;",
+            "select * from t1, t2 order by a desc;",
+            "select t1.b from t1 cross join t2 cross join t3 order by a;",
+        ] {
+            assert_eq!(
+                s.execute(sql),
+                Err(RSDBError::AmbiguousColumn("a".to_string())),
+                "{}",
+                sql
+            );
+        }
+
+        // 限定后可以分别引用
+        match s.execute("select t1.a, t2.a, c from t1 cross join t2 order by t2.a desc, t1.a;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["t1.a", "t2.a", "c"]);
+                let i = Value::Integer;
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![i(1), i(4), i(40)],
+                        vec![i(2), i(4), i(40)],
+                        vec![i(1), i(3), i(30)],
+                        vec![i(2), i(3), i(30)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_self_join() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table employees (id int primary key, name text, manager_id int);")?;
+        s.execute(
+            "insert into employees values (1, 'alice', null), (2, 'bob', 1), (3, 'carol', 1), (4, 'dave', 2);",
+        )?;
+        let rows = |rows: Vec<Vec<&str>>| -> Vec<Vec<Value>> {
+            rows.into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .map(|v| match v {
+                            "NULL" => Value::Null,
+                            v => Value::String(v.to_string()),
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+
+        // 同一张表以不同的别名出现，列名以别名限定
+        match s.execute(
+            "select e.name, m.name from employees e join employees m on e.manager_id = m.id order by e.id;",
+        )? {
+            ResultSet::Scan {
+                columns, rows: r, ..
+            } => {
+                assert_eq!(columns, vec!["e.name", "m.name"]);
+                assert_eq!(
+                    r,
+                    rows(vec![
+                        vec!["bob", "alice"],
+                        vec!["carol", "alice"],
+                        vec!["dave", "bob"]
+                    ])
+                );
             }
             _ => unreachable!(),
         }
 
+        // 外连接、AS 别名，以及下推到一侧的过滤条件
         match s.execute(
-            "select b, sum(c) as sum_c from t1 group by b having sum_c < 5 order by sum_c;",
+            "select e.name, m.name as manager from employees as e left join employees as m \
+             on m.id = e.manager_id where e.id < 4 order by e.name desc;",
         )? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(2, columns.len());
-                assert_eq!(3, rows.len());
+            ResultSet::Scan {
+                columns, rows: r, ..
+            } => {
+                assert_eq!(columns, vec!["e.name", "manager"]);
+                assert_eq!(
+                    r,
+                    rows(vec![
+                        vec!["carol", "alice"],
+                        vec!["bob", "alice"],
+                        vec!["alice", "NULL"]
+                    ])
+                );
             }
             _ => unreachable!(),
         }
+        match s.execute(
+            "select m.name, count(e.id) from employees e join employees m \
+             on e.manager_id = m.id group by m.name order by m.name;",
+        )? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::String("alice".to_string()), Value::Integer(2)],
+                    vec![Value::String("bob".to_string()), Value::Integer(1)],
+                ]
+            ),
+            _ => unreachable!(),
+        }
 
-        std::fs::remove_dir_all(p.parent().unwrap())?;
+        // 没有限定的列在两侧都存在，有歧义；单表查询也可以使用别名
+        assert_eq!(
+            s.execute("select name from employees e join employees m on e.manager_id = m.id;"),
+            Err(RSDBError::AmbiguousColumn("name".to_string()))
+        );
+        match s.execute("select e.name from employees e where e.id = 4;")? {
+            ResultSet::Scan { rows: r, .. } => assert_eq!(r, rows(vec![vec!["dave"]])),
+            _ => unreachable!(),
+        }
         Ok(())
     }
 
     #[test]
-    fn test_index() -> RSDBResult<()> {
+    fn test_keyword_identifiers() -> RSDBResult<()> {
         let p = tempfile::tempdir()?.keep().join("rsdb-log");
         let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
         let mut s = kvengine.session()?;
-        s.execute("create table t (a int primary key, b text index, c float index, d bool);")?;
-        s.execute("insert into t values (1, 'a', 1.1, true);")?;
-        s.execute("insert into t values (2, 'b', 2.1, true);")?;
-        s.execute("insert into t values (3, 'a', 3.2, false);")?;
-        s.execute("insert into t values (4, 'c', 1.1, true);")?;
-        s.execute("insert into t values (5, 'd', 2.1, false);")?;
-
-        s.execute("delete from t where a = 4;")?;
+        // 非保留关键字可以直接作为列名，保留关键字需要用双引号括起来
+        s.execute(
+            "create table index (key int primary key, text text, left bool, right float, \"order\" int index);",
+        )?;
+        s.execute(
+            "insert into index (key, text, left, right, \"order\") values (1, 'a', true, 1.5, 10);",
+        )?;
+        s.execute("insert into index values (2, 'b', false, 2.5, 20);")?;
+        s.execute("update index set text = 'c' where key = 2;")?;
 
-        match s.execute("select * from t where c = 1.1;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns.len(), 4);
-                assert_eq!(rows.len(), 1);
+        match s.execute(
+            "select key, text as desc, \"order\" from index where \"order\" = 20 order by key desc;",
+        )? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["key", "desc", "order"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(2),
+                        Value::String("c".to_string()),
+                        Value::Integer(20),
+                    ]]
+                );
             }
             _ => unreachable!(),
         }
 
+        // 保留关键字不加引号仍然报错
+        assert!(
+            s.execute("create table t (order int primary key);")
+                .is_err()
+        );
+        assert!(s.execute("create table t (\"\" int primary key);").is_err());
+
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
     #[test]
-    fn test_primary_key_scan() -> RSDBResult<()> {
+    fn test_modulo() -> RSDBResult<()> {
         let p = tempfile::tempdir()?.keep().join("rsdb-log");
         let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
         let mut s = kvengine.session()?;
-        s.execute("create table t (a int primary key, b text index, c float index, d bool);")?;
-        s.execute("insert into t values (1, 'a', 1.1, true);")?;
-        s.execute("insert into t values (2, 'b', 2.1, true);")?;
-        s.execute("insert into t values (3, 'a', 3.2, false);")?;
+        s.execute("create table t (a int primary key, b float);")?;
+        s.execute("insert into t values (-7, -7.5), (-2, 2.5), (3, 3.5), (4, 4.0), (7, 7.5);")?;
 
-        match s.execute("select * from t where a = 2;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns.len(), 4);
-                assert_eq!(rows.len(), 1);
+        let select_a = |s: &mut Session<_>, sql: &str| -> RSDBResult<Vec<Value>> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => {
+                    Ok(rows.into_iter().map(|r| r[0].clone()).collect())
+                }
+                _ => unreachable!(),
             }
-            _ => unreachable!(),
-        }
+        };
+
+        assert_eq!(
+            select_a(&mut s, "select a from t where a % 2 = 0 order by a;")?,
+            vec![Value::Integer(-2), Value::Integer(4)]
+        );
+        // 取模的结果与被除数同号
+        assert_eq!(
+            select_a(&mut s, "select a from t where a % 2 = -1 order by a;")?,
+            vec![Value::Integer(-7)]
+        );
+        assert_eq!(
+            select_a(&mut s, "select a from t where a % -4 = 3 order by a;")?,
+            vec![Value::Integer(3), Value::Integer(7)]
+        );
+        // 整数除法向 0 截断
+        assert_eq!(
+            select_a(&mut s, "select a from t where a div 2 = -3 order by a;")?,
+            vec![Value::Integer(-7)]
+        );
+        // 浮点数
+        assert_eq!(
+            select_a(&mut s, "select a from t where b % 2 = 1.5 order by a;")?,
+            vec![Value::Integer(3), Value::Integer(7)]
+        );
+        assert_eq!(
+            select_a(&mut s, "select a from t where b % 2 = -1.5 order by a;")?,
+            vec![Value::Integer(-7)]
+        );
+        // 除数为 0
+        assert!(s.execute("select a from t where a % 0 = 0;").is_err());
+        assert!(s.execute("select a from t where b div 0.0 = 0;").is_err());
 
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
     #[test]
-    fn test_hash_join() -> RSDBResult<()> {
+    fn test_agg() -> RSDBResult<()> {
         let p = tempfile::tempdir()?.keep().join("rsdb-log");
         let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
         let mut s = kvengine.session()?;
-        s.execute("create table t1 (a int primary key);")?;
-        s.execute("create table t2 (b int primary key);")?;
-        s.execute("create table t3 (c int primary key);")?;
+        s.execute("create table t1 (a int primary key, b text, c float);")?;
 
-        s.execute("insert into t1 values (1), (2), (3);")?;
-        s.execute("insert into t2 values (2), (3), (4);")?;
-        s.execute("insert into t3 values (3), (8), (9);")?;
+        s.execute("insert into t1 values (1, 'aa', 3.1);")?;
+        s.execute("insert into t1 values (2, 'cc', 5.3);")?;
+        s.execute("insert into t1 values (3, null, NULL);")?;
+        s.execute("insert into t1 values (4, 'dd', 4.6);")?;
 
-        match s.execute("select * from t1 join t2 on a = b join t3 on a = c;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns.len(), 3);
-                assert_eq!(rows.len(), 1);
+        match s.execute("select count(a) as total, max(b), min(a), sum(c), avg(c) from t1;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(
+                    columns,
+                    vec!["total", "MAX(b)", "MIN(a)", "SUM(c)", "AVG(c)"]
+                );
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(4),
+                        Value::String("dd".to_string()),
+                        Value::Integer(1),
+                        Value::Float(13.0),
+                        Value::Float(13.0 / 3.0)
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        s.execute("create table t2 (a int primary key, b text, c float);")?;
+        s.execute("insert into t2 values (1, NULL, NULL);")?;
+        s.execute("insert into t2 values (2, NULL, NULL);")?;
+        match s.execute("select count(a) as total, max(b), min(a), sum(c), avg(c) from t2;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(
+                    columns,
+                    vec!["total", "MAX(b)", "MIN(a)", "SUM(c)", "AVG(c)"]
+                );
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(2),
+                        Value::Null,
+                        Value::Integer(1),
+                        Value::Null,
+                        Value::Null
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 整数列的和是整数，平均值仍然是浮点数
+        match s.execute("select sum(a), avg(a) from t1;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(10), Value::Float(2.5)]]);
+            }
+            _ => unreachable!(),
+        }
+        s.execute("create table t3 (a int primary key, b int);")?;
+        s.execute("insert into t3 values (1, 9223372036854775807), (2, 1);")?;
+        match s.execute("select sum(b) from t3;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Float(9223372036854775808.0)]]);
             }
             _ => unreachable!(),
         }
@@ -954,4 +2522,4267 @@ mod tests {
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
+
+    #[test]
+    fn test_group_by() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c float);")?;
+
+        s.execute("insert into t1 values (1, 'aa', 3.1);")?;
+        s.execute("insert into t1 values (2, 'bb', 5.3);")?;
+        s.execute("insert into t1 values (3, null, NULL);")?;
+        s.execute("insert into t1 values (4, null, 4.6);")?;
+        s.execute("insert into t1 values (5, 'bb', 5.8);")?;
+        s.execute("insert into t1 values (6, 'dd', 1.4);")?;
+
+        match s.execute(
+            "select b, min(c), max(a), avg(c) as avg_c from t1 group by b order by avg_c;",
+        )? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["b", "MIN(c)", "MAX(a)", "avg_c"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![
+                            Value::String("dd".to_string()),
+                            Value::Float(1.4),
+                            Value::Integer(6),
+                            Value::Float(1.4)
+                        ],
+                        vec![
+                            Value::String("aa".to_string()),
+                            Value::Float(3.1),
+                            Value::Integer(1),
+                            Value::Float(3.1)
+                        ],
+                        vec![
+                            Value::Null,
+                            Value::Float(4.6),
+                            Value::Integer(4),
+                            Value::Float(4.6)
+                        ],
+                        vec![
+                            Value::String("bb".to_string()),
+                            Value::Float(5.3),
+                            Value::Integer(5),
+                            Value::Float(5.55)
+                        ],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by_multiple_columns() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, a int, b text, c int);")?;
+        s.execute(
+            "insert into t values (1, 1, 'x', 10), (2, 1, 'x', 20), (3, 1, 'y', 30), (4, 1, null, 40),
+                (5, null, 'x', 50), (6, null, 'x', 60), (7, null, null, 70), (8, 2, null, 80),
+                (9, 1, null, 90), (10, null, null, 100);",
+        )?;
+
+        // 两个分组列中都有 NULL，NULL 与 NULL 分在同一组，输出的列顺序与 SELECT 中一致
+        let sorted = |rs: ResultSet| match rs {
+            ResultSet::Scan {
+                columns, mut rows, ..
+            } => {
+                rows.sort_by(|x, y| {
+                    x.iter()
+                        .zip(y)
+                        .map(|(x, y)| x.total_cmp(y))
+                        .find(|o| o.is_ne())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                (columns, rows)
+            }
+            _ => unreachable!(),
+        };
+        let (i, t, null) = (
+            Value::Integer,
+            |s: &str| Value::String(s.to_string()),
+            || Value::Null,
+        );
+        let (columns, rows) =
+            sorted(s.execute("select b, count(id), a, sum(c) from t group by a, b;")?);
+        assert_eq!(columns, vec!["b", "COUNT(id)", "a", "SUM(c)"]);
+        assert_eq!(
+            rows,
+            vec![
+                vec![null(), i(1), i(2), i(80)],
+                vec![null(), i(2), null(), i(170)],
+                vec![null(), i(2), i(1), i(130)],
+                vec![t("x"), i(2), null(), i(110)],
+                vec![t("x"), i(2), i(1), i(30)],
+                vec![t("y"), i(1), i(1), i(30)],
+            ]
+        );
+        // 分组列的顺序不影响分组的结果
+        let (_, reversed) =
+            sorted(s.execute("select b, count(id), a, sum(c) from t group by b, a;")?);
+        assert_eq!(reversed, rows);
+
+        // 只选择部分分组列
+        let (columns, rows) =
+            sorted(s.execute("select a as k, max(c) as m from t group by a, b having m > 50;")?);
+        assert_eq!(columns, vec!["k", "m"]);
+        assert_eq!(
+            rows,
+            vec![
+                vec![null(), i(60)],
+                vec![null(), i(100)],
+                vec![i(1), i(90)],
+                vec![i(2), i(80)],
+            ]
+        );
+
+        match s.execute("explain select a, b, count(c) from t group by a, b;")? {
+            ResultSet::Explain { plan } => assert_eq!(
+                plan,
+                "Aggregate: a, b, count(c) (group by: a, b)\n  Scan: t [a, b, c]"
+            ),
+            _ => unreachable!(),
+        }
+        // 不在分组列中的列
+        assert!(
+            s.execute("select a, c, count(id) from t group by a, b;")
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by_order() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+        s.execute(
+            "insert into t values (1, 'dd'), (2, 'bb'), (3, null), (4, 'dd'), (5, 'aa'), (6, 'bb'), (7, 'cc');",
+        )?;
+
+        // 分组按照在输入中第一次出现的顺序输出，多次执行结果完全相同
+        let expected = vec![
+            vec![Value::String("dd".to_string()), Value::Integer(2)],
+            vec![Value::String("bb".to_string()), Value::Integer(2)],
+            vec![Value::Null, Value::Integer(1)],
+            vec![Value::String("aa".to_string()), Value::Integer(1)],
+            vec![Value::String("cc".to_string()), Value::Integer(1)],
+        ];
+        for _ in 0..20 {
+            match s.execute("select b, count(a) from t group by b;")? {
+                ResultSet::Scan { rows, .. } => assert_eq!(rows, expected),
+                _ => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_having_hidden_aggregate() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, c int);")?;
+        s.execute(
+            "insert into t values (1, 'x', 5), (2, 'y', 1), (3, 'x', 2), (4, 'z', 7), (5, 'y', 3), (6, 'x', 4), (7, 'z', null);",
+        )?;
+        let strs = |v: &[&str]| {
+            v.iter()
+                .map(|s| vec![Value::String(s.to_string())])
+                .collect::<Vec<_>>()
+        };
+
+        // HAVING 中的聚集函数不在 SELECT 中，作为隐藏列计算后去掉
+        match s.execute("select b from t group by b having count(a) > 1;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["b"]);
+                assert_eq!(rows, strs(&["x", "y", "z"]));
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select b from t group by b having count(c) > 1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, strs(&["x", "y"])),
+            _ => unreachable!(),
+        }
+
+        // ORDER BY 同一个隐藏的聚集值
+        match s.execute("select b from t group by b having sum(c) > 3 order by sum(c) desc;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["b"]);
+                assert_eq!(rows, strs(&["x", "z", "y"]));
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select b from t group by b order by max(c);")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, strs(&["y", "x", "z"])),
+            _ => unreachable!(),
+        }
+
+        // SELECT 中已有的聚集函数直接使用，别名照常可用
+        match s.execute(
+            "select b, count(a), sum(c) as s from t group by b having count(a) > 2 or s < 5;",
+        )? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["b", "COUNT(a)", "s"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![
+                            Value::String("x".to_string()),
+                            Value::Integer(3),
+                            Value::Integer(11)
+                        ],
+                        vec![
+                            Value::String("y".to_string()),
+                            Value::Integer(2),
+                            Value::Integer(4)
+                        ],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("explain select b from t group by b having count(a) > 1;")? {
+            ResultSet::Explain { plan } => assert_eq!(
+                plan,
+                "Projection: b
+  Filter: COUNT(a) > 1
+    Aggregate: b, count(a) (group by: b)
+      Scan: t [a, b]"
+            ),
+            _ => unreachable!(),
+        }
+
+        // 非聚集查询不能按聚集函数排序
+        assert!(s.execute("select b from t order by count(a);").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bool_agg() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, d boolean);")?;
+        s.execute(
+            "insert into t values (1, 'x', true), (2, 'x', false), (3, 'y', true), (4, 'y', null), (5, 'z', null);",
+        )?;
+
+        match s.execute("select bool_and(d), bool_or(d) from t;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["BOOL_AND(d)", "BOOL_OR(d)"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![Value::Boolean(false), Value::Boolean(true)]]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 跳过 NULL，全部为 NULL 时结果为 NULL
+        match s.execute("select b, bool_and(d) as all_d, bool_or(d) from t group by b;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["b", "all_d", "BOOL_OR(d)"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![
+                            Value::String("x".to_string()),
+                            Value::Boolean(false),
+                            Value::Boolean(true)
+                        ],
+                        vec![
+                            Value::String("y".to_string()),
+                            Value::Boolean(true),
+                            Value::Boolean(true)
+                        ],
+                        vec![Value::String("z".to_string()), Value::Null, Value::Null],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select b from t group by b having bool_or(d) = true;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::String("x".to_string())],
+                    vec![Value::String("y".to_string())]
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        // 非布尔列报错，错误信息中包含列名
+        match s.execute("select bool_or(b) from t;") {
+            Err(RSDBError::TypeMismatch(message)) => assert!(message.contains("column b")),
+            res => panic!("unexpected result {:?}", res),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_max_mixed_types() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 5), (2, 1);")?;
+
+        // 绕过类型校验直接写入一行，int 列中出现字符串
+        let txn = kvengine.begin()?;
+        let row: Row = vec![Value::Integer(3), Value::String("zz".to_string())];
+        txn.txn.set(
+            Key::Row(
+                DEFAULT_DATABASE.to_string(),
+                "t".to_string(),
+                Value::Integer(3),
+            )
+            .encode()?,
+            bincode::serialize(&row)?,
+        )?;
+        txn.commit()?;
+
+        // 不同类型之间按照全序比较，不会 panic
+        match s.execute("select min(b), max(b) from t;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![vec![Value::Integer(1), Value::String("zz".to_string())]]
+            ),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c float, d bool);")?;
+
+        s.execute("insert into t1 values (1, 'aa', 3.1, true);")?;
+        s.execute("insert into t1 values (2, 'bb', 5.3, true);")?;
+        s.execute("insert into t1 values (3, null, NULL, false);")?;
+        s.execute("insert into t1 values (4, null, 4.6, false);")?;
+        s.execute("insert into t1 values (5, 'bb', 5.8, true);")?;
+        s.execute("insert into t1 values (6, 'dd', 1.4, false);")?;
+
+        match s.execute("select * from t1 where d < true;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(4, columns.len());
+                assert_eq!(3, rows.len());
+            }
+            _ => unreachable!(),
+        }
+
+        match s.execute(
+            "select b, sum(c) as sum_c from t1 group by b having sum_c < 5 order by sum_c;",
+        )? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(2, columns.len());
+                assert_eq!(3, rows.len());
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c float index, d bool);")?;
+        s.execute("insert into t values (1, 'a', 1.1, true);")?;
+        s.execute("insert into t values (2, 'b', 2.1, true);")?;
+        s.execute("insert into t values (3, 'a', 3.2, false);")?;
+        s.execute("insert into t values (4, 'c', 1.1, true);")?;
+        s.execute("insert into t values (5, 'd', 2.1, false);")?;
+
+        s.execute("delete from t where a = 4;")?;
+
+        match s.execute("select * from t where c = 1.1;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns.len(), 4);
+                assert_eq!(rows.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_primary_key_scan() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c float index, d bool);")?;
+        s.execute("insert into t values (1, 'a', 1.1, true);")?;
+        s.execute("insert into t values (2, 'b', 2.1, true);")?;
+        s.execute("insert into t values (3, 'a', 3.2, false);")?;
+
+        match s.execute("select * from t where a = 2;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns.len(), 4);
+                assert_eq!(rows.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+
+        // 主键的等值条件只读取一行，列可以在等号的任意一侧
+        for sql in [
+            "select * from t where a = 2;",
+            "select * from t where 2 = a;",
+        ] {
+            assert_eq!(
+                s.execute(&format!("explain {}", sql))?,
+                ResultSet::Explain {
+                    plan: "PrimaryKeyScan: t (2)".to_string()
+                }
+            );
+            assert_eq!(
+                s.execute(sql)?,
+                ResultSet::Scan {
+                    columns: vec!["a".into(), "b".into(), "c".into(), "d".into()],
+                    types: vec![
+                        Some(DataType::Integer),
+                        Some(DataType::String),
+                        Some(DataType::Float),
+                        Some(DataType::Boolean),
+                    ],
+                    rows: vec![vec![
+                        Value::Integer(2),
+                        Value::String("b".to_string()),
+                        Value::Float(2.1),
+                        Value::Boolean(true),
+                    ]],
+                }
+            );
+        }
+        match s.execute("select * from t where a = 4;")? {
+            ResultSet::Scan { rows, .. } => assert!(rows.is_empty()),
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            s.execute("explain delete from t where a = 3;")?,
+            ResultSet::Explain {
+                plan: "Delete: t\n  PrimaryKeyScan: t (3)".to_string()
+            }
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_mutation_index_scan() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c int);")?;
+        s.execute(
+            "insert into t values (1, 'x', 10), (2, 'y', 20), (3, 'x', 30), (4, 'z', 40), (5, 'y', 50);",
+        )?;
+        let rows = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| match s.execute(sql)? {
+            ResultSet::Scan { rows, .. } => Ok::<_, RSDBError>(rows),
+            _ => unreachable!(),
+        };
+
+        // 主键等值条件的删除只读取一行
+        assert_eq!(
+            s.execute("explain delete from t where a = 5;")?,
+            ResultSet::Explain {
+                plan: "Delete: t\n  PrimaryKeyScan: t (5)".to_string()
+            }
+        );
+        assert_eq!(
+            s.execute("delete from t where a = 5;")?,
+            ResultSet::Delete { count: 1 }
+        );
+        assert_eq!(
+            s.execute("delete from t where a = 5;")?,
+            ResultSet::Delete { count: 0 }
+        );
+        assert_eq!(rows(&mut s, "select * from t where b = 'y';")?.len(), 1);
+
+        // 索引列的更新和删除，其余的行不受影响
+        assert_eq!(
+            s.execute("explain update t set c = 0 where b = 'x';")?,
+            ResultSet::Explain {
+                plan: "Update: t (set c = 0)\n  IndexScan: t (b = 'x')".to_string()
+            }
+        );
+        assert_eq!(
+            s.execute("update t set c = 0 where b = 'x';")?,
+            ResultSet::Update { count: 2 }
+        );
+        assert_eq!(
+            s.execute("explain delete from t where b in ('y', 'z');")?,
+            ResultSet::Explain {
+                plan: "Delete: t\n  MultiIndexScan: t (b IN ('y', 'z'))".to_string()
+            }
+        );
+        assert_eq!(
+            s.execute("delete from t where b in ('y', 'z');")?,
+            ResultSet::Delete { count: 2 }
+        );
+        assert_eq!(
+            rows(&mut s, "select a, c from t order by a;")?,
+            vec![
+                vec![Value::Integer(1), Value::Integer(0)],
+                vec![Value::Integer(3), Value::Integer(0)],
+            ]
+        );
+        assert!(rows(&mut s, "select * from t where b = 'y';")?.is_empty());
+
+        // 按非主键的索引列删除，行和索引项都被删除
+        assert_eq!(
+            s.execute("explain delete from t where b = 'x';")?,
+            ResultSet::Explain {
+                plan: "Delete: t\n  IndexScan: t (b = 'x')".to_string()
+            }
+        );
+        assert_eq!(
+            s.execute("delete from t where b = 'x';")?,
+            ResultSet::Delete { count: 2 }
+        );
+        assert!(rows(&mut s, "select * from t;")?.is_empty());
+        let txn = kvengine.begin()?;
+        for b in ["x", "y", "z"] {
+            let key = Key::Index(
+                DEFAULT_DATABASE.to_string(),
+                "t".to_string(),
+                "b".to_string(),
+                vec![Value::String(b.to_string())],
+            );
+            assert_eq!(txn.txn.get(key.encode()?)?, None);
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_table() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c int);")?;
+        s.execute("create index t_c on t (c);")?;
+        s.execute("create table tt (a int primary key, b text index);")?;
+        s.execute("insert into t values (1, 'x', 10), (2, 'y', 20);")?;
+        s.execute("insert into tt values (1, 'x');")?;
+        s.execute("analyze t;")?;
+
+        // 删除表之后，行、索引和统计信息都被删除
+        s.execute("drop table t;")?;
+        let txn = kvengine.begin()?;
+        for prefix in [
+            KeyPrefix::Row(DEFAULT_DATABASE.to_string(), "t".to_string()),
+            KeyPrefix::Index(DEFAULT_DATABASE.to_string(), "t".to_string()),
+        ] {
+            assert!(txn.txn.scan_prefix(prefix.encode()?)?.is_empty());
+        }
+        assert_eq!(txn.get_stats("t")?, None);
+        // 名称前缀相同的表不受影响
+        assert_eq!(
+            txn.txn
+                .scan_prefix(
+                    KeyPrefix::Row(DEFAULT_DATABASE.to_string(), "tt".to_string()).encode()?
+                )?
+                .len(),
+            1
+        );
+        txn.commit()?;
+
+        // 使用不同的表结构重新创建，新表是空的
+        s.execute("create table t (a text primary key, b int index);")?;
+        match s.execute("select * from t;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["a", "b"]);
+                assert!(rows.is_empty());
+            }
+            _ => unreachable!(),
+        }
+        s.execute("insert into t values ('k', 1);")?;
+        match s.execute("select a from t where b = 1;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::String("k".to_string())]])
+            }
+            _ => unreachable!(),
+        }
+
+        // 表不存在时报错，IF EXISTS 时不报错
+        s.execute("drop table t;")?;
+        assert_eq!(
+            s.execute("drop table t;"),
+            Err(RSDBError::TableNotFound("t".to_string()))
+        );
+        assert_eq!(
+            s.execute("drop table if exists t;")?,
+            ResultSet::DropTable {
+                table_name: "t".to_string()
+            }
+        );
+        s.execute("drop table if exists tt;")?;
+        assert!(s.execute("select * from tt;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_constant_filter() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+        s.execute("create table u (x int primary key, y text);")?;
+        s.execute("insert into t values (1, 'a'), (2, 'b');")?;
+        s.execute("insert into u values (1, 'c');")?;
+
+        // 恒为假的条件不读取数据
+        for sql in [
+            "select * from t where 1 = 2;",
+            "select * from t where false;",
+            "select * from t where a = 1 and 1 = null;",
+        ] {
+            assert_eq!(
+                s.execute(&format!("explain {}", sql))?,
+                ResultSet::Explain {
+                    plan: "Nothing: a, b".to_string()
+                }
+            );
+            assert_eq!(
+                s.execute(sql)?,
+                ResultSet::Scan {
+                    columns: vec!["a".to_string(), "b".to_string()],
+                    types: vec![None, None],
+                    rows: vec![],
+                }
+            );
+        }
+        assert_eq!(
+            s.execute("explain select * from t left join u on a = x where false;")?,
+            ResultSet::Explain {
+                plan: "HashJoin: left outer on a = x\n  Nothing: a, b\n  Nothing: x, y".to_string()
+            }
+        );
+        assert_eq!(
+            s.execute("explain select count(a) from t group by b having 1 > 2;")?,
+            ResultSet::Explain {
+                plan: "Nothing: COUNT(a)".to_string()
+            }
+        );
+        match s.execute("select count(a) from t group by b having 1 > 2;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["COUNT(a)"]);
+                assert!(rows.is_empty());
+            }
+            _ => unreachable!(),
+        }
+
+        // 恒为真的条件直接去掉
+        assert_eq!(
+            s.execute("explain select * from t where true;")?,
+            ResultSet::Explain {
+                plan: "Scan: t".to_string()
+            }
+        );
+        assert_eq!(
+            s.execute("explain select * from t where 1 = 1 and b = 'a';")?,
+            ResultSet::Explain {
+                plan: "Scan: t (filter: b = 'a')".to_string()
+            }
+        );
+
+        assert_eq!(
+            s.execute("explain delete from t where false;")?,
+            ResultSet::Explain {
+                plan: "Delete: t\n  Nothing: a, b".to_string()
+            }
+        );
+        assert_eq!(
+            s.execute("delete from t where false;")?,
+            ResultSet::Delete { count: 0 }
+        );
+        assert_eq!(
+            s.execute("update t set b = 'z' where 2 < 1;")?,
+            ResultSet::Update { count: 0 }
+        );
+        assert_eq!(
+            s.execute("delete from t where true;")?,
+            ResultSet::Delete { count: 2 }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_filter_shapes() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index, c int);")?;
+        s.execute("insert into t values (1, 1, 5), (2, 3, 2), (3, 3, 7);")?;
+        for (sql, plan, expected) in [
+            // 常量在左边时仍然使用主键和索引
+            (
+                "select a from t where 1 = a;",
+                "Projection: a\n  PrimaryKeyScan: t (1)",
+                vec![1],
+            ),
+            (
+                "select a from t where 3 = b;",
+                "Projection: a\n  IndexScan: t (b = 3)",
+                vec![2, 3],
+            ),
+            // 列与列比较、嵌套的运算退化为普通的扫描
+            (
+                "select a from t where a = b;",
+                "Projection: a\n  Scan: t [a] (filter: a = b)",
+                vec![1, 3],
+            ),
+            (
+                "select a from t where a = a;",
+                "Projection: a\n  Scan: t [a] (filter: a = a)",
+                vec![1, 2, 3],
+            ),
+            (
+                "select a from t where a + 1 = c - 3;",
+                "Projection: a\n  Scan: t [a] (filter: a + 1 = c - 3)",
+                vec![1, 3],
+            ),
+            (
+                "select a from t where b = 3 and c % 2 = 1;",
+                "Projection: a\n  Filter: c % 2 = 1\n    IndexScan: t (b = 3)",
+                vec![3],
+            ),
+        ] {
+            assert_eq!(explain(&mut s, sql)?, plan);
+            assert_eq!(
+                query_rows(&mut s, sql)?,
+                expected
+                    .into_iter()
+                    .map(|a| vec![Value::Integer(a)])
+                    .collect::<Vec<_>>()
+            );
+        }
+        assert_eq!(
+            query_rows(&mut s, "select a from t where a = 1 or b = 3 and c > 4;")?,
+            vec![vec![Value::Integer(1)], vec![Value::Integer(3)]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_conjunction() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c float index, d int);")?;
+        s.execute("create table u (a int primary key, b text, c float, d int);")?;
+        for table in ["t", "u"] {
+            s.execute(&format!(
+                "insert into {} values (1, 'a', 1.1, 1), (2, 'a', 2.2, 2), (3, 'b', 1.1, 3),
+                    (4, 'a', 1.1, 4), (5, null, 1.1, 5), (6, 'c', null, 6);",
+                table
+            ))?;
+        }
+
+        // 无论选择哪个索引，结果都与全表扫描一致
+        for filter in [
+            "b = 'a' and c = 1.1",
+            "c = 1.1 and b = 'a'",
+            "b = 'a' and c = 1.1 and d > 1",
+            "d > 1 and c = 1.1",
+            "b = 'a' and a = 4",
+            "b in ('a', 'b') and c = 1.1",
+            "b = 'a' and b = 'b'",
+            "b = null and c = 1.1",
+        ] {
+            let sql = format!("select a from {{}} where {} order by a;", filter);
+            let plan = explain(&mut s, &sql.replace("{}", "t"))?;
+            assert!(!plan.contains("  Scan: t"), "{}", plan);
+            assert_eq!(
+                query_rows(&mut s, &sql.replace("{}", "t"))?,
+                query_rows(&mut s, &sql.replace("{}", "u"))?,
+                "{}",
+                filter
+            );
+        }
+
+        // 没有统计信息时选择第一个索引条件，主键条件总是优先
+        assert_eq!(
+            explain(&mut s, "select a from t where b = 'a' and c = 1.1;")?,
+            "Projection: a\n  Filter: c = 1.1\n    IndexScan: t (b = 'a')"
+        );
+        assert_eq!(
+            explain(&mut s, "select a from t where b = 'a' and a = 4;")?,
+            "Projection: a\n  Filter: b = 'a'\n    PrimaryKeyScan: t (4)"
+        );
+        // 有统计信息时选择区分度更高的索引
+        s.execute("analyze t;")?;
+        assert_eq!(
+            explain(&mut s, "select a from t where c = 1.1 and b = 'b';")?,
+            "Projection: a\n  Filter: c = 1.1\n    IndexScan: t (b = 'b')"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_composite_index() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, country text, city text, n int);")?;
+        s.execute("create table u (id int primary key, country text, city text, n int);")?;
+        let insert = |s: &mut Session<_>, values: &str| {
+            s.execute(&format!("insert into t values {};", values))?;
+            s.execute(&format!("insert into u values {};", values))
+        };
+        // 创建索引前已有的数据也会建立索引
+        insert(
+            &mut s,
+            "(1, 'cn', 'sh', 1), (2, 'cn', 'bj', 2), (3, 'us', 'ny', 3)",
+        )?;
+        assert_eq!(
+            s.execute("create index idx_cc on t (country, city);")?,
+            ResultSet::CreateIndex {
+                index_name: "idx_cc".to_string()
+            }
+        );
+        insert(
+            &mut s,
+            "(4, 'cn', 'sh', 4), (5, 'us', 'sh', 5), (6, null, 'sh', 6)",
+        )?;
+
+        let query = |s: &mut Session<_>, filter: &str| {
+            let sql = format!("select id from {{}} where {} order by id;", filter);
+            assert_eq!(
+                query_rows(s, &sql.replace("{}", "t"))?,
+                query_rows(s, &sql.replace("{}", "u"))?,
+                "{}",
+                filter
+            );
+            explain(s, &sql.replace("{}", "t"))
+        };
+
+        // 所有索引列都有等值条件
+        assert_eq!(
+            query(&mut s, "city = 'sh' and country = 'cn'")?,
+            "Projection: id\n  Order: id ASC\n    IndexScan: t USING idx_cc (country = 'cn' AND city = 'sh')"
+        );
+        assert_eq!(
+            query(&mut s, "country = 'cn' and n > 1 and city = 'sh'")?,
+            "Projection: id\n  Order: id ASC\n    Filter: n > 1\n      IndexScan: t USING idx_cc (country = 'cn' AND city = 'sh')"
+        );
+        // 只有前缀列有等值条件
+        assert_eq!(
+            query(&mut s, "country = 'us' and n > 3")?,
+            "Projection: id\n  Order: id ASC\n    Filter: n > 3\n      IndexScan: t USING idx_cc (country = 'us')"
+        );
+        // 没有前缀列的等值条件时不能使用索引
+        assert_eq!(
+            query(&mut s, "city = 'sh'")?,
+            "Projection: id\n  Order: id ASC\n    Scan: t [id] (filter: city = 'sh')"
+        );
+        query(&mut s, "country = null and city = 'sh'")?;
+
+        // 只更新其中一列时维护索引
+        for sql in [
+            "update {} set city = 'gz' where id = 1;",
+            "update {} set country = 'us' where id = 4;",
+            "update {} set country = 'cn' where id = 6;",
+            "update {} set n = 0 where id = 2;",
+            "delete from {} where id = 5;",
+            "update {} set id = 7 where id = 3;",
+        ] {
+            s.execute(&sql.replace("{}", "t"))?;
+            s.execute(&sql.replace("{}", "u"))?;
+            for filter in [
+                "country = 'cn'",
+                "country = 'us'",
+                "country = 'cn' and city = 'sh'",
+                "country = 'cn' and city = 'gz'",
+                "country = 'us' and city = 'sh'",
+                "country = 'us' and city = 'ny'",
+            ] {
+                query(&mut s, filter)?;
+            }
+        }
+
+        // 索引信息保存在表结构中
+        let table = s.get_table("t".to_string())?;
+        assert_eq!(
+            table.indexes,
+            vec![Index {
+                name: "idx_cc".to_string(),
+                columns: vec!["country".to_string(), "city".to_string()],
+            }]
+        );
+        assert_eq!(
+            table.to_string(),
+            "CREATE TABLE t (\n    id Integer PRIMARY KEY,\n    country String DEFAULT NULL,\n    city String DEFAULT NULL,\n    n Integer DEFAULT NULL\n)\nCREATE INDEX idx_cc ON t (country, city)"
+        );
+        assert!(s.execute("create index idx_cc on t (n);").is_err());
+        assert!(s.execute("create index idx_n on t (n, n);").is_err());
+        assert!(matches!(
+            s.execute("create index idx_x on t (x);"),
+            Err(RSDBError::ColumnNotFound { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_analyze() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c int);")?;
+        s.execute("create table u (e int primary key, d int);")?;
+        s.execute("insert into t values (1, 'x', 10), (2, 'y', 20), (3, 'x', 30), (4, 'z', 40);")?;
+        s.execute("insert into u values (1, 100), (3, 300);")?;
+
+        // 每一行拆分为节点和输出的行数，耗时只需要是非负数
+        let analyze = |s: &mut Session<_>, sql: &str| {
+            let plan = match s.execute(&format!("explain analyze {}", sql))? {
+                ResultSet::Explain { plan } => plan,
+                _ => unreachable!(),
+            };
+            Ok::<_, RSDBError>(
+                plan.lines()
+                    .map(|line| {
+                        let (node, stats) = line.rsplit_once(" (rows=").unwrap();
+                        let (rows, time) = stats.split_once(", time=").unwrap();
+                        let time = time.strip_suffix("ms)").unwrap().parse::<f64>().unwrap();
+                        assert!(time >= 0.0);
+                        (node.to_string(), rows.parse::<usize>().unwrap())
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let node = |node: &str, rows: usize| (node.to_string(), rows);
+
+        assert_eq!(
+            analyze(&mut s, "select a from t where c > 15 order by a limit 2;")?,
+            vec![
+                node("Projection: a", 2),
+                node("  Limit: 2", 2),
+                // Limit 拉取到 2 行后不再继续拉取
+                node("    Order: a ASC", 2),
+                node("      Scan: t [a] (filter: c > 15)", 3),
+            ]
+        );
+        assert_eq!(
+            analyze(&mut s, "select b, count(a) from t group by b;")?,
+            vec![
+                node("Aggregate: b, count(a) (group by: b)", 3),
+                node("  Scan: t [a, b]", 4),
+            ]
+        );
+        assert_eq!(
+            analyze(&mut s, "select a, d from t join u on a = e;")?,
+            vec![
+                node("Projection: a, d", 2),
+                node("  HashJoin: inner on a = e", 2),
+                node("    Scan: t", 4),
+                node("    Scan: u", 2),
+            ]
+        );
+
+        // 修改数据的语句会执行，但是不会修改数据
+        assert_eq!(
+            analyze(&mut s, "update t set c = 0 where b = 'x';")?,
+            vec![
+                node("Update: t (set c = 0)", 2),
+                node("  IndexScan: t (b = 'x')", 2),
+            ]
+        );
+        assert_eq!(
+            analyze(&mut s, "delete from t;")?,
+            vec![node("Delete: t", 4), node("  Scan: t", 4)]
+        );
+        assert_eq!(
+            analyze(&mut s, "insert into u values (5, 500);")?,
+            vec![node("Insert: u (1 rows)", 1)]
+        );
+        match s.execute("select a, c from t order by a;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(1), Value::Integer(10)],
+                    vec![Value::Integer(2), Value::Integer(20)],
+                    vec![Value::Integer(3), Value::Integer(30)],
+                    vec![Value::Integer(4), Value::Integer(40)],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+        match s.execute("select e from u;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 2),
+            _ => unreachable!(),
+        }
+
+        // 事务中只能分析查询语句
+        s.execute("begin;")?;
+        s.execute("insert into u values (5, 500);")?;
+        assert_eq!(
+            analyze(&mut s, "select e from u;")?,
+            vec![node("Projection: e", 3), node("  Scan: u [e]", 3)]
+        );
+        assert!(s.execute("explain analyze delete from u;").is_err());
+        s.execute("rollback;")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_statement_cache() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 10), (2, 20);")?;
+        let misses = s.cache_stats().misses;
+
+        // 重复执行的语句只解析一次
+        let sql = "select b from t where a = ?;";
+        for i in 0..100 {
+            match s.execute_with_params(sql, &[Value::Integer(i % 2 + 1)])? {
+                ResultSet::Scan { rows, .. } => {
+                    assert_eq!(rows, vec![vec![Value::Integer((i % 2 + 1) * 10)]])
+                }
+                _ => unreachable!(),
+            }
+        }
+        let stats = s.cache_stats();
+        assert_eq!(stats.misses, misses + 1);
+        assert_eq!(stats.hits, 99);
+
+        // DDL 之后引用该表的语句需要重新解析
+        s.execute("drop table t;")?;
+        s.execute("create table t (a int primary key, b text);")?;
+        s.execute("insert into t values (1, 'x');")?;
+        let misses = s.cache_stats().misses;
+        match s.execute_with_params(sql, &[Value::Integer(1)])? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::String("x".to_string())]])
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(s.cache_stats().misses, misses + 1);
+
+        // 解析失败的语句不会被缓存
+        assert!(s.execute("select from;").is_err());
+        assert!(s.execute("select from;").is_err());
+        assert_eq!(s.cache_stats().misses, misses + 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_analyze() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, c int);")?;
+        s.execute(
+            "insert into t values (1, 'x', 3), (2, 'y', null), (3, 'x', 1), (4, null, null);",
+        )?;
+
+        match s.execute("analyze t;")? {
+            ResultSet::Analyze {
+                table_name,
+                row_count,
+            } => {
+                assert_eq!(table_name, "t");
+                assert_eq!(row_count, 4);
+            }
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            s.execute("analyze t2;"),
+            Err(RSDBError::TableNotFound(_))
+        ));
+
+        let expect = TableStats {
+            row_count: 4,
+            columns: vec![
+                ColumnStats {
+                    name: "a".to_string(),
+                    distinct: 4,
+                    min: Value::Integer(1),
+                    max: Value::Integer(4),
+                },
+                ColumnStats {
+                    name: "b".to_string(),
+                    distinct: 2,
+                    min: Value::String("x".to_string()),
+                    max: Value::String("y".to_string()),
+                },
+                ColumnStats {
+                    name: "c".to_string(),
+                    distinct: 2,
+                    min: Value::Integer(1),
+                    max: Value::Integer(3),
+                },
+            ],
+        };
+        let txn = kvengine.begin()?;
+        assert_eq!(txn.get_stats("t")?, Some(expect.clone()));
+        txn.commit()?;
+        drop(txn);
+        drop(s);
+        drop(kvengine);
+
+        // 统计信息持久化，重启后依然存在
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let txn = kvengine.begin()?;
+        assert_eq!(txn.get_stats("t")?, Some(expect));
+        txn.commit()?;
+
+        // 删除表时一并删除统计信息
+        let mut s = kvengine.session()?;
+        s.execute("drop table t;")?;
+        s.execute("create table t (a int primary key);")?;
+        let txn = kvengine.begin()?;
+        assert_eq!(txn.get_stats("t")?, None);
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_join() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key);")?;
+        s.execute("create table t2 (b int primary key);")?;
+        s.execute("create table t3 (c int primary key);")?;
+
+        s.execute("insert into t1 values (1), (2), (3);")?;
+        s.execute("insert into t2 values (2), (3), (4);")?;
+        s.execute("insert into t3 values (3), (8), (9);")?;
+
+        match s.execute("select * from t1 join t2 on a = b join t3 on a = c;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns.len(), 3);
+                assert_eq!(rows.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_filter_pushdown() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, x text);")?;
+        s.execute("create table t2 (b int primary key, c text);")?;
+        s.execute("insert into t1 values (1, 'x'), (2, 'y'), (3, 'x'), (4, 'z');")?;
+        s.execute("insert into t2 values (1, 'x'), (2, 'x'), (3, 'y'), (5, 'x');")?;
+
+        // 只涉及一张表的条件下推到该表的扫描节点
+        let sql = "select * from t1 join t2 on a = b where a > 1 and c = 'x';";
+        assert_eq!(
+            explain(&mut s, sql)?,
+            "HashJoin: inner on a = b
+  Scan: t1 (filter: a > 1)
+  Scan: t2 (filter: c = 'x')"
+        );
+        assert_eq!(
+            query_rows(&mut s, sql)?,
+            vec![vec![
+                Value::Integer(2),
+                Value::String("y".to_string()),
+                Value::Integer(2),
+                Value::String("x".to_string()),
+            ]]
+        );
+
+        // 涉及两张表的条件在 Join 之后过滤
+        let sql = "select a, b from t1 cross join t2 where x = c and b > 2 and a < 3;";
+        assert_eq!(
+            explain(&mut s, sql)?,
+            "Projection: a, b
+  Filter: x = c
+    NestLoopJoin: cross
+      Scan: t1 (filter: a < 3)
+      Scan: t2 (filter: b > 2)"
+        );
+        assert_eq!(
+            query_rows(&mut s, sql)?,
+            vec![
+                vec![Value::Integer(1), Value::Integer(5)],
+                vec![Value::Integer(2), Value::Integer(3)],
+            ]
+        );
+
+        // left join 右侧的条件不能下推，否则会多出填充 NULL 的行
+        let sql = "select a, b from t1 left join t2 on a = b where c = 'x' and a < 4;";
+        assert_eq!(
+            explain(&mut s, sql)?,
+            "Projection: a, b
+  Filter: c = 'x'
+    HashJoin: left outer on a = b
+      Scan: t1 (filter: a < 4)
+      Scan: t2"
+        );
+        assert_eq!(
+            query_rows(&mut s, sql)?,
+            vec![
+                vec![Value::Integer(1), Value::Integer(1)],
+                vec![Value::Integer(2), Value::Integer(2)],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_index_scan() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c int);")?;
+        s.execute(
+            "insert into t values (5, 'a', 1), (2, 'c', 2), (3, 'b', 3), (1, 'a', 4), (4, null, 5);",
+        )?;
+
+        let pks = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> RSDBResult<Vec<i64>> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows
+                    .iter()
+                    .map(|r| match r[0] {
+                        Value::Integer(i) => i,
+                        _ => unreachable!(),
+                    })
+                    .collect()),
+                _ => unreachable!(),
+            }
+        };
+
+        // 合并多个值的主键，去重后按照主键排序
+        assert_eq!(
+            s.execute("explain select * from t where b = 'a' or b = 'c';")?,
+            ResultSet::Explain {
+                plan: "MultiIndexScan: t (b IN ('a', 'c'))".to_string()
+            }
+        );
+        assert_eq!(
+            pks(&mut s, "select * from t where b = 'a' or b = 'c';")?,
+            vec![1, 2, 5]
+        );
+        assert_eq!(
+            s.execute("explain select * from t where b in ('c', 'a', 'c', null);")?,
+            ResultSet::Explain {
+                plan: "MultiIndexScan: t (b IN ('c', 'a'))".to_string()
+            }
+        );
+        assert_eq!(
+            pks(&mut s, "select * from t where b in ('c', 'a', 'c', null);")?,
+            vec![1, 2, 5]
+        );
+        assert_eq!(
+            pks(&mut s, "select * from t where b in ('x', 'b');")?,
+            vec![3]
+        );
+
+        // 不同的列、非索引列仍然全表扫描
+        assert_eq!(
+            s.execute("explain select * from t where b = 'b' or c = 1;")?,
+            ResultSet::Explain {
+                plan: "Scan: t (filter: b = 'b' OR c = 1)".to_string()
+            }
+        );
+        assert_eq!(
+            pks(&mut s, "select * from t where b = 'b' or c = 1;")?,
+            vec![3, 5]
+        );
+        assert_eq!(
+            pks(&mut s, "select * from t where c in (2, 5) order by a;")?,
+            vec![2, 4]
+        );
+        // OR 的优先级低于 AND
+        assert_eq!(
+            pks(
+                &mut s,
+                "select * from t where c = 4 or b = 'a' and c = 1 order by a;"
+            )?,
+            vec![1, 5]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c float, d bool);")?;
+        s.execute("insert into t values (1, 'a', 1.1, true);")?;
+        s.execute("insert into t values (2, 'b', 2.1, true);")?;
+
+        // 索引列
+        let res = s.execute("explain select * from t where b = 'a';")?;
+        assert_eq!(
+            res,
+            ResultSet::Explain {
+                plan: "IndexScan: t (b = 'a')".to_string()
+            }
+        );
+
+        // 非索引列
+        let res = s.execute("explain select a, b from t where c = 1.1 order by a desc limit 1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Explain {
+                plan: "Projection: a, b
+  Limit: 1
+    Order: a DESC
+      Scan: t [a, b] (filter: c = 1.1)"
+                    .to_string()
+            }
+        );
+
+        // join、聚集、分组、offset
+        s.execute("create table u (x int primary key, y text);")?;
+        let res = s.execute(
+            "explain select b, count(a) from t left join u on a = x group by b having count(a) > 1 limit 10 offset 2;",
+        )?;
+        assert_eq!(
+            res,
+            ResultSet::Explain {
+                plan: "Limit: 10
+  Offset: 2
+    Filter: COUNT(a) > 1
+      Aggregate: b, count(a) (group by: b)
+        HashJoin: left outer on a = x
+          Scan: t
+          Scan: u"
+                    .to_string()
+            }
+        );
+        let res = s.execute("explain select * from t, u where y = 'a';")?;
+        assert_eq!(
+            res,
+            ResultSet::Explain {
+                plan: "NestLoopJoin: cross (swapped)
+  Scan: t
+  Scan: u (filter: y = 'a')"
+                    .to_string()
+            }
+        );
+
+        // explain update 不会修改数据
+        let res = s.execute("explain update t set d = false where a = 1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Explain {
+                plan: "Update: t (set d = FALSE)
+  PrimaryKeyScan: t (1)"
+                    .to_string()
+            }
+        );
+        match s.execute("select * from t where a = 1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows[0][3], Value::Boolean(true)),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t (a int primary key, b text index, c float not null default 1.5, d bool);",
+        )?;
+
+        let res = s.execute("describe t;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec![
+                    "name".to_string(),
+                    "datatype".to_string(),
+                    "nullable".to_string(),
+                    "default".to_string(),
+                    "primary_key".to_string(),
+                    "index".to_string(),
+                ],
+                types: vec![
+                    Some(DataType::String),
+                    Some(DataType::String),
+                    Some(DataType::Boolean),
+                    None,
+                    Some(DataType::Boolean),
+                    Some(DataType::Boolean),
+                ],
+                rows: vec![
+                    vec![
+                        Value::String("a".to_string()),
+                        Value::String("Integer".to_string()),
+                        Value::Boolean(false),
+                        Value::Null,
+                        Value::Boolean(true),
+                        Value::Boolean(false),
+                    ],
+                    vec![
+                        Value::String("b".to_string()),
+                        Value::String("String".to_string()),
+                        Value::Boolean(true),
+                        Value::Null,
+                        Value::Boolean(false),
+                        Value::Boolean(true),
+                    ],
+                    vec![
+                        Value::String("c".to_string()),
+                        Value::String("Float".to_string()),
+                        Value::Boolean(false),
+                        Value::Float(1.5),
+                        Value::Boolean(false),
+                        Value::Boolean(false),
+                    ],
+                    vec![
+                        Value::String("d".to_string()),
+                        Value::String("Boolean".to_string()),
+                        Value::Boolean(true),
+                        Value::Null,
+                        Value::Boolean(false),
+                        Value::Boolean(false),
+                    ],
+                ],
+            }
+        );
+
+        // 输出格式
+        assert_eq!(
+            res.to_string(),
+            "name |datatype  |nullable |default |primary_key |index
+-----+----------+---------+--------+------------+------
+'a'  |'Integer' |FALSE    |NULL    |TRUE        |FALSE
+'b'  |'String'  |TRUE     |NULL    |FALSE       |TRUE 
+'c'  |'Float'   |FALSE    |1.5     |FALSE       |FALSE
+'d'  |'Boolean' |TRUE     |NULL    |FALSE       |FALSE
+4 ROWS"
+        );
+
+        // show create table 与 describe 等价
+        assert_eq!(s.execute("show create table t;")?, res);
+
+        // 表不存在
+        match s.execute("describe t1;") {
+            Err(RSDBError::TableNotFound(name)) => assert_eq!(name, "t1"),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_read_only() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("create table t (a int primary key, b int);")?;
+        s1.execute("insert into t values (1, 10);")?;
+        let begin = |s: &mut Session<_>| match s.execute("begin;") {
+            Ok(ResultSet::Begin { version }) => version,
+            r => panic!("unexpected result {:?}", r),
+        };
+        let select_b = |s: &mut Session<_>| match s.execute("select b from t where a = 1;") {
+            Ok(ResultSet::Scan { rows, .. }) => rows[0][0].clone(),
+            r => panic!("unexpected result {:?}", r),
+        };
+
+        // 自动提交的查询不分配版本号
+        let version = begin(&mut s1);
+        s1.execute("commit;")?;
+        for _ in 0..3 {
+            select_b(&mut s2);
+        }
+        assert_eq!(begin(&mut s1), version + 1);
+
+        // 写事务提交前，查询看到一致的快照，并且不影响写事务提交
+        s1.execute("update t set b = 20 where a = 1;")?;
+        assert_eq!(select_b(&mut s2), Value::Integer(10));
+        s1.execute("commit;")?;
+        assert_eq!(select_b(&mut s2), Value::Integer(20));
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_as_of() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+        s.execute("insert into t values (1, 'old');")?;
+        // 开启事务时的版本号之前提交的数据都可见
+        let version = match s.execute("begin;")? {
+            ResultSet::Begin { version } => version,
+            _ => unreachable!(),
+        };
+        s.execute("update t set b = 'new' where a = 1;")?;
+        s.execute("insert into t values (2, 'x');")?;
+        s.execute("commit;")?;
+
+        let mut select = |sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Scan { rows, .. }) => Ok(rows),
+            Ok(r) => panic!("unexpected result {:?}", r),
+            Err(e) => Err(e),
+        };
+        let row = |a: i64, b: &str| vec![Value::Integer(a), Value::String(b.to_string())];
+        assert_eq!(
+            select(&format!("select * from t as of version {};", version))?,
+            vec![row(1, "old")]
+        );
+        assert_eq!(
+            select(&format!(
+                "select * from t where a = 1 as of version {};",
+                version + 1
+            ))?,
+            vec![row(1, "new")]
+        );
+        assert_eq!(
+            select("select * from t;")?,
+            vec![row(1, "new"), row(2, "x")]
+        );
+        assert!(select("select * from t as of version 100;").is_err());
+
+        // 显式事务中不能查询历史版本
+        s.execute("begin;")?;
+        assert!(matches!(
+            s.execute(&format!("select * from t as of version {};", version)),
+            Err(RSDBError::Internal(_))
+        ));
+        s.execute("rollback;")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_begin_read_only() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+        s.execute("insert into t values (1, 'a');")?;
+
+        // 记录修改前的版本号
+        let version = match s.execute("begin;")? {
+            ResultSet::Begin { version } => version,
+            _ => unreachable!(),
+        };
+        s.execute("update t set b = 'b' where a = 1;")?;
+        s.execute("commit;")?;
+
+        let select_b = |s: &mut Session<_>| -> RSDBResult<Value> {
+            match s.execute("select b from t;")? {
+                ResultSet::Scan { rows, .. } => Ok(rows[0][0].clone()),
+                _ => unreachable!(),
+            }
+        };
+
+        // 历史版本能看到修改前的数据，并且其他事务的提交不影响快照
+        let mut s1 = kvengine.session()?;
+        assert_eq!(
+            s1.execute(&format!("begin as of version {};", version))?,
+            ResultSet::Begin { version }
+        );
+        s.execute("update t set b = 'c' where a = 1;")?;
+        assert_eq!(select_b(&mut s1)?, Value::String("a".to_string()));
+        assert_eq!(
+            s1.execute("insert into t values (2, 'x');"),
+            Err(RSDBError::ReadOnlyTransaction)
+        );
+        s1.execute("commit;")?;
+        assert_eq!(select_b(&mut s1)?, Value::String("c".to_string()));
+
+        // 只读事务
+        s1.execute("begin read only;")?;
+        s.execute("update t set b = 'd' where a = 1;")?;
+        assert_eq!(select_b(&mut s1)?, Value::String("c".to_string()));
+        assert_eq!(
+            s1.execute("update t set b = 'e' where a = 1;"),
+            Err(RSDBError::ReadOnlyTransaction)
+        );
+        s1.execute("rollback;")?;
+        assert_eq!(select_b(&mut s1)?, Value::String("d".to_string()));
+
+        assert!(s1.execute("begin as of version 100;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_unicode_identifiers() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        {
+            let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+            let mut s = kvengine.session()?;
+            s.execute("create table 用户 (_id int primary key, 名字 text index);")?;
+            s.execute("insert into 用户 values (1, '张三'), (2, '李四');")?;
+        }
+
+        // 重新打开磁盘引擎，验证 key 能正确编解码
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        match s.execute("select _id, 名字 from 用户 where 名字 = '李四';")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["_id", "名字"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![Value::Integer(2), Value::String("李四".to_string())]]
+                );
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(s.get_table_names()?, vec!["用户".to_string()]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_timestamp() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        {
+            let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+            let mut s = kvengine.session()?;
+            s.execute("create table t (d date primary key, ts timestamp index, n int default 0);")?;
+            s.execute(
+                "insert into t values ('2024-01-31', '2024-01-31 12:00:00', 1),
+                    (DATE '2023-12-01', TIMESTAMP '2023-12-01 08:30:00.25', 2),
+                    ('2024-03-01', '2024-01-31 12:00:00', 3);",
+            )?;
+            assert!(
+                s.execute("insert into t values ('2024-02-30', '2024-02-01', 4);")
+                    .is_err()
+            );
+            assert!(
+                s.execute("insert into t values ('2024-02-01', 'abc', 4);")
+                    .is_err()
+            );
+        }
+
+        // 重新打开磁盘引擎，验证主键、索引能正确编解码
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        match s.execute("select * from t order by d desc;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows.iter()
+                    .map(|r| r.iter().map(|v| v.to_string()).collect::<Vec<_>>())
+                    .collect::<Vec<_>>(),
+                vec![
+                    vec!["2024-03-01", "2024-01-31 12:00:00", "3"],
+                    vec!["2024-01-31", "2024-01-31 12:00:00", "1"],
+                    vec!["2023-12-01", "2023-12-01 08:30:00.25", "2"],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        // 主键查询
+        match s.execute("select n from t where d = '2024-01-31';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(1)]]),
+            _ => unreachable!(),
+        }
+        // 索引查询
+        match s.execute("select n from t where ts = TIMESTAMP '2024-01-31 12:00:00' order by n;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(1)], vec![Value::Integer(3)]])
+            }
+            _ => unreachable!(),
+        }
+        // 与字符串、日期之间的比较
+        match s.execute("select n from t where d > '2024-01-01' order by n;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(1)], vec![Value::Integer(3)]])
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select n from t where ts < DATE '2024-01-31';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(2)]]),
+            _ => unreachable!(),
+        }
+
+        s.execute("update t set ts = '2024-03-01 00:00:00' where d = '2024-03-01';")?;
+        match s.execute("select d from t where ts = '2024-03-01';")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(
+                    rows,
+                    vec![vec![Value::Date(types::parse_date("2024-03-01")?)]]
+                )
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimal() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        {
+            let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+            let mut s = kvengine.session()?;
+            s.execute(
+                "create table t (id decimal(10, 2) primary key, amount numeric(10, 2) index, n int);",
+            )?;
+            s.execute(
+                "insert into t values (1, 0.1, 1), ('2.5', 0.2, 2), (-3.125, 0.1, 3),
+                    (DECIMAL '4.00', 10, 4);",
+            )?;
+            assert!(s.execute("insert into t values ('abc', 1, 5);").is_err());
+        }
+
+        // 重新打开磁盘引擎，验证主键、索引能正确编解码
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        let to_strings = |rs: ResultSet| match rs {
+            ResultSet::Scan { rows, .. } => rows
+                .iter()
+                .map(|r| r.iter().map(|v| v.to_string()).collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            to_strings(s.execute("select id, amount from t order by id;")?),
+            vec![
+                vec!["-3.13", "0.10"],
+                vec!["1.00", "0.10"],
+                vec!["2.50", "0.20"],
+                vec!["4.00", "10.00"],
+            ]
+        );
+
+        // 主键、索引查询
+        assert_eq!(
+            to_strings(s.execute("select n from t where id = 2.5;")?),
+            vec![vec!["2"]]
+        );
+        assert_eq!(
+            to_strings(s.execute("select n from t where amount = '0.1' order by n;")?),
+            vec![vec!["1"], vec!["3"]]
+        );
+        // 比较和取模
+        assert_eq!(
+            to_strings(s.execute("select n from t where amount > 0.15 order by n;")?),
+            vec![vec!["2"], vec!["4"]]
+        );
+        assert_eq!(
+            to_strings(s.execute("select n from t where id % 2 = 0.5;")?),
+            vec![vec!["2"]]
+        );
+        // 四则运算
+        assert_eq!(
+            to_strings(s.execute("select n from t where amount * 2 = 0.4;")?),
+            vec![vec!["2"]]
+        );
+        assert_eq!(
+            to_strings(s.execute("select n from t where id - 0.5 = 2 order by n;")?),
+            vec![vec!["2"]]
+        );
+        assert_eq!(
+            to_strings(s.execute("select n from t where amount / 3 > 3.3333;")?),
+            vec![vec!["4"]]
+        );
+        assert!(s.execute("select n from t where amount / 0 = 1;").is_err());
+
+        // 0.1 + 0.2 + 0.1 + 10 精确累加
+        assert_eq!(
+            to_strings(s.execute("select sum(amount), avg(amount) from t;")?),
+            vec![vec!["10.40", "2.600000"]]
+        );
+
+        // 超出声明的精度时报错，decimal(5, 2) 的整数部分最多 3 位
+        s.execute("create table dd (a int primary key, c decimal(5, 2));")?;
+        s.execute("insert into dd values (1, 999.99), (2, -12.5);")?;
+        assert_eq!(
+            s.execute("insert into dd values (3, 123456789.12);"),
+            Err(RSDBError::InsertRow {
+                row: 1,
+                primary_key: Some("3".to_string()),
+                source: Box::new(RSDBError::ValueOutOfRange {
+                    column: "c".to_string(),
+                    value: "123456789.12".to_string(),
+                    datatype: "Decimal(5, 2)".to_string(),
+                }),
+            })
+        );
+        assert!(s.execute("insert into dd values (3, 999.999);").is_err());
+        assert!(s.execute("update dd set c = c * 10 where a = 1;").is_err());
+        assert_eq!(
+            to_strings(s.execute("select c from dd order by a;")?),
+            vec![vec!["999.99"], vec!["-12.50"]]
+        );
+        assert_eq!(
+            to_strings(s.execute("describe dd;")?)[1][1],
+            "'Decimal(5, 2)'"
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    fn bytes(eng: impl StorageEngine + 'static) -> RSDBResult<()> {
+        let kvengine = KVEngine::new(eng);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (k bytea primary key, v blob index, n int);")?;
+        // 包含 0x00 的值会经过 keycode 的转义
+        s.execute(
+            "insert into t values (x'00', x'0000ff', 1), (x'0001', x'00', 2),
+                (x'', x'0000ff', 3), (x'ff00', x'', 4);",
+        )?;
+
+        match s.execute("select * from t order by k;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![
+                        Value::Bytes(vec![]),
+                        Value::Bytes(vec![0, 0, 255]),
+                        Value::Integer(3)
+                    ],
+                    vec![
+                        Value::Bytes(vec![0]),
+                        Value::Bytes(vec![0, 0, 255]),
+                        Value::Integer(1)
+                    ],
+                    vec![
+                        Value::Bytes(vec![0, 1]),
+                        Value::Bytes(vec![0]),
+                        Value::Integer(2)
+                    ],
+                    vec![
+                        Value::Bytes(vec![255, 0]),
+                        Value::Bytes(vec![]),
+                        Value::Integer(4)
+                    ],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        // 主键、索引查询
+        match s.execute("select n from t where k = x'0001';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(2)]]),
+            _ => unreachable!(),
+        }
+        match s.execute("select n from t where v = x'0000FF' order by n;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(1)], vec![Value::Integer(3)]])
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select n from t where v > x'00' order by n;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(1)], vec![Value::Integer(3)]])
+            }
+            _ => unreachable!(),
+        }
+
+        // 以十六进制展示
+        match s.execute("select k, v from t where k = x'ff00';")? {
+            rs @ ResultSet::Scan { .. } => assert_eq!(
+                rs.to_string(),
+                "k       |v  \n--------+----\nx'ff00' |x''\n1 ROWS"
+            ),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_bytes() -> RSDBResult<()> {
+        bytes(MemoryEngine::new())?;
+
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        bytes(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_varchar_length() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t (a int primary key, b varchar(3) default 'x', c text, d decimal(10, 2), e bytea);",
+        )?;
+
+        // 按字符个数计算长度
+        s.execute("insert into t (a, b) values (1, '中文字'), (2, 'abc');")?;
+        assert_eq!(
+            s.execute("insert into t (a, b) values (3, 'abcd');"),
+            Err(RSDBError::InsertRow {
+                row: 1,
+                primary_key: Some("3".to_string()),
+                source: Box::new(RSDBError::ValueTooLong {
+                    column: "b".to_string(),
+                    length: 4,
+                    max_length: 3,
+                }),
+            })
+        );
+        assert_eq!(
+            s.execute("update t set b = '中文字符' where a = 1;"),
+            Err(RSDBError::ValueTooLong {
+                column: "b".to_string(),
+                length: 4,
+                max_length: 3,
+            })
+        );
+        assert!(
+            s.execute("create table t1 (a int primary key, b varchar(1) default 'xy');")
+                .is_err()
+        );
+        assert!(
+            s.execute("create table t1 (a int primary key, b varchar(0));")
+                .is_err()
+        );
+
+        // 建表语句可以重新执行
+        let table = s.get_table("t".to_string())?;
+        assert_eq!(table.columns[1].max_length, Some(3));
+        assert_eq!(table.columns[3].datatype, DataType::Decimal(2));
+        let create = table.to_string();
+        assert_eq!(
+            create,
+            "CREATE TABLE t (
+    a Integer PRIMARY KEY,
+    b Varchar(3) DEFAULT 'x',
+    c String DEFAULT NULL,
+    d Decimal(10, 2) DEFAULT NULL,
+    e Bytea DEFAULT NULL
+)"
+        );
+        s.execute("drop table t;")?;
+        s.execute(&format!("{};", create))?;
+        assert_eq!(s.get_table("t".to_string())?, table);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_integer_width() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t (a bigint primary key, b tinyint, c smallint default 0, d int);",
+        )?;
+
+        // 边界值可以写入
+        s.execute("insert into t values (1, 127, 32767, 2147483648);")?;
+        s.execute("insert into t values (2, -128, -32768, -2147483649);")?;
+        s.execute("insert into t values (9223372036854775807, 0, 0, 0);")?;
+        assert_eq!(
+            s.execute("insert into t values (3, 128, 0, 0);"),
+            Err(RSDBError::InsertRow {
+                row: 1,
+                primary_key: Some("3".to_string()),
+                source: Box::new(RSDBError::ValueOutOfRange {
+                    column: "b".to_string(),
+                    value: "128".to_string(),
+                    datatype: "Tinyint".to_string(),
+                }),
+            })
+        );
+        assert_eq!(
+            s.execute("insert into t (a, b) values (3, -129);"),
+            Err(RSDBError::InsertRow {
+                row: 1,
+                primary_key: Some("3".to_string()),
+                source: Box::new(RSDBError::ValueOutOfRange {
+                    column: "b".to_string(),
+                    value: "-129".to_string(),
+                    datatype: "Tinyint".to_string(),
+                }),
+            })
+        );
+        assert_eq!(
+            s.execute("update t set c = 32768 where a = 1;"),
+            Err(RSDBError::ValueOutOfRange {
+                column: "c".to_string(),
+                value: "32768".to_string(),
+                datatype: "Smallint".to_string(),
+            })
+        );
+        assert!(s.execute("update t set c = -32769 where a = 2;").is_err());
+        assert!(
+            s.execute("create table t1 (a int primary key, b tinyint default 1000);")
+                .is_err()
+        );
+        match s.execute("select b, c from t where a = 1;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(127), Value::Integer(32767)]])
+            }
+            _ => unreachable!(),
+        }
+
+        // 展示声明的类型
+        let table = s.get_table("t".to_string())?;
+        assert_eq!(
+            table
+                .columns
+                .iter()
+                .map(|c| c.int_width)
+                .collect::<Vec<_>>(),
+            vec![
+                Some(types::IntegerWidth::Big),
+                Some(types::IntegerWidth::Tiny),
+                Some(types::IntegerWidth::Small),
+                None
+            ]
+        );
+        let create = table.to_string();
+        assert_eq!(
+            create,
+            "CREATE TABLE t (
+    a Bigint PRIMARY KEY,
+    b Tinyint DEFAULT NULL,
+    c Smallint DEFAULT 0,
+    d Integer DEFAULT NULL
+)"
+        );
+        s.execute("drop table t;")?;
+        s.execute(&format!("{};", create))?;
+        assert_eq!(s.get_table("t".to_string())?, table);
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_mismatch() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b bool, c float, d decimal(10, 2));")?;
+
+        // 空表也会在构建执行计划时报错
+        assert_eq!(
+            s.execute("select * from t where b = 1;"),
+            Err(RSDBError::TypeMismatch(
+                "column b of type Boolean can not be compared with 1".to_string()
+            ))
+        );
+        s.execute("insert into t values (1, true, 1.5, '2.50'), (2, false, 2, 3);")?;
+        assert_eq!(
+            s.execute("select * from t where a = true;"),
+            Err(RSDBError::TypeMismatch(
+                "column a of type Integer can not be compared with TRUE".to_string()
+            ))
+        );
+        assert!(matches!(
+            s.execute("update t set c = 0.5 where 'x' > b;"),
+            Err(RSDBError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            s.execute("delete from t where a = 'x';"),
+            Err(RSDBError::TypeMismatch(_))
+        ));
+
+        // 可以比较的类型
+        let count = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> RSDBResult<usize> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(count(&mut s, "select * from t where b = true;")?, 1);
+        assert_eq!(count(&mut s, "select * from t where c = 2;")?, 1);
+        assert_eq!(count(&mut s, "select * from t where a = 1.0;")?, 1);
+        assert_eq!(count(&mut s, "select * from t where d > 2;")?, 2);
+        assert_eq!(count(&mut s, "select * from t where d = '2.5';")?, 1);
+        assert_eq!(count(&mut s, "select * from t where c > 'NaN';")?, 0);
+        assert_eq!(count(&mut s, "select * from t where b = null;")?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_float_nan_infinity() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, f float index);")?;
+        s.execute(
+            "insert into t values (1, 'NaN'), (2, 'Infinity'), (3, '-infinity'), (4, 1.5), (5, null);",
+        )?;
+        s.execute_with_params(
+            "insert into t values (?, ?);",
+            &[Value::Integer(6), Value::Float(-f64::NAN)],
+        )?;
+        assert!(s.execute("insert into t values (7, 'abc');").is_err());
+
+        let to_strings = |rs: ResultSet| match rs {
+            ResultSet::Scan { rows, .. } => rows
+                .iter()
+                .map(|r| {
+                    r.iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        // NaN 大于所有其他值
+        assert_eq!(
+            to_strings(s.execute("select a, f from t order by f, a;")?),
+            vec![
+                "5,NULL",
+                "3,-Infinity",
+                "4,1.5",
+                "2,Infinity",
+                "1,NaN",
+                "6,NaN"
+            ]
+        );
+        assert_eq!(
+            to_strings(s.execute("select min(f), max(f) from t;")?),
+            vec!["-Infinity,NaN"]
+        );
+        // NaN = NaN 为 TRUE，索引查询和全表扫描结果一致
+        assert_eq!(
+            to_strings(s.execute("select a from t where f = 'NaN';")?),
+            vec!["1", "6"]
+        );
+        assert_eq!(
+            to_strings(s.execute("select a from t where f > 'Infinity' order by a;")?),
+            vec!["1", "6"]
+        );
+        // GROUP BY 时所有 NaN 在同一组
+        assert_eq!(
+            to_strings(s.execute("select f, count(a) from t group by f order by f;")?),
+            vec!["NULL,1", "-Infinity,1", "1.5,1", "Infinity,1", "NaN,2"]
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_with_params() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, c float);")?;
+
+        // 字符串参数中的单引号不需要转义
+        s.execute_with_params(
+            "insert into t values (?, ?, ?), (?, 'x', 2.0);",
+            &[
+                Value::Integer(1),
+                Value::String("it's".to_string()),
+                Value::Null,
+                Value::Integer(2),
+            ],
+        )?;
+        s.execute_with_params(
+            "update t set c = ? where a = ?;",
+            &[Value::Float(1.5), Value::Integer(1)],
+        )?;
+
+        match s.execute_with_params(
+            "select * from t where b = ?;",
+            &[Value::String("it's".to_string())],
+        )? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![vec![
+                    Value::Integer(1),
+                    Value::String("it's".to_string()),
+                    Value::Float(1.5),
+                ]]
+            ),
+            _ => unreachable!(),
+        }
+        match s.execute_with_params(
+            "select a from t order by a limit ? offset ?;",
+            &[Value::Integer(1), Value::Integer(1)],
+        )? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(2)]]),
+            _ => unreachable!(),
+        }
+
+        // 参数个数不匹配
+        assert!(
+            s.execute_with_params("select * from t where a = ?;", &[])
+                .is_err()
+        );
+        assert!(
+            s.execute_with_params(
+                "select * from t where a = ?;",
+                &[Value::Integer(1), Value::Integer(2)]
+            )
+            .is_err()
+        );
+        assert!(s.execute("delete from t where a = ?;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_result_format() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, f float);")?;
+        s.execute("insert into t values (1, 'NULL', 1.5), (2, null, 2.25);")?;
+        let rs = s.execute("select * from t;")?;
+
+        // 默认输出与 to_string 一致
+        assert_eq!(rs.format(&FormatOptions::default()), rs.to_string());
+        assert_eq!(
+            rs.to_string(),
+            "a |b      |   f\n--+-------+-----\n1 |'NULL' | 1.5\n2 |NULL   |2.25\n2 ROWS"
+        );
+
+        let mut opts = FormatOptions::default();
+        opts.set("quote", "off")?;
+        opts.set("null", "")?;
+        opts.set("precision", "1")?;
+        assert_eq!(
+            rs.format(&opts),
+            "a |b    |  f\n--+-----+----\n1 |NULL |1.5\n2 |     |2.2\n2 ROWS"
+        );
+        opts.set("precision", "none")?;
+        assert_eq!(opts.float_precision, None);
+
+        assert!(opts.set("quote", "maybe").is_err());
+        assert!(opts.set("precision", "-1").is_err());
+        assert!(opts.set("width", "0").is_err());
+        assert!(opts.set("color", "on").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_statement_result_format() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        let mut exec = |sql: &str| -> RSDBResult<String> { Ok(s.execute(sql)?.to_string()) };
+
+        assert_eq!(
+            exec("create table t (a int primary key);")?,
+            "CREATE TABLE `t`"
+        );
+        let begin = exec("begin;")?;
+        let version = begin
+            .strip_prefix("BEGIN (version ")
+            .and_then(|v| v.strip_suffix(')'))
+            .map(|v| v.parse::<u64>());
+        assert!(matches!(version, Some(Ok(_))), "{}", begin);
+        assert_eq!(exec("insert into t values (1), (2);")?, "INSERT 2 ROWS");
+        assert_eq!(exec("commit;")?, "COMMIT");
+        exec("begin;")?;
+        assert_eq!(exec("delete from t where a = 1;")?, "DELETE 1 ROWS");
+        assert_eq!(exec("rollback;")?, "ROLLBACK");
+        assert_eq!(exec("drop table t;")?, "DROP TABLE t");
+        assert_eq!(exec("drop table if exists t;")?, "DROP TABLE t");
+        Ok(())
+    }
+
+    #[test]
+    fn test_result_table_rendering() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, d decimal(10, 2));")?;
+        s.execute("insert into t values (1, 'line1\nline2\tend', 1.5), (20, null, 12.25);")?;
+        s.execute(&format!(
+            "insert into t values (300, '{}', null);",
+            "x".repeat(2048)
+        ))?;
+
+        // 数值列右对齐，换行符和制表符转义，过长的值截断为最大宽度
+        let rs = s.execute("select * from t;")?;
+        let w = DEFAULT_MAX_WIDTH;
+        let long = format!("'{}…", "x".repeat(w - 2));
+        let lines = [
+            format!("  a |{:w$} |    d", "b"),
+            format!("----+{}+------", "-".repeat(w + 1)),
+            format!("  1 |{:w$} | 1.50", "'line1\\nline2\\tend'"),
+            format!(" 20 |{:w$} |12.25", "NULL"),
+            format!("300 |{} | NULL", long),
+            "3 ROWS".to_string(),
+        ];
+        assert_eq!(rs.to_string(), lines.join("\n"));
+
+        // 最大宽度和 NULL 的展示文本可以修改
+        let mut opts = FormatOptions::default();
+        opts.set("width", "6")?;
+        opts.set("null", "-")?;
+        let rs = s.execute("select b, d from t where a < 300;")?;
+        assert_eq!(
+            rs.format(&opts),
+            "b      |    d\n-------+------\n'line… | 1.50\n-      |12.25\n2 ROWS"
+        );
+
+        // 空结果只展示表头和 (0 rows)
+        let rs = s.execute("select a, b from t where a > 1000;")?;
+        assert_eq!(rs.to_string(), "a |b\n(0 rows)");
+
+        // 原始模式输出完整的值，以制表符分隔
+        let mut opts = FormatOptions::default();
+        opts.set("raw", "on")?;
+        opts.set("quote", "off")?;
+        let rs = s.execute("select a, b, d from t where a = 1;")?;
+        assert_eq!(rs.format(&opts), "a\tb\td\n1\tline1\nline2\tend\t1.50");
+        let rs = s.execute("select b from t where a = 300;")?;
+        assert_eq!(rs.format(&opts), format!("b\n{}", "x".repeat(2048)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_result_types() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, f float, d decimal(10, 2));")?;
+        s.execute("create table u (x int primary key, y bool);")?;
+        s.execute("insert into t values (1, 'x', 1.5, 2.25), (2, 'y', 2.5, 1.00);")?;
+        s.execute("insert into u values (1, true);")?;
+        let mut types = |sql: &str| -> RSDBResult<Vec<Option<DataType>>> {
+            match s.execute(sql)? {
+                ResultSet::Scan { types, .. } => Ok(types),
+                _ => unreachable!(),
+            }
+        };
+        let (int, float) = (Some(DataType::Integer), Some(DataType::Float));
+
+        // 扫描的列类型来自表结构，投影按照表达式推断，NULL 常量的类型未知
+        assert_eq!(
+            types("select * from t;")?,
+            vec![
+                int.clone(),
+                Some(DataType::String),
+                float.clone(),
+                Some(DataType::Decimal(2))
+            ]
+        );
+        assert_eq!(
+            types("select a + 1, a * f, a % 2, 'k', null from t;")?,
+            vec![
+                int.clone(),
+                float.clone(),
+                int.clone(),
+                Some(DataType::String),
+                None
+            ]
+        );
+
+        // 聚集函数的结果类型由参数列的类型推断
+        assert_eq!(
+            types("select b, count(b), sum(a), sum(f), avg(a), avg(d), max(b) from t group by b;")?,
+            vec![
+                Some(DataType::String),
+                int.clone(),
+                int.clone(),
+                float.clone(),
+                float.clone(),
+                Some(DataType::Decimal(6)),
+                Some(DataType::String)
+            ]
+        );
+
+        // 连接的结果为两侧的列类型拼接
+        assert_eq!(
+            types("select * from t join u on a = x;")?,
+            vec![
+                int.clone(),
+                Some(DataType::String),
+                float,
+                Some(DataType::Decimal(2)),
+                int,
+                Some(DataType::Boolean)
+            ]
+        );
+
+        // 列类型只在开启 types 选项时展示
+        let rs = s.execute("select a, y, null from t join u on a = x;")?;
+        assert_eq!(
+            rs.to_string(),
+            "a |y    |NULL\n--+-----+-----\n1 |TRUE |NULL\n1 ROWS"
+        );
+        let mut opts = FormatOptions::default();
+        opts.set("types", "on")?;
+        assert_eq!(
+            rs.format(&opts),
+            "      a |y       |NULL\nInteger |Boolean |?   \n--------+--------+-----\n      1 |TRUE    |NULL\n1 ROWS"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_projection_exprs() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c varchar);")?;
+        s.execute("insert into t values (1, 10, 'x'), (2, null, 'y');")?;
+
+        match s.execute("select a, 1, a * 2 + b, c as name, 'k' as k from t order by a;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["a", "1", "a * 2 + b", "name", "k"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![
+                            Value::Integer(1),
+                            Value::Integer(1),
+                            Value::Integer(12),
+                            Value::String("x".to_string()),
+                            Value::String("k".to_string()),
+                        ],
+                        vec![
+                            Value::Integer(2),
+                            Value::Integer(1),
+                            Value::Null,
+                            Value::String("y".to_string()),
+                            Value::String("k".to_string()),
+                        ],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+        // 常量表达式在计划阶段折叠
+        match s.execute("select 2 * 3 from t;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["6"]);
+                assert_eq!(rows, vec![vec![Value::Integer(6)]; 2]);
+            }
+            _ => unreachable!(),
+        }
+        // 表达式中引用了不存在的列
+        assert!(s.execute("select a + d from t;").is_err());
+        // 表达式求值出错
+        assert!(s.execute("select a % 0 from t;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_projection_pushdown() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        let columns = (0..20)
+            .map(|i| match i {
+                0 => "c0 int primary key".to_string(),
+                _ => format!("c{} int", i),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        s.execute(&format!("create table t ({});", columns))?;
+        for i in 0..5 {
+            let values = (0..20)
+                .map(|j| (i * 100 + j).to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            s.execute(&format!("insert into t values ({});", values))?;
+        }
+
+        // Scan 只输出查询用到的列，过滤条件可以使用未选择的列
+        let res = s.execute("explain select c3, c1 from t where c7 > 300 order by c5 desc;")?;
+        assert_eq!(
+            res,
+            ResultSet::Explain {
+                plan: "Projection: c3, c1
+  Order: c5 DESC
+    Scan: t [c1, c3, c5] (filter: c7 > 300)"
+                    .to_string()
+            }
+        );
+        match s.execute("select c3, c1 from t where c7 > 300 order by c5 desc;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["c3", "c1"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::Integer(403), Value::Integer(401)],
+                        vec![Value::Integer(303), Value::Integer(301)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 聚集函数和分组列
+        match s.execute("select c2, sum(c4) from t group by c2;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["c2", "SUM(c4)"]);
+                assert_eq!(rows.len(), 5);
+            }
+            _ => unreachable!(),
+        }
+        let res = s.execute("explain select max(c9) from t;")?;
+        assert_eq!(
+            res,
+            ResultSet::Explain {
+                plan: "Aggregate: max(c9)\n  Scan: t [c9]".to_string()
+            }
+        );
+
+        // select * 与 UPDATE 需要完整的行
+        match s.execute("select * from t where c0 = 100;")? {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns.len(), 20);
+                assert_eq!(rows[0].len(), 20);
+            }
+            _ => unreachable!(),
+        }
+        s.execute("update t set c19 = 0 where c1 > 400;")?;
+        match s.execute("select c0, c18, c19 from t where c1 > 400;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![vec![
+                    Value::Integer(400),
+                    Value::Integer(418),
+                    Value::Integer(0)
+                ]]
+            ),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_functions() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table scores (id int primary key, team text, score int);")?;
+        s.execute(
+            "insert into scores values (1, 'a', 90), (2, 'b', 70), (3, 'a', 80), \
+             (4, 'b', 70), (5, 'a', 90), (6, 'b', 60);",
+        )?;
+        let mut query = |sql: &str| -> RSDBResult<(Vec<String>, Vec<Vec<i64>>)> {
+            match s.execute(sql)? {
+                ResultSet::Scan { columns, rows, .. } => Ok((
+                    columns,
+                    rows.into_iter()
+                        .map(|row| {
+                            row.into_iter()
+                                .map(|v| match v {
+                                    Value::Integer(i) => i,
+                                    v => panic!("unexpected value {:?}", v),
+                                })
+                                .collect()
+                        })
+                        .collect(),
+                )),
+                _ => unreachable!(),
+            }
+        };
+
+        // 没有别名时以函数的文本作为列名，按照窗口的排序顺序输出
+        let (columns, rows) =
+            query("select id, row_number() over (order by score desc, id) from scores;")?;
+        assert_eq!(
+            columns,
+            vec!["id", "ROW_NUMBER() OVER (ORDER BY score DESC, id ASC)"]
+        );
+        assert_eq!(
+            rows,
+            vec![
+                vec![1, 1],
+                vec![5, 2],
+                vec![3, 3],
+                vec![2, 4],
+                vec![4, 5],
+                vec![6, 6]
+            ]
+        );
+
+        // RANK 中分数相同的排名相同，之后跳过相应的名次
+        let (_, rows) = query(
+            "select id, rank() over (order by score desc) as r, \
+             row_number() over (partition by team order by score desc, id) as n \
+             from scores order by id;",
+        )?;
+        assert_eq!(
+            rows,
+            vec![
+                vec![1, 1, 1],
+                vec![2, 4, 1],
+                vec![3, 3, 3],
+                vec![4, 4, 2],
+                vec![5, 1, 2],
+                vec![6, 6, 3]
+            ]
+        );
+
+        // 分区内分别排名，可以按照窗口函数的别名排序和过滤之后的行计算
+        let (_, rows) = query(
+            "select id, rank() over (partition by team order by score) as r from scores \
+             where id > 1 order by r desc, id limit 3;",
+        )?;
+        assert_eq!(rows, vec![vec![2, 2], vec![4, 2], vec![5, 2]]);
+
+        match s.execute(
+            "explain select id, rank() over (partition by team order by score) as r from scores;",
+        )? {
+            ResultSet::Explain { plan } => assert_eq!(
+                plan,
+                "Projection: id, r\n  Window: RANK() OVER (PARTITION BY team ORDER BY score ASC) as r\n    Scan: scores [id, team, score]"
+            ),
+            _ => unreachable!(),
+        }
+
+        // 暂不支持与 GROUP BY、聚集函数一起使用，也不能作为表达式的一部分
+        for (sql, err) in [
+            (
+                "select team, rank() over (order by team) from scores group by team;",
+                "window functions cannot be used with GROUP BY or aggregate functions",
+            ),
+            (
+                "select count(id), row_number() over () from scores;",
+                "window functions cannot be used with GROUP BY or aggregate functions",
+            ),
+            (
+                "select row_number() over () + 1 from scores;",
+                "window functions are only allowed as select columns",
+            ),
+            (
+                "select lag() over (order by id) from scores;",
+                "unsupported window function lag",
+            ),
+        ] {
+            assert_eq!(s.execute(sql), Err(RSDBError::Internal(err.to_string())));
+        }
+        assert!(matches!(
+            s.execute("select row_number() over (order by missing) from scores;"),
+            Err(RSDBError::ColumnNotFound { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_script() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        let script = "
+            create table t (a int primary key, b int);
+            insert into t values (1, 10);
+            begin;
+            insert into t values (2, 20);
+            update t set b = 11 where a = 1;
+            commit;
+            insert into t values (2, 21);
+            insert into t values (3, 30);
+        ";
+        let results = s.execute_script(script, true);
+        assert_eq!(
+            results.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4, 5, 6]
+        );
+        assert!(matches!(results[2].1, Ok(ResultSet::Begin { .. })));
+        assert!(matches!(results[4].1, Ok(ResultSet::Update { count: 1 })));
+        assert!(matches!(results[5].1, Ok(ResultSet::Commit { .. })));
+        // 提交之后的错误不影响已提交的事务，遇到错误后停止执行
+        assert!(results[6].1.is_err());
+        let select = |s: &mut Session<_>| match s.execute("select * from t;") {
+            Ok(ResultSet::Scan { rows, .. }) => rows,
+            r => panic!("unexpected result {:?}", r),
+        };
+        assert_eq!(
+            select(&mut s),
+            vec![
+                vec![Value::Integer(1), Value::Integer(11)],
+                vec![Value::Integer(2), Value::Integer(20)]
+            ]
+        );
+
+        // 不停止时继续执行之后的语句，每条语句在各自的隐式事务中执行
+        let results = s.execute_script(
+            "insert into t values (2, 21); insert into t values (3, 30);",
+            false,
+        );
+        assert_eq!(results.len(), 2);
+        assert!(results[0].1.is_err());
+        assert!(matches!(results[1].1, Ok(ResultSet::Insert { count: 1 })));
+        assert_eq!(select(&mut s).len(), 3);
+
+        // 脚本结束时未提交的事务保持打开，解析错误之后的语句不会执行
+        let results = s.execute_script(
+            "begin; delete from t where a = 3; selec * from t; delete from t;",
+            false,
+        );
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[2].1, Err(RSDBError::Parse { .. })));
+        assert_eq!(select(&mut s).len(), 2);
+        s.execute("rollback;")?;
+        assert_eq!(select(&mut s).len(), 3);
+
+        // 空语句被跳过
+        assert!(s.execute_script(" ;; ", true).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_settings() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        let show = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Scan { columns, rows, .. }) => {
+                assert_eq!(columns, vec!["name", "value"]);
+                rows.into_iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|v| v.to_string())
+                            .collect::<Vec<_>>()
+                            .join("=")
+                    })
+                    .collect::<Vec<_>>()
+            }
+            r => panic!("unexpected result {:?}", r),
+        };
+        assert_eq!(
+            show(&mut s1, "show all;"),
+            vec!["'output_format'='table'", "'timeout'='0'", "'retries'='3'"]
+        );
+
+        // 设置只对当前 session 生效
+        assert_eq!(
+            s1.execute("set output_format = 'json';")?.to_string(),
+            "SET output_format"
+        );
+        s1.execute("SET TIMEOUT = 5000;")?;
+        assert_eq!(
+            show(&mut s1, "show all;"),
+            vec![
+                "'output_format'='json'",
+                "'timeout'='5000'",
+                "'retries'='3'"
+            ]
+        );
+        assert_eq!(s1.settings().timeout, Some(Duration::from_millis(5000)));
+        assert_eq!(show(&mut s2, "show timeout;"), vec!["'timeout'='0'"]);
+        assert_eq!(s2.settings(), &SessionSettings::default());
+
+        // 未知的设置和不合法的值
+        for (sql, err) in [
+            ("set colour = 'red';", "unknown setting colour"),
+            ("show colour;", "unknown setting colour"),
+            (
+                "set output_format = 'xml';",
+                "invalid value 'xml' for setting output_format",
+            ),
+            ("set timeout = -1;", "invalid value -1 for setting timeout"),
+            (
+                "set timeout = '10s';",
+                "invalid value '10s' for setting timeout",
+            ),
+        ] {
+            assert_eq!(s1.execute(sql), Err(RSDBError::Internal(err.to_string())));
+        }
+        assert_eq!(s1.settings().timeout, Some(Duration::from_millis(5000)));
+
+        // 查询结果按照 session 设置的输出格式展示
+        s1.execute("create table t (a int primary key, b text, c float, d bool);")?;
+        s1.execute("insert into t values (1, 'say \"hi\"', 1.5, true), (2, null, null, false);")?;
+        let result = s1.execute("select * from t;")?;
+        assert_eq!(
+            result.format(&s1.settings().format),
+            r#"[{"a":1,"b":"say \"hi\"","c":1.5,"d":true},{"a":2,"b":null,"c":null,"d":false}]"#
+        );
+        assert!(
+            result
+                .format(&s2.settings().format)
+                .starts_with("a |b          |   c |d")
+        );
+
+        // 超时的语句返回错误，隐式事务回滚
+        s2.execute("set timeout = 1;")?;
+        let values = (3..20000)
+            .map(|i| format!("({}, 'v', 0.5, true)", i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        assert_eq!(
+            s2.execute(&format!("insert into t values {};", values)),
+            Err(RSDBError::StatementTimeout(1))
+        );
+        s2.execute("set timeout = 0;")?;
+        match s2.execute("select * from t;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 2),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_show_transaction() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        let status = |s: &mut Session<_>| match s.execute("show transaction;") {
+            Ok(ResultSet::Scan { columns, rows, .. }) => {
+                assert_eq!(
+                    columns,
+                    vec!["in_transaction", "version", "read_only", "writes"]
+                );
+                rows[0].clone()
+            }
+            r => panic!("unexpected result {:?}", r),
+        };
+        assert_eq!(
+            status(&mut s),
+            vec![
+                Value::Boolean(false),
+                Value::Null,
+                Value::Null,
+                Value::Integer(0)
+            ]
+        );
+
+        s.execute("create table t (a int primary key, b int index);")?;
+        let version = match s.execute("begin;")? {
+            ResultSet::Begin { version } => version as i64,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            status(&mut s),
+            vec![
+                Value::Boolean(true),
+                Value::Integer(version),
+                Value::Boolean(false),
+                Value::Integer(0)
+            ]
+        );
+        // 写入的 key 包括行和索引
+        s.execute("insert into t values (1, 10);")?;
+        assert_eq!(status(&mut s)[3], Value::Integer(2));
+        s.execute("select * from t;")?;
+        assert_eq!(status(&mut s)[3], Value::Integer(2));
+        s.execute("rollback;")?;
+        assert_eq!(status(&mut s)[0], Value::Boolean(false));
+
+        s.execute("begin read only;")?;
+        assert_eq!(status(&mut s)[2], Value::Boolean(true));
+        assert_eq!(status(&mut s)[3], Value::Integer(0));
+        s.execute("commit;")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_show_status() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        let status = |s: &mut Session<_>| -> RSDBResult<HashMap<String, i64>> {
+            match s.execute("show status;")? {
+                ResultSet::Scan { columns, rows, .. } => {
+                    assert_eq!(columns, vec!["name".to_string(), "value".to_string()]);
+                    Ok(rows
+                        .into_iter()
+                        .map(|r| match (&r[0], &r[1]) {
+                            (Value::String(name), Value::Integer(v)) => (name.clone(), *v),
+                            _ => unreachable!(),
+                        })
+                        .collect())
+                }
+                _ => unreachable!(),
+            }
+        };
+        s1.execute("create table t (a int primary key, b text);")?;
+        let before = status(&mut s1)?;
+        assert_eq!(before.len(), 7);
+        assert_eq!(before["active_txns"], 0);
+        assert_eq!(before["engine_disk_size"], 0);
+
+        // 显式事务中查看，自己也是活跃事务
+        s1.execute("begin;")?;
+        s1.execute("insert into t values (1, 'a'), (2, 'b'), (3, 'c');")?;
+        let during = status(&mut s1)?;
+        assert_eq!(during["active_txns"], 1);
+        assert_eq!(during["next_version"], before["next_version"] + 1);
+        assert_eq!(during["total_keys"], before["total_keys"] + 3);
+        assert_eq!(during["total_versions"], before["total_versions"] + 3);
+        assert!(during["engine_size"] > before["engine_size"]);
+        assert_eq!(status(&mut s2)?["active_txns"], 1);
+        s1.execute("commit;")?;
+
+        // 更新后每行多一个版本，key 的个数不变
+        s2.execute("update t set b = 'x';")?;
+        let after = status(&mut s2)?;
+        assert_eq!(after["active_txns"], 0);
+        assert_eq!(after["next_version"], before["next_version"] + 2);
+        assert_eq!(after["total_keys"], during["total_keys"]);
+        assert_eq!(after["total_versions"], during["total_versions"] + 3);
+        // 没有活跃事务时，存储引擎中只有各个版本以及 NextVersion
+        assert_eq!(after["engine_keys"], after["total_versions"] + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_show_tables() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        let show = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Scan { rows, .. }) => rows,
+            r => panic!("unexpected result {:?}", r),
+        };
+        assert!(show(&mut s, "show tables;").is_empty());
+
+        s.execute("create table t1 (a int primary key, b text);")?;
+        s.execute("create table t2 (a int primary key, b int index);")?;
+        s.execute("create table t3 (a int primary key);")?;
+        let mut rows = Vec::new();
+        for i in 0..300 {
+            s.execute(&format!("insert into t1 values ({}, 'value{}');", i, i))?;
+            s.execute(&format!("insert into t2 values ({}, {});", i, i % 10))?;
+            rows.push(vec![
+                Value::Integer(i),
+                Value::String(format!("value{}", i)),
+            ]);
+        }
+        s.execute("delete from t1 where a > 199;")?;
+        let t1_bytes = rows[..200]
+            .iter()
+            .map(|row| bincode::serialize(row).map(|v| v.len()))
+            .sum::<Result<usize, _>>()?;
+
+        assert_eq!(
+            show(&mut s, "show tables;"),
+            vec![
+                vec![Value::String("t1".to_string())],
+                vec![Value::String("t2".to_string())],
+                vec![Value::String("t3".to_string())],
+            ]
+        );
+        let tables = show(&mut s, "show tables extended;");
+        assert_eq!(
+            tables[0],
+            vec![
+                Value::String("t1".to_string()),
+                Value::Integer(200),
+                Value::Integer(t1_bytes as i64)
+            ]
+        );
+        // 索引也计算在表的大小中
+        assert_eq!(tables[1][1], Value::Integer(300));
+        match (&tables[1][2], &tables[0][2]) {
+            (Value::Integer(t2), Value::Integer(t1)) => assert!(t2 > t1),
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            tables[2],
+            vec![
+                Value::String("t3".to_string()),
+                Value::Integer(0),
+                Value::Integer(0)
+            ]
+        );
+
+        // 事务中能看到未提交的写入
+        s.execute("begin;")?;
+        s.execute("insert into t3 values (1);")?;
+        assert_eq!(
+            show(&mut s, "show tables extended;")[2][1],
+            Value::Integer(1)
+        );
+        s.execute("rollback;")?;
+        assert_eq!(
+            s.execute("explain show tables extended;")?.to_string(),
+            "ShowTables: extended"
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_sessions() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text);")?;
+        s.execute("create table t2 (a int primary key, b text);")?;
+
+        // 两个 session 同时在各自的事务中写入不同的表
+        // 存储引擎的锁只在单次读写时持有，两个事务都未提交时另一方也能继续执行
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let handles = ["t1", "t2"]
+            .into_iter()
+            .map(|table| {
+                let kvengine = kvengine.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || -> RSDBResult<()> {
+                    let mut s = kvengine.session()?;
+                    s.execute("begin;")?;
+                    for i in 0..100 {
+                        s.execute(&format!("insert into {} values ({}, 'v{}');", table, i, i))?;
+                        if i == 50 {
+                            barrier.wait();
+                        }
+                    }
+                    // 等待对方也写完，此时两个事务都持有未提交的写入
+                    barrier.wait();
+                    s.execute("commit;")?;
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        for table in ["t1", "t2"] {
+            match s.execute(&format!("select count(a) from {};", table))? {
+                ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(100)]]),
+                _ => unreachable!(),
+            }
+        }
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepared_statements() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, c float);")?;
+
+        let insert = s.prepare("insert into t values (?, ?, ?);")?;
+        assert_eq!(
+            insert.param_types(),
+            &[
+                Some(DataType::Integer),
+                Some(DataType::String),
+                Some(DataType::Float)
+            ]
+        );
+        // 循环插入时只在 prepare 时解析一次
+        let stats = s.cache_stats();
+        for i in 0..1000 {
+            s.execute_prepared(
+                &insert,
+                &[
+                    Value::Integer(i),
+                    Value::String(format!("v{}", i)),
+                    Value::Integer(i),
+                ],
+            )?;
+        }
+        assert_eq!(s.cache_stats().misses, stats.misses);
+        assert_eq!(s.cache_stats().hits, stats.hits);
+
+        // 校验参数的个数和类型，NULL 可以作为任意类型的参数
+        assert_eq!(
+            s.execute_prepared(&insert, &[Value::Integer(1000), Value::Null]),
+            Err(RSDBError::Internal(
+                "expected 3 parameters, got 2".to_string()
+            ))
+        );
+        assert_eq!(
+            s.execute_prepared(
+                &insert,
+                &[Value::String("x".to_string()), Value::Null, Value::Null]
+            ),
+            Err(RSDBError::Internal(
+                "parameter 1 expects Integer, got 'x'".to_string()
+            ))
+        );
+        s.execute_prepared(&insert, &[Value::Integer(1000), Value::Null, Value::Null])?;
+
+        let select = s.prepare("select b from t where a = ? or c > ?;")?;
+        assert_eq!(
+            select.param_types(),
+            &[Some(DataType::Integer), Some(DataType::Float)]
+        );
+        let query = |s: &mut Session<_>, params: &[Value]| match s.execute_prepared(&select, params)
+        {
+            Ok(ResultSet::Scan { rows, .. }) => Ok(rows),
+            Ok(r) => panic!("unexpected result {:?}", r),
+            Err(err) => Err(err),
+        };
+        assert_eq!(
+            query(&mut s, &[Value::Integer(7), Value::Float(998.0)])?,
+            vec![
+                vec![Value::String("v7".to_string())],
+                vec![Value::String("v999".to_string())]
+            ]
+        );
+
+        // 事务提交或回滚后仍然可以执行
+        s.execute("begin;")?;
+        s.execute_prepared(&insert, &[Value::Integer(-1), Value::Null, Value::Null])?;
+        s.execute("rollback;")?;
+        let update = s.prepare("update t set b = ? where a = ?;")?;
+        assert_eq!(
+            update.param_types(),
+            &[Some(DataType::String), Some(DataType::Integer)]
+        );
+        s.execute_prepared(
+            &update,
+            &[Value::String("x".to_string()), Value::Integer(7)],
+        )?;
+        assert_eq!(
+            query(&mut s, &[Value::Integer(7), Value::Null])?,
+            vec![vec![Value::String("x".to_string())]]
+        );
+
+        // 释放之后不能再执行
+        assert_eq!(s.prepared_count(), 3);
+        s.deallocate(&update)?;
+        let not_found = Err(RSDBError::Internal(format!(
+            "prepared statement {} does not exist",
+            update.id()
+        )));
+        assert_eq!(s.execute_prepared(&update, &[]), not_found);
+        assert_eq!(s.deallocate(&update), not_found.map(|_| ()));
+
+        // 表被删除后，引用了该表的语句失效，重建表之后也需要重新 prepare
+        s.execute("drop table t;")?;
+        s.execute("create table t (a text primary key);")?;
+        assert_eq!(
+            query(&mut s, &[Value::Integer(7), Value::Null]),
+            Err(RSDBError::Internal(format!(
+                "prepared statement {} is invalid because table t has changed, prepare it again",
+                select.id()
+            )))
+        );
+        let insert = s.prepare("insert into t values (?);")?;
+        assert_eq!(insert.param_types(), &[Some(DataType::String)]);
+        s.execute_prepared(&insert, &[Value::String("a".to_string())])?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_cache() -> RSDBResult<()> {
+        let scans = Arc::new(AtomicUsize::new(0));
+        let kvengine = KVEngine::new(CountingEngine {
+            inner: MemoryEngine::new(),
+            sets: Arc::default(),
+            scans: scans.clone(),
+            reads: Arc::default(),
+        });
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int index);")?;
+        s.execute("create table t2 (a int primary key, c text);")?;
+        s.execute("insert into t1 values (1, 10), (2, 20), (3, 10);")?;
+        s.execute("insert into t2 values (1, 'x'), (2, 'y');")?;
+
+        // 同一个事务中只从存储引擎读取一次表结构
+        let txn = kvengine.begin()?;
+        let before = scans.load(Ordering::SeqCst);
+        let table = txn.must_get_table("t1".to_string())?;
+        assert_eq!(scans.load(Ordering::SeqCst) - before, 1);
+        for _ in 0..10 {
+            assert_eq!(txn.must_get_table("t1".to_string())?, table);
+        }
+        assert_eq!(txn.get_table("t3".to_string())?, None);
+        assert_eq!(txn.get_table("t3".to_string())?, None);
+        assert_eq!(scans.load(Ordering::SeqCst) - before, 2);
+
+        // 本事务的 DDL 修改表结构后，能读到新的表结构
+        txn.create_index(
+            "t1",
+            Index {
+                name: "idx_ab".to_string(),
+                columns: vec!["a".to_string(), "b".to_string()],
+            },
+        )?;
+        assert_eq!(txn.must_get_table("t1".to_string())?.indexes.len(), 1);
+        txn.drop_table("t2".to_string())?;
+        assert_eq!(txn.get_table("t2".to_string())?, None);
+        txn.rollback()?;
+
+        // 连接查询和更新语句中多次获取表结构，只读取一次
+        let mut count_scans = |sql: &str| -> RSDBResult<usize> {
+            let before = scans.load(Ordering::SeqCst);
+            s.execute(sql)?;
+            Ok(scans.load(Ordering::SeqCst) - before)
+        };
+        // 单表查询读取表结构、统计信息和表数据各一次，没有缓存时表结构会读取两次
+        assert_eq!(count_scans("select * from t2;")?, 3);
+        // 没有缓存时为 15 次
+        assert_eq!(count_scans("select * from t1 join t2 on t1.a = t2.a;")?, 9);
+        count_scans("update t1 set b = 30 where b = 10;")?;
+        match s.execute("select a from t1 where b = 30;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 2),
+            _ => unreachable!(),
+        }
+        match s.execute("select c from t2;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 2),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_engine_transaction_options() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 10);")?;
+        let rows = |txn: &KVTransaction<MemoryEngine>| -> RSDBResult<Vec<Row>> {
+            txn.scan_table(&txn.must_get_table("t".to_string())?, None)
+        };
+
+        // 默认的选项为读写事务，每个事务分配新的版本号
+        assert_eq!(
+            TransactionOptions::default(),
+            TransactionOptions {
+                read_only: false,
+                as_of: None,
+                ..Default::default()
+            }
+        );
+        let txn = kvengine.begin()?;
+        assert!(!txn.read_only());
+        let version = txn.version();
+        let table = txn.must_get_table("t".to_string())?;
+        txn.create_row(&table, vec![Value::Integer(2), Value::Integer(20)])?;
+        assert_eq!(txn.write_count()?, 1);
+        txn.commit()?;
+        let txn = kvengine.begin_with(TransactionOptions::default())?;
+        assert_eq!(txn.version(), version + 1);
+        txn.rollback()?;
+
+        // 只读事务使用最新的快照，不分配版本号，所有的写入都报错
+        let txn = kvengine.begin_read_only()?;
+        assert!(txn.read_only());
+        assert_eq!(txn.version(), version + 2);
+        assert_eq!(rows(&txn)?.len(), 2);
+        assert_eq!(
+            txn.create_row(&table, vec![Value::Integer(3), Value::Integer(30)]),
+            Err(RSDBError::ReadOnlyTransaction)
+        );
+        assert_eq!(
+            txn.delete_row(&table, &Value::Integer(1)),
+            Err(RSDBError::ReadOnlyTransaction)
+        );
+        assert_eq!(
+            txn.drop_table("t".to_string()),
+            Err(RSDBError::ReadOnlyTransaction)
+        );
+        assert_eq!(txn.write_count()?, 0);
+        txn.commit()?;
+        assert_eq!(kvengine.begin_read_only()?.version(), version + 2);
+
+        // 历史版本事务只能看到指定版本开始前已经提交的数据，隐含只读
+        let txn = kvengine.begin_with(TransactionOptions {
+            read_only: false,
+            as_of: Some(version),
+            ..Default::default()
+        })?;
+        assert!(txn.read_only());
+        assert_eq!(txn.version(), version);
+        assert_eq!(
+            rows(&txn)?,
+            vec![vec![Value::Integer(1), Value::Integer(10)]]
+        );
+        assert_eq!(
+            txn.create_row(&table, vec![Value::Integer(3), Value::Integer(30)]),
+            Err(RSDBError::ReadOnlyTransaction)
+        );
+        txn.commit()?;
+        assert_eq!(
+            kvengine
+                .begin_with(TransactionOptions {
+                    read_only: true,
+                    as_of: Some(version + 3),
+                    ..Default::default()
+                })
+                .err(),
+            Some(RSDBError::Internal(format!(
+                "version {} does not exist, latest version is {}",
+                version + 3,
+                version + 2
+            )))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_conflict_retry() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("create table t (a int primary key, b int);")?;
+        s1.execute("insert into t values (1, 0);")?;
+
+        // 另一个事务持有未提交的写入，重试次数用完后返回写冲突
+        s1.execute("begin;")?;
+        s1.execute("update t set b = 100 where a = 1;")?;
+        s2.execute("set retries = 2;")?;
+        assert_eq!(
+            s2.execute("update t set b = 1 where a = 1;"),
+            Err(RSDBError::WriteConflict)
+        );
+        // 显式事务中不重试，直接返回写冲突
+        s2.execute("begin;")?;
+        assert_eq!(
+            s2.execute("update t set b = 1 where a = 1;"),
+            Err(RSDBError::WriteConflict)
+        );
+        s2.execute("rollback;")?;
+        s1.execute("rollback;")?;
+
+        // 两个 session 同时更新同一行，重试后都能成功
+        let n = 200;
+        let handles = (0..2)
+            .map(|_| {
+                let kvengine = kvengine.clone();
+                std::thread::spawn(move || -> RSDBResult<()> {
+                    let mut s = kvengine.session()?;
+                    s.execute("set retries = 1000;")?;
+                    for _ in 0..n {
+                        s.execute("update t set b = b + 1 where a = 1;")?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+        match s1.execute("select b from t;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(2 * n)]]),
+            _ => unreachable!(),
+        }
+
+        assert_eq!(
+            s1.execute("set retries = -1;"),
+            Err(RSDBError::Internal(
+                "invalid value -1 for setting retries".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc_deleted_rows() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index);")?;
+        let values = (0..100)
+            .map(|i| format!("({}, 'v{}')", i, i % 10))
+            .collect::<Vec<_>>()
+            .join(", ");
+        s.execute(&format!("insert into t values {};", values))?;
+        s.execute("delete from t where a % 2 = 0;")?;
+        let count = |s: &mut Session<_>, sql: &str| -> RSDBResult<Value> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows[0][0].clone()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(
+            count(&mut s, "select count(a) from t;")?,
+            Value::Integer(50)
+        );
+
+        // 删除的行及其写入的版本都被清理，索引中删除的主键同样如此
+        let before = kvengine.kv.status()?;
+        kvengine.kv.gc()?;
+        let after = kvengine.kv.status()?;
+        assert!(
+            after.engine.keys + 100 <= before.engine.keys,
+            "{:?} -> {:?}",
+            before,
+            after
+        );
+        assert_eq!(after.total_keys, after.total_versions);
+        assert_eq!(
+            count(&mut s, "select count(a) from t;")?,
+            Value::Integer(50)
+        );
+        assert_eq!(
+            count(&mut s, "select count(a) from t where b = 'v1';")?,
+            Value::Integer(10)
+        );
+        assert_eq!(
+            count(&mut s, "select count(a) from t where b = 'v2';")?,
+            Value::Integer(0)
+        );
+
+        // 重新打开后数据不变，删除的主键可以再次插入
+        drop(s);
+        drop(kvengine);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        assert_eq!(kvengine.kv.status()?.engine.keys, after.engine.keys);
+        s.execute("insert into t values (0, 'v0');")?;
+        assert_eq!(
+            count(&mut s, "select a from t order by a limit 1;")?,
+            Value::Integer(0)
+        );
+        assert_eq!(
+            count(&mut s, "select count(a) from t;")?,
+            Value::Integer(51)
+        );
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        let rows = |s: &mut Session<_>| -> RSDBResult<Vec<Row>> {
+            match s.execute("select * from t;")? {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        s.execute("create table t (a int primary key, b text index, c int);")?;
+        let values = (0..200)
+            .map(|i| format!("({}, 'v{}', 0)", i, i % 10))
+            .collect::<Vec<_>>()
+            .join(", ");
+        s.execute(&format!("insert into t values {};", values))?;
+        for _ in 0..10 {
+            s.execute("update t set c = c + 1;")?;
+        }
+        s.execute("delete from t where a > 99;")?;
+        let expected = rows(&mut s)?;
+        assert_eq!(expected.len(), 100);
+
+        // 显式事务中不能执行
+        s.execute("begin;")?;
+        assert_eq!(
+            s.execute("vacuum;"),
+            Err(RSDBError::Internal(
+                "vacuum can not run inside a transaction".to_string()
+            ))
+        );
+        s.execute("rollback;")?;
+        let disk_size = std::fs::metadata(&p)?.len();
+
+        let result = s.execute("vacuum;")?;
+        let ResultSet::Vacuum {
+            versions,
+            disk_size_before,
+            disk_size_after,
+        } = result
+        else {
+            unreachable!()
+        };
+        // 每行有 11 个版本，保留的行只剩最新的一个，删除的行全部清理
+        assert!(versions >= 100 * 10 + 100 * 12, "{}", versions);
+        assert_eq!(disk_size_before, disk_size);
+        assert_eq!(disk_size_after, std::fs::metadata(&p)?.len());
+        assert!(disk_size_after * 10 < disk_size_before, "{:?}", result);
+        assert_eq!(rows(&mut s)?, expected);
+        assert_eq!(
+            match s.execute("select count(a) from t where b = 'v3';")? {
+                ResultSet::Scan { rows, .. } => rows[0][0].clone(),
+                _ => unreachable!(),
+            },
+            Value::Integer(10)
+        );
+
+        // 压缩后可以继续写入，重新打开后数据不变
+        s.execute("update t set c = 0 where a = 1;")?;
+        s.execute("insert into t values (100, 'v0', 0);")?;
+        let expected = rows(&mut s)?;
+        drop(s);
+        drop(kvengine);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        assert_eq!(rows(&mut s)?, expected);
+        assert!(s.execute("vacuum;")?.to_string().starts_with("VACUUM ("));
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_serializable() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("create table doctors (id int primary key, on_call bool);")?;
+        let reset = |s: &mut Session<_>| s.execute("update doctors set on_call = true;");
+        s1.execute("insert into doctors values (1, true), (2, true);")?;
+        let on_call = |s: &mut Session<_>| -> RSDBResult<Vec<Row>> {
+            match s.execute("select count(id) from doctors where on_call = true;")? {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+
+        // 两个医生都看到还有另一个人值班，各自请假
+        let skew = |s1: &mut Session<_>, s2: &mut Session<_>, begin: &str| -> RSDBResult<()> {
+            s1.execute(begin)?;
+            s2.execute(begin)?;
+            assert_eq!(on_call(s1)?, vec![vec![Value::Integer(2)]]);
+            assert_eq!(on_call(s2)?, vec![vec![Value::Integer(2)]]);
+            s1.execute("update doctors set on_call = false where id = 1;")?;
+            s2.execute("update doctors set on_call = false where id = 2;")?;
+            s1.execute("commit;")?;
+            Ok(())
+        };
+
+        // 默认的快照隔离下都能提交，没有人值班
+        skew(&mut s1, &mut s2, "begin;")?;
+        s2.execute("commit;")?;
+        assert_eq!(on_call(&mut s1)?, vec![vec![Value::Integer(0)]]);
+        reset(&mut s1)?;
+
+        skew(&mut s1, &mut s2, "begin isolation level snapshot;")?;
+        s2.execute("commit;")?;
+        assert_eq!(on_call(&mut s1)?, vec![vec![Value::Integer(0)]]);
+        reset(&mut s1)?;
+
+        // 可串行化时后提交的事务失败并回滚，session 不再处于事务中
+        skew(&mut s1, &mut s2, "begin isolation level serializable;")?;
+        assert_eq!(s2.execute("commit;"), Err(RSDBError::SerializationFailure));
+        match s2.execute("show transaction;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows[0][0], Value::Boolean(false)),
+            _ => unreachable!(),
+        }
+        assert_eq!(on_call(&mut s2)?, vec![vec![Value::Integer(1)]]);
+
+        // 重新执行时可以看到另一个事务的修改
+        s2.execute("begin isolation level serializable;")?;
+        assert_eq!(on_call(&mut s2)?, vec![vec![Value::Integer(1)]]);
+        s2.execute("commit;")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_temporary_tables() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        // 存储中所有数据库的表，包括其他 session 的临时表
+        let table_count = || -> RSDBResult<usize> {
+            let txn = kvengine.begin()?;
+            let count = txn.txn.scan_prefix(LegacyKeyPrefix::Table.encode()?)?.len();
+            txn.commit()?;
+            Ok(count)
+        };
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+        s.execute("insert into t values (1, 'permanent');")?;
+
+        // 两个 session 同时创建同名的临时表，只能看到自己的数据
+        let handles = (0..2)
+            .map(|i| {
+                let kvengine = kvengine.clone();
+                std::thread::spawn(move || -> RSDBResult<()> {
+                    let mut s = kvengine.session()?;
+                    assert_eq!(
+                        s.execute("create temporary table tmp (a int primary key, b int);")?,
+                        ResultSet::CreateTable {
+                            table_name: "tmp".to_string()
+                        }
+                    );
+                    for j in 0..10 {
+                        s.execute(&format!("insert into tmp values ({}, {});", j, i))?;
+                    }
+                    let rows = query_rows(&mut s, "select min(b), max(b), count(a) from tmp;")?;
+                    assert_eq!(
+                        rows,
+                        vec![vec![
+                            Value::Integer(i),
+                            Value::Integer(i),
+                            Value::Integer(10)
+                        ]]
+                    );
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+        // session 结束后临时表被删除
+        assert_eq!(table_count()?, 1);
+
+        // 与普通表同名时，当前 session 中使用临时表，其他 session 不受影响
+        let mut s2 = kvengine.session()?;
+        s2.execute("create temp table t (a int primary key, c int);")?;
+        s2.execute("insert into t values (1, 100), (2, 200);")?;
+        assert_eq!(
+            query_rows(&mut s2, "select t.c from t where t.a = 2;")?,
+            vec![vec![Value::Integer(200)]]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select * from t;")?,
+            vec![vec![
+                Value::Integer(1),
+                Value::String("permanent".to_string())
+            ]]
+        );
+        assert_eq!(s2.get_table_names()?, vec!["t".to_string()]);
+        assert!(matches!(
+            s.execute("select * from tmp;"),
+            Err(RSDBError::TableNotFound(_))
+        ));
+        assert_eq!(
+            s2.execute("create temp table t (a int primary key);"),
+            Err(RSDBError::Internal("table t already exists".to_string()))
+        );
+
+        // 临时表可以在事务中读写，但不能在事务中创建和删除
+        s2.execute("begin;")?;
+        s2.execute("update t set c = 300 where a = 2;")?;
+        assert_eq!(
+            s2.execute("create temp table t2 (a int primary key);"),
+            Err(RSDBError::Internal(
+                "temporary tables can not be created inside a transaction".to_string()
+            ))
+        );
+        assert_eq!(
+            s2.execute("drop table t;"),
+            Err(RSDBError::Internal(
+                "temporary tables can not be dropped inside a transaction".to_string()
+            ))
+        );
+        s2.execute("commit;")?;
+        assert_eq!(
+            query_rows(&mut s2, "select c from t where a = 2;")?,
+            vec![vec![Value::Integer(300)]]
+        );
+
+        // 删除临时表后，同名的普通表重新可见
+        s2.execute("drop table t;")?;
+        assert_eq!(query_rows(&mut s2, "select * from t;")?.len(), 1);
+        assert_eq!(table_count()?, 1);
+
+        // session 断开时回滚未提交的事务并删除临时表
+        s2.execute("create temp table tmp (a int primary key);")?;
+        s2.execute("begin;")?;
+        s2.execute("insert into t values (2, 'uncommitted');")?;
+        assert_eq!(table_count()?, 2);
+        drop(s2);
+        assert_eq!(table_count()?, 1);
+        assert_eq!(query_rows(&mut s, "select * from t;")?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequences() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        assert_eq!(
+            s.execute("create sequence seq;")?,
+            ResultSet::CreateSequence {
+                name: "seq".to_string()
+            }
+        );
+        assert!(s.execute("create sequence seq;").is_err());
+        s.execute("create table t (a int primary key, b int);")?;
+
+        // 调用 nextval 之前 currval 没有值
+        assert!(
+            s.execute("insert into t values (0, currval('seq'));")
+                .is_err()
+        );
+        // 多行插入时每一行分别取值
+        s.execute("insert into t values (nextval('seq'), 1), (nextval('seq'), 2);")?;
+        s.execute("insert into t values (nextval('seq'), 3);")?;
+        assert_eq!(
+            query_rows(&mut s, "select a, b from t;")?,
+            vec![
+                vec![Value::Integer(1), Value::Integer(1)],
+                vec![Value::Integer(2), Value::Integer(2)],
+                vec![Value::Integer(3), Value::Integer(3)],
+            ]
+        );
+
+        // 回滚后已经分配的值不会被重用
+        s.execute("begin;")?;
+        s.execute("insert into t values (nextval('seq'), 0);")?;
+        s.execute("rollback;")?;
+        s.execute("insert into t values (nextval('seq'), 0);")?;
+        assert_eq!(
+            query_rows(&mut s, "select max(a) from t;")?,
+            vec![vec![Value::Integer(5)]]
+        );
+
+        // 并发的事务取到不同的值，并且不会产生写冲突
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("begin;")?;
+        s2.execute("begin;")?;
+        s1.execute("insert into t values (nextval('seq'), 10);")?;
+        s2.execute("insert into t values (nextval('seq'), 20);")?;
+        s1.execute("insert into t values (nextval('seq'), 10);")?;
+        s1.execute("commit;")?;
+        s2.execute("commit;")?;
+        assert_eq!(
+            query_rows(&mut s, "select a from t where b = 20;")?,
+            vec![vec![Value::Integer(7)]]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select count(a), max(a) from t where b = 10;")?,
+            vec![vec![Value::Integer(2), Value::Integer(8)]]
+        );
+
+        // currval 返回当前 session 最近一次分配的值，其他 session 之后分配的值不会影响它
+        s2.execute("insert into t values (nextval('seq'), 20);")?;
+        s1.execute("insert into t values (101, currval('seq'));")?;
+        s2.execute("insert into t values (102, currval('seq'));")?;
+        assert_eq!(
+            query_rows(&mut s, "select a, b from t where a > 100;")?,
+            vec![
+                vec![Value::Integer(101), Value::Integer(8)],
+                vec![Value::Integer(102), Value::Integer(9)],
+            ]
+        );
+        // 没有调用过 nextval 的 session 中 currval 没有值
+        assert!(
+            kvengine
+                .session()?
+                .execute("insert into t values (103, currval('seq'));")
+                .is_err()
+        );
+
+        // 查询和 UPDATE 中按照行的顺序依次分配
+        s.execute("create table t3 (a int primary key, b int);")?;
+        s.execute("insert into t3 values (1, 0), (2, 0), (3, 0);")?;
+        assert_eq!(
+            query_rows(&mut s, "select a, nextval('seq') + 100 from t3;")?,
+            vec![
+                vec![Value::Integer(1), Value::Integer(110)],
+                vec![Value::Integer(2), Value::Integer(111)],
+                vec![Value::Integer(3), Value::Integer(112)],
+            ]
+        );
+        s.execute("update t3 set b = nextval('seq');")?;
+        assert_eq!(
+            query_rows(&mut s, "select b, currval('seq') from t3;")?,
+            vec![
+                vec![Value::Integer(13), Value::Integer(15)],
+                vec![Value::Integer(14), Value::Integer(15)],
+                vec![Value::Integer(15), Value::Integer(15)],
+            ]
+        );
+        assert!(
+            s.execute("select a from t3 where a = nextval('seq');")
+                .is_err()
+        );
+
+        // 默认值为 nextval 时，没有给出值的行每次分配下一个值
+        s.execute("create table t4 (a int primary key default nextval('seq'), b int);")?;
+        s.execute("insert into t4 (b) values (1), (2);")?;
+        s.execute("insert into t4 values (100, 3);")?;
+        assert_eq!(
+            query_rows(&mut s, "select a, b from t4;")?,
+            vec![
+                vec![Value::Integer(16), Value::Integer(1)],
+                vec![Value::Integer(17), Value::Integer(2)],
+                vec![Value::Integer(100), Value::Integer(3)],
+            ]
+        );
+        assert!(
+            s.get_table("t4".to_string())?
+                .to_string()
+                .contains("a Integer PRIMARY KEY DEFAULT nextval('seq')")
+        );
+        assert!(
+            s.execute("create table t5 (a int primary key default currval('seq'));")
+                .is_err()
+        );
+        assert!(
+            s.execute("create table t5 (a varchar primary key default nextval('seq'));")
+                .is_err()
+        );
+
+        // 删除后不能再使用，重新创建后从头开始
+        assert_eq!(
+            s.execute("drop sequence seq;")?,
+            ResultSet::DropSequence {
+                name: "seq".to_string()
+            }
+        );
+        assert!(
+            s.execute("insert into t values (nextval('seq'), 0);")
+                .is_err()
+        );
+        assert!(s.execute("drop sequence seq;").is_err());
+        // 清理旧版本后存储中不再有这个序列的任何 key，包括计数器
+        s.execute("vacuum;")?;
+        let seq_key = Key::Sequence(DEFAULT_DATABASE.to_string(), "seq".to_string()).encode()?;
+        let keys = kvengine.kv.with_engine(|engine| {
+            let mut iter = engine.scan(..);
+            let mut keys = Vec::new();
+            while let Some((key, _)) = iter.next().transpose()? {
+                keys.push(MvccKey::decode(key)?);
+            }
+            Ok(keys)
+        })?;
+        assert!(!keys.iter().any(|key| match key {
+            MvccKey::Version(key, _) | MvccKey::TxnWrite(_, key) | MvccKey::Unversioned(key) =>
+                *key == seq_key,
+            _ => false,
+        }));
+        s.execute("drop sequence if exists seq;")?;
+        s.execute("create sequence seq;")?;
+        s.execute("create table t2 (a int primary key);")?;
+        s.execute("insert into t2 values (nextval('seq'));")?;
+        assert_eq!(
+            query_rows(&mut s, "select a from t2;")?,
+            vec![vec![Value::Integer(1)]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_database_sequences() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create sequence seq;")?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (nextval('seq'), 0);")?;
+
+        // 不同数据库中可以有同名的序列，各自独立计数
+        s.execute("create database d2;")?;
+        s.execute("use d2;")?;
+        s.execute("create sequence seq;")?;
+        s.execute("create table t (a int primary key default nextval('seq'), b int);")?;
+        s.execute("insert into t (b) values (1), (2);")?;
+        assert_eq!(
+            query_rows(
+                &mut s,
+                "select a, currval('seq'), currval('default.seq') from t;"
+            )?,
+            vec![
+                vec![Value::Integer(1), Value::Integer(2), Value::Integer(1)],
+                vec![Value::Integer(2), Value::Integer(2), Value::Integer(1)],
+            ]
+        );
+
+        // 序列名可以带有数据库名
+        s.execute("use default;")?;
+        s.execute("insert into t values (nextval('d2.seq'), 3);")?;
+        assert_eq!(
+            query_rows(
+                &mut s,
+                "select a, currval('seq'), currval('d2.seq') from t;"
+            )?,
+            vec![
+                vec![Value::Integer(1), Value::Integer(1), Value::Integer(3)],
+                vec![Value::Integer(3), Value::Integer(1), Value::Integer(3)],
+            ]
+        );
+
+        // 删除数据库时同时删除其中的序列，重新创建后从头开始
+        s.execute("drop database d2;")?;
+        assert!(
+            s.execute("insert into t values (nextval('d2.seq'), 0);")
+                .is_err()
+        );
+        s.execute("create database d2;")?;
+        s.execute("use d2;")?;
+        s.execute("create sequence seq;")?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (nextval('seq'), nextval('default.seq'));")?;
+        assert_eq!(
+            query_rows(&mut s, "select a, b from t;")?,
+            vec![vec![Value::Integer(1), Value::Integer(2)]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_databases() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        assert_eq!(s.database(), DEFAULT_DATABASE);
+        assert_eq!(
+            s.execute("create database app;")?,
+            ResultSet::CreateDatabase {
+                name: "app".to_string()
+            }
+        );
+        assert!(s.execute("create database app;").is_err());
+        assert!(s.execute("use missing;").is_err());
+
+        // 两个数据库中的同名表互不影响
+        s.execute("create table users (id int primary key, name text index);")?;
+        s.execute("insert into users values (1, 'default');")?;
+        assert_eq!(
+            s.execute("use app;")?,
+            ResultSet::UseDatabase {
+                name: "app".to_string()
+            }
+        );
+        assert_eq!(s.database(), "app");
+        assert!(s.get_table_names()?.is_empty());
+        s.execute("create table users (id int primary key, name text index, age int);")?;
+        s.execute("insert into users values (1, 'app', 20), (2, 'app', 30);")?;
+        assert_eq!(s.get_table_names()?, vec!["users".to_string()]);
+        assert_eq!(s.get_table("users".to_string())?.columns.len(), 3);
+        assert_eq!(
+            query_rows(&mut s, "select count(id) from users where name = 'app';")?,
+            vec![vec![Value::Integer(2)]]
+        );
+
+        // 其他 session 仍然在默认数据库中
+        let mut s2 = kvengine.session()?;
+        assert_eq!(
+            query_rows(&mut s2, "select * from users;")?,
+            vec![vec![
+                Value::Integer(1),
+                Value::String("default".to_string())
+            ]]
+        );
+        // 带有数据库名的表，没有别名时可以用表名限定列名
+        assert_eq!(
+            query_rows(
+                &mut s2,
+                "select users.age from app.users where users.id = 2;"
+            )?,
+            vec![vec![Value::Integer(30)]]
+        );
+        assert_eq!(
+            query_rows(
+                &mut s2,
+                "select a.name, b.name from app.users a join default.users b on a.id = b.id;"
+            )?,
+            vec![vec![
+                Value::String("app".to_string()),
+                Value::String("default".to_string())
+            ]]
+        );
+
+        // 显式事务中切换数据库，之后的语句使用新的数据库
+        s.execute("begin;")?;
+        s.execute("use default;")?;
+        s.execute("insert into users values (2, 'txn');")?;
+        s.execute("commit;")?;
+        assert_eq!(
+            query_rows(&mut s2, "select name from users where id = 2;")?,
+            vec![vec![Value::String("txn".to_string())]]
+        );
+
+        // 不能删除当前数据库和默认数据库
+        s.execute("use app;")?;
+        assert!(s.execute("drop database app;").is_err());
+        assert!(s2.execute("drop database default;").is_err());
+
+        // 删除数据库时删除其中所有的表，以及表中的数据和索引
+        s.execute("analyze users;")?;
+        assert_eq!(
+            s2.execute("drop database app;")?,
+            ResultSet::DropDatabase {
+                name: "app".to_string()
+            }
+        );
+        let txn = kvengine.begin()?;
+        for prefix in [
+            KeyPrefix::Table("app".to_string()).encode()?,
+            KeyPrefix::Row("app".to_string(), "users".to_string()).encode()?,
+            KeyPrefix::Index("app".to_string(), "users".to_string()).encode()?,
+            Key::Stats("app".to_string(), "users".to_string()).encode()?,
+            Key::Database("app".to_string()).encode()?,
+        ] {
+            assert!(txn.txn.scan_prefix(prefix)?.is_empty());
+        }
+        txn.commit()?;
+        assert_eq!(query_rows(&mut s2, "select * from users;")?.len(), 2);
+        assert!(s2.execute("drop database app;").is_err());
+        s2.execute("drop database if exists app;")?;
+
+        // 当前数据库被其他 session 删除后，不能再创建表
+        assert!(s.execute("select * from users;").is_err());
+        assert!(s.execute("create table t (a int primary key);").is_err());
+        s.execute("use default;")?;
+        assert_eq!(query_rows(&mut s, "select * from users;")?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_legacy_keys() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index);")?;
+        s.execute("insert into t values (1, 'x'), (2, 'y');")?;
+        s.execute("analyze t;")?;
+        s.execute("create index idx_ab on t (a, b);")?;
+        s.execute("create database app;")?;
+
+        // 去掉默认数据库中的数据库名，得到旧版本的 key
+        let all_keys = || -> RSDBResult<Vec<(Vec<u8>, Vec<u8>)>> {
+            let txn = kvengine.begin()?;
+            let mut keys = Vec::new();
+            for prefix in 0u8..=5 {
+                for result in txn.txn.scan_prefix(vec![prefix])? {
+                    keys.push((result.key, result.value));
+                }
+            }
+            txn.commit()?;
+            Ok(keys)
+        };
+        let keys = all_keys()?;
+        let db = crate::storage::keycode::serialize_key(&DEFAULT_DATABASE)?;
+        let txn = kvengine.begin()?;
+        for (key, value) in &keys {
+            if key[0] <= 3 && key[1..].starts_with(&db) {
+                let legacy = [&key[..1], &key[1 + db.len()..]].concat();
+                txn.txn.delete(key.clone())?;
+                txn.txn.set(legacy, value.clone())?;
+            }
+        }
+        txn.commit()?;
+        assert_ne!(all_keys()?, keys);
+        assert!(s.execute("select * from t;").is_err());
+
+        // 迁移后与原来的数据一致，重复迁移不会修改数据
+        kvengine.migrate()?;
+        assert_eq!(all_keys()?, keys);
+        kvengine.migrate()?;
+        assert_eq!(all_keys()?, keys);
+        assert_eq!(
+            query_rows(&mut s, "select a from t where b = 'y';")?,
+            vec![vec![Value::Integer(2)]]
+        );
+
+        // 同时存在最初格式的表时一起迁移：字符串直接保存为原始字节，整数为大端字节，值以类型标记开头
+        let tu = LegacyTable {
+            name: "tu".to_string(),
+            columns: vec![
+                LegacyColumn {
+                    name: "a".to_string(),
+                    datatype: DataType::Integer,
+                    nullable: false,
+                    default: None,
+                    primary_key: true,
+                    index: false,
+                },
+                LegacyColumn {
+                    name: "b".to_string(),
+                    datatype: DataType::String,
+                    nullable: true,
+                    default: Some(Value::Null),
+                    primary_key: false,
+                    index: true,
+                },
+            ],
+        };
+        let txn = kvengine.begin()?;
+        txn.txn.set(b"\0tu".to_vec(), bincode::serialize(&tu)?)?;
+        for (a, b) in [(1i64, "x"), (2, "x")] {
+            let row = vec![Value::Integer(a), Value::String(b.to_string())];
+            let key = [&b"\x01tu\x02"[..], &a.to_be_bytes()].concat();
+            txn.txn.set(key, bincode::serialize(&row)?)?;
+        }
+        let pks = HashSet::from([Value::Integer(1), Value::Integer(2)]);
+        txn.txn
+            .set(b"\x02tub\x04x".to_vec(), bincode::serialize(&pks)?)?;
+        txn.commit()?;
+        kvengine.migrate()?;
+        let migrated = all_keys()?;
+        assert!(keys.iter().all(|key| migrated.contains(key)));
+        assert!(migrated.iter().all(|(key, _)| key[1..].contains(&0)));
+        kvengine.migrate()?;
+        assert_eq!(all_keys()?, migrated);
+        assert_eq!(
+            query_rows(&mut s, "select a from tu where b = 'x';")?,
+            vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select b from tu where a = 2;")?,
+            vec![vec![Value::String("x".to_string())]]
+        );
+        assert_eq!(query_rows(&mut s, "select * from t;")?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_raw_string_keys() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        // 按照最初版本的编码写入数据文件：字符串直接保存为原始字节，值以类型标记开头，浮点数为大端字节
+        {
+            let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+            let raw = |tag: u8, parts: &[&[u8]]| [vec![tag], parts.concat()].concat();
+            let column = |name: &str, datatype, primary_key: bool, index| LegacyColumn {
+                name: name.to_string(),
+                datatype,
+                nullable: !primary_key,
+                default: (!primary_key).then_some(Value::Null),
+                primary_key,
+                index,
+            };
+            let t = LegacyTable {
+                name: "t".to_string(),
+                columns: vec![
+                    column("a", DataType::Float, true, false),
+                    column("b", DataType::String, false, true),
+                ],
+            };
+            let tt = LegacyTable {
+                name: "tt".to_string(),
+                columns: vec![
+                    column("k", DataType::String, true, false),
+                    column("v", DataType::Integer, false, false),
+                ],
+            };
+            let txn = kvengine.begin()?;
+            txn.txn.set(raw(0, &[b"t"]), bincode::serialize(&t)?)?;
+            txn.txn.set(raw(0, &[b"tt"]), bincode::serialize(&tt)?)?;
+            for (a, b) in [(1.5f64, "x"), (2.25, "y"), (-0.5, "x")] {
+                let row = vec![Value::Float(a), Value::String(b.to_string())];
+                let key = raw(1, &[b"t", &[3], &a.to_be_bytes()]);
+                txn.txn.set(key, bincode::serialize(&row)?)?;
+            }
+            for (b, pks) in [("x", vec![1.5, -0.5]), ("y", vec![2.25])] {
+                let pks = pks.into_iter().map(Value::Float).collect::<HashSet<_>>();
+                let key = raw(2, &[b"t", b"b", &[4], b.as_bytes()]);
+                txn.txn.set(key, bincode::serialize(&pks)?)?;
+            }
+            for (k, v) in [("a", 1), ("bb", 2)] {
+                let row = vec![Value::String(k.to_string()), Value::Integer(v)];
+                let key = raw(1, &[b"tt", &[4], k.as_bytes()]);
+                txn.txn.set(key, bincode::serialize(&row)?)?;
+            }
+            txn.commit()?;
+        }
+
+        // 重新打开数据文件，迁移后可以正常查询，重复迁移不会修改数据
+        let kvengine = KVEngine::new(DiskEngine::new(p)?);
+        kvengine.migrate()?;
+        kvengine.migrate()?;
+        let mut s = kvengine.session()?;
+        assert_eq!(
+            query_rows(&mut s, "select a from t where b = 'x' order by a;")?,
+            vec![vec![Value::Float(-0.5)], vec![Value::Float(1.5)]]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select b from t where a = 2.25;")?,
+            vec![vec![Value::String("y".to_string())]]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select k, v from tt;")?,
+            vec![
+                vec![Value::String("a".to_string()), Value::Integer(1)],
+                vec![Value::String("bb".to_string()), Value::Integer(2)],
+            ]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select v from tt where k = 'bb';")?,
+            vec![vec![Value::Integer(2)]]
+        );
+        s.execute("insert into t values (3.5, 'y');")?;
+        assert_eq!(
+            query_rows(&mut s, "select a from t where b = 'y';")?.len(),
+            2
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_float_keys() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a float primary key, b float index, c int);")?;
+        s.execute("create index idx_cb on t (c, b);")?;
+        s.execute("insert into t values (1.5, -2.5, 1), (-0.5, 1.5, 2), (0.0, -2.5, 3);")?;
+        s.execute("create table u (a int primary key, b float);")?;
+        s.execute("insert into u values (1, 1.5);")?;
+
+        let all_keys = || -> RSDBResult<Vec<(Vec<u8>, Vec<u8>)>> {
+            let txn = kvengine.begin()?;
+            let keys = txn
+                .txn
+                .scan_prefix(vec![])?
+                .into_iter()
+                .map(|result| (result.key, result.value))
+                .collect();
+            txn.commit()?;
+            Ok(keys)
+        };
+        // 旧版本中浮点数直接保存为大端字节，将当前编码中的浮点数替换为旧的编码
+        let legacy_key = |key: &[u8]| -> RSDBResult<Vec<u8>> {
+            let mut key = key.to_vec();
+            for v in [1.5, -0.5, 0.0, -2.5] {
+                let encoded = crate::storage::keycode::serialize_key(&v)?;
+                while let Some(pos) = key.windows(8).position(|w| w == encoded.as_slice()) {
+                    key[pos..pos + 8].copy_from_slice(&(v as f64).to_be_bytes());
+                }
+            }
+            Ok(key)
+        };
+        let keys = all_keys()?;
+        let txn = kvengine.begin()?;
+        for (key, value) in &keys {
+            let legacy = legacy_key(key)?;
+            if legacy != *key {
+                txn.txn.delete(key.clone())?;
+                txn.txn.set(legacy, value.clone())?;
+            }
+        }
+        txn.commit()?;
+        assert_ne!(all_keys()?, keys);
+        assert_eq!(
+            query_rows(&mut s, "select c from t where a = 1.5;")?.len(),
+            0
+        );
+
+        // 迁移后的 key 与原来一致，另外记录了完成的迁移，索引中的主键集合序列化后的顺序可能不同
+        // 重复迁移不会修改数据
+        kvengine.migrate()?;
+        let migrated = all_keys()?;
+        assert_eq!(migrated.len(), keys.len() + 1);
+        assert!(
+            keys.iter()
+                .all(|(key, _)| migrated.iter().any(|(k, _)| k == key))
+        );
+        kvengine.migrate()?;
+        assert_eq!(all_keys()?, migrated);
+        assert_eq!(
+            query_rows(&mut s, "select c from t where a = 1.5;")?,
+            vec![vec![Value::Integer(1)]]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select c from t where b = -2.5 order by c;")?,
+            vec![vec![Value::Integer(1)], vec![Value::Integer(3)]]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select a from t where c = 2 and b = 1.5;")?,
+            vec![vec![Value::Float(-0.5)]]
+        );
+
+        // 当前编码写入的数据不需要迁移
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a float primary key, b float index);")?;
+        s.execute("insert into t values (1.5, -2.5), (-0.5, 1.5);")?;
+        kvengine.migrate()?;
+        assert_eq!(
+            query_rows(&mut s, "select a from t where b = -2.5;")?,
+            vec![vec![Value::Float(1.5)]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_primary_key_while_scanning() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        let values = (1..=200)
+            .map(|i| format!("({}, {})", i, i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        s.execute(&format!("insert into t values {};", values))?;
+        // 扫描是分批读取的，更新后的主键在扫描范围之后，不能被再次更新
+        assert_eq!(
+            s.execute("update t set a = a + 1000;")?,
+            ResultSet::Update { count: 200 }
+        );
+        match s.execute("select min(a), max(a), count(a) from t;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![vec![
+                    Value::Integer(1001),
+                    Value::Integer(1200),
+                    Value::Integer(200)
+                ]]
+            ),
+            r => panic!("unexpected result {:?}", r),
+        }
+        // 不更新主键时逐行更新
+        assert_eq!(
+            s.execute("update t set b = b + 1 where a > 1100;")?,
+            ResultSet::Update { count: 100 }
+        );
+        Ok(())
+    }
 }
@@ -1,4 +1,15 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use cache::{CacheStats, StatementCache};
+use prepared::{PreparedStatement, PreparedStatements};
+use settings::{SETTING_NAMES, SessionSettings};
 
 use crate::{
     error::{RSDBError, RSDBResult},
@@ -9,23 +20,68 @@ use crate::{
             ast::{self, Expression},
         },
         plan::Plan,
-        schema::Table,
-        types::{Row, Value},
+        schema::{Index, Table, TableSize, TableStats},
+        types::{DataType, Row, Rows, Value},
     },
+    storage::mvcc::{MvccStatus, TransactionOptions, VacuumStats},
 };
 
+pub mod cache;
 pub mod kv;
+pub mod prepared;
+pub mod settings;
+
+// 每个 session 缓存的语句个数
+const STATEMENT_CACHE_SIZE: usize = 128;
+
+// 临时表实际的表名前缀，完整的表名为 #tmp<进程号>_<session 编号>.<表名>
+// 用户的表名中不能包含 # 和 .，因此不会与普通表重名
+pub const TEMP_TABLE_PREFIX: &str = "#tmp";
+
+// 默认数据库，没有执行过 USE 的 session 以及旧版本的数据都属于这个数据库
+pub const DEFAULT_DATABASE: &str = "default";
+
+// 下一个 session 的编号，用于区分不同 session 的临时表
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+// session 中每个序列最近一次 nextval 分配的值，以带有数据库名的序列名作为 key
+// session 开启的事务共享，currval 读取这里的值
+pub type SequenceValues = Arc<Mutex<HashMap<String, i64>>>;
 
 // 抽象的 SQL 引擎层定义，目前只有一个 KVEngine
 pub trait Engine: Clone {
     type Transaction: Transaction;
 
-    fn begin(&self) -> RSDBResult<Self::Transaction>;
+    fn begin(&self) -> RSDBResult<Self::Transaction> {
+        self.begin_with(TransactionOptions::default())
+    }
+
+    // 根据选项开启事务，如只读事务、历史版本事务
+    fn begin_with(&self, opts: TransactionOptions) -> RSDBResult<Self::Transaction>;
+
+    // 清理所有快照都不再需要的旧版本，并压缩存储引擎
+    fn vacuum(&self) -> RSDBResult<VacuumStats>;
+
+    // 开启只读事务，读取最新的快照
+    fn begin_read_only(&self) -> RSDBResult<Self::Transaction> {
+        self.begin_with(TransactionOptions {
+            read_only: true,
+            as_of: None,
+            ..Default::default()
+        })
+    }
 
     fn session(&self) -> RSDBResult<Session<Self>> {
         Ok(Session {
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::SeqCst),
             engin: self.clone(),
             txn: None,
+            cache: StatementCache::new(STATEMENT_CACHE_SIZE),
+            settings: SessionSettings::default(),
+            prepared: PreparedStatements::default(),
+            temp_tables: HashMap::new(),
+            database: DEFAULT_DATABASE.to_string(),
+            sequences: SequenceValues::default(),
         })
     }
 }
@@ -40,28 +96,62 @@ pub trait Transaction {
     // 版本号
     fn version(&self) -> u64;
 
+    // 是否是只读事务
+    fn read_only(&self) -> bool;
+
+    // 事务中写入的 key 的个数，包括索引
+    fn write_count(&self) -> RSDBResult<usize>;
+
+    // MVCC 和存储引擎的统计信息，与事务的快照无关
+    fn status(&self) -> RSDBResult<MvccStatus>;
+
     // 创建行
     fn create_row(&self, table: &Table, row: Row) -> RSDBResult<()>;
+    // 批量创建行，出错时返回的错误带有行在 rows 中从 1 开始的序号
+    fn create_rows(&self, table: &Table, rows: Vec<Row>) -> RSDBResult<()> {
+        for (i, row) in rows.into_iter().enumerate() {
+            let pk = table.get_primary_key(&row)?;
+            self.create_row(table, row)
+                .map_err(|e| e.at_insert_row(i + 1, Some(pk.to_string())))?;
+        }
+        Ok(())
+    }
     // 更新行
     fn update_row(&self, table: &Table, old_pk: &Value, row: Row) -> RSDBResult<()>;
     // 删除行
     fn delete_row(&self, table: &Table, pk: &Value) -> RSDBResult<()>;
-    // 扫描表
-    fn scan_table(&self, table: &Table, filter: Option<Expression>) -> RSDBResult<Vec<Row>>;
+    // 扫描表，返回的行在拉取时才反序列化和过滤，迭代器不持有事务
+    // limit 为需要的行数上限，满足条件的行足够后不再继续读取
+    // reverse 为 true 时按主键从大到小的顺序返回，否则从小到大
+    fn scan_rows(
+        &self,
+        table: &Table,
+        filter: Option<Expression>,
+        limit: Option<usize>,
+        reverse: bool,
+    ) -> RSDBResult<Rows>;
+    // 扫描表，按主键的顺序返回所有满足条件的行
+    fn scan_table(&self, table: &Table, filter: Option<Expression>) -> RSDBResult<Vec<Row>> {
+        self.scan_rows(table, filter, None, false)?.collect()
+    }
+    // 统计表中的行数，用于估算执行代价
+    fn count_rows(&self, table: &Table) -> RSDBResult<usize>;
+    // 统计表的行数和占用的字节数，需要扫描表中所有的数据和索引
+    fn table_size(&self, table: &Table) -> RSDBResult<TableSize>;
 
-    // 获取索引
+    // 获取索引，单列索引的索引名即列名，多列索引可以只给出前缀列的值
     fn load_index(
         &self,
         table_name: &str,
-        col_name: &str,
-        col_value: &Value,
+        index_name: &str,
+        values: &[Value],
     ) -> RSDBResult<HashSet<Value>>;
     // 保存索引
     fn save_index(
         &self,
         table_name: &str,
-        col_name: &str,
-        col_value: &Value,
+        index_name: &str,
+        values: &[Value],
         index: HashSet<Value>,
     ) -> RSDBResult<()>;
     // 根据主键获取行
@@ -72,38 +162,213 @@ pub trait Transaction {
     fn create_table(&self, table: Table) -> RSDBResult<()>;
     // 删除表
     fn drop_table(&self, table_name: String) -> RSDBResult<()>;
-    // 获取所有的表名
+    // 创建索引，并为表中已有的数据建立索引
+    fn create_index(&self, table_name: &str, index: Index) -> RSDBResult<()>;
+    // 获取当前数据库中所有的表名
     fn get_table_names(&self) -> RSDBResult<Vec<String>>;
+    // 创建数据库
+    fn create_database(&self, name: &str) -> RSDBResult<()>;
+    // 删除数据库，以及其中所有的表和序列
+    fn drop_database(&self, name: &str) -> RSDBResult<()>;
+    // 数据库是否存在，默认数据库总是存在
+    fn database_exists(&self, name: &str) -> RSDBResult<bool>;
+    // 设置当前数据库，表名不带数据库名时属于当前数据库，默认为 DEFAULT_DATABASE
+    fn set_database(&self, name: &str);
+    // 设置所属 session 中序列最近一次分配的值，nextval 更新这些值，currval 读取这些值
+    fn set_sequence_values(&self, values: SequenceValues);
+    // 创建序列，序列的值从 1 开始
+    fn create_sequence(&self, name: &str) -> RSDBResult<()>;
+    // 删除序列
+    fn drop_sequence(&self, name: &str) -> RSDBResult<()>;
+    // 序列是否存在
+    fn sequence_exists(&self, name: &str) -> RSDBResult<bool>;
+    // 分配序列的下一个值，不受事务隔离的影响，回滚后分配的值也不会被重新使用
+    fn nextval(&self, name: &str) -> RSDBResult<i64>;
+    // 当前 session 中序列最近一次 nextval 分配的值，不受其他 session 的影响
+    fn currval(&self, name: &str) -> RSDBResult<i64>;
     // 获取表信息
     fn get_table(&self, table_name: String) -> RSDBResult<Option<Table>>;
+    // 保存表的统计信息
+    fn save_stats(&self, table_name: &str, stats: &TableStats) -> RSDBResult<()>;
+    // 获取表的统计信息，没有执行过 ANALYZE 时为 None
+    fn get_stats(&self, table_name: &str) -> RSDBResult<Option<TableStats>>;
     // 获取表信息，若不存在则报错
     fn must_get_table(&self, table_name: String) -> RSDBResult<Table> {
         self.get_table(table_name.clone())?
-            .ok_or(RSDBError::Internal(format!(
-                "table {} does not exist",
-                table_name
-            )))
+            .ok_or(RSDBError::TableNotFound(table_name))
     }
 }
 
 // 客户端 session 定义
 pub struct Session<E: Engine> {
+    id: u64,
     engin: E,
     txn: Option<E::Transaction>,
+    cache: StatementCache,
+    settings: SessionSettings,
+    prepared: PreparedStatements,
+    // 当前 session 的临时表，表名到实际表名的映射，同名时优先于普通表
+    temp_tables: HashMap<String, String>,
+    // 当前数据库，通过 USE 切换
+    database: String,
+    // 序列最近一次分配的值，事务回滚后也保留
+    sequences: SequenceValues,
+}
+
+// session 结束时回滚未提交的事务，并删除所有的临时表
+impl<E: Engine> Drop for Session<E> {
+    fn drop(&mut self) {
+        if let Some(txn) = self.txn.take() {
+            let _ = txn.rollback();
+        }
+        if self.temp_tables.is_empty() {
+            return;
+        }
+        if let Ok(txn) = self.engin.begin() {
+            let result = self
+                .temp_tables
+                .values()
+                .try_for_each(|name| txn.drop_table(name.clone()));
+            let _ = match result {
+                Ok(_) => txn.commit(),
+                Err(_) => txn.rollback(),
+            };
+        }
+    }
 }
 
 impl<E: Engine + 'static> Session<E> {
     // 执行客户端 SQL 语句
     pub fn execute(&mut self, sql: &str) -> RSDBResult<ResultSet> {
-        match Parser::new(sql).parse()? {
-            ast::Statement::Begin if self.txn.is_some() => {
+        self.execute_with_params(sql, &[])
+    }
+
+    // 执行带 ? 占位符的 SQL 语句，解析后将占位符替换为参数值，再构建执行计划
+    pub fn execute_with_params(&mut self, sql: &str, params: &[Value]) -> RSDBResult<ResultSet> {
+        let mut stmt = self.parse(sql)?;
+        stmt.bind(params)?;
+        self.execute_statement(stmt)
+    }
+
+    // 预编译 SQL 语句，只解析一次，之后通过 execute_prepared 多次执行
+    // 根据当前的表结构推断参数类型，执行时校验参数的个数和类型
+    pub fn prepare(&mut self, sql: &str) -> RSDBResult<PreparedStatement> {
+        let stmt = self.parse(sql)?;
+        match self.txn.as_ref() {
+            Some(txn) => self.prepared.prepare(sql, stmt, txn),
+            None => {
+                let txn = self.begin_with(TransactionOptions {
+                    read_only: true,
+                    as_of: None,
+                    ..Default::default()
+                })?;
+                let prepared = self.prepared.prepare(sql, stmt, &txn);
+                txn.commit()?;
+                prepared
+            }
+        }
+    }
+
+    // 执行预编译的语句，不需要再次解析
+    pub fn execute_prepared(
+        &mut self,
+        prepared: &PreparedStatement,
+        params: &[Value],
+    ) -> RSDBResult<ResultSet> {
+        let mut stmt = self.prepared.get(prepared)?;
+        prepared.check_params(params)?;
+        stmt.bind(params)?;
+        self.execute_statement(stmt)
+    }
+
+    // 释放预编译的语句，之后不能再执行
+    pub fn deallocate(&mut self, prepared: &PreparedStatement) -> RSDBResult<()> {
+        self.prepared.deallocate(prepared)
+    }
+
+    // 当前 session 中预编译的语句个数
+    pub fn prepared_count(&self) -> usize {
+        self.prepared.len()
+    }
+
+    // 解析 SQL 语句，优先使用缓存的结果
+    fn parse(&mut self, sql: &str) -> RSDBResult<ast::Statement> {
+        if let Some(stmt) = self.cache.get(sql) {
+            return Ok(stmt);
+        }
+        let stmt = Parser::new(sql).parse()?;
+        self.cache.insert(sql, stmt.clone());
+        Ok(stmt)
+    }
+
+    // 执行包含多条语句的脚本，返回每条语句的序号和执行结果
+    // - 不在显式事务中的语句各自在隐式事务中执行，脚本中的 BEGIN 开启的事务延续到之后的语句
+    // - stop_on_error 为 true 时，遇到第一个错误后不再执行之后的语句
+    // - 解析出错时无法确定下一条语句从哪里开始，总是停止执行
+    pub fn execute_script(
+        &mut self,
+        script: &str,
+        stop_on_error: bool,
+    ) -> Vec<(usize, RSDBResult<ResultSet>)> {
+        let mut parser = Parser::new(script);
+        let mut results = Vec::new();
+        loop {
+            let result = match parser.parse_next() {
+                Ok(Some(mut stmt)) => stmt.bind(&[]).and_then(|_| self.execute_statement(stmt)),
+                Ok(None) => break,
+                Err(err) => {
+                    results.push((results.len(), Err(err)));
+                    break;
+                }
+            };
+            let failed = result.is_err();
+            results.push((results.len(), result));
+            if failed && stop_on_error {
+                break;
+            }
+        }
+        results
+    }
+
+    fn execute_statement(&mut self, stmt: ast::Statement) -> RSDBResult<ResultSet> {
+        // DDL 会修改表结构，缓存中引用了该表的语句需要重新解析
+        if let ast::Statement::CreateTable { name, .. } | ast::Statement::DropTable { name, .. } =
+            &stmt
+        {
+            self.cache.invalidate(name);
+            self.prepared.invalidate(name);
+        }
+        let stmt = match stmt {
+            ast::Statement::CreateTable {
+                temporary: true, ..
+            } => return self.create_temp_table(stmt),
+            ast::Statement::DropTable { ref name, .. } if self.temp_tables.contains_key(name) => {
+                return self.drop_temp_table(stmt);
+            }
+            // 同名的临时表存在时，新建的仍然是普通表
+            ast::Statement::CreateTable { .. } => stmt,
+            mut stmt => {
+                stmt.rename_tables(&|name| self.temp_tables.get(name).cloned());
+                stmt
+            }
+        };
+        match stmt {
+            ast::Statement::Begin { .. } if self.txn.is_some() => {
                 Err(RSDBError::Internal("Already in transaction".to_string()))
             }
             ast::Statement::Commit | ast::Statement::Rollback if self.txn.is_none() => {
                 Err(RSDBError::Internal("Not in transaction".to_string()))
             }
-            ast::Statement::Begin => {
-                let txn = self.engin.begin()?;
+            ast::Statement::Begin {
+                read_only,
+                as_of,
+                serializable,
+            } => {
+                let txn = self.begin_with(TransactionOptions {
+                    read_only,
+                    as_of,
+                    serializable,
+                })?;
                 let version = txn.version();
                 self.txn = Some(txn);
                 Ok(ResultSet::Begin { version })
@@ -120,49 +385,318 @@ impl<E: Engine + 'static> Session<E> {
                 txn.rollback()?;
                 Ok(ResultSet::Rollback { version })
             }
-            stmt if self.txn.is_some() => {
-                Plan::build(stmt, self.txn.as_mut().unwrap())?.execute(self.txn.as_mut().unwrap())
+            // 切换当前数据库，显式事务中之后的语句也使用新的数据库
+            ast::Statement::UseDatabase { name } => {
+                let exists = match self.txn.as_ref() {
+                    Some(txn) => txn.database_exists(&name)?,
+                    None => {
+                        let txn = self.begin_with(TransactionOptions {
+                            read_only: true,
+                            as_of: None,
+                            ..Default::default()
+                        })?;
+                        let exists = txn.database_exists(&name);
+                        txn.commit()?;
+                        exists?
+                    }
+                };
+                if !exists {
+                    return Err(RSDBError::Internal(format!(
+                        "database {} does not exist",
+                        name
+                    )));
+                }
+                if let Some(txn) = self.txn.as_ref() {
+                    txn.set_database(&name);
+                }
+                self.database = name.clone();
+                Ok(ResultSet::UseDatabase { name })
             }
-            stmt => {
-                let mut txn = self.engin.begin()?;
-                // 构建 plan，执行 SQL 语句
-                match Plan::build(stmt, &mut txn)?.execute(&mut txn) {
-                    Ok(result) => {
+            ast::Statement::DropDatabase { ref name, .. } if *name == self.database => Err(
+                RSDBError::Internal(format!("can not drop the current database {}", name)),
+            ),
+            // session 级别的设置不需要事务
+            ast::Statement::Set { name, value } => {
+                self.settings.set(&name, &Value::from_expression(value))?;
+                Ok(ResultSet::Set {
+                    name: name.to_lowercase(),
+                })
+            }
+            // 当前是否在显式事务中，以及事务的版本号、是否只读和写入的 key 的个数
+            ast::Statement::ShowTransaction => {
+                let row = match &self.txn {
+                    Some(txn) => vec![
+                        Value::Boolean(true),
+                        Value::Integer(txn.version() as i64),
+                        Value::Boolean(txn.read_only()),
+                        Value::Integer(txn.write_count()? as i64),
+                    ],
+                    None => vec![
+                        Value::Boolean(false),
+                        Value::Null,
+                        Value::Null,
+                        Value::Integer(0),
+                    ],
+                };
+                Ok(ResultSet::Scan {
+                    columns: ["in_transaction", "version", "read_only", "writes"]
+                        .map(String::from)
+                        .to_vec(),
+                    types: vec![
+                        Some(DataType::Boolean),
+                        Some(DataType::Integer),
+                        Some(DataType::Boolean),
+                        Some(DataType::Integer),
+                    ],
+                    rows: vec![row],
+                })
+            }
+            // 与创建临时表一样，显式事务无法撤销清理，只能在事务之外执行
+            ast::Statement::Vacuum if self.txn.is_some() => Err(RSDBError::Internal(
+                "vacuum can not run inside a transaction".to_string(),
+            )),
+            ast::Statement::Vacuum => {
+                let stats = self.engin.vacuum()?;
+                Ok(ResultSet::Vacuum {
+                    versions: stats.versions,
+                    disk_size_before: stats.disk_size_before,
+                    disk_size_after: stats.disk_size_after,
+                })
+            }
+            // MVCC 和存储引擎的统计信息，不在事务中时使用只读事务获取
+            ast::Statement::ShowStatus => {
+                let status = match self.txn.as_ref() {
+                    Some(txn) => txn.status()?,
+                    None => {
+                        let txn = self.begin_with(TransactionOptions {
+                            read_only: true,
+                            ..Default::default()
+                        })?;
+                        let status = txn.status();
                         txn.commit()?;
-                        Ok(result)
+                        status?
+                    }
+                };
+                let rows = [
+                    ("active_txns", status.active_txns),
+                    ("next_version", status.next_version),
+                    ("total_keys", status.total_keys),
+                    ("total_versions", status.total_versions),
+                    ("engine_keys", status.engine.keys),
+                    ("engine_size", status.engine.size),
+                    ("engine_disk_size", status.engine.disk_size),
+                ]
+                .into_iter()
+                .map(|(name, value)| {
+                    vec![
+                        Value::String(name.to_string()),
+                        Value::Integer(value as i64),
+                    ]
+                })
+                .collect();
+                Ok(ResultSet::Scan {
+                    columns: vec!["name".to_string(), "value".to_string()],
+                    types: vec![Some(DataType::String), Some(DataType::Integer)],
+                    rows,
+                })
+            }
+            ast::Statement::ShowSettings { name } => {
+                let names = match &name {
+                    Some(name) => vec![name.as_str()],
+                    None => SETTING_NAMES.to_vec(),
+                };
+                let mut rows = Vec::new();
+                for name in names {
+                    rows.push(vec![
+                        Value::String(name.to_lowercase()),
+                        Value::String(self.settings.get(name)?),
+                    ]);
+                }
+                Ok(ResultSet::Scan {
+                    columns: vec!["name".to_string(), "value".to_string()],
+                    types: vec![Some(DataType::String); 2],
+                    rows,
+                })
+            }
+            // Explain 只构建执行计划，不执行，因此不会修改任何数据
+            // Explain Analyze 会实际执行，在单独的事务中执行并回滚，同样不会修改数据
+            ast::Statement::Explain { stmt, analyze } => {
+                let explain = |plan: Plan, txn: &mut E::Transaction| match analyze {
+                    true => plan.explain_analyze(txn),
+                    false => Ok(plan.to_string()),
+                };
+                let plan = match self.txn.as_mut() {
+                    // 显式事务中无法只回滚这一条语句
+                    Some(_) if analyze && !matches!(*stmt, ast::Statement::Select { .. }) => {
+                        return Err(RSDBError::Internal(
+                            "EXPLAIN ANALYZE only supports SELECT in a transaction".to_string(),
+                        ));
                     }
-                    Err(err) => {
+                    Some(txn) => explain(Plan::build(*stmt, txn)?, txn)?,
+                    None => {
+                        let mut txn = self.begin_with(TransactionOptions::default())?;
+                        let plan =
+                            Plan::build(*stmt, &mut txn).and_then(|plan| explain(plan, &mut txn));
                         txn.rollback()?;
-                        Err(err)
+                        plan?
+                    }
+                };
+                Ok(ResultSet::Explain {
+                    plan: plan.trim_end().to_string(),
+                })
+            }
+            // 显式事务的快照在开启时已经确定，历史版本需要使用 begin as of version n
+            ast::Statement::Select {
+                as_of: Some(version),
+                ..
+            } if self.txn.is_some() => Err(RSDBError::Internal(format!(
+                "cannot query version {} inside a transaction, use BEGIN AS OF VERSION {}",
+                version, version
+            ))),
+            stmt if self.txn.is_some() => Plan::build(stmt, self.txn.as_mut().unwrap())?
+                .execute_with_timeout(self.txn.as_mut().unwrap(), self.settings.timeout),
+            // 隐式事务遇到写冲突时，语句的修改已经全部回滚，可以重新执行，每次重试前稍微等待
+            // 显式事务中之前的语句可能依赖于冲突前读到的数据，直接返回错误
+            stmt => {
+                let mut retries = 0;
+                loop {
+                    match self.execute_implicit(stmt.clone()) {
+                        Err(RSDBError::WriteConflict) if retries < self.settings.retries => {
+                            retries += 1;
+                            std::thread::sleep(Duration::from_millis(retries as u64));
+                        }
+                        result => return result,
                     }
                 }
             }
         }
     }
 
-    pub fn get_table(&self, table_name: String) -> RSDBResult<String> {
-        let table = match self.txn.as_ref() {
-            Some(txn) => txn.must_get_table(table_name)?,
+    // 创建临时表，实际的表名中包含进程号和 session 编号，不会与其他 session 冲突
+    // 显式事务回滚后无法撤销对临时表的记录，因此临时表只能在事务之外创建和删除
+    fn create_temp_table(&mut self, mut stmt: ast::Statement) -> RSDBResult<ResultSet> {
+        let ast::Statement::CreateTable { name, .. } = &stmt else {
+            unreachable!()
+        };
+        let name = name.clone();
+        if self.txn.is_some() {
+            return Err(RSDBError::Internal(
+                "temporary tables can not be created inside a transaction".to_string(),
+            ));
+        }
+        if self.temp_tables.contains_key(&name) {
+            return Err(RSDBError::Internal(format!(
+                "table {} already exists",
+                name
+            )));
+        }
+        let real_name = format!(
+            "{}{}_{}.{}",
+            TEMP_TABLE_PREFIX,
+            std::process::id(),
+            self.id,
+            name
+        );
+        stmt.rename_tables(&|_| Some(real_name.clone()));
+        self.execute_implicit(stmt)?;
+        self.temp_tables.insert(name.clone(), real_name);
+        Ok(ResultSet::CreateTable { table_name: name })
+    }
+
+    fn drop_temp_table(&mut self, mut stmt: ast::Statement) -> RSDBResult<ResultSet> {
+        let ast::Statement::DropTable { name, .. } = &stmt else {
+            unreachable!()
+        };
+        let name = name.clone();
+        if self.txn.is_some() {
+            return Err(RSDBError::Internal(
+                "temporary tables can not be dropped inside a transaction".to_string(),
+            ));
+        }
+        stmt.rename_tables(&|name| self.temp_tables.get(name).cloned());
+        self.execute_implicit(stmt)?;
+        self.temp_tables.remove(&name);
+        Ok(ResultSet::DropTable { table_name: name })
+    }
+
+    // 开启事务，并设置为 session 的当前数据库
+    fn begin_with(&self, opts: TransactionOptions) -> RSDBResult<E::Transaction> {
+        let txn = self.engin.begin_with(opts)?;
+        txn.set_database(&self.database);
+        txn.set_sequence_values(self.sequences.clone());
+        Ok(txn)
+    }
+
+    // 在隐式事务中执行语句，成功后提交，失败或超时后回滚
+    fn execute_implicit(&mut self, stmt: ast::Statement) -> RSDBResult<ResultSet> {
+        // 自动提交的查询在只读事务中执行，不分配版本号，也不会与其他事务冲突
+        // 查询历史版本时，事务的快照为指定的版本；调用了 nextval 的查询需要写入序列的计数器
+        let mut txn = match stmt {
+            ast::Statement::Select { as_of, .. } if as_of.is_some() || !stmt.calls_nextval() => {
+                self.begin_with(TransactionOptions {
+                    read_only: true,
+                    as_of,
+                    ..Default::default()
+                })?
+            }
+            _ => self.begin_with(TransactionOptions::default())?,
+        };
+        match Plan::build(stmt, &mut txn)
+            .and_then(|plan| plan.execute_with_timeout(&mut txn, self.settings.timeout))
+        {
+            Ok(result) => {
+                txn.commit()?;
+                Ok(result)
+            }
+            Err(err) => {
+                txn.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    // 当前数据库
+    pub fn database(&self) -> &str {
+        &self.database
+    }
+
+    // 当前 session 的设置
+    pub fn settings(&self) -> &SessionSettings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut SessionSettings {
+        &mut self.settings
+    }
+
+    // 语句缓存的命中统计
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    // 获取表结构，由调用方决定如何展示，如 to_string() 得到建表语句
+    pub fn get_table(&self, table_name: String) -> RSDBResult<Table> {
+        match self.txn.as_ref() {
+            Some(txn) => txn.must_get_table(table_name),
             None => {
-                let txn = self.engin.begin()?;
+                let txn = self.begin_with(TransactionOptions::default())?;
                 let table = txn.must_get_table(table_name)?;
                 txn.commit()?;
-                table
+                Ok(table)
             }
-        };
-        Ok(table.to_string())
+        }
     }
 
-    pub fn get_table_names(&self) -> RSDBResult<String> {
-        let names = match self.txn.as_ref() {
-            Some(txn) => txn.get_table_names()?,
+    // 获取所有的表名
+    pub fn get_table_names(&self) -> RSDBResult<Vec<String>> {
+        match self.txn.as_ref() {
+            Some(txn) => txn.get_table_names(),
             None => {
-                let txn = self.engin.begin()?;
+                let txn = self.begin_with(TransactionOptions::default())?;
                 let names = txn.get_table_names()?;
                 txn.commit()?;
-                names
+                Ok(names)
             }
-        };
-        Ok(names.join("\n"))
+        }
     }
 }
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::{RSDBError, RSDBResult},
+    sql::{
+        engine::Transaction,
+        parser::ast::{Expression, Operation, Statement},
+        schema::Table,
+        types::{DataType, Value},
+    },
+};
+
+// 预编译的语句，由 Session::prepare 创建，只在创建它的 session 中有效
+// 解析后的语句保存在 session 中，这里只保留编号和参数信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedStatement {
+    id: u64,
+    sql: String,
+    // 每个占位符期望的参数类型，无法从表结构推断时为 None，可以是任意类型
+    param_types: Vec<Option<DataType>>,
+}
+
+impl PreparedStatement {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    pub fn param_types(&self) -> &[Option<DataType>] {
+        &self.param_types
+    }
+
+    // 校验参数的个数和类型，NULL 可以作为任意类型的参数
+    pub fn check_params(&self, params: &[Value]) -> RSDBResult<()> {
+        if params.len() != self.param_types.len() {
+            return Err(RSDBError::Internal(format!(
+                "expected {} parameters, got {}",
+                self.param_types.len(),
+                params.len()
+            )));
+        }
+        for (i, (value, expected)) in params.iter().zip(&self.param_types).enumerate() {
+            if let (Some(datatype), Some(expected)) = (value.datatype(), expected)
+                && !datatype.is_comparable(expected)
+            {
+                return Err(RSDBError::Internal(format!(
+                    "parameter {} expects {:?}, got {}",
+                    i + 1,
+                    expected,
+                    value
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Entry {
+    stmt: Statement,
+    tables: Vec<String>,
+    // 引用的表被删除或重建后语句失效，记录导致失效的表名
+    invalidated_by: Option<String>,
+}
+
+// session 中所有预编译的语句，事务提交或回滚后仍然有效，直到调用 deallocate
+// 目前执行计划依赖于事务，因此只缓存解析后的语句，每次执行时重新构建执行计划
+#[derive(Default)]
+pub struct PreparedStatements {
+    next_id: u64,
+    entries: HashMap<u64, Entry>,
+}
+
+impl PreparedStatements {
+    // 保存解析后的语句，并根据当前的表结构推断参数类型
+    pub fn prepare<T: Transaction>(
+        &mut self,
+        sql: &str,
+        stmt: Statement,
+        txn: &T,
+    ) -> RSDBResult<PreparedStatement> {
+        let param_types = param_types(&stmt, txn)?;
+        self.next_id += 1;
+        self.entries.insert(
+            self.next_id,
+            Entry {
+                tables: stmt.tables(),
+                stmt,
+                invalidated_by: None,
+            },
+        );
+        Ok(PreparedStatement {
+            id: self.next_id,
+            sql: sql.to_string(),
+            param_types,
+        })
+    }
+
+    // 获取预编译的语句，已经释放或失效时报错
+    pub fn get(&self, prepared: &PreparedStatement) -> RSDBResult<Statement> {
+        match self.entries.get(&prepared.id) {
+            Some(Entry {
+                invalidated_by: Some(table),
+                ..
+            }) => Err(RSDBError::Internal(format!(
+                "prepared statement {} is invalid because table {} has changed, prepare it again",
+                prepared.id, table
+            ))),
+            Some(entry) => Ok(entry.stmt.clone()),
+            None => Err(Self::not_found(prepared)),
+        }
+    }
+
+    pub fn deallocate(&mut self, prepared: &PreparedStatement) -> RSDBResult<()> {
+        self.entries
+            .remove(&prepared.id)
+            .map(|_| ())
+            .ok_or_else(|| Self::not_found(prepared))
+    }
+
+    // 表被删除或重建后，引用了该表的语句都失效
+    pub fn invalidate(&mut self, table_name: &str) {
+        for entry in self.entries.values_mut() {
+            if entry.invalidated_by.is_none() && entry.tables.iter().any(|t| t == table_name) {
+                entry.invalidated_by = Some(table_name.to_string());
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn not_found(prepared: &PreparedStatement) -> RSDBError {
+        RSDBError::Internal(format!("prepared statement {} does not exist", prepared.id))
+    }
+}
+
+// 推断每个占位符的类型，目前支持：
+// - INSERT 的值，类型为对应的列
+// - UPDATE 中 SET 的值，类型为对应的列
+// - WHERE 中列与占位符的比较，如 a = ?、? > b
+fn param_types<T: Transaction>(stmt: &Statement, txn: &T) -> RSDBResult<Vec<Option<DataType>>> {
+    let mut types = vec![None; stmt.param_count()];
+    // 表不存在时无法推断，执行时再报错
+    let mut tables = Vec::new();
+    for name in stmt.tables() {
+        if let Some(table) = txn.get_table(name)? {
+            tables.push(table);
+        }
+    }
+    let mut assign = |expr: &Expression, datatype: Option<&DataType>| {
+        if let (Expression::Placeholder(i), Some(datatype)) = (expr, datatype) {
+            types[*i] = Some(datatype.clone());
+        }
+    };
+    match stmt {
+        Statement::Insert {
+            columns, values, ..
+        } => {
+            if let Some(table) = tables.first() {
+                let names = match columns {
+                    Some(columns) => columns.clone(),
+                    None => table.columns.iter().map(|c| c.name.clone()).collect(),
+                };
+                for row in values {
+                    for (name, expr) in names.iter().zip(row) {
+                        assign(expr, column_type(&tables, name));
+                    }
+                }
+            }
+        }
+        Statement::Update {
+            columns,
+            where_clause,
+            ..
+        } => {
+            for (name, expr) in columns {
+                assign(expr, column_type(&tables, name));
+            }
+            if let Some(expr) = where_clause {
+                comparisons(expr, &tables, &mut assign);
+            }
+        }
+        Statement::Select { where_clause, .. } | Statement::Delete { where_clause, .. } => {
+            if let Some(expr) = where_clause {
+                comparisons(expr, &tables, &mut assign);
+            }
+        }
+        _ => {}
+    }
+    Ok(types)
+}
+
+// 找出 AND / OR 连接的比较条件中，与占位符比较的列
+fn comparisons(
+    expr: &Expression,
+    tables: &[Table],
+    assign: &mut dyn FnMut(&Expression, Option<&DataType>),
+) {
+    match expr {
+        Expression::Operation(Operation::And(l, r) | Operation::Or(l, r)) => {
+            comparisons(l, tables, assign);
+            comparisons(r, tables, assign);
+        }
+        Expression::Operation(
+            Operation::Equal(l, r) | Operation::GreaterThan(l, r) | Operation::LessThan(l, r),
+        ) => match (l.as_ref(), r.as_ref()) {
+            (Expression::Field(name), expr) | (expr, Expression::Field(name)) => {
+                assign(expr, column_type(tables, name))
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+// 列名可能带有表名或别名前缀，只有在唯一的表中找到时才能确定类型
+fn column_type<'a>(tables: &'a [Table], name: &str) -> Option<&'a DataType> {
+    let name = name.rsplit('.').next().unwrap_or(name);
+    let mut found = tables
+        .iter()
+        .filter_map(|t| t.columns.iter().find(|c| c.name == name));
+    match (found.next(), found.next()) {
+        (Some(col), None) => Some(&col.datatype),
+        _ => None,
+    }
+}
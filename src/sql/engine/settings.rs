@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use crate::{
+    error::{RSDBError, RSDBResult},
+    sql::{executor::FormatOptions, types::Value},
+};
+
+// 隐式事务遇到写冲突时默认的重试次数
+pub const DEFAULT_RETRIES: u32 = 3;
+
+// session 级别的设置，通过 SET 修改、SHOW 查看，只对当前 session 生效
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSettings {
+    // 查询结果的展示方式，output_format 对应其中的输出格式
+    pub format: FormatOptions,
+    // 单条语句的执行时间上限，None 表示不限制
+    pub timeout: Option<Duration>,
+    // 隐式事务遇到写冲突时的最大重试次数，0 表示不重试
+    pub retries: u32,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            format: FormatOptions::default(),
+            timeout: None,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+}
+
+// 所有可以设置的名称，SHOW ALL 按照这个顺序输出
+pub const SETTING_NAMES: &[&str] = &["output_format", "timeout", "retries"];
+
+impl SessionSettings {
+    // 修改设置，如 set output_format = 'json'、set timeout = 5000（毫秒，0 表示不限制）、set retries = 0
+    pub fn set(&mut self, name: &str, value: &Value) -> RSDBResult<()> {
+        let invalid =
+            || RSDBError::Internal(format!("invalid value {} for setting {}", value, name));
+        match name.to_lowercase().as_str() {
+            "output_format" => match value {
+                Value::String(s) => self.format.set("output", s).map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            },
+            "timeout" => {
+                self.timeout = match value {
+                    Value::Integer(0) => None,
+                    Value::Integer(ms) if *ms > 0 => Some(Duration::from_millis(*ms as u64)),
+                    _ => return Err(invalid()),
+                }
+            }
+            "retries" => {
+                self.retries = match value {
+                    Value::Integer(n) if *n >= 0 && *n <= u32::MAX as i64 => *n as u32,
+                    _ => return Err(invalid()),
+                }
+            }
+            _ => return Err(RSDBError::Internal(format!("unknown setting {}", name))),
+        }
+        Ok(())
+    }
+
+    // 设置当前的值，以文本的形式展示
+    pub fn get(&self, name: &str) -> RSDBResult<String> {
+        Ok(match name.to_lowercase().as_str() {
+            "output_format" => self.format.output.to_string(),
+            "timeout" => self.timeout.map_or(0, |t| t.as_millis()).to_string(),
+            "retries" => self.retries.to_string(),
+            _ => return Err(RSDBError::Internal(format!("unknown setting {}", name))),
+        })
+    }
+}
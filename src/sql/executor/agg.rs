@@ -4,23 +4,23 @@ use crate::{
     error::{RSDBError, RSDBResult},
     sql::{
         engine::Transaction,
-        executor::{Executor, ResultSet},
+        executor::{Executor, Output, rows_of},
         parser::ast::{self, Expression},
-        types::Value,
+        types::{self, DataType, Value},
     },
 };
 
 pub struct Aggregate<T: Transaction> {
     source: Box<dyn Executor<T>>,
     exprs: Vec<(Expression, Option<String>)>,
-    group_by: Option<Expression>,
+    group_by: Vec<Expression>,
 }
 
 impl<T: Transaction> Aggregate<T> {
     pub fn new(
         source: Box<dyn Executor<T>>,
         exprs: Vec<(Expression, Option<String>)>,
-        group_by: Option<Expression>,
+        group_by: Vec<Expression>,
     ) -> Box<Self> {
         Box::new(Self {
             source,
@@ -31,85 +31,119 @@ impl<T: Transaction> Aggregate<T> {
 }
 
 impl<T: Transaction> Executor<T> for Aggregate<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
-        if let ResultSet::Scan { columns, rows } = self.source.execute(txn)? {
-            let mut new_cols = Vec::new();
-            let mut new_rows = Vec::new();
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        if let Output::Rows {
+            columns,
+            types,
+            rows,
+        } = self.source.execute(txn)?
+        {
+            // 分组列在表中的位置
+            let group_pos = self
+                .group_by
+                .iter()
+                .map(|expr| match expr {
+                    ast::Expression::Field(group_col) => ast::column_position(&columns, group_col),
+                    _ => Err(RSDBError::Internal(format!(
+                        "unsupported expression in group by: {}",
+                        expr
+                    ))),
+                })
+                .collect::<RSDBResult<Vec<_>>>()?;
 
-            // 计算函数
-            let mut calc = |col_val: Option<&Value>,
-                            rows: &Vec<Vec<Value>>|
-             -> RSDBResult<Vec<Value>> {
-                let mut new_row = Vec::new();
-                for (expr, alias) in &self.exprs {
-                    match expr {
-                        ast::Expression::Function(func_name, col_name) => {
-                            let calculator = <dyn Calculator>::build(func_name)?;
-                            let val = calculator.calc(col_name, &columns, rows)?;
-
-                            if new_cols.len() < self.exprs.len() {
-                                new_cols.push(alias.clone().unwrap_or(format!(
-                                    "{}({})",
-                                    func_name.to_uppercase(),
-                                    col_name
-                                )));
-                            }
-                            new_row.push(val);
-                        }
-                        ast::Expression::Field(col_name) => {
-                            if let Some(ast::Expression::Field(group_col)) = &self.group_by {
-                                if col_name != group_col {
-                                    return Err(RSDBError::Internal(format!(
-                                        "{} must apppear in the GROUP BY clause or be used in an aggregate function",
-                                        col_name
-                                    )));
-                                }
-                            }
-                            if new_cols.len() < self.exprs.len() {
-                                new_cols.push(alias.clone().unwrap_or(col_name.clone()));
-                            }
-                            new_row.push(col_val.unwrap().clone());
-                        }
-                        _ => {
+            // 预先解析每个输出列：聚集函数记录参数列的位置，普通列记录其在分组列中的下标
+            let mut new_cols = Vec::new();
+            let mut new_types = Vec::new();
+            let mut outputs = Vec::new();
+            let mut funcs = Vec::new();
+            for (expr, alias) in &self.exprs {
+                match expr {
+                    ast::Expression::Function(func_name, col_name) => {
+                        // 提前检查函数名是否合法
+                        <dyn Accumulator>::init(func_name, col_name)?;
+                        let pos = ast::column_position(&columns, col_name)?;
+                        new_cols.push(alias.clone().unwrap_or(format!(
+                            "{}({})",
+                            func_name.to_uppercase(),
+                            col_name
+                        )));
+                        new_types.push(<dyn Accumulator>::datatype(func_name, types[pos].clone()));
+                        outputs.push(AggOutput::Func(funcs.len()));
+                        funcs.push((func_name, col_name, pos));
+                    }
+                    ast::Expression::Field(col_name) => {
+                        // 普通列必须是分组列之一
+                        let pos = ast::column_position(&columns, col_name)?;
+                        let Some(i) = group_pos.iter().position(|p| *p == pos) else {
                             return Err(RSDBError::Internal(format!(
-                                "unsupported expression in aggregate: {:?}",
-                                expr
+                                "{} must appear in the GROUP BY clause or be used in an aggregate function",
+                                col_name
                             )));
-                        }
+                        };
+                        new_cols.push(alias.clone().unwrap_or(col_name.clone()));
+                        new_types.push(types[pos].clone());
+                        outputs.push(AggOutput::Group(i));
+                    }
+                    _ => {
+                        return Err(RSDBError::Internal(format!(
+                            "unsupported expression in aggregate: {:?}",
+                            expr
+                        )));
                     }
                 }
-                Ok(new_row)
+            }
+            let init = || -> RSDBResult<Accumulators> {
+                funcs
+                    .iter()
+                    .map(|(func_name, col_name, _)| <dyn Accumulator>::init(func_name, col_name))
+                    .collect()
             };
 
-            if let Some(ast::Expression::Field(group_col)) = &self.group_by {
-                // 对数据进行分组，然后计算每组的统计
-                let pos = match columns.iter().position(|c| c == group_col) {
-                    Some(pos) => pos,
+            // 逐行累加，只为每个分组保存累加器的状态，不缓存输入的行
+            // 针对 Group By 列进行分组，NULL 与 NULL 分在同一组
+            // 分组按照第一次出现的顺序输出，相同的输入总是得到相同顺序的结果
+            let mut group_index: HashMap<Vec<Value>, usize> = HashMap::new();
+            let mut groups: Vec<(Vec<Value>, Accumulators)> = Vec::new();
+            if group_pos.is_empty() {
+                // 没有 Group By 时，即使没有输入也输出一行
+                groups.push((Vec::new(), init()?));
+            }
+            for row in rows {
+                let row = row?;
+                let key = group_pos
+                    .iter()
+                    .map(|&i| row[i].clone())
+                    .collect::<Vec<_>>();
+                let i = match group_index.get(&key) {
+                    Some(i) => *i,
+                    None if group_pos.is_empty() => 0,
                     None => {
-                        return Err(RSDBError::Internal(format!(
-                            "group by column {} not found",
-                            group_col
-                        )));
+                        group_index.insert(key.clone(), groups.len());
+                        groups.push((key, init()?));
+                        groups.len() - 1
                     }
                 };
-                // 针对 Group By 列进行分组
-                let mut agg_map = HashMap::new();
-                for row in rows.iter() {
-                    let key = &row[pos];
-                    let value = agg_map.entry(key).or_insert(Vec::new());
-                    value.push(row.clone());
+                for (acc, (_, _, pos)) in groups[i].1.iter_mut().zip(&funcs) {
+                    acc.update(&row[*pos])?;
                 }
-                for (key, rows) in agg_map {
-                    let row = calc(Some(key), &rows)?;
-                    new_rows.push(row);
-                }
-            } else {
-                let row = calc(None, &rows)?;
+            }
+
+            // 然后计算每组的统计
+            let mut new_rows = Vec::new();
+            for (key, accs) in groups {
+                let row = outputs
+                    .iter()
+                    .map(|output| match output {
+                        AggOutput::Func(i) => accs[*i].finish(),
+                        AggOutput::Group(i) => Ok(key[*i].clone()),
+                    })
+                    .collect::<RSDBResult<Vec<_>>>()?;
                 new_rows.push(row);
             }
-            return Ok(ResultSet::Scan {
+            return Ok(Output::Rows {
                 columns: new_cols,
-                rows: new_rows,
+                types: new_types,
+                rows: rows_of(new_rows),
             });
         }
         Err(RSDBError::Internal(
@@ -118,222 +152,470 @@ impl<T: Transaction> Executor<T> for Aggregate<T> {
     }
 }
 
-// 通用 Agg 计算定义
-pub trait Calculator {
-    fn calc(
-        &self,
-        col_name: &String,
-        cols: &Vec<String>,
-        rows: &Vec<Vec<Value>>,
-    ) -> RSDBResult<Value>;
+// 一个分组的所有累加器
+type Accumulators = Vec<Box<dyn Accumulator>>;
+
+// 聚集的输出列：聚集函数的结果或分组列的值
+enum AggOutput {
+    Func(usize),
+    Group(usize),
 }
 
-impl dyn Calculator {
-    pub fn build(func_name: &String) -> RSDBResult<Box<dyn Calculator>> {
-        Ok(match func_name.to_uppercase().as_ref() {
-            "COUNT" => Count::new(),
-            "MIN" => Min::new(),
-            "MAX" => Max::new(),
-            "SUM" => Sum::new(),
-            "AVG" => Avg::new(),
-            _ => {
-                return Err(RSDBError::Internal(format!(
-                    "unknown aggregate function {}",
-                    func_name
-                )));
-            }
-        })
+// 通用 Agg 累加器定义，init 创建初始状态，update 逐行累加，finish 得到最终结果
+pub trait Accumulator {
+    fn update(&mut self, value: &Value) -> RSDBResult<()>;
+    fn finish(&self) -> RSDBResult<Value>;
+}
+
+// 聚集函数注册表，函数名对应创建累加器的方法，参数为聚集的列名
+type AccumulatorInit = fn(&str) -> Box<dyn Accumulator>;
+// 由参数列的类型推断结果的类型，参数类型未知时返回 None
+type AccumulatorType = fn(Option<DataType>) -> Option<DataType>;
+
+const AGGREGATES: &[(&str, AccumulatorInit, AccumulatorType)] = &[
+    (
+        "COUNT",
+        |_| Box::new(Count::new()),
+        |_| Some(DataType::Integer),
+    ),
+    ("MIN", |_| Box::new(Min::new()), |t| t),
+    ("MAX", |_| Box::new(Max::new()), |t| t),
+    ("SUM", |col_name| Box::new(Sum::new(col_name)), |t| t),
+    (
+        "AVG",
+        |col_name| Box::new(Avg::new(col_name)),
+        |t| match t? {
+            DataType::Decimal(scale) => Some(DataType::Decimal(
+                (scale + 4).min(types::MAX_DECIMAL_DIGITS),
+            )),
+            _ => Some(DataType::Float),
+        },
+    ),
+    (
+        "BOOL_AND",
+        |col_name| Box::new(BoolAnd::new(col_name)),
+        |_| Some(DataType::Boolean),
+    ),
+    (
+        "BOOL_OR",
+        |col_name| Box::new(BoolOr::new(col_name)),
+        |_| Some(DataType::Boolean),
+    ),
+];
+
+impl dyn Accumulator {
+    pub fn init(func_name: &str, col_name: &str) -> RSDBResult<Box<dyn Accumulator>> {
+        match AGGREGATES
+            .iter()
+            .find(|(name, _, _)| name.eq_ignore_ascii_case(func_name))
+        {
+            Some((_, init, _)) => Ok(init(col_name)),
+            None => Err(RSDBError::Internal(format!(
+                "unknown aggregate function {}",
+                func_name
+            ))),
+        }
+    }
+
+    // 聚集结果的类型，如 COUNT 为 Integer，SUM 与参数列的类型一致
+    pub fn datatype(func_name: &str, input: Option<DataType>) -> Option<DataType> {
+        AGGREGATES
+            .iter()
+            .find(|(name, _, _)| name.eq_ignore_ascii_case(func_name))
+            .and_then(|(_, _, datatype)| datatype(input))
     }
 }
 
-pub struct Count;
+pub struct Count {
+    count: i64,
+}
 
 impl Count {
-    fn new() -> Box<Self> {
-        Box::new(Self {})
+    fn new() -> Self {
+        Self { count: 0 }
     }
 }
 
-impl Calculator for Count {
-    fn calc(
-        &self,
-        col_name: &String,
-        cols: &Vec<String>,
-        rows: &Vec<Vec<Value>>,
-    ) -> RSDBResult<Value> {
-        let pos = match cols.iter().position(|c| c == col_name) {
-            Some(pos) => pos,
-            None => {
-                return Err(RSDBError::Internal(format!(
-                    "column {} not found",
-                    col_name
-                )));
-            }
-        };
-        let mut count = 0;
-        for row in rows {
-            if row[pos] != Value::Null {
-                count += 1;
-            }
+impl Accumulator for Count {
+    fn update(&mut self, value: &Value) -> RSDBResult<()> {
+        if *value != Value::Null {
+            self.count += 1;
         }
-        Ok(Value::Integer(count))
+        Ok(())
+    }
+
+    fn finish(&self) -> RSDBResult<Value> {
+        Ok(Value::Integer(self.count))
     }
 }
 
-pub struct Min;
+pub struct Min {
+    min_val: Value,
+}
 
 impl Min {
-    fn new() -> Box<Self> {
-        Box::new(Self {})
+    fn new() -> Self {
+        Self {
+            min_val: Value::Null,
+        }
     }
 }
 
-impl Calculator for Min {
-    fn calc(
-        &self,
-        col_name: &String,
-        cols: &Vec<String>,
-        rows: &Vec<Vec<Value>>,
-    ) -> RSDBResult<Value> {
-        let pos = match cols.iter().position(|c| c == col_name) {
-            Some(pos) => pos,
-            None => {
-                return Err(RSDBError::Internal(format!(
-                    "column {} not found",
-                    col_name
-                )));
-            }
-        };
-        let mut min_val = Value::Null;
-        let mut values = Vec::new();
-        for row in rows.iter() {
-            if row[pos] != Value::Null {
-                values.push(&row[pos]);
-            }
-        }
-        if !values.is_empty() {
-            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            min_val = values[0].clone();
+impl Accumulator for Min {
+    fn update(&mut self, value: &Value) -> RSDBResult<()> {
+        // 相等时保留先出现的值
+        if *value != Value::Null
+            && (self.min_val == Value::Null || value.total_cmp(&self.min_val).is_lt())
+        {
+            self.min_val = value.clone();
         }
-        Ok(min_val)
+        Ok(())
+    }
+
+    fn finish(&self) -> RSDBResult<Value> {
+        Ok(self.min_val.clone())
     }
 }
 
-pub struct Max;
+pub struct Max {
+    max_val: Value,
+}
 
 impl Max {
-    fn new() -> Box<Self> {
-        Box::new(Self {})
+    fn new() -> Self {
+        Self {
+            max_val: Value::Null,
+        }
     }
 }
 
-impl Calculator for Max {
-    fn calc(
-        &self,
-        col_name: &String,
-        cols: &Vec<String>,
-        rows: &Vec<Vec<Value>>,
-    ) -> RSDBResult<Value> {
-        let pos = match cols.iter().position(|c| c == col_name) {
-            Some(pos) => pos,
-            None => {
-                return Err(RSDBError::Internal(format!(
-                    "column {} not found",
-                    col_name
-                )));
-            }
-        };
-        let mut max_val = Value::Null;
-        let mut values = Vec::new();
-        for row in rows.iter() {
-            if row[pos] != Value::Null {
-                values.push(&row[pos]);
-            }
+impl Accumulator for Max {
+    fn update(&mut self, value: &Value) -> RSDBResult<()> {
+        // 相等时保留后出现的值
+        if *value != Value::Null
+            && (self.max_val == Value::Null || value.total_cmp(&self.max_val).is_ge())
+        {
+            self.max_val = value.clone();
         }
-        if !values.is_empty() {
-            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            max_val = values[values.len() - 1].clone();
-        }
-        Ok(max_val)
+        Ok(())
+    }
+
+    fn finish(&self) -> RSDBResult<Value> {
+        Ok(self.max_val.clone())
     }
 }
 
-pub struct Sum;
+pub struct Sum {
+    col_name: String,
+    // 全部是整数时精确累加，遇到浮点数后改为浮点数累加
+    int_sum: Option<i128>,
+    sum: Option<f64>,
+    // 定点数单独精确累加，不转换为浮点数
+    decimal_sum: Option<(i128, u32)>,
+}
 
 impl Sum {
-    fn new() -> Box<Self> {
-        Box::new(Self {})
+    fn new(col_name: &str) -> Self {
+        Self {
+            col_name: col_name.to_string(),
+            int_sum: None,
+            sum: None,
+            decimal_sum: None,
+        }
     }
 }
 
-impl Calculator for Sum {
-    fn calc(
-        &self,
-        col_name: &String,
-        cols: &Vec<String>,
-        rows: &Vec<Vec<Value>>,
-    ) -> RSDBResult<Value> {
-        let pos = match cols.iter().position(|c| c == col_name) {
-            Some(pos) => pos,
-            None => {
+impl Accumulator for Sum {
+    fn update(&mut self, value: &Value) -> RSDBResult<()> {
+        match value {
+            Value::Null => {}
+            Value::Decimal(v, scale) => {
+                let (acc, acc_scale) = self.decimal_sum.unwrap_or((0, *scale));
+                let (acc, v, scale) = types::align_decimals((acc, acc_scale), (*v, *scale))?;
+                let acc = acc.checked_add(v).ok_or(RSDBError::Internal(format!(
+                    "sum of column {} out of range",
+                    self.col_name
+                )))?;
+                self.decimal_sum = Some((acc, scale));
+            }
+            Value::Integer(i) => match self.sum {
+                Some(sum) => self.sum = Some(sum + *i as f64),
+                None => self.int_sum = Some(self.int_sum.unwrap_or(0) + *i as i128),
+            },
+            Value::Float(f) => {
+                let sum = self.sum.or(self.int_sum.take().map(|s| s as f64));
+                self.sum = Some(sum.unwrap_or(0.0) + f);
+            }
+            _ => {
                 return Err(RSDBError::Internal(format!(
-                    "column {} not found",
-                    col_name
+                    "column {} is not numeric",
+                    self.col_name
                 )));
             }
-        };
-        let mut sum = None;
-        for row in rows.iter() {
-            match row[pos] {
-                Value::Null => continue,
-                Value::Integer(i) => {
-                    if sum == None {
-                        sum = Some(0.0);
-                    }
-                    sum = Some(sum.unwrap() + i as f64);
-                }
-                Value::Float(f) => {
-                    if sum == None {
-                        sum = Some(0.0);
-                    }
-                    sum = Some(sum.unwrap() + f);
-                }
-                _ => {
-                    return Err(RSDBError::Internal(format!(
-                        "column {} is not numeric",
-                        col_name
-                    )));
-                }
-            }
         }
-        if let Some(s) = sum {
-            Ok(Value::Float(s))
-        } else {
-            Ok(Value::Null)
+        Ok(())
+    }
+
+    fn finish(&self) -> RSDBResult<Value> {
+        match (self.int_sum, self.sum, self.decimal_sum) {
+            (Some(_), _, Some(_)) | (_, Some(_), Some(_)) => Err(RSDBError::Internal(format!(
+                "column {} mixes decimal and non-decimal values",
+                self.col_name
+            ))),
+            // 超出 i64 范围时返回浮点数
+            (Some(s), _, None) => {
+                Ok(i64::try_from(s).map_or(Value::Float(s as f64), Value::Integer))
+            }
+            (None, Some(s), None) => Ok(Value::Float(s)),
+            (None, None, Some((v, scale))) => Ok(Value::Decimal(v, scale)),
+            (None, None, None) => Ok(Value::Null),
         }
     }
 }
 
-pub struct Avg;
+pub struct Avg {
+    sum: Sum,
+    count: Count,
+}
 
 impl Avg {
-    fn new() -> Box<Self> {
-        Box::new(Self {})
+    fn new(col_name: &str) -> Self {
+        Self {
+            sum: Sum::new(col_name),
+            count: Count::new(),
+        }
     }
 }
 
-impl Calculator for Avg {
-    fn calc(
-        &self,
-        col_name: &String,
-        cols: &Vec<String>,
-        rows: &Vec<Vec<Value>>,
-    ) -> RSDBResult<Value> {
-        let sum = Sum::new().calc(col_name, cols, rows)?;
-        let count = Count::new().calc(col_name, cols, rows)?;
-        Ok(match (sum, count) {
+impl Accumulator for Avg {
+    fn update(&mut self, value: &Value) -> RSDBResult<()> {
+        self.sum.update(value)?;
+        self.count.update(value)
+    }
+
+    fn finish(&self) -> RSDBResult<Value> {
+        Ok(match (self.sum.finish()?, self.count.finish()?) {
+            // 平均值始终是浮点数
+            (Value::Integer(s), Value::Integer(c)) => Value::Float(s as f64 / c as f64),
             (Value::Float(s), Value::Integer(c)) => Value::Float(s / c as f64),
+            // 定点数的平均值增加 4 位小数，按照结果的精度四舍五入
+            (Value::Decimal(v, scale), Value::Integer(c)) => {
+                let avg_scale = (scale + 4).min(types::MAX_DECIMAL_DIGITS);
+                let v = types::rescale_decimal(v, scale, avg_scale + 1)? / c as i128;
+                Value::Decimal(
+                    types::rescale_decimal(v, avg_scale + 1, avg_scale)?,
+                    avg_scale,
+                )
+            }
             _ => Value::Null,
         })
     }
 }
+
+// 布尔聚集的参数必须是布尔值，NULL 返回 None
+fn bool_value(value: &Value, func_name: &str, col_name: &str) -> RSDBResult<Option<bool>> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Boolean(b) => Ok(Some(*b)),
+        v => Err(RSDBError::TypeMismatch(format!(
+            "{} requires a boolean column, column {} has value {}",
+            func_name, col_name, v
+        ))),
+    }
+}
+
+pub struct BoolAnd {
+    col_name: String,
+    result: Option<bool>,
+}
+
+impl BoolAnd {
+    fn new(col_name: &str) -> Self {
+        Self {
+            col_name: col_name.to_string(),
+            result: None,
+        }
+    }
+}
+
+impl Accumulator for BoolAnd {
+    fn update(&mut self, value: &Value) -> RSDBResult<()> {
+        if let Some(b) = bool_value(value, "BOOL_AND", &self.col_name)? {
+            self.result = Some(self.result.unwrap_or(true) && b);
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> RSDBResult<Value> {
+        Ok(self.result.map_or(Value::Null, Value::Boolean))
+    }
+}
+
+pub struct BoolOr {
+    col_name: String,
+    result: Option<bool>,
+}
+
+impl BoolOr {
+    fn new(col_name: &str) -> Self {
+        Self {
+            col_name: col_name.to_string(),
+            result: None,
+        }
+    }
+}
+
+impl Accumulator for BoolOr {
+    fn update(&mut self, value: &Value) -> RSDBResult<()> {
+        if let Some(b) = bool_value(value, "BOOL_OR", &self.col_name)? {
+            self.result = Some(self.result.unwrap_or(false) || b);
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> RSDBResult<Value> {
+        Ok(self.result.map_or(Value::Null, Value::Boolean))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::{Accumulator, Aggregate};
+    use crate::{
+        error::RSDBResult,
+        sql::{
+            engine::{Engine, Transaction, kv::KVEngine},
+            executor::{Executor, Output, ResultSet},
+            parser::ast::Expression,
+            types::{DataType, Value},
+        },
+        storage::memory::MemoryEngine,
+    };
+
+    // 按需生成 (i % groups, i) 的数据源，记录当前已经生成的行数
+    struct Generate {
+        count: i64,
+        groups: i64,
+        pulled: Rc<Cell<i64>>,
+    }
+
+    impl<T: Transaction> Executor<T> for Generate {
+        fn execute(self: Box<Self>, _txn: &mut T) -> RSDBResult<Output> {
+            let (groups, pulled) = (self.groups, self.pulled);
+            Ok(Output::Rows {
+                columns: vec!["g".to_string(), "v".to_string()],
+                types: vec![Some(DataType::Integer), Some(DataType::Integer)],
+                rows: Box::new((0..self.count).map(move |i| {
+                    pulled.set(pulled.get() + 1);
+                    Ok(vec![Value::Integer(i % groups), Value::Integer(i)])
+                })),
+            })
+        }
+    }
+
+    #[test]
+    fn test_aggregate_large_input() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+        let (count, groups) = (1_000_000, 4);
+        let pulled = Rc::new(Cell::new(0));
+        let func = |name: &str| (Expression::Function(name.into(), "v".into()), None);
+        let agg = Aggregate::new(
+            Box::new(Generate {
+                count,
+                groups,
+                pulled: pulled.clone(),
+            }),
+            vec![
+                (Expression::Field("g".into()), None),
+                func("count"),
+                func("min"),
+                func("max"),
+                func("sum"),
+                func("avg"),
+            ],
+            vec![Expression::Field("g".into())],
+        );
+
+        // 输入的行边读边累加，每个分组只保存一组累加器，输出的行数等于分组数
+        let Output::Rows {
+            columns,
+            types,
+            rows,
+        } = agg.execute(&mut txn)?
+        else {
+            unreachable!()
+        };
+        assert_eq!(pulled.get(), count);
+        assert_eq!(
+            columns,
+            vec!["g", "COUNT(v)", "MIN(v)", "MAX(v)", "SUM(v)", "AVG(v)"]
+        );
+        let int = Some(DataType::Integer);
+        assert_eq!(
+            types,
+            vec![
+                int.clone(),
+                int.clone(),
+                int.clone(),
+                int.clone(),
+                int,
+                Some(DataType::Float)
+            ]
+        );
+        let mut rows = rows.collect::<RSDBResult<Vec<_>>>()?;
+        rows.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        assert_eq!(rows.len(), groups as usize);
+        for (g, row) in rows.into_iter().enumerate() {
+            let g = g as i64;
+            let n = count / groups;
+            let sum = (0..n).map(|k| k * groups + g).sum::<i64>();
+            assert_eq!(
+                row,
+                vec![
+                    Value::Integer(g),
+                    Value::Integer(n),
+                    Value::Integer(g),
+                    Value::Integer(count - groups + g),
+                    Value::Integer(sum),
+                    Value::Float(sum as f64 / n as f64),
+                ]
+            );
+        }
+
+        // 整数和浮点数混合的列，遇到浮点数后按浮点数累加
+        let mut sum = <dyn Accumulator>::init("sum", "v")?;
+        let mut avg = <dyn Accumulator>::init("avg", "v")?;
+        for v in [
+            Value::Integer(3),
+            Value::Null,
+            Value::Float(0.5),
+            Value::Integer(2),
+        ] {
+            sum.update(&v)?;
+            avg.update(&v)?;
+        }
+        assert_eq!(sum.finish()?, Value::Float(5.5));
+        assert_eq!(avg.finish()?, Value::Float(5.5 / 3.0));
+
+        // 没有输入行也没有 Group By 时，输出一行
+        let agg = Aggregate::new(
+            Box::new(Generate {
+                count: 0,
+                groups,
+                pulled: Rc::new(Cell::new(0)),
+            }),
+            vec![func("count"), func("sum")],
+            vec![],
+        );
+        match agg.execute(&mut txn)?.into_result_set()? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(0), Value::Null]])
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
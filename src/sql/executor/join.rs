@@ -1,12 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     error::{RSDBError, RSDBResult},
     sql::{
         engine::Transaction,
-        executor::{Executor, ResultSet},
-        parser::ast::{self, Expression, evaluate_expr},
-        types::Value,
+        executor::{Executor, Output, rows_of},
+        parser::ast::{self, Expression, JoinType, evaluate_expr},
+        plan::JoinTables,
+        types::{DataType, Row, Value},
     },
 };
 
@@ -14,7 +15,10 @@ pub struct NestLoopJoin<T: Transaction> {
     left: Box<dyn Executor<T>>,
     right: Box<dyn Executor<T>>,
     predicate: Option<Expression>,
-    outer: bool,
+    join_type: JoinType,
+    using: Vec<String>,
+    swapped: bool,
+    tables: JoinTables,
 }
 
 impl<T: Transaction> NestLoopJoin<T> {
@@ -22,47 +26,75 @@ impl<T: Transaction> NestLoopJoin<T> {
         left: Box<dyn Executor<T>>,
         right: Box<dyn Executor<T>>,
         predicate: Option<Expression>,
-        outer: bool,
+        join_type: JoinType,
+        using: Vec<String>,
+        swapped: bool,
+        tables: JoinTables,
     ) -> Box<Self> {
         Box::new(Self {
             left,
             right,
             predicate,
-            outer,
+            join_type,
+            using,
+            swapped,
+            tables,
         })
     }
 }
 
 impl<T: Transaction> Executor<T> for NestLoopJoin<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         // 先执行左边的
-        if let ResultSet::Scan {
+        if let Output::Rows {
             columns: left_cols,
+            types: left_types,
             rows: left_rows,
         } = self.left.execute(txn)?
         {
             // 再执行右边的
-            if let ResultSet::Scan {
+            if let Output::Rows {
                 columns: right_cols,
+                types: right_types,
                 rows: right_rows,
             } = self.right.execute(txn)?
             {
+                // 两侧的行都需要拉取到内存中
+                let left_rows = left_rows.collect::<RSDBResult<Vec<_>>>()?;
+                let right_rows = right_rows.collect::<RSDBResult<Vec<_>>>()?;
                 let mut new_rows = Vec::new();
+                // 输出的列名以表名限定，如 t1.a
+                let (left_cols, right_cols) = qualify_columns(&self.tables, left_cols, right_cols);
                 let mut new_cols = left_cols.clone();
                 new_cols.extend(right_cols.clone());
-                for lrow in &left_rows {
+                let mut new_types = left_types;
+                new_types.extend(right_types);
+                let predicate = resolve_fields(self.predicate, &new_cols);
+                let pad = JoinPadding::new(left_cols.len(), right_cols.len());
+                // 交换后右表作为外层循环，保留行的一侧也随之交换
+                let (keep_left, keep_right) = self.join_type.keeps();
+                let (outer_rows, inner_rows, keep_outer, keep_inner) = match self.swapped {
+                    true => (&right_rows, &left_rows, keep_right, keep_left),
+                    false => (&left_rows, &right_rows, keep_left, keep_right),
+                };
+                // 记录内层表中被匹配过的行，用于外连接
+                let mut inner_matched = vec![false; inner_rows.len()];
+                for orow in outer_rows {
                     let mut matched = false;
-                    for rrow in &right_rows {
-                        let mut row = lrow.clone();
+                    for (i, irow) in inner_rows.iter().enumerate() {
+                        let (lrow, rrow) = match self.swapped {
+                            true => (irow, orow),
+                            false => (orow, irow),
+                        };
                         // 如果有条件，查看是否满足 Join 条件
-                        if let Some(expr) = &self.predicate {
+                        if let Some(expr) = &predicate {
                             match evaluate_expr(expr, &left_cols, lrow, &right_cols, rrow)? {
                                 Value::Null => {}
                                 Value::Boolean(false) => {}
                                 Value::Boolean(true) => {
-                                    row.extend(rrow.clone());
-                                    new_rows.push(row);
+                                    new_rows.push(pad.join(lrow, rrow));
                                     matched = true;
+                                    inner_matched[i] = true;
                                 }
                                 _ => {
                                     return Err(RSDBError::Internal(format!(
@@ -72,22 +104,28 @@ impl<T: Transaction> Executor<T> for NestLoopJoin<T> {
                                 }
                             }
                         } else {
-                            row.extend(rrow.clone());
-                            new_rows.push(row);
+                            new_rows.push(pad.join(lrow, rrow));
+                            matched = true;
+                            inner_matched[i] = true;
                         }
                     }
-                    if self.outer && !matched {
-                        let mut row = lrow.clone();
-                        for _ in 0..right_cols.len() {
-                            row.push(Value::Null);
-                        }
-                        new_rows.push(row);
+                    if keep_outer && !matched {
+                        new_rows.push(pad.fill(orow, self.swapped));
                     }
                 }
-                return Ok(ResultSet::Scan {
-                    columns: new_cols,
-                    rows: new_rows,
-                });
+                // 外连接还需要输出内层表中没有匹配的行，另一侧填充 NULL
+                if keep_inner {
+                    for (irow, _) in inner_rows.iter().zip(inner_matched).filter(|(_, m)| !m) {
+                        new_rows.push(pad.fill(irow, !self.swapped));
+                    }
+                }
+                return merge_using_columns(
+                    &self.using,
+                    left_cols.len(),
+                    new_cols,
+                    new_types,
+                    new_rows,
+                );
             }
         }
         Err(RSDBError::Internal(
@@ -100,7 +138,10 @@ pub struct HashJoin<T: Transaction> {
     left: Box<dyn Executor<T>>,
     right: Box<dyn Executor<T>>,
     predicate: Option<Expression>,
-    outer: bool,
+    join_type: JoinType,
+    using: Vec<String>,
+    swapped: bool,
+    tables: JoinTables,
 }
 
 impl<T: Transaction> HashJoin<T> {
@@ -108,36 +149,52 @@ impl<T: Transaction> HashJoin<T> {
         left: Box<dyn Executor<T>>,
         right: Box<dyn Executor<T>>,
         predicate: Option<Expression>,
-        outer: bool,
+        join_type: JoinType,
+        using: Vec<String>,
+        swapped: bool,
+        tables: JoinTables,
     ) -> Box<Self> {
         Box::new(Self {
             left,
             right,
             predicate,
-            outer,
+            join_type,
+            using,
+            swapped,
+            tables,
         })
     }
 }
 
 impl<T: Transaction> Executor<T> for HashJoin<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         // 先执行左边的
-        if let ResultSet::Scan {
+        if let Output::Rows {
             columns: left_cols,
+            types: left_types,
             rows: left_rows,
         } = self.left.execute(txn)?
         {
             // 再执行右边的
-            if let ResultSet::Scan {
+            if let Output::Rows {
                 columns: right_cols,
+                types: right_types,
                 rows: right_rows,
             } = self.right.execute(txn)?
             {
+                // 两侧的行都需要拉取到内存中
+                let left_rows = left_rows.collect::<RSDBResult<Vec<_>>>()?;
+                let right_rows = right_rows.collect::<RSDBResult<Vec<_>>>()?;
                 let mut new_rows = Vec::new();
+                // 输出的列名以表名限定，如 t1.a
+                let (left_cols, right_cols) = qualify_columns(&self.tables, left_cols, right_cols);
                 let mut new_cols = left_cols.clone();
                 new_cols.extend(right_cols.clone());
+                let mut new_types = left_types;
+                new_types.extend(right_types);
+                let predicate = resolve_fields(self.predicate, &new_cols);
                 // 解析 HashJoin 条件
-                let (left_field, right_field) = match parse_join_filter(self.predicate) {
+                let (left_field, right_field) = match parse_join_filter(predicate) {
                     Some(filter) => filter,
                     None => {
                         return Err(RSDBError::Internal(
@@ -146,55 +203,61 @@ impl<T: Transaction> Executor<T> for HashJoin<T> {
                     }
                 };
                 // 获取 join 列在表中的位置
-                let lpos = match left_cols.iter().position(|c| *c == left_field) {
-                    Some(pos) => pos,
-                    None => {
-                        return Err(RSDBError::Internal(format!(
-                            "Join field '{}' not found in table",
-                            left_field
-                        )));
-                    }
-                };
-                let rpos = match right_cols.iter().position(|c| *c == right_field) {
-                    Some(pos) => pos,
-                    None => {
-                        return Err(RSDBError::Internal(format!(
-                            "Join field '{}' not found in table",
-                            right_field
-                        )));
-                    }
-                };
-                // 构建哈希表
+                let lpos = ast::column_position(&left_cols, &left_field)?;
+                let rpos = ast::column_position(&right_cols, &right_field)?;
+                let pad = JoinPadding::new(left_cols.len(), right_cols.len());
+                // 交换后使用左表构建哈希表，右表进行探测，保留行的一侧也随之交换
+                let (keep_left, keep_right) = self.join_type.keeps();
+                let (probe_rows, build_rows, probe_pos, build_pos, keep_probe, keep_build) =
+                    match self.swapped {
+                        true => (&right_rows, &left_rows, rpos, lpos, keep_right, keep_left),
+                        false => (&left_rows, &right_rows, lpos, rpos, keep_left, keep_right),
+                    };
+                // 构建哈希表，NULL 与任何值都不相等，不参与匹配
                 let mut hash_map = HashMap::new();
-                for row in &right_rows {
-                    let rows = hash_map.entry(row[rpos].clone()).or_insert_with(Vec::new);
-                    rows.push(row.clone());
+                for row in build_rows.iter().filter(|r| r[build_pos] != Value::Null) {
+                    let rows = hash_map
+                        .entry(row[build_pos].clone())
+                        .or_insert_with(Vec::new);
+                    rows.push(row);
                 }
-                // 遍历左表的行，查找匹配的右表行
-                for lrow in &left_rows {
-                    match hash_map.get(&lrow[lpos]) {
+                // 记录构建侧被匹配过的 join 列的值，用于外连接
+                let mut matched_keys = HashSet::new();
+                // 遍历探测侧的行，查找匹配的构建侧的行
+                for prow in probe_rows {
+                    match hash_map.get(&prow[probe_pos]) {
                         Some(rows) => {
-                            for r in rows {
-                                let mut row = lrow.clone();
-                                row.extend(r.clone());
-                                new_rows.push(row);
+                            matched_keys.insert(&prow[probe_pos]);
+                            for brow in rows {
+                                new_rows.push(match self.swapped {
+                                    true => pad.join(brow, prow),
+                                    false => pad.join(prow, brow),
+                                });
                             }
                         }
                         None => {
-                            if self.outer {
-                                let mut row = lrow.clone();
-                                for _ in 0..right_cols.len() {
-                                    row.push(Value::Null);
-                                }
-                                new_rows.push(row);
+                            if keep_probe {
+                                new_rows.push(pad.fill(prow, self.swapped));
                             }
                         }
                     }
                 }
-                return Ok(ResultSet::Scan {
-                    columns: new_cols,
-                    rows: new_rows,
-                });
+                // 外连接还需要输出构建侧没有匹配的行，另一侧填充 NULL
+                if keep_build {
+                    for brow in build_rows
+                        .iter()
+                        .filter(|r| !matched_keys.contains(&r[build_pos]))
+                    {
+                        new_rows.push(pad.fill(brow, !self.swapped));
+                    }
+                }
+                return merge_using_columns(
+                    &self.using,
+                    left_cols.len(),
+                    new_cols,
+                    new_types,
+                    new_rows,
+                );
             }
         }
         Err(RSDBError::Internal(
@@ -203,6 +266,64 @@ impl<T: Transaction> Executor<T> for HashJoin<T> {
     }
 }
 
+// 输出的列名以表名限定，一侧为 Join 时其输出的列已经限定过
+fn qualify_columns(
+    tables: &JoinTables,
+    left: Vec<String>,
+    right: Vec<String>,
+) -> (Vec<String>, Vec<String>) {
+    let qualify = |table: &Option<String>, columns: Vec<String>| match table {
+        Some(table) => columns
+            .into_iter()
+            .map(|c| match c.contains('.') {
+                true => c,
+                false => format!("{}.{}", table, c),
+            })
+            .collect(),
+        None => columns,
+    };
+    (qualify(&tables.left, left), qualify(&tables.right, right))
+}
+
+// 条件中的列名替换为输出中完整的列名，避免每一行都重新查找，有歧义的列名保持不变
+fn resolve_fields(predicate: Option<Expression>, columns: &[String]) -> Option<Expression> {
+    predicate.map(|expr| {
+        expr.map_fields(&|col| match ast::column_position(columns, col) {
+            Ok(pos) => columns[pos].clone(),
+            Err(_) => col.clone(),
+        })
+    })
+}
+
+// 拼接 Join 两侧的行，没有匹配的一侧填充 NULL
+struct JoinPadding {
+    left_len: usize,
+    right_len: usize,
+}
+
+impl JoinPadding {
+    fn new(left_len: usize, right_len: usize) -> Self {
+        Self {
+            left_len,
+            right_len,
+        }
+    }
+
+    fn join(&self, lrow: &Row, rrow: &Row) -> Row {
+        let mut row = lrow.clone();
+        row.extend(rrow.iter().cloned());
+        row
+    }
+
+    // is_right 表示 row 是否为右表的行
+    fn fill(&self, row: &Row, is_right: bool) -> Row {
+        match is_right {
+            true => self.join(&vec![Value::Null; self.left_len], row),
+            false => self.join(row, &vec![Value::Null; self.right_len]),
+        }
+    }
+}
+
 fn parse_join_filter(predicate: Option<Expression>) -> Option<(String, String)> {
     if let Some(expr) = predicate {
         match expr {
@@ -220,3 +341,43 @@ fn parse_join_filter(predicate: Option<Expression>) -> Option<(String, String)>
     };
     None
 }
+
+// 合并 USING 子句中的列，结果中只保留左表的列，列名不再以表名限定
+// 外连接时左表的值可能是填充的 NULL，此时取右表的值
+fn merge_using_columns(
+    using: &[String],
+    left_len: usize,
+    mut columns: Vec<String>,
+    mut types: Vec<Option<DataType>>,
+    mut rows: Vec<Row>,
+) -> RSDBResult<Output> {
+    let mut right_pos = Vec::new();
+    for col in using {
+        let lpos = ast::column_position(&columns[..left_len], col)?;
+        let rpos = ast::column_position(&columns[left_len..], col)? + left_len;
+        columns[lpos] = col.clone();
+        if types[lpos].is_none() {
+            types[lpos] = types[rpos].clone();
+        }
+        for row in rows.iter_mut() {
+            if row[lpos] == Value::Null {
+                row[lpos] = row[rpos].clone();
+            }
+        }
+        right_pos.push(rpos);
+    }
+    // 从后往前删除右表中重复的列
+    right_pos.sort_unstable_by(|a, b| b.cmp(a));
+    for pos in right_pos {
+        columns.remove(pos);
+        types.remove(pos);
+        for row in rows.iter_mut() {
+            row.remove(pos);
+        }
+    }
+    Ok(Output::Rows {
+        columns,
+        types,
+        rows: rows_of(rows),
+    })
+}
@@ -1,16 +1,30 @@
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
 use crate::{
-    error::RSDBResult,
+    error::{RSDBError, RSDBResult},
     sql::{
         engine::Transaction,
         executor::{
             agg::Aggregate,
             join::{HashJoin, NestLoopJoin},
             mutation::{Delete, Insert, Update},
-            query::{Filter, IndexScan, Limit, Offset, Order, PrimaryKeyScan, Projection, Scan},
-            schema::{CreateTable, DropTable},
+            query::{
+                Distinct, Filter, IndexScan, Limit, Nothing, Offset, Order, PrimaryKeyScan,
+                Projection, Scan, Window,
+            },
+            schema::{
+                Analyze, CreateDatabase, CreateIndex, CreateSequence, CreateTable, DescribeTable,
+                DropDatabase, DropSequence, DropTable, ShowTables,
+            },
         },
+        parser::ast::{Expression, SequenceFunction, evaluate_expr},
         plan::Node,
-        types::Row,
+        types::{DataType, Row, Rows, Value},
     },
 };
 
@@ -22,58 +36,286 @@ mod schema;
 
 // 执行器定义
 pub trait Executor<T: Transaction> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet>;
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output>;
+}
+
+// 执行器的输出，查询类的执行器输出列名、列类型和按需拉取的行，其他执行器直接输出结果
+// 行在上层执行器拉取时才计算，如 Limit 拉取到足够的行后就不再继续拉取
+pub enum Output {
+    Rows {
+        columns: Vec<String>,
+        types: Vec<Option<DataType>>,
+        rows: Rows,
+    },
+    Result(ResultSet),
+}
+
+// 将已经物化的行转换为按需拉取的行
+fn rows_of(rows: Vec<Row>) -> Rows {
+    Box::new(rows.into_iter().map(Ok))
+}
+
+// 计算序列函数的值，nextval('s') 分配序列的下一个值，currval('s') 为当前 session 最近一次分配的值
+fn sequence_value<T: Transaction>(
+    txn: &T,
+    func: &SequenceFunction,
+    name: &str,
+) -> RSDBResult<Value> {
+    Ok(Value::Integer(match func {
+        SequenceFunction::NextVal => txn.nextval(name)?,
+        SequenceFunction::CurrVal => txn.currval(name)?,
+    }))
+}
+
+// 在一行上计算表达式，其中的序列函数先替换为序列的值，按照从左到右的顺序分配
+fn evaluate_row_expr<T: Transaction>(
+    txn: &T,
+    expr: &Expression,
+    columns: &Vec<String>,
+    row: &Row,
+) -> RSDBResult<Value> {
+    if !expr.has_sequence(None) {
+        return evaluate_expr(expr, columns, row, columns, row);
+    }
+    let expr = expr.map_sequences(&mut |func, name| sequence_value(txn, func, name))?;
+    evaluate_expr(&expr, columns, row, columns, row)
+}
+
+impl Output {
+    // 物化所有的行，作为最终的查询结果
+    pub fn into_result_set(self) -> RSDBResult<ResultSet> {
+        match self {
+            Output::Rows {
+                columns,
+                types,
+                rows,
+            } => Ok(ResultSet::Scan {
+                columns,
+                types,
+                rows: rows.collect::<RSDBResult<_>>()?,
+            }),
+            Output::Result(result) => Ok(result),
+        }
+    }
 }
 
 impl<T: Transaction + 'static> dyn Executor<T> {
     pub fn build(node: Node) -> Box<dyn Executor<T>> {
-        match node {
+        Self::build_with(node, None)
+    }
+
+    // 构建执行器，并在每个节点外包装一层统计，统计信息按先序遍历的顺序保存
+    pub fn build_profiled(
+        node: Node,
+        stats: &mut Vec<Rc<RefCell<ExecStats>>>,
+    ) -> Box<dyn Executor<T>> {
+        Self::build_with(node, Some(stats))
+    }
+
+    fn build_with(
+        node: Node,
+        mut profile: Option<&mut Vec<Rc<RefCell<ExecStats>>>>,
+    ) -> Box<dyn Executor<T>> {
+        let slot = profile.as_mut().map(|stats| {
+            let slot = Rc::new(RefCell::new(ExecStats::default()));
+            stats.push(Rc::clone(&slot));
+            slot
+        });
+        let executor: Box<dyn Executor<T>> = match node {
             Node::CreateTable { schema } => CreateTable::new(schema),
-            Node::DropTable { table_name } => DropTable::new(table_name),
+            Node::DropTable {
+                table_name,
+                if_exists,
+            } => DropTable::new(table_name, if_exists),
+            Node::CreateIndex { table_name, index } => CreateIndex::new(table_name, index),
+            Node::CreateSequence { name } => CreateSequence::new(name),
+            Node::DropSequence { name, if_exists } => DropSequence::new(name, if_exists),
+            Node::CreateDatabase { name } => CreateDatabase::new(name),
+            Node::DropDatabase { name, if_exists } => DropDatabase::new(name, if_exists),
+            Node::DescribeTable { table_name } => DescribeTable::new(table_name),
+            Node::ShowTables { extended } => ShowTables::new(extended),
+            Node::Analyze { table_name } => Analyze::new(table_name),
             Node::Insert {
                 table_name,
                 columns,
                 values,
             } => Insert::new(table_name, columns, values),
-            Node::Scan { table_name, filter } => Scan::new(table_name, filter),
+            Node::Scan {
+                table_name,
+                filter,
+                columns,
+                limit,
+                reverse,
+            } => Scan::new(table_name, filter, columns, limit, reverse),
             Node::Update {
                 table_name,
                 source,
                 columns,
-            } => Update::new(table_name, Self::build(*source), columns),
-            Node::Delete { table_name, source } => Delete::new(table_name, Self::build(*source)),
-            Node::Order { source, order_by } => Order::new(Self::build(*source), order_by),
-            Node::Limit { source, limit } => Limit::new(Self::build(*source), limit),
-            Node::Offset { source, offset } => Offset::new(Self::build(*source), offset),
-            Node::Projection { source, exprs } => Projection::new(Self::build(*source), exprs),
+            } => Update::new(
+                table_name,
+                Self::build_with(*source, profile.as_deref_mut()),
+                columns,
+            ),
+            Node::Delete { table_name, source } => Delete::new(
+                table_name,
+                Self::build_with(*source, profile.as_deref_mut()),
+            ),
+            Node::Order { source, order_by } => {
+                Order::new(Self::build_with(*source, profile.as_deref_mut()), order_by)
+            }
+            Node::Limit { source, limit } => {
+                Limit::new(Self::build_with(*source, profile.as_deref_mut()), limit)
+            }
+            Node::Offset { source, offset } => {
+                Offset::new(Self::build_with(*source, profile.as_deref_mut()), offset)
+            }
+            Node::Projection { source, exprs } => {
+                Projection::new(Self::build_with(*source, profile.as_deref_mut()), exprs)
+            }
             Node::NestLoopJoin {
                 left,
                 right,
                 predicate,
-                outer,
-            } => NestLoopJoin::new(Self::build(*left), Self::build(*right), predicate, outer),
+                join_type,
+                using,
+                swapped,
+                tables,
+            } => NestLoopJoin::new(
+                Self::build_with(*left, profile.as_deref_mut()),
+                Self::build_with(*right, profile.as_deref_mut()),
+                predicate,
+                join_type,
+                using,
+                swapped,
+                tables,
+            ),
             Node::Aggregate {
                 source,
                 exprs,
                 group_by,
-            } => Aggregate::new(Self::build(*source), exprs, group_by),
-            Node::Filter { source, predicate } => Filter::new(Self::build(*source), predicate),
+            } => Aggregate::new(
+                Self::build_with(*source, profile.as_deref_mut()),
+                exprs,
+                group_by,
+            ),
+            Node::Distinct { source } => Distinct::new(Self::build_with(*source, profile)),
+            Node::Window { source, functions } => {
+                Window::new(Self::build_with(*source, profile.as_deref_mut()), functions)
+            }
+            Node::Filter { source, predicate } => {
+                Filter::new(Self::build_with(*source, profile.as_deref_mut()), predicate)
+            }
             Node::IndexScan {
+                table_name,
+                index,
+                values,
+                ..
+            } => IndexScan::new(table_name, index, vec![values]),
+            Node::MultiIndexScan {
                 table_name,
                 field,
-                value,
-            } => IndexScan::new(table_name, field, value),
+                values,
+            } => IndexScan::new(
+                table_name,
+                field,
+                values.into_iter().map(|v| vec![v]).collect(),
+            ),
             Node::PrimaryKeyScan { table_name, value } => PrimaryKeyScan::new(table_name, value),
+            Node::Nothing { columns } => Nothing::new(columns),
             Node::HashJoin {
                 left,
                 right,
                 predicate,
-                outer,
-            } => HashJoin::new(Self::build(*left), Self::build(*right), predicate, outer),
+                join_type,
+                using,
+                swapped,
+                tables,
+            } => HashJoin::new(
+                Self::build_with(*left, profile.as_deref_mut()),
+                Self::build_with(*right, profile),
+                predicate,
+                join_type,
+                using,
+                swapped,
+                tables,
+            ),
+        };
+        match slot {
+            Some(stats) => Box::new(Profile {
+                source: executor,
+                stats,
+            }),
+            None => executor,
         }
     }
 }
 
+// 单个执行节点的统计信息，耗时包含子节点
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExecStats {
+    pub rows: usize,
+    pub elapsed: Duration,
+}
+
+// 统计执行节点输出的行数和耗时，用于 EXPLAIN ANALYZE
+struct Profile<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    stats: Rc<RefCell<ExecStats>>,
+}
+
+impl<T: Transaction> Executor<T> for Profile<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        let start = Instant::now();
+        let output = self.source.execute(txn)?;
+        self.stats.borrow_mut().elapsed += start.elapsed();
+        match output {
+            // 行是按需拉取的，拉取每一行的耗时也需要统计
+            Output::Rows {
+                columns,
+                types,
+                rows,
+            } => Ok(Output::Rows {
+                columns,
+                types,
+                rows: Box::new(ProfileRows {
+                    rows,
+                    stats: self.stats,
+                }),
+            }),
+            Output::Result(result) => {
+                self.stats.borrow_mut().rows = match &result {
+                    ResultSet::Scan { rows, .. } => rows.len(),
+                    ResultSet::Insert { count }
+                    | ResultSet::Update { count }
+                    | ResultSet::Delete { count } => *count,
+                    _ => 0,
+                };
+                Ok(Output::Result(result))
+            }
+        }
+    }
+}
+
+struct ProfileRows {
+    rows: Rows,
+    stats: Rc<RefCell<ExecStats>>,
+}
+
+impl Iterator for ProfileRows {
+    type Item = RSDBResult<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        let row = self.rows.next();
+        let mut stats = self.stats.borrow_mut();
+        stats.elapsed += start.elapsed();
+        if let Some(Ok(_)) = row {
+            stats.rows += 1;
+        }
+        row
+    }
+}
+
 // 执行结果集
 #[derive(Debug, PartialEq)]
 pub enum ResultSet {
@@ -83,11 +325,35 @@ pub enum ResultSet {
     DropTable {
         table_name: String,
     },
+    CreateIndex {
+        index_name: String,
+    },
+    CreateSequence {
+        name: String,
+    },
+    DropSequence {
+        name: String,
+    },
+    CreateDatabase {
+        name: String,
+    },
+    DropDatabase {
+        name: String,
+    },
+    UseDatabase {
+        name: String,
+    },
+    Analyze {
+        table_name: String,
+        row_count: usize,
+    },
     Insert {
         count: usize,
     },
+    // 列类型与列一一对应，无法确定类型时为 None，如 NULL 常量
     Scan {
         columns: Vec<String>,
+        types: Vec<Option<DataType>>,
         rows: Vec<Row>,
     },
     Update {
@@ -105,58 +371,332 @@ pub enum ResultSet {
     Rollback {
         version: u64,
     },
+    Explain {
+        plan: String,
+    },
+    Set {
+        name: String,
+    },
+    Vacuum {
+        versions: usize,
+        disk_size_before: u64,
+        disk_size_after: u64,
+    },
+}
+
+// 查询结果的展示选项，默认与 Value 的 Display 一致
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOptions {
+    // 字符串是否用单引号括起来
+    pub quote_strings: bool,
+    // NULL 的展示文本
+    pub null: String,
+    // 浮点数保留的小数位数，None 表示按最短的表示展示
+    pub float_precision: Option<usize>,
+    // 是否在列名下方展示列类型，供客户端解析结果时使用
+    pub show_types: bool,
+    // 单元格展示的最大字符数，超出部分以省略号代替，None 表示不截断
+    pub max_width: Option<usize>,
+    // 输出格式，默认为对齐的表格
+    pub output: OutputFormat,
+}
+
+// 查询结果的输出格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    // 对齐的表格
+    Table,
+    // 原始模式，按制表符分隔输出完整的值，不对齐、不转义、不截断，便于程序解析
+    Raw,
+    // 所有的行组成一个 JSON 数组，每一行是以列名为键的对象，输出在同一行中
+    Json,
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Table => write!(f, "table"),
+            OutputFormat::Raw => write!(f, "raw"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+// 单元格默认展示的最大字符数
+pub const DEFAULT_MAX_WIDTH: usize = 40;
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            quote_strings: true,
+            null: "NULL".to_string(),
+            float_precision: None,
+            show_types: false,
+            max_width: Some(DEFAULT_MAX_WIDTH),
+            output: OutputFormat::Table,
+        }
+    }
+}
+
+impl FormatOptions {
+    // 修改单个选项，如 quote=off、null=、precision=2、precision=none、types=on、width=20、
+    // output=json，raw=on 等价于 output=raw
+    pub fn set(&mut self, key: &str, value: &str) -> RSDBResult<()> {
+        let invalid = || RSDBError::Internal(format!("invalid format option {}={}", key, value));
+        let switch = || match value.to_lowercase().as_str() {
+            "on" | "true" => Ok(true),
+            "off" | "false" => Ok(false),
+            _ => Err(invalid()),
+        };
+        match key.to_lowercase().as_str() {
+            "quote" => self.quote_strings = switch()?,
+            "types" => self.show_types = switch()?,
+            "raw" => {
+                self.output = match switch()? {
+                    true => OutputFormat::Raw,
+                    false => OutputFormat::Table,
+                }
+            }
+            "output" => {
+                self.output = match value.to_lowercase().as_str() {
+                    "table" => OutputFormat::Table,
+                    "raw" => OutputFormat::Raw,
+                    "json" => OutputFormat::Json,
+                    _ => return Err(invalid()),
+                }
+            }
+            "null" => self.null = value.to_string(),
+            "precision" => {
+                self.float_precision = match value.to_lowercase().as_str() {
+                    "none" => None,
+                    v => Some(v.parse().map_err(|_| invalid())?),
+                }
+            }
+            "width" => {
+                self.max_width = match value.to_lowercase().as_str() {
+                    "none" => None,
+                    v => match v.parse() {
+                        Ok(width) if width > 0 => Some(width),
+                        _ => return Err(invalid()),
+                    },
+                }
+            }
+            _ => return Err(invalid()),
+        }
+        Ok(())
+    }
+
+    fn format_value(&self, value: &Value) -> String {
+        match value {
+            Value::Null => self.null.clone(),
+            Value::String(s) if !self.quote_strings => s.clone(),
+            Value::Float(f) if f.is_finite() => match self.float_precision {
+                Some(precision) => format!("{:.*}", precision, f),
+                None => value.to_string(),
+            },
+            _ => value.to_string(),
+        }
+    }
+
+    // 表格中单元格的展示文本，转义换行符和制表符，超过最大宽度时截断
+    fn format_cell(&self, value: &Value) -> String {
+        let text = self.format_value(value);
+        if self.output == OutputFormat::Raw {
+            return text;
+        }
+        let mut cell = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '\n' => cell.push_str("\\n"),
+                '\r' => cell.push_str("\\r"),
+                '\t' => cell.push_str("\\t"),
+                c => cell.push(c),
+            }
+        }
+        match self.max_width {
+            Some(width) if cell.chars().count() > width => {
+                let mut cell = cell.chars().take(width - 1).collect::<String>();
+                cell.push('…');
+                cell
+            }
+            _ => cell,
+        }
+    }
 }
 
 impl ResultSet {
     pub fn to_string(&self) -> String {
+        self.format(&FormatOptions::default())
+    }
+
+    pub fn format(&self, opts: &FormatOptions) -> String {
         match self {
             ResultSet::CreateTable { table_name } => format!("CREATE TABLE `{}`", table_name),
-            ResultSet::DropTable { table_name } => format!("DROP TABLE `{}`", table_name),
+            ResultSet::DropTable { table_name } => format!("DROP TABLE {}", table_name),
+            ResultSet::CreateIndex { index_name } => format!("CREATE INDEX `{}`", index_name),
+            ResultSet::CreateSequence { name } => format!("CREATE SEQUENCE `{}`", name),
+            ResultSet::DropSequence { name } => format!("DROP SEQUENCE {}", name),
+            ResultSet::CreateDatabase { name } => format!("CREATE DATABASE `{}`", name),
+            ResultSet::DropDatabase { name } => format!("DROP DATABASE {}", name),
+            ResultSet::UseDatabase { name } => format!("USE {}", name),
+            ResultSet::Analyze {
+                table_name,
+                row_count,
+            } => format!("ANALYZE `{}` ({} ROWS)", table_name, row_count),
             ResultSet::Insert { count } => format!("INSERT {} ROWS", count),
-            ResultSet::Scan { columns, rows } => {
-                let row_len = rows.len();
-                // 找到每一列最大的长度
-                let mut max_len = columns.iter().map(|c| c.len()).collect::<Vec<_>>();
-                for row in rows {
-                    for (i, val) in row.iter().enumerate() {
-                        let val_len = val.to_string().len();
-                        if val_len > max_len[i] {
-                            max_len[i] = val_len;
-                        }
-                    }
-                }
-                // 展示列
-                let columns = columns
-                    .iter()
-                    .zip(max_len.iter())
-                    .map(|(col, len)| format!("{:width$}", col, width = len))
-                    .collect::<Vec<_>>()
-                    .join(" |");
-                // 展示分割符
-                let separator = max_len
-                    .iter()
-                    .map(|len| "-".repeat(*len + 1))
-                    .collect::<Vec<_>>()
-                    .join("+");
-                // 展示行
-                let rows = rows
-                    .iter()
-                    .map(|row| {
-                        row.iter()
-                            .zip(max_len.iter())
-                            .map(|(val, len)| format!("{:width$}", val.to_string(), width = len))
-                            .collect::<Vec<_>>()
-                            .join(" |")
-                    })
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                format!("{}\n{}\n{}\n{} ROWS", columns, separator, rows, row_len)
-            }
+            ResultSet::Scan {
+                columns,
+                types,
+                rows,
+            } => match opts.output {
+                OutputFormat::Json => format_json(columns, rows),
+                _ => format_table(columns, types, rows, opts),
+            },
             ResultSet::Update { count } => format!("UPDATE {} ROWS", count),
             ResultSet::Delete { count } => format!("DELETE {} ROWS", count),
-            ResultSet::Begin { version } => format!("TRANSACTION {} BEGIN", version),
-            ResultSet::Commit { version } => format!("TRANSACTION {} COMMIT", version),
-            ResultSet::Rollback { version } => format!("TRANSACTION {} ROLLBACK", version),
+            // 客户端根据事务命令的结果跟踪当前事务的版本
+            ResultSet::Begin { version } => format!("BEGIN (version {})", version),
+            ResultSet::Commit { .. } => "COMMIT".to_string(),
+            ResultSet::Rollback { .. } => "ROLLBACK".to_string(),
+            ResultSet::Explain { plan } => plan.clone(),
+            ResultSet::Set { name } => format!("SET {}", name),
+            ResultSet::Vacuum {
+                versions,
+                disk_size_before,
+                disk_size_after,
+            } => format!(
+                "VACUUM ({} VERSIONS, {} -> {} BYTES)",
+                versions, disk_size_before, disk_size_after
+            ),
+        }
+    }
+}
+
+// 以表格的形式展示查询结果，数值类型的列右对齐，其余列左对齐
+// 原始模式下按制表符分隔输出表头和每一行，不输出分割符和行数
+fn format_table(
+    columns: &[String],
+    types: &[Option<DataType>],
+    rows: &[Row],
+    opts: &FormatOptions,
+) -> String {
+    let rows = rows
+        .iter()
+        .map(|row| row.iter().map(|v| opts.format_cell(v)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    // 表头为列名，需要时在列名下方展示列类型，未知的类型展示为 ?
+    let mut header = vec![columns.to_vec()];
+    if opts.show_types {
+        header.push(
+            types
+                .iter()
+                .map(|t| t.as_ref().map_or("?".to_string(), |t| format!("{:?}", t)))
+                .collect(),
+        );
+    }
+    if opts.output == OutputFormat::Raw {
+        return header
+            .iter()
+            .chain(rows.iter())
+            .map(|row| row.join("\t"))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    // 找到每一列最大的长度，按字符计算，与格式化时的宽度一致
+    let mut max_len = vec![0; columns.len()];
+    for row in header.iter().chain(rows.iter()) {
+        for (i, val) in row.iter().enumerate() {
+            let val_len = val.chars().count();
+            if val_len > max_len[i] {
+                max_len[i] = val_len;
+            }
+        }
+    }
+    let right_align = types
+        .iter()
+        .map(|t| {
+            matches!(
+                t,
+                Some(DataType::Integer | DataType::Float | DataType::Decimal(_))
+            )
+        })
+        .collect::<Vec<_>>();
+    let format_line = |row: &Vec<String>| {
+        row.iter()
+            .enumerate()
+            .map(|(i, val)| match right_align.get(i) {
+                Some(true) => format!("{:>width$}", val, width = max_len[i]),
+                _ => format!("{:width$}", val, width = max_len[i]),
+            })
+            .collect::<Vec<_>>()
+            .join(" |")
+    };
+    // 展示表头
+    let header = header
+        .iter()
+        .map(format_line)
+        .collect::<Vec<_>>()
+        .join("\n");
+    // 没有结果时不展示分割符
+    if rows.is_empty() {
+        return format!("{}\n(0 rows)", header);
+    }
+    // 展示分割符
+    let separator = max_len
+        .iter()
+        .map(|len| "-".repeat(*len + 1))
+        .collect::<Vec<_>>()
+        .join("+");
+    // 展示行
+    let lines = rows.iter().map(format_line).collect::<Vec<_>>().join("\n");
+    format!("{}\n{}\n{}\n{} ROWS", header, separator, lines, rows.len())
+}
+
+// 以 JSON 数组的形式输出查询结果，如 [{"a":1,"b":"x"}]
+// 数值和布尔值输出为 JSON 中对应的类型，NULL 为 null，其余类型输出为字符串
+fn format_json(columns: &[String], rows: &[Row]) -> String {
+    let objects = rows
+        .iter()
+        .map(|row| {
+            let fields = columns
+                .iter()
+                .zip(row)
+                .map(|(col, value)| format!("{}:{}", json_string(col), json_value(value)))
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("{{{}}}", fields)
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("[{}]", objects)
+}
+
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Integer(_) | Value::Decimal(_, _) => value.to_string(),
+        Value::Float(f) if f.is_finite() => value.to_string(),
+        Value::String(s) => json_string(s),
+        value => json_string(&value.to_string()),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
         }
     }
+    out.push('"');
+    out
 }
@@ -4,10 +4,10 @@ use crate::{
     error::{RSDBError, RSDBResult},
     sql::{
         engine::Transaction,
-        executor::{Executor, ResultSet},
+        executor::{Executor, Output, ResultSet, evaluate_row_expr},
         parser::ast::Expression,
-        schema::Table,
-        types::{Row, Value},
+        schema::{Column, Table},
+        types::{Row, Rows, Value},
     },
 };
 
@@ -32,39 +32,96 @@ impl Insert {
 }
 
 impl<T: Transaction> Executor<T> for Insert {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
-        let mut count = 0;
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         let table = txn.must_get_table(self.table_name.clone())?;
-        for exprs in self.values {
-            // 将表达式转换成 value
-            let row = exprs
-                .into_iter()
-                .map(|e| Value::from_expression(e))
-                .collect::<Vec<_>>();
-            // 如果没有指定插入的列
-            let insert_row = if self.columns.is_empty() {
-                pad_row(&table, &row)?
-            } else {
-                // 指定了插入的列，需要对 value 信息进行整理
-                make_row(&table, &self.columns, &row)?
-            };
-            // 插入数据
-            txn.create_row(&table, insert_row)?;
-            count += 1;
-        }
-        Ok(ResultSet::Insert { count })
+        // 出错时带上行号，方便定位多行 INSERT 中出错的行
+        let rows = self
+            .values
+            .into_iter()
+            .enumerate()
+            .map(|(n, exprs)| {
+                exprs
+                    .iter()
+                    .map(|expr| evaluate_row_expr(txn, expr, &vec![], &vec![]))
+                    .collect::<RSDBResult<Vec<_>>>()
+                    .and_then(|row| build_row(txn, &table, &self.columns, row, n + 1))
+                    .map_err(|e| e.at_insert_row(n + 1, None))
+            })
+            .collect::<RSDBResult<Vec<_>>>()?;
+        let count = rows.len();
+        // 批量插入数据
+        txn.create_rows(&table, rows)?;
+        Ok(Output::Result(ResultSet::Insert { count }))
+    }
+}
+
+// 根据 INSERT 的一组值构造完整的一行，值中的序列函数已经按照行和列的顺序分配
+fn build_row<T: Transaction>(
+    txn: &T,
+    table: &Table,
+    columns: &Vec<String>,
+    row: Row,
+    row_num: usize,
+) -> RSDBResult<Row> {
+    if row.len() > table.columns.len() {
+        return Err(RSDBError::Internal(format!(
+            "table {} has {} columns but {} values were supplied",
+            table.name,
+            table.columns.len(),
+            row.len()
+        )));
     }
+    // 如果没有指定插入的列
+    let insert_row = if columns.is_empty() {
+        pad_row(txn, table, &row, row_num)?
+    } else {
+        // 指定了插入的列，需要对 value 信息进行整理
+        make_row(txn, table, columns, &row, row_num)?
+    };
+    // 按照列的类型转换值，如 '2024-01-31' 写入 DATE 列
+    insert_row
+        .into_iter()
+        .enumerate()
+        .map(|(i, v)| match table.columns.get(i) {
+            Some(col) => v.coerce(&col.datatype),
+            None => Ok(v),
+        })
+        .collect()
+}
+
+// 显式给出的 NULL 就是 NULL，不会替换为默认值，非空列上报错
+// row_num 为 VALUES 中从 1 开始的行号
+fn check_null(table: &Table, col: &Column, value: &Value, row_num: usize) -> RSDBResult<()> {
+    if *value == Value::Null && !col.nullable {
+        return Err(RSDBError::InsertNullValue {
+            table: table.name.clone(),
+            column: col.name.clone(),
+            row: row_num,
+        });
+    }
+    Ok(())
+}
+
+// 列的默认值，默认值为 nextval('s') 时每次分配序列的下一个值
+fn default_value<T: Transaction>(txn: &T, column: &Column) -> RSDBResult<Option<Value>> {
+    Ok(match &column.default_sequence {
+        Some(name) => Some(Value::Integer(txn.nextval(name)?)),
+        None => column.default.clone(),
+    })
 }
 
 // 列对齐
 // insert into tbl values(1, 2, 3);
 // a       b        c        d
 // 1       2        3    default 填充
-fn pad_row(table: &Table, row: &Row) -> RSDBResult<Row> {
+fn pad_row<T: Transaction>(txn: &T, table: &Table, row: &Row, row_num: usize) -> RSDBResult<Row> {
+    for (col, value) in table.columns.iter().zip(row) {
+        check_null(table, col, value, row_num)?;
+    }
     let mut results = row.clone();
     for column in table.columns.iter().skip(row.len()) {
-        if let Some(default) = &column.default {
-            results.push(default.clone());
+        if let Some(default) = default_value(txn, column)? {
+            results.push(default);
         } else {
             return Err(RSDBError::Internal(format!(
                 "No default value for column {}",
@@ -78,7 +135,13 @@ fn pad_row(table: &Table, row: &Row) -> RSDBResult<Row> {
 // insert into tbl(d, c) values(1, 2);
 //     a         b         c          d
 // default    default      2          1
-fn make_row(table: &Table, columns: &Vec<String>, value: &Row) -> RSDBResult<Row> {
+fn make_row<T: Transaction>(
+    txn: &T,
+    table: &Table,
+    columns: &Vec<String>,
+    value: &Row,
+    row_num: usize,
+) -> RSDBResult<Row> {
     // 判断列数是否和value数一致
     if columns.len() != value.len() {
         return Err(RSDBError::Internal(format!(
@@ -92,9 +155,10 @@ fn make_row(table: &Table, columns: &Vec<String>, value: &Row) -> RSDBResult<Row
     let mut results = Vec::new();
     for col in table.columns.iter() {
         if let Some(value) = inputs.get(&col.name) {
+            check_null(table, col, value, row_num)?;
             results.push(value.clone());
-        } else if let Some(value) = &col.default {
-            results.push(value.clone());
+        } else if let Some(value) = default_value(txn, col)? {
+            results.push(value);
         } else {
             return Err(RSDBError::Internal(format!(
                 "No value given for column {}",
@@ -127,20 +191,31 @@ impl<T: Transaction> Update<T> {
 }
 
 impl<T: Transaction> Executor<T> for Update<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         let mut count = 0;
         // 执行扫描操作，获取到扫描的结果
         match self.source.execute(txn)? {
-            ResultSet::Scan { columns, rows } => {
+            Output::Rows { columns, rows, .. } => {
                 let table = txn.must_get_table(self.table_name)?;
-                // 遍历所有需要更新的行
+                // 扫描是边读边返回的，更新主键后新的行可能出现在之后的扫描结果中，被再次更新
+                // 因此更新主键时先读取所有需要更新的行，否则逐行拉取
+                let pk_updated = table
+                    .columns
+                    .iter()
+                    .any(|c| c.primary_key && self.columns.contains_key(&c.name));
+                let rows: Rows = match pk_updated {
+                    true => Box::new(rows.collect::<RSDBResult<Vec<_>>>()?.into_iter().map(Ok)),
+                    false => rows,
+                };
                 for row in rows {
+                    let row = row?;
                     let mut new_row = row.clone();
                     let pk = table.get_primary_key(&row)?;
-                    for (i, col) in columns.iter().enumerate() {
-                        if let Some(expr) = self.columns.get(col) {
-                            new_row[i] = Value::from_expression(expr.clone());
-                        }
+                    // SET 中的表达式都基于更新前的行计算
+                    for (col, expr) in &self.columns {
+                        let i = table.get_col_index(col)?;
+                        let value = evaluate_row_expr(txn, expr, &columns, &row)?;
+                        new_row[i] = value.coerce(&table.columns[i].datatype)?;
                     }
                     // 执行更新操作
                     // 如果有主键更新，删除原来的数据，新增一条新的数据
@@ -155,7 +230,7 @@ impl<T: Transaction> Executor<T> for Update<T> {
                 ));
             }
         }
-        Ok(ResultSet::Update { count })
+        Ok(Output::Result(ResultSet::Update { count }))
     }
 }
 
@@ -171,17 +246,18 @@ impl<T: Transaction> Delete<T> {
     }
 }
 impl<T: Transaction> Executor<T> for Delete<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         let mut count = 0;
         match self.source.execute(txn)? {
-            ResultSet::Scan { columns: _, rows } => {
+            Output::Rows { rows, .. } => {
                 let table = txn.must_get_table(self.table_name)?;
                 for row in rows {
+                    let row = row?;
                     let pk = table.get_primary_key(&row)?;
                     txn.delete_row(&table, &pk)?;
                     count += 1;
                 }
-                Ok(ResultSet::Delete { count })
+                Ok(Output::Result(ResultSet::Delete { count }))
             }
             _ => {
                 return Err(RSDBError::Internal(
@@ -1,85 +1,152 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter, Seek, SeekFrom},
+    rc::Rc,
+};
 
 use crate::{
     error::{RSDBError, RSDBResult},
     sql::{
         engine::Transaction,
-        executor::{Executor, ResultSet},
-        parser::ast::{Expression, OrderDirection, evaluate_expr},
-        types::Value,
+        executor::{Executor, Output, evaluate_row_expr, rows_of},
+        parser::ast::{
+            Expression, OrderDirection, WindowFunction, column_position, evaluate_expr,
+            expression_type,
+        },
+        types::{DataType, Row, Value},
     },
 };
 
 pub struct Scan {
     table_name: String,
     filter: Option<Expression>,
+    columns: Option<Vec<String>>,
+    limit: Option<usize>,
+    reverse: bool,
 }
 
 impl Scan {
-    pub fn new(table_name: String, filter: Option<Expression>) -> Box<Self> {
-        Box::new(Self { table_name, filter })
+    pub fn new(
+        table_name: String,
+        filter: Option<Expression>,
+        columns: Option<Vec<String>>,
+        limit: Option<usize>,
+        reverse: bool,
+    ) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            filter,
+            columns,
+            limit,
+            reverse,
+        })
     }
 }
 
 impl<T: Transaction> Executor<T> for Scan {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         let table = txn.must_get_table(self.table_name.clone())?;
-        let rows = txn.scan_table(&table, self.filter)?;
-        Ok(ResultSet::Scan {
-            columns: table.columns.into_iter().map(|c| c.name).collect(),
-            rows,
+        // 过滤条件在完整的行上计算，之后再裁剪出需要的列
+        let rows = txn.scan_rows(&table, self.filter, self.limit, self.reverse)?;
+        let (columns, types): (Vec<String>, Vec<_>) = table
+            .columns
+            .into_iter()
+            .map(|c| (c.name, Some(c.datatype)))
+            .unzip();
+        let Some(selected) = self.columns else {
+            return Ok(Output::Rows {
+                columns,
+                types,
+                rows,
+            });
+        };
+        let positions = selected
+            .iter()
+            .map(|name| {
+                columns.iter().position(|c| c == name).ok_or_else(|| {
+                    RSDBError::Internal(format!("column {} not found in table", name))
+                })
+            })
+            .collect::<RSDBResult<Vec<_>>>()?;
+        Ok(Output::Rows {
+            columns: selected,
+            types: positions.iter().map(|&i| types[i].clone()).collect(),
+            rows: Box::new(rows.map(move |row| {
+                row.map(|row| positions.iter().map(|&i| row[i].clone()).collect())
+            })),
         })
     }
 }
 
+// 内存中最多缓存的行数，超过后排序并写入临时文件
+pub const ORDER_SPILL_THRESHOLD: usize = 100_000;
+
 pub struct Order<T: Transaction> {
     source: Box<dyn Executor<T>>,
     order_by: Vec<(String, OrderDirection)>,
+    spill_threshold: usize,
 }
 
 impl<T: Transaction> Order<T> {
     pub fn new(source: Box<dyn Executor<T>>, order_by: Vec<(String, OrderDirection)>) -> Box<Self> {
-        Box::new(Self { source, order_by })
+        Box::new(Self {
+            source,
+            order_by,
+            spill_threshold: ORDER_SPILL_THRESHOLD,
+        })
+    }
+
+    // 调整内存中缓存的行数上限
+    #[allow(dead_code)]
+    pub fn with_spill_threshold(mut self: Box<Self>, spill_threshold: usize) -> Box<Self> {
+        self.spill_threshold = spill_threshold.max(1);
+        self
     }
 }
 
 impl<T: Transaction> Executor<T> for Order<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         match self.source.execute(txn)? {
-            ResultSet::Scan { columns, mut rows } => {
+            Output::Rows {
+                columns,
+                types,
+                rows,
+            } => {
                 // 找到 order by 的列对应表中的列的位置
-                let mut order_col_index = HashMap::new();
-                for (i, (col_name, _)) in self.order_by.iter().enumerate() {
-                    match columns.iter().position(|c| *c == *col_name) {
-                        Some(pos) => order_col_index.insert(i, pos),
-                        None => {
-                            return Err(RSDBError::Internal(format!(
-                                "order by column {} not found in table",
-                                col_name
-                            )));
-                        }
-                    };
+                let mut keys = Vec::new();
+                for (col_name, direction) in self.order_by {
+                    keys.push((column_position(&columns, &col_name)?, direction));
                 }
-                rows.sort_by(|col1, col2| {
-                    for (i, (_, direction)) in self.order_by.iter().enumerate() {
-                        let col_index = order_col_index.get(&i).unwrap();
-                        let x = &col1[*col_index];
-                        let y = &col2[*col_index];
-                        match x.partial_cmp(y) {
-                            Some(std::cmp::Ordering::Equal) => {}
-                            Some(o) => {
-                                return if *direction == OrderDirection::Asc {
-                                    o
-                                } else {
-                                    o.reverse()
-                                };
-                            }
-                            None => {}
-                        }
+                let keys: Rc<[(usize, OrderDirection)]> = keys.into();
+
+                // 排序需要拉取所有的行，超过阈值的部分排序后写入临时文件
+                let mut buffer = Vec::new();
+                let mut runs = Vec::new();
+                for row in rows {
+                    buffer.push(row?);
+                    if buffer.len() >= self.spill_threshold {
+                        sort_rows(&mut buffer, &keys);
+                        runs.push(SortedRun::write(std::mem::take(&mut buffer))?);
                     }
-                    Ordering::Equal
-                });
-                Ok(ResultSet::Scan { columns, rows })
+                }
+                sort_rows(&mut buffer, &keys);
+                if runs.is_empty() {
+                    return Ok(Output::Rows {
+                        columns,
+                        types,
+                        rows: rows_of(buffer),
+                    });
+                }
+                if !buffer.is_empty() {
+                    runs.push(SortedRun::write(buffer)?);
+                }
+                Ok(Output::Rows {
+                    columns,
+                    types,
+                    rows: Box::new(MergeRuns::new(runs, keys)?),
+                })
             }
             _ => {
                 return Err(RSDBError::Internal(
@@ -90,6 +157,132 @@ impl<T: Transaction> Executor<T> for Order<T> {
     }
 }
 
+// 按照排序键比较两行，内存排序与外部排序共用
+fn compare_rows(keys: &[(usize, OrderDirection)], row1: &Row, row2: &Row) -> Ordering {
+    for (col_index, direction) in keys {
+        match row1[*col_index].total_cmp(&row2[*col_index]) {
+            Ordering::Equal => {}
+            o => {
+                return if *direction == OrderDirection::Asc {
+                    o
+                } else {
+                    o.reverse()
+                };
+            }
+        }
+    }
+    Ordering::Equal
+}
+
+fn sort_rows(rows: &mut [Row], keys: &[(usize, OrderDirection)]) {
+    rows.sort_by(|row1, row2| compare_rows(keys, row1, row2));
+}
+
+// 已排序并写入临时文件的一段数据，文件关闭后自动删除
+struct SortedRun {
+    reader: BufReader<File>,
+    remaining: usize,
+}
+
+impl SortedRun {
+    fn write(rows: Vec<Row>) -> RSDBResult<Self> {
+        let mut writer = BufWriter::new(tempfile::tempfile()?);
+        for row in &rows {
+            bincode::serialize_into(&mut writer, row)?;
+        }
+        let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(Self {
+            reader: BufReader::new(file),
+            remaining: rows.len(),
+        })
+    }
+
+    fn next_row(&mut self) -> RSDBResult<Option<Row>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        Ok(Some(bincode::deserialize_from(&mut self.reader)?))
+    }
+}
+
+// 堆中的元素，保存每段数据当前的第一行
+struct MergeEntry {
+    row: Row,
+    run: usize,
+    keys: Rc<[(usize, OrderDirection)]>,
+}
+
+impl PartialEq for MergeEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MergeEntry {}
+
+impl PartialOrd for MergeEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MergeEntry {
+    // BinaryHeap 是大顶堆，这里反转顺序，最小的行先出堆
+    // 相等的行按照段的顺序输出，与内存中的稳定排序结果一致
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_rows(&self.keys, &self.row, &other.row)
+            .then(self.run.cmp(&other.run))
+            .reverse()
+    }
+}
+
+// 多路归并已排序的数据段
+struct MergeRuns {
+    runs: Vec<SortedRun>,
+    heap: BinaryHeap<MergeEntry>,
+    keys: Rc<[(usize, OrderDirection)]>,
+}
+
+impl MergeRuns {
+    fn new(mut runs: Vec<SortedRun>, keys: Rc<[(usize, OrderDirection)]>) -> RSDBResult<Self> {
+        let mut heap = BinaryHeap::with_capacity(runs.len());
+        for (run, sorted_run) in runs.iter_mut().enumerate() {
+            if let Some(row) = sorted_run.next_row()? {
+                heap.push(MergeEntry {
+                    row,
+                    run,
+                    keys: keys.clone(),
+                });
+            }
+        }
+        Ok(Self { runs, heap, keys })
+    }
+}
+
+impl Iterator for MergeRuns {
+    type Item = RSDBResult<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let MergeEntry { row, run, .. } = self.heap.pop()?;
+        match self.runs[run].next_row() {
+            Ok(Some(next)) => self.heap.push(MergeEntry {
+                row: next,
+                run,
+                keys: self.keys.clone(),
+            }),
+            Ok(None) => {}
+            Err(err) => {
+                // 读取失败后不再输出
+                self.heap.clear();
+                return Some(Err(err));
+            }
+        }
+        Some(Ok(row))
+    }
+}
+
 pub struct Limit<T: Transaction> {
     source: Box<dyn Executor<T>>,
     limit: usize,
@@ -102,11 +295,17 @@ impl<T: Transaction> Limit<T> {
 }
 
 impl<T: Transaction> Executor<T> for Limit<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         match self.source.execute(txn)? {
-            ResultSet::Scan { columns, rows } => Ok(ResultSet::Scan {
+            // 拉取到足够的行后不再从下层拉取
+            Output::Rows {
+                columns,
+                types,
+                rows,
+            } => Ok(Output::Rows {
                 columns,
-                rows: rows.into_iter().take(self.limit).collect(),
+                types,
+                rows: Box::new(rows.take(self.limit)),
             }),
             _ => {
                 return Err(RSDBError::Internal(
@@ -129,12 +328,28 @@ impl<T: Transaction> Offset<T> {
 }
 
 impl<T: Transaction> Executor<T> for Offset<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         match self.source.execute(txn)? {
-            ResultSet::Scan { columns, rows } => Ok(ResultSet::Scan {
+            Output::Rows {
                 columns,
-                rows: rows.into_iter().skip(self.offset).collect(),
-            }),
+                types,
+                rows,
+            } => {
+                // 跳过的行中出现的错误仍然需要返回
+                let (offset, mut skipped) = (self.offset, 0);
+                let rows = rows.filter(move |row| {
+                    if row.is_err() || skipped >= offset {
+                        return true;
+                    }
+                    skipped += 1;
+                    false
+                });
+                Ok(Output::Rows {
+                    columns,
+                    types,
+                    rows: Box::new(rows),
+                })
+            }
             _ => {
                 return Err(RSDBError::Internal(
                     "Offset source must be a Scan".to_string(),
@@ -158,43 +373,92 @@ impl<T: Transaction> Projection<T> {
     }
 }
 
+// 投影中的一列
+enum Projected {
+    // 直接取下层的某一列
+    Column(usize),
+    // 常量只计算一次
+    Const(Value),
+    // 其他表达式对每一行求值
+    Expr(Expression),
+}
+
+impl Projected {
+    // 计算一行中这一列的值
+    fn evaluate(&self, columns: &Vec<String>, row: &Row) -> RSDBResult<Value> {
+        match self {
+            Projected::Column(i) => Ok(row[*i].clone()),
+            Projected::Const(value) => Ok(value.clone()),
+            Projected::Expr(expr) => evaluate_expr(expr, columns, row, columns, row),
+        }
+    }
+}
+
 impl<T: Transaction> Executor<T> for Projection<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         match self.source.execute(txn)? {
-            ResultSet::Scan { columns, rows } => {
-                // 找到需要输出哪些列
-                let mut selected = Vec::new();
+            Output::Rows {
+                columns,
+                types,
+                rows,
+            } => {
+                // 找到需要输出哪些列，没有别名的表达式以表达式文本作为列名
+                let mut projected = Vec::new();
                 let mut new_columns = Vec::new();
+                let mut new_types = Vec::new();
                 for (expr, alias) in self.exprs {
-                    if let Expression::Field(col_name) = expr {
-                        let pos = match columns.iter().position(|c| *c == col_name) {
-                            Some(pos) => pos,
-                            None => {
-                                return Err(RSDBError::Internal(format!(
-                                    "column {} not found in table",
-                                    col_name
-                                )));
-                            }
-                        };
-                        selected.push(pos);
-                        if let Some(a) = alias {
-                            new_columns.push(a);
-                        } else {
-                            new_columns.push(col_name);
+                    new_columns.push(alias.unwrap_or_else(|| expr.to_string()));
+                    new_types.push(expression_type(&expr, &columns, &types));
+                    projected.push(match expr {
+                        Expression::Field(col_name) => {
+                            Projected::Column(column_position(&columns, &col_name)?)
                         }
-                    }
+                        Expression::Consts(_) => Projected::Const(evaluate_expr(
+                            &expr,
+                            &vec![],
+                            &vec![],
+                            &vec![],
+                            &vec![],
+                        )?),
+                        expr => Projected::Expr(expr),
+                    });
                 }
-                let mut new_rows = Vec::new();
-                for row in rows.into_iter() {
-                    let mut new_row = Vec::new();
-                    for i in selected.iter() {
-                        new_row.push(row[*i].clone());
-                    }
-                    new_rows.push(new_row);
+                // 序列函数需要读写计数器，返回的行不能持有事务，因此逐行求值后一次性返回
+                if projected
+                    .iter()
+                    .any(|p| matches!(p, Projected::Expr(expr) if expr.has_sequence(None)))
+                {
+                    let rows = rows
+                        .map(|row| {
+                            let row = row?;
+                            projected
+                                .iter()
+                                .map(|p| match p {
+                                    Projected::Expr(expr) => {
+                                        evaluate_row_expr(txn, expr, &columns, &row)
+                                    }
+                                    p => p.evaluate(&columns, &row),
+                                })
+                                .collect()
+                        })
+                        .collect::<RSDBResult<Vec<_>>>()?;
+                    return Ok(Output::Rows {
+                        columns: new_columns,
+                        types: new_types,
+                        rows: rows_of(rows),
+                    });
                 }
-                Ok(ResultSet::Scan {
+                let rows = rows.map(move |row| {
+                    let row = row?;
+                    projected
+                        .iter()
+                        .map(|p| p.evaluate(&columns, &row))
+                        .collect()
+                });
+                Ok(Output::Rows {
                     columns: new_columns,
-                    rows: new_rows,
+                    types: new_types,
+                    rows: Box::new(rows),
                 })
             }
             _ => {
@@ -218,29 +482,34 @@ impl<T: Transaction> Filter<T> {
 }
 
 impl<T: Transaction> Executor<T> for Filter<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         match self.source.execute(txn)? {
-            ResultSet::Scan { columns, rows } => {
-                let mut new_rows = Vec::new();
-                for row in rows {
-                    match evaluate_expr(&self.predicate, &columns, &row, &columns, &row)? {
-                        Value::Null => {}
-                        Value::Boolean(false) => {}
-                        Value::Boolean(true) => {
-                            new_rows.push(row);
-                        }
-                        _ => {
-                            return Err(RSDBError::Internal(
-                                "
-                                Predicate must evaluate to a boolean value"
-                                    .to_string(),
-                            ));
-                        }
+            Output::Rows {
+                columns,
+                types,
+                rows,
+            } => {
+                let predicate = self.predicate;
+                let filter_columns = columns.clone();
+                let rows = rows.filter_map(move |row| {
+                    let row = match row {
+                        Ok(row) => row,
+                        Err(err) => return Some(Err(err)),
+                    };
+                    let cols = &filter_columns;
+                    match evaluate_expr(&predicate, cols, &row, cols, &row) {
+                        Ok(Value::Null) | Ok(Value::Boolean(false)) => None,
+                        Ok(Value::Boolean(true)) => Some(Ok(row)),
+                        Ok(_) => Some(Err(RSDBError::Internal(
+                            "Predicate must evaluate to a boolean value".to_string(),
+                        ))),
+                        Err(err) => Some(Err(err)),
                     }
-                }
-                Ok(ResultSet::Scan {
+                });
+                Ok(Output::Rows {
                     columns,
-                    rows: new_rows,
+                    types,
+                    rows: Box::new(rows),
                 })
             }
             _ => {
@@ -252,40 +521,163 @@ impl<T: Transaction> Executor<T> for Filter<T> {
     }
 }
 
+// 去重，按照整行判断是否重复，保留第一次出现的行
+// 全部为 NULL 的两行也视为重复
+pub struct Distinct<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+}
+
+impl<T: Transaction> Distinct<T> {
+    pub fn new(source: Box<dyn Executor<T>>) -> Box<Self> {
+        Box::new(Self { source })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Distinct<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        match self.source.execute(txn)? {
+            Output::Rows {
+                columns,
+                types,
+                rows,
+            } => {
+                let mut seen = HashSet::new();
+                let rows = rows.filter(move |row| match row {
+                    Ok(row) => seen.insert(row.clone()),
+                    Err(_) => true,
+                });
+                Ok(Output::Rows {
+                    columns,
+                    types,
+                    rows: Box::new(rows),
+                })
+            }
+            _ => Err(RSDBError::Internal(
+                "Distinct source must be a Scan".to_string(),
+            )),
+        }
+    }
+}
+
+// 窗口函数，按照分区列和排序列排序后，在每一行后面追加窗口函数的结果
+// - ROW_NUMBER：分区内的行号，从 1 开始
+// - RANK：排序列相同的行排名相同，下一个不同的行跳过相应的名次，如 1、1、3
+pub struct Window<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    functions: Vec<(WindowFunction, String)>,
+}
+
+impl<T: Transaction> Window<T> {
+    pub fn new(
+        source: Box<dyn Executor<T>>,
+        functions: Vec<(WindowFunction, String)>,
+    ) -> Box<Self> {
+        Box::new(Self { source, functions })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Window<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        match self.source.execute(txn)? {
+            Output::Rows {
+                mut columns,
+                mut types,
+                rows,
+            } => {
+                // 窗口函数需要分区内所有的行，先拉取所有的行
+                let mut rows = rows.collect::<RSDBResult<Vec<_>>>()?;
+                let mut order = (0..rows.len()).collect::<Vec<_>>();
+                for (window, name) in self.functions {
+                    let mut partition = Vec::new();
+                    for col_name in &window.partition_by {
+                        partition.push((column_position(&columns, col_name)?, OrderDirection::Asc));
+                    }
+                    let mut keys = partition.clone();
+                    for (col_name, direction) in &window.order_by {
+                        keys.push((column_position(&columns, col_name)?, direction.clone()));
+                    }
+                    // 同一分区的行排序后相邻，分区内按照排序列排序
+                    order = (0..rows.len()).collect();
+                    order.sort_by(|&i, &j| compare_rows(&keys, &rows[i], &rows[j]));
+                    let rank = window.func.eq_ignore_ascii_case("rank");
+                    let (mut number, mut value) = (0, 0);
+                    for pos in 0..order.len() {
+                        let i = order[pos];
+                        match pos.checked_sub(1).map(|p| order[p]) {
+                            Some(prev)
+                                if compare_rows(&partition, &rows[prev], &rows[i]).is_eq() =>
+                            {
+                                number += 1;
+                                if !rank || compare_rows(&keys, &rows[prev], &rows[i]).is_ne() {
+                                    value = number;
+                                }
+                            }
+                            _ => (number, value) = (1, 1),
+                        }
+                        rows[i].push(Value::Integer(value));
+                    }
+                    columns.push(name);
+                    types.push(Some(DataType::Integer));
+                }
+                // 按照最后一个窗口函数的分区和排序顺序输出
+                let rows = order
+                    .into_iter()
+                    .map(|i| std::mem::take(&mut rows[i]))
+                    .collect();
+                Ok(Output::Rows {
+                    columns,
+                    types,
+                    rows: rows_of(rows),
+                })
+            }
+            _ => Err(RSDBError::Internal(
+                "Window source must be a Scan".to_string(),
+            )),
+        }
+    }
+}
+
+// 索引查询，有多个 key 时合并每个 key 对应的主键，按照主键排序
 pub struct IndexScan {
     table_name: String,
-    field: String,
-    value: Value,
+    index: String,
+    keys: Vec<Vec<Value>>,
 }
 
 impl IndexScan {
-    pub fn new(table_name: String, field: String, value: Value) -> Box<Self> {
+    pub fn new(table_name: String, index: String, keys: Vec<Vec<Value>>) -> Box<Self> {
         Box::new(Self {
             table_name,
-            field,
-            value,
+            index,
+            keys,
         })
     }
 }
 
 impl<T: Transaction> Executor<T> for IndexScan {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         let table = txn.must_get_table(self.table_name.clone())?;
-        let index = txn.load_index(&self.table_name, &self.field, &self.value)?;
+        let mut index = HashSet::new();
+        for key in &self.keys {
+            index.extend(txn.load_index(&self.table_name, &self.index, key)?);
+        }
         let mut pks = index.iter().collect::<Vec<_>>();
-        pks.sort_by(|v1, v2| match v1.partial_cmp(v2) {
-            Some(ord) => ord,
-            None => Ordering::Equal,
-        });
+        pks.sort_by(|v1, v2| v1.total_cmp(v2));
         let mut rows = Vec::new();
         for pk in pks {
             if let Some(row) = txn.read_by_pk(&self.table_name, pk)? {
                 rows.push(row);
             }
         }
-        Ok(ResultSet::Scan {
-            columns: table.columns.into_iter().map(|c| c.name).collect(),
-            rows,
+        let (columns, types) = table
+            .columns
+            .into_iter()
+            .map(|c| (c.name, Some(c.datatype)))
+            .unzip();
+        Ok(Output::Rows {
+            columns,
+            types,
+            rows: rows_of(rows),
         })
     }
 }
@@ -302,18 +694,355 @@ impl PrimaryKeyScan {
 }
 
 impl<T: Transaction> Executor<T> for PrimaryKeyScan {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         let table = txn.must_get_table(self.table_name.clone())?;
-        if let Some(row) = txn.read_by_pk(&self.table_name, &self.value)? {
-            Ok(ResultSet::Scan {
-                columns: table.columns.into_iter().map(|c| c.name).collect(),
-                rows: vec![row],
+        let row = txn.read_by_pk(&self.table_name, &self.value)?;
+        let (columns, types) = table
+            .columns
+            .into_iter()
+            .map(|c| (c.name, Some(c.datatype)))
+            .unzip();
+        Ok(Output::Rows {
+            columns,
+            types,
+            rows: rows_of(row.into_iter().collect()),
+        })
+    }
+}
+
+// 空结果，只包含列名
+pub struct Nothing {
+    columns: Vec<String>,
+}
+
+impl Nothing {
+    pub fn new(columns: Vec<String>) -> Box<Self> {
+        Box::new(Self { columns })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Nothing {
+    fn execute(self: Box<Self>, _txn: &mut T) -> RSDBResult<Output> {
+        Ok(Output::Rows {
+            types: vec![None; self.columns.len()],
+            columns: self.columns,
+            rows: rows_of(Vec::new()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::{Distinct, Filter, Limit, Offset, Order, Projection};
+    use crate::{
+        error::RSDBResult,
+        sql::{
+            engine::{Engine, Transaction, kv::KVEngine},
+            executor::{Executor, Output, ResultSet},
+            parser::ast::{Consts, Expression, Operation, OrderDirection},
+            types::{DataType, Row, Value},
+        },
+        storage::memory::MemoryEngine,
+    };
+
+    // 记录被拉取了多少行的数据源
+    struct Counting {
+        count: usize,
+        pulled: Rc<Cell<usize>>,
+    }
+
+    impl<T: Transaction> Executor<T> for Counting {
+        fn execute(self: Box<Self>, _txn: &mut T) -> RSDBResult<Output> {
+            let pulled = self.pulled;
+            Ok(Output::Rows {
+                columns: vec!["a".to_string()],
+                types: vec![Some(DataType::Integer)],
+                rows: Box::new((0..self.count as i64).map(move |i| {
+                    pulled.set(pulled.get() + 1);
+                    Ok(vec![Value::Integer(i)])
+                })),
             })
-        } else {
-            Ok(ResultSet::Scan {
-                columns: table.columns.into_iter().map(|c| c.name).collect(),
-                rows: Vec::new(),
+        }
+    }
+
+    // 手工构造的数据源
+    struct Values {
+        columns: Vec<String>,
+        rows: Vec<Row>,
+    }
+
+    impl<T: Transaction> Executor<T> for Values {
+        fn execute(self: Box<Self>, _txn: &mut T) -> RSDBResult<Output> {
+            Ok(Output::Rows {
+                types: vec![None; self.columns.len()],
+                columns: self.columns,
+                rows: Box::new(self.rows.into_iter().map(Ok)),
             })
         }
     }
+
+    fn execute<T: Transaction>(
+        executor: Box<dyn Executor<T>>,
+        txn: &mut T,
+    ) -> RSDBResult<Vec<Row>> {
+        match executor.execute(txn)?.into_result_set()? {
+            ResultSet::Scan { rows, .. } => Ok(rows),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_stream_pull_early() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+        let ints = |v: &[i64]| {
+            v.iter()
+                .map(|i| vec![Value::Integer(*i)])
+                .collect::<Vec<_>>()
+        };
+        let source = |pulled: &Rc<Cell<usize>>| {
+            Box::new(Counting {
+                count: 10000,
+                pulled: pulled.clone(),
+            })
+        };
+
+        // Limit 拉取到足够的行后就停止
+        let pulled = Rc::new(Cell::new(0));
+        let rows = execute(Limit::new(source(&pulled), 3), &mut txn)?;
+        assert_eq!(rows, ints(&[0, 1, 2]));
+        assert_eq!(pulled.get(), 3);
+
+        let pulled = Rc::new(Cell::new(0));
+        let rows = execute(Limit::new(Offset::new(source(&pulled), 5), 2), &mut txn)?;
+        assert_eq!(rows, ints(&[5, 6]));
+        assert_eq!(pulled.get(), 7);
+
+        // a > 100，过滤掉的行也需要拉取
+        let pulled = Rc::new(Cell::new(0));
+        let predicate = Expression::Operation(Operation::GreaterThan(
+            Box::new(Expression::Field("a".to_string())),
+            Box::new(Expression::Consts(Consts::Integer(100))),
+        ));
+        let rows = execute(
+            Limit::new(Filter::new(source(&pulled), predicate), 1),
+            &mut txn,
+        )?;
+        assert_eq!(rows, ints(&[101]));
+        assert_eq!(pulled.get(), 102);
+
+        // Order 需要拉取所有的行
+        let pulled = Rc::new(Cell::new(0));
+        let order_by = vec![("a".to_string(), OrderDirection::Desc)];
+        let rows = execute(
+            Limit::new(Order::new(source(&pulled), order_by), 1),
+            &mut txn,
+        )?;
+        assert_eq!(rows, ints(&[9999]));
+        assert_eq!(pulled.get(), 10000);
+        txn.rollback()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_distinct() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+        let (i, f, t) = (Value::Integer, Value::Float, |s: &str| {
+            Value::String(s.to_string())
+        });
+        let source = |rows: Vec<Row>| {
+            Box::new(Values {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows,
+            })
+        };
+
+        // 保留第一次出现的行，列名与数据源一致
+        let rows = vec![
+            vec![i(2), t("x")],
+            vec![i(1), t("y")],
+            vec![i(2), t("x")],
+            vec![i(2), t("y")],
+            vec![i(1), t("y")],
+        ];
+        match Distinct::new(source(rows))
+            .execute(&mut txn)?
+            .into_result_set()?
+        {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["a".to_string(), "b".to_string()]);
+                assert_eq!(
+                    rows,
+                    vec![vec![i(2), t("x")], vec![i(1), t("y")], vec![i(2), t("y")],]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // NULL 与 NULL 视为重复，但与其他值不重复
+        let rows = vec![
+            vec![Value::Null, Value::Null],
+            vec![i(1), Value::Null],
+            vec![Value::Null, Value::Null],
+            vec![i(1), Value::Null],
+            vec![f(1.5), t("")],
+            vec![f(1.5), t("")],
+        ];
+        assert_eq!(
+            execute(Distinct::new(source(rows)), &mut txn)?,
+            vec![
+                vec![Value::Null, Value::Null],
+                vec![i(1), Value::Null],
+                vec![f(1.5), t("")],
+            ]
+        );
+
+        // 空输入
+        assert!(execute(Distinct::new(source(vec![])), &mut txn)?.is_empty());
+
+        // 按需拉取，Limit 之后不再继续拉取
+        let pulled = Rc::new(Cell::new(0));
+        let counting = Box::new(Counting {
+            count: 100,
+            pulled: pulled.clone(),
+        });
+        let rows = execute(Limit::new(Distinct::new(counting), 2), &mut txn)?;
+        assert_eq!(rows, vec![vec![i(0)], vec![i(1)]]);
+        assert_eq!(pulled.get(), 2);
+        txn.rollback()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_spill() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+        // 包含重复值与 NULL，第二列用于检查相等行的先后顺序
+        let rows = (0..1000)
+            .map(|i: i64| {
+                let a = match i % 7 {
+                    0 => Value::Null,
+                    _ => Value::Integer((i * 37) % 101),
+                };
+                vec![a, Value::Integer(i)]
+            })
+            .collect::<Vec<_>>();
+        let source = || {
+            Box::new(Values {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: rows.clone(),
+            })
+        };
+
+        for direction in [OrderDirection::Asc, OrderDirection::Desc] {
+            let order_by = vec![("a".to_string(), direction)];
+            let expected = execute(Order::new(source(), order_by.clone()), &mut txn)?;
+            assert_eq!(expected.len(), 1000);
+            for threshold in [1, 7, 100, 999, 1000] {
+                let order = Order::new(source(), order_by.clone()).with_spill_threshold(threshold);
+                assert_eq!(execute(order, &mut txn)?, expected);
+            }
+        }
+
+        // NULL 排在最前面
+        let order_by = vec![
+            ("a".to_string(), OrderDirection::Asc),
+            ("b".to_string(), OrderDirection::Desc),
+        ];
+        let rows = execute(
+            Order::new(source(), order_by).with_spill_threshold(64),
+            &mut txn,
+        )?;
+        assert_eq!(rows[0], vec![Value::Null, Value::Integer(994)]);
+        assert!(rows.windows(2).all(|w| {
+            w[0][0]
+                .total_cmp(&w[1][0])
+                .then(w[1][1].total_cmp(&w[0][1]))
+                == std::cmp::Ordering::Less
+        }));
+
+        // 与 Limit 组合
+        let order_by = vec![("b".to_string(), OrderDirection::Desc)];
+        let rows = execute(
+            Limit::new(Order::new(source(), order_by).with_spill_threshold(10), 2),
+            &mut txn,
+        )?;
+        assert_eq!(
+            rows.into_iter().map(|r| r[1].clone()).collect::<Vec<_>>(),
+            vec![Value::Integer(999), Value::Integer(998)]
+        );
+        txn.rollback()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_projection_exprs() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+        let field = |name: &str| Box::new(Expression::Field(name.to_string()));
+        let int = |i: i64| Box::new(Expression::Consts(Consts::Integer(i)));
+        let source = || {
+            Box::new(Values {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![
+                    vec![Value::Integer(1), Value::Integer(10)],
+                    vec![Value::Integer(2), Value::Null],
+                ],
+            })
+        };
+
+        // 列、常量与算术表达式，没有别名时以表达式文本作为列名
+        let exprs = vec![
+            (*field("b"), None),
+            (*int(1), None),
+            (
+                Expression::Operation(Operation::Add(field("a"), field("b"))),
+                None,
+            ),
+            (
+                Expression::Operation(Operation::Multiply(field("a"), int(3))),
+                Some("x".to_string()),
+            ),
+        ];
+        match Projection::new(source(), exprs)
+            .execute(&mut txn)?
+            .into_result_set()?
+        {
+            ResultSet::Scan { columns, rows, .. } => {
+                assert_eq!(columns, vec!["b", "1", "a + b", "x"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![
+                            Value::Integer(10),
+                            Value::Integer(1),
+                            Value::Integer(11),
+                            Value::Integer(3)
+                        ],
+                        vec![
+                            Value::Null,
+                            Value::Integer(1),
+                            Value::Null,
+                            Value::Integer(6)
+                        ],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 表达式中引用了不存在的列
+        let exprs = vec![(
+            Expression::Operation(Operation::Add(field("a"), field("c"))),
+            None,
+        )];
+        assert!(execute(Projection::new(source(), exprs), &mut txn).is_err());
+        let exprs = vec![(*field("c"), None)];
+        assert!(execute(Projection::new(source(), exprs), &mut txn).is_err());
+        txn.rollback()?;
+        Ok(())
+    }
 }
@@ -2,8 +2,9 @@ use crate::{
     error::RSDBResult,
     sql::{
         engine::Transaction,
-        executor::{Executor, ResultSet},
-        schema::Table,
+        executor::{Executor, Output, ResultSet, rows_of},
+        schema::{ColumnStats, Index, Table, TableStats},
+        types::{DataType, Value},
     },
 };
 
@@ -18,28 +19,279 @@ impl CreateTable {
 }
 
 impl<T: Transaction> Executor<T> for CreateTable {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
         let table_name = self.schema.name.clone();
         txn.create_table(self.schema)?;
-        Ok(ResultSet::CreateTable { table_name })
+        Ok(Output::Result(ResultSet::CreateTable { table_name }))
     }
 }
 
 pub struct DropTable {
     table_name: String,
+    if_exists: bool,
 }
 
 impl DropTable {
-    pub fn new(table_name: String) -> Box<Self> {
-        Box::new(Self { table_name })
+    pub fn new(table_name: String, if_exists: bool) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            if_exists,
+        })
     }
 }
 
 impl<T: Transaction> Executor<T> for DropTable {
-    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<ResultSet> {
-        txn.drop_table(self.table_name.clone())?;
-        Ok(ResultSet::DropTable {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        // IF EXISTS 时表不存在则什么都不做
+        if !self.if_exists || txn.get_table(self.table_name.clone())?.is_some() {
+            txn.drop_table(self.table_name.clone())?;
+        }
+        Ok(Output::Result(ResultSet::DropTable {
             table_name: self.table_name,
+        }))
+    }
+}
+
+pub struct CreateIndex {
+    table_name: String,
+    index: Index,
+}
+
+impl CreateIndex {
+    pub fn new(table_name: String, index: Index) -> Box<Self> {
+        Box::new(Self { table_name, index })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateIndex {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        let index_name = self.index.name.clone();
+        txn.create_index(&self.table_name, self.index)?;
+        Ok(Output::Result(ResultSet::CreateIndex { index_name }))
+    }
+}
+
+pub struct CreateSequence {
+    name: String,
+}
+
+impl CreateSequence {
+    pub fn new(name: String) -> Box<Self> {
+        Box::new(Self { name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateSequence {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        txn.create_sequence(&self.name)?;
+        Ok(Output::Result(ResultSet::CreateSequence {
+            name: self.name,
+        }))
+    }
+}
+
+pub struct DropSequence {
+    name: String,
+    if_exists: bool,
+}
+
+impl DropSequence {
+    pub fn new(name: String, if_exists: bool) -> Box<Self> {
+        Box::new(Self { name, if_exists })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropSequence {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        // IF EXISTS 时序列不存在则什么都不做
+        if !self.if_exists || txn.sequence_exists(&self.name)? {
+            txn.drop_sequence(&self.name)?;
+        }
+        Ok(Output::Result(ResultSet::DropSequence { name: self.name }))
+    }
+}
+
+pub struct CreateDatabase {
+    name: String,
+}
+
+impl CreateDatabase {
+    pub fn new(name: String) -> Box<Self> {
+        Box::new(Self { name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateDatabase {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        txn.create_database(&self.name)?;
+        Ok(Output::Result(ResultSet::CreateDatabase {
+            name: self.name,
+        }))
+    }
+}
+
+pub struct DropDatabase {
+    name: String,
+    if_exists: bool,
+}
+
+impl DropDatabase {
+    pub fn new(name: String, if_exists: bool) -> Box<Self> {
+        Box::new(Self { name, if_exists })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropDatabase {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        // IF EXISTS 时数据库不存在则什么都不做
+        if !self.if_exists || txn.database_exists(&self.name)? {
+            txn.drop_database(&self.name)?;
+        }
+        Ok(Output::Result(ResultSet::DropDatabase { name: self.name }))
+    }
+}
+
+pub struct DescribeTable {
+    table_name: String,
+}
+
+impl DescribeTable {
+    pub fn new(table_name: String) -> Box<Self> {
+        Box::new(Self { table_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DescribeTable {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        let table = txn.must_get_table(self.table_name)?;
+        // 每一列展示为一行
+        let rows = table
+            .columns
+            .into_iter()
+            .map(|col| {
+                let datatype = col.datatype_desc();
+                vec![
+                    Value::String(col.name),
+                    Value::String(datatype),
+                    Value::Boolean(col.nullable),
+                    match col.default_sequence {
+                        Some(name) => Value::String(format!("nextval('{}')", name)),
+                        None => col.default.unwrap_or(Value::Null),
+                    },
+                    Value::Boolean(col.primary_key),
+                    Value::Boolean(col.index),
+                ]
+            })
+            .collect();
+        Ok(Output::Rows {
+            columns: vec![
+                "name".to_string(),
+                "datatype".to_string(),
+                "nullable".to_string(),
+                "default".to_string(),
+                "primary_key".to_string(),
+                "index".to_string(),
+            ],
+            types: vec![
+                Some(DataType::String),
+                Some(DataType::String),
+                Some(DataType::Boolean),
+                None,
+                Some(DataType::Boolean),
+                Some(DataType::Boolean),
+            ],
+            rows: rows_of(rows),
+        })
+    }
+}
+
+pub struct ShowTables {
+    extended: bool,
+}
+
+impl ShowTables {
+    pub fn new(extended: bool) -> Box<Self> {
+        Box::new(Self { extended })
+    }
+}
+
+impl<T: Transaction> Executor<T> for ShowTables {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        let names = txn.get_table_names()?;
+        if !self.extended {
+            return Ok(Output::Rows {
+                columns: vec!["name".to_string()],
+                types: vec![Some(DataType::String)],
+                rows: rows_of(names.into_iter().map(|n| vec![Value::String(n)]).collect()),
+            });
+        }
+        // 每个表都需要完整扫描一遍，表很大时开销较高
+        let mut rows = Vec::new();
+        for name in names {
+            let size = txn.table_size(&txn.must_get_table(name.clone())?)?;
+            rows.push(vec![
+                Value::String(name),
+                Value::Integer(size.rows as i64),
+                Value::Integer(size.bytes as i64),
+            ]);
+        }
+        Ok(Output::Rows {
+            columns: vec!["name".to_string(), "rows".to_string(), "bytes".to_string()],
+            types: vec![
+                Some(DataType::String),
+                Some(DataType::Integer),
+                Some(DataType::Integer),
+            ],
+            rows: rows_of(rows),
         })
     }
 }
+
+pub struct Analyze {
+    table_name: String,
+}
+
+impl Analyze {
+    pub fn new(table_name: String) -> Box<Self> {
+        Box::new(Self { table_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Analyze {
+    fn execute(self: Box<Self>, txn: &mut T) -> RSDBResult<Output> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        let rows = txn.scan_table(&table, None)?;
+        let columns = table
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, col)| {
+                // NULL 不参与 distinct 和 min/max 的统计
+                let mut values = rows
+                    .iter()
+                    .map(|row| &row[i])
+                    .filter(|v| **v != Value::Null)
+                    .collect::<Vec<_>>();
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                let min = values.first().map_or(Value::Null, |v| (*v).clone());
+                let max = values.last().map_or(Value::Null, |v| (*v).clone());
+                values.dedup();
+                ColumnStats {
+                    name: col.name.clone(),
+                    distinct: values.len(),
+                    min,
+                    max,
+                }
+            })
+            .collect();
+        let stats = TableStats {
+            row_count: rows.len(),
+            columns,
+        };
+        txn.save_stats(&self.table_name, &stats)?;
+        Ok(Output::Result(ResultSet::Analyze {
+            table_name: self.table_name,
+            row_count: stats.row_count,
+        }))
+    }
+}
@@ -1,19 +1,48 @@
-use std::collections::BTreeMap;
+use std::{cmp::Ordering, collections::BTreeMap, fmt::Display, ops::Rem};
 
 use crate::{
     error::{RSDBError, RSDBResult},
-    sql::types::{DataType, Value},
+    sql::types::{self, DataType, IntegerWidth, Value},
 };
 
 // Abstract Syntax Tree 抽象语法树
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
     CreateTable {
         name: String,
         columns: Vec<Column>,
+        // 临时表，只对创建它的 session 可见，session 结束时删除
+        temporary: bool,
     },
     DropTable {
         name: String,
+        if_exists: bool,
+    },
+    CreateSequence {
+        name: String,
+    },
+    DropSequence {
+        name: String,
+        if_exists: bool,
+    },
+    // 数据库，表名不带数据库名时属于 session 的当前数据库
+    CreateDatabase {
+        name: String,
+    },
+    DropDatabase {
+        name: String,
+        if_exists: bool,
+    },
+    // 切换 session 的当前数据库
+    UseDatabase {
+        name: String,
+    },
+    // 清理旧版本并压缩存储引擎
+    Vacuum,
+    CreateIndex {
+        name: String,
+        table_name: String,
+        columns: Vec<String>,
     },
     Insert {
         table_name: String,
@@ -24,11 +53,13 @@ pub enum Statement {
         select: Vec<(Expression, Option<String>)>,
         from: FromItem,
         where_clause: Option<Expression>,
-        group_by: Option<Expression>,
+        group_by: Vec<Expression>,
         having: Option<Expression>,
         order_by: Vec<(String, OrderDirection)>,
         limit: Option<Expression>,
         offset: Option<Expression>,
+        // 查询历史版本的数据，as of version n
+        as_of: Option<u64>,
     },
     Update {
         table_name: String,
@@ -39,16 +70,51 @@ pub enum Statement {
         table_name: String,
         where_clause: Option<Expression>,
     },
-    Begin,
+    Begin {
+        read_only: bool,
+        as_of: Option<u64>,
+        serializable: bool,
+    },
     Commit,
     Rollback,
+    Explain {
+        stmt: Box<Statement>,
+        // EXPLAIN ANALYZE，实际执行并统计每个节点的行数和耗时
+        analyze: bool,
+    },
+    Describe {
+        table_name: String,
+    },
+    // 列出所有的表，extended 时同时统计每个表的行数和大小
+    ShowTables {
+        extended: bool,
+    },
+    Analyze {
+        table_name: String,
+    },
+    // 修改 session 级别的设置，值只能是常量
+    Set {
+        name: String,
+        value: Expression,
+    },
+    // 查看 session 级别的设置，None 表示 SHOW ALL
+    ShowSettings {
+        name: Option<String>,
+    },
+    // 查看当前事务的状态
+    ShowTransaction,
+    // 查看 MVCC 和存储引擎的统计信息
+    ShowStatus,
 }
 
 // 列定义
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Column {
     pub name: String,
     pub datatype: DataType,
+    pub max_length: Option<usize>,       // varchar(32) 中的长度
+    pub int_width: Option<IntegerWidth>, // tinyint、smallint、bigint 的宽度
+    pub precision: Option<u32>,          // decimal(5, 2) 中的精度
     pub nullable: Option<bool>,
     pub default: Option<Expression>,
     pub primary_key: bool,
@@ -61,7 +127,36 @@ pub enum Expression {
     Field(String), // 列名
     Consts(Consts),
     Operation(Operation),
-    Function(String, String), // 聚集函数名和参数
+    Function(String, String),           // 聚集函数名和参数
+    Placeholder(usize),                 // 参数占位符 ?，按出现顺序从 0 开始编号
+    Window(WindowFunction),             // 窗口函数，只能出现在 SELECT 中
+    Sequence(SequenceFunction, String), // 序列函数和序列名，如 nextval('s')，可以出现在 SELECT、INSERT、UPDATE 和默认值中
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SequenceFunction {
+    // 分配序列的下一个值
+    NextVal,
+    // 序列最近一次分配的值
+    CurrVal,
+}
+
+impl Display for SequenceFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceFunction::NextVal => write!(f, "nextval"),
+            SequenceFunction::CurrVal => write!(f, "currval"),
+        }
+    }
+}
+
+// 窗口函数，如 ROW_NUMBER() OVER (PARTITION BY a ORDER BY b DESC)
+// 目前只支持 ROW_NUMBER 和 RANK，不支持窗口帧
+#[derive(Debug, PartialEq, Clone)]
+pub struct WindowFunction {
+    pub func: String,
+    pub partition_by: Vec<String>,
+    pub order_by: Vec<(String, OrderDirection)>,
 }
 
 impl From<Consts> for Expression {
@@ -70,6 +165,341 @@ impl From<Consts> for Expression {
     }
 }
 
+impl From<Value> for Expression {
+    fn from(value: Value) -> Self {
+        Self::Consts(match value {
+            Value::Null => Consts::Null,
+            Value::Boolean(b) => Consts::Boolean(b),
+            Value::Integer(i) => Consts::Integer(i),
+            Value::Float(f) => Consts::Float(f),
+            Value::String(s) => Consts::String(s),
+            Value::Date(d) => Consts::Date(d),
+            Value::Timestamp(t) => Consts::Timestamp(t),
+            Value::Decimal(v, scale) => Consts::Decimal(v, scale),
+            Value::Bytes(b) => Consts::Bytes(b),
+        })
+    }
+}
+
+impl Statement {
+    // 将参数占位符替换为对应的参数值，参数个数必须与占位符个数一致
+    pub fn bind(&mut self, params: &[Value]) -> RSDBResult<()> {
+        let mut count = 0;
+        self.bind_inner(params, &mut count);
+        if count != params.len() {
+            return Err(RSDBError::Internal(format!(
+                "expected {} parameters, got {}",
+                count,
+                params.len()
+            )));
+        }
+        Ok(())
+    }
+
+    // 语句中占位符的个数
+    pub fn param_count(&self) -> usize {
+        let mut count = 0;
+        self.clone().bind_inner(&[], &mut count);
+        count
+    }
+
+    // 自动提交时是否需要在读写事务中执行，即查询中调用了 nextval
+    pub fn calls_nextval(&self) -> bool {
+        match self {
+            Statement::Select { select, .. } => select
+                .iter()
+                .any(|(expr, _)| expr.has_sequence(Some(&SequenceFunction::NextVal))),
+            _ => false,
+        }
+    }
+
+    // 语句中引用的所有表名
+    pub fn tables(&self) -> Vec<String> {
+        match self {
+            Statement::CreateTable { name, .. } | Statement::DropTable { name, .. } => {
+                vec![name.clone()]
+            }
+            Statement::CreateIndex { table_name, .. }
+            | Statement::Insert { table_name, .. }
+            | Statement::Update { table_name, .. }
+            | Statement::Delete { table_name, .. }
+            | Statement::Describe { table_name }
+            | Statement::Analyze { table_name } => vec![table_name.clone()],
+            Statement::Select { from, .. } => {
+                let mut tables = Vec::new();
+                from.tables(&mut tables);
+                tables
+            }
+            Statement::Explain { stmt, .. } => stmt.tables(),
+            Statement::Begin { .. }
+            | Statement::Commit
+            | Statement::Rollback
+            | Statement::Set { .. }
+            | Statement::ShowSettings { .. }
+            | Statement::ShowTransaction
+            | Statement::ShowStatus
+            | Statement::ShowTables { .. }
+            | Statement::CreateSequence { .. }
+            | Statement::DropSequence { .. }
+            | Statement::CreateDatabase { .. }
+            | Statement::DropDatabase { .. }
+            | Statement::UseDatabase { .. }
+            | Statement::Vacuum => Vec::new(),
+        }
+    }
+
+    // 替换语句中引用的表名，f 返回 None 时保持不变，用于将临时表替换为实际的表名
+    pub fn rename_tables(&mut self, f: &dyn Fn(&str) -> Option<String>) {
+        let rename = |name: &mut String| {
+            if let Some(new_name) = f(name) {
+                *name = new_name;
+            }
+        };
+        match self {
+            Statement::CreateTable { name, .. } | Statement::DropTable { name, .. } => rename(name),
+            Statement::CreateIndex { table_name, .. }
+            | Statement::Insert { table_name, .. }
+            | Statement::Update { table_name, .. }
+            | Statement::Delete { table_name, .. }
+            | Statement::Describe { table_name }
+            | Statement::Analyze { table_name } => rename(table_name),
+            Statement::Select { from, .. } => from.rename_tables(f),
+            Statement::Explain { stmt, .. } => stmt.rename_tables(f),
+            _ => {}
+        }
+    }
+
+    fn bind_inner(&mut self, params: &[Value], count: &mut usize) {
+        let mut bind = |expr: &mut Expression| expr.bind(params, count);
+        match self {
+            Statement::CreateTable { columns, .. } => columns
+                .iter_mut()
+                .filter_map(|c| c.default.as_mut())
+                .for_each(bind),
+            Statement::Insert { values, .. } => values.iter_mut().flatten().for_each(bind),
+            Statement::Select {
+                select,
+                from,
+                where_clause,
+                group_by,
+                having,
+                limit,
+                offset,
+                ..
+            } => {
+                select.iter_mut().for_each(|(expr, _)| bind(expr));
+                from.bind(params, count);
+                group_by
+                    .iter_mut()
+                    .for_each(|expr| expr.bind(params, count));
+                [where_clause, having, limit, offset]
+                    .into_iter()
+                    .flatten()
+                    .for_each(|expr| expr.bind(params, count));
+            }
+            Statement::Update {
+                columns,
+                where_clause,
+                ..
+            } => {
+                columns.values_mut().for_each(&mut bind);
+                where_clause.iter_mut().for_each(bind);
+            }
+            Statement::Delete { where_clause, .. } => where_clause.iter_mut().for_each(bind),
+            Statement::Explain { stmt, .. } => stmt.bind_inner(params, count),
+            _ => {}
+        }
+    }
+}
+
+impl FromItem {
+    // 表没有别名时，以原来的表名作为别名，列名仍然可以用原来的表名限定
+    fn rename_tables(&mut self, f: &dyn Fn(&str) -> Option<String>) {
+        match self {
+            FromItem::Table { name, alias } => {
+                if let Some(new_name) = f(name) {
+                    alias.get_or_insert_with(|| name.clone());
+                    *name = new_name;
+                }
+            }
+            FromItem::Join { left, right, .. } => {
+                left.rename_tables(f);
+                right.rename_tables(f);
+            }
+        }
+    }
+
+    fn tables(&self, tables: &mut Vec<String>) {
+        match self {
+            FromItem::Table { name, .. } => tables.push(name.clone()),
+            FromItem::Join { left, right, .. } => {
+                left.tables(tables);
+                right.tables(tables);
+            }
+        }
+    }
+
+    fn bind(&mut self, params: &[Value], count: &mut usize) {
+        if let FromItem::Join {
+            left,
+            right,
+            predicate,
+            ..
+        } = self
+        {
+            left.bind(params, count);
+            right.bind(params, count);
+            if let Some(expr) = predicate {
+                expr.bind(params, count);
+            }
+        }
+    }
+}
+
+impl Expression {
+    // 替换表达式中引用的列名
+    pub fn map_fields(&self, f: &dyn Fn(&String) -> String) -> Expression {
+        let Expression::Operation(operation) = self else {
+            return match self {
+                Expression::Field(col) => Expression::Field(f(col)),
+                Expression::Function(func, col) => Expression::Function(func.clone(), f(col)),
+                Expression::Window(window) => Expression::Window(WindowFunction {
+                    func: window.func.clone(),
+                    partition_by: window.partition_by.iter().map(f).collect(),
+                    order_by: window
+                        .order_by
+                        .iter()
+                        .map(|(col, dir)| (f(col), dir.clone()))
+                        .collect(),
+                }),
+                expr => expr.clone(),
+            };
+        };
+        type Ctor = fn(Box<Expression>, Box<Expression>) -> Operation;
+        let (ctor, l, r): (Ctor, _, _) = match operation {
+            Operation::Equal(l, r) => (Operation::Equal, l, r),
+            Operation::GreaterThan(l, r) => (Operation::GreaterThan, l, r),
+            Operation::LessThan(l, r) => (Operation::LessThan, l, r),
+            Operation::And(l, r) => (Operation::And, l, r),
+            Operation::Or(l, r) => (Operation::Or, l, r),
+            Operation::Add(l, r) => (Operation::Add, l, r),
+            Operation::Subtract(l, r) => (Operation::Subtract, l, r),
+            Operation::Multiply(l, r) => (Operation::Multiply, l, r),
+            Operation::Divide(l, r) => (Operation::Divide, l, r),
+            Operation::Modulo(l, r) => (Operation::Modulo, l, r),
+            Operation::Div(l, r) => (Operation::Div, l, r),
+        };
+        Expression::Operation(ctor(Box::new(l.map_fields(f)), Box::new(r.map_fields(f))))
+    }
+
+    // 替换表达式中的聚集函数
+    pub fn map_functions(&self, f: &mut dyn FnMut(&String, &String) -> Expression) -> Expression {
+        let Expression::Operation(operation) = self else {
+            return match self {
+                Expression::Function(func, col) => f(func, col),
+                expr => expr.clone(),
+            };
+        };
+        type Ctor = fn(Box<Expression>, Box<Expression>) -> Operation;
+        let (ctor, l, r): (Ctor, _, _) = match operation {
+            Operation::Equal(l, r) => (Operation::Equal, l, r),
+            Operation::GreaterThan(l, r) => (Operation::GreaterThan, l, r),
+            Operation::LessThan(l, r) => (Operation::LessThan, l, r),
+            Operation::And(l, r) => (Operation::And, l, r),
+            Operation::Or(l, r) => (Operation::Or, l, r),
+            Operation::Add(l, r) => (Operation::Add, l, r),
+            Operation::Subtract(l, r) => (Operation::Subtract, l, r),
+            Operation::Multiply(l, r) => (Operation::Multiply, l, r),
+            Operation::Divide(l, r) => (Operation::Divide, l, r),
+            Operation::Modulo(l, r) => (Operation::Modulo, l, r),
+            Operation::Div(l, r) => (Operation::Div, l, r),
+        };
+        let l = l.map_functions(f);
+        let r = r.map_functions(f);
+        Expression::Operation(ctor(Box::new(l), Box::new(r)))
+    }
+
+    // 将序列函数替换为 f 返回的值，按照从左到右的顺序调用
+    pub fn map_sequences(
+        &self,
+        f: &mut dyn FnMut(&SequenceFunction, &String) -> RSDBResult<Value>,
+    ) -> RSDBResult<Expression> {
+        let Expression::Operation(operation) = self else {
+            return Ok(match self {
+                Expression::Sequence(func, name) => f(func, name)?.into(),
+                expr => expr.clone(),
+            });
+        };
+        type Ctor = fn(Box<Expression>, Box<Expression>) -> Operation;
+        let (ctor, l, r): (Ctor, _, _) = match operation {
+            Operation::Equal(l, r) => (Operation::Equal, l, r),
+            Operation::GreaterThan(l, r) => (Operation::GreaterThan, l, r),
+            Operation::LessThan(l, r) => (Operation::LessThan, l, r),
+            Operation::And(l, r) => (Operation::And, l, r),
+            Operation::Or(l, r) => (Operation::Or, l, r),
+            Operation::Add(l, r) => (Operation::Add, l, r),
+            Operation::Subtract(l, r) => (Operation::Subtract, l, r),
+            Operation::Multiply(l, r) => (Operation::Multiply, l, r),
+            Operation::Divide(l, r) => (Operation::Divide, l, r),
+            Operation::Modulo(l, r) => (Operation::Modulo, l, r),
+            Operation::Div(l, r) => (Operation::Div, l, r),
+        };
+        let l = l.map_sequences(f)?;
+        let r = r.map_sequences(f)?;
+        Ok(Expression::Operation(ctor(Box::new(l), Box::new(r))))
+    }
+
+    // 表达式中是否调用了序列函数，func 为 None 时匹配任意序列函数
+    pub fn has_sequence(&self, func: Option<&SequenceFunction>) -> bool {
+        match self {
+            Expression::Sequence(f, _) => func.is_none_or(|func| func == f),
+            Expression::Operation(
+                Operation::Equal(l, r)
+                | Operation::GreaterThan(l, r)
+                | Operation::LessThan(l, r)
+                | Operation::And(l, r)
+                | Operation::Or(l, r)
+                | Operation::Add(l, r)
+                | Operation::Subtract(l, r)
+                | Operation::Multiply(l, r)
+                | Operation::Divide(l, r)
+                | Operation::Modulo(l, r)
+                | Operation::Div(l, r),
+            ) => l.has_sequence(func) || r.has_sequence(func),
+            _ => false,
+        }
+    }
+
+    // 替换占位符，并统计占位符的个数
+    fn bind(&mut self, params: &[Value], count: &mut usize) {
+        match self {
+            Expression::Placeholder(i) => {
+                *count += 1;
+                if let Some(value) = params.get(*i) {
+                    *self = value.clone().into();
+                }
+            }
+            Expression::Operation(
+                Operation::Equal(l, r)
+                | Operation::GreaterThan(l, r)
+                | Operation::LessThan(l, r)
+                | Operation::And(l, r)
+                | Operation::Or(l, r)
+                | Operation::Add(l, r)
+                | Operation::Subtract(l, r)
+                | Operation::Multiply(l, r)
+                | Operation::Divide(l, r)
+                | Operation::Modulo(l, r)
+                | Operation::Div(l, r),
+            ) => {
+                l.bind(params, count);
+                r.bind(params, count);
+            }
+            _ => {}
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Consts {
     Null,
@@ -77,33 +507,54 @@ pub enum Consts {
     Integer(i64),
     Float(f64),
     String(String),
+    Date(i64),          // DATE '2024-01-31'，距 1970-01-01 的天数
+    Timestamp(i64),     // TIMESTAMP '2024-01-31 12:00:00'，距 1970-01-01 00:00:00 的微秒数
+    Decimal(i128, u32), // DECIMAL '12.34'，值和小数位数
+    Bytes(Vec<u8>),     // x'deadbeef'
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum OrderDirection {
     Asc,
     Desc,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum FromItem {
     Table {
         name: String,
+        // 表的别名，如 from employees e，列名以别名限定
+        alias: Option<String>,
     },
     Join {
         left: Box<FromItem>,
         right: Box<FromItem>,
         join_type: JoinType,
         predicate: Option<Expression>,
+        // USING 子句中的列，结果中只保留一份
+        using: Vec<String>,
     },
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum JoinType {
     Cross,
     Inner,
     Left,
     Right,
+    Full,
+}
+
+impl JoinType {
+    // 外连接中没有匹配的行也需要输出的一侧，分别为左表、右表
+    pub fn keeps(&self) -> (bool, bool) {
+        match self {
+            JoinType::Cross | JoinType::Inner => (false, false),
+            JoinType::Left => (true, false),
+            JoinType::Right => (false, true),
+            JoinType::Full => (true, true),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -111,6 +562,188 @@ pub enum Operation {
     Equal(Box<Expression>, Box<Expression>),
     GreaterThan(Box<Expression>, Box<Expression>),
     LessThan(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    Divide(Box<Expression>, Box<Expression>),
+    Modulo(Box<Expression>, Box<Expression>),
+    Div(Box<Expression>, Box<Expression>),
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expression::Field(col_name) => write!(f, "{}", col_name),
+            Expression::Consts(c) => write!(f, "{}", c),
+            Expression::Operation(operation) => match operation {
+                Operation::Equal(l, r) => write!(f, "{} = {}", l, r),
+                Operation::GreaterThan(l, r) => write!(f, "{} > {}", l, r),
+                Operation::LessThan(l, r) => write!(f, "{} < {}", l, r),
+                Operation::And(l, r) => write!(f, "{} AND {}", l, r),
+                Operation::Or(l, r) => write!(f, "{} OR {}", l, r),
+                Operation::Add(l, r) => write!(f, "{} + {}", l, r),
+                Operation::Subtract(l, r) => write!(f, "{} - {}", l, r),
+                Operation::Multiply(l, r) => write!(f, "{} * {}", l, r),
+                Operation::Divide(l, r) => write!(f, "{} / {}", l, r),
+                Operation::Modulo(l, r) => write!(f, "{} % {}", l, r),
+                Operation::Div(l, r) => write!(f, "{} DIV {}", l, r),
+            },
+            Expression::Function(func_name, col_name) => write!(f, "{}({})", func_name, col_name),
+            Expression::Placeholder(_) => write!(f, "?"),
+            Expression::Window(window) => write!(f, "{}", window),
+            Expression::Sequence(func, name) => write!(f, "{}('{}')", func, name),
+        }
+    }
+}
+
+impl Display for WindowFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut spec = Vec::new();
+        if !self.partition_by.is_empty() {
+            spec.push(format!("PARTITION BY {}", self.partition_by.join(", ")));
+        }
+        if !self.order_by.is_empty() {
+            let orders = self
+                .order_by
+                .iter()
+                .map(|(col, dir)| format!("{} {}", col, dir))
+                .collect::<Vec<_>>()
+                .join(", ");
+            spec.push(format!("ORDER BY {}", orders));
+        }
+        write!(
+            f,
+            "{}() OVER ({})",
+            self.func.to_uppercase(),
+            spec.join(" ")
+        )
+    }
+}
+
+impl Display for Consts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Consts::Null => write!(f, "NULL"),
+            Consts::Boolean(b) if *b => write!(f, "TRUE"),
+            Consts::Boolean(_) => write!(f, "FALSE"),
+            Consts::Integer(i) => write!(f, "{}", i),
+            Consts::Float(fl) => write!(f, "{}", types::format_float(*fl)),
+            Consts::String(s) => write!(f, "'{}'", s),
+            Consts::Date(d) => write!(f, "DATE '{}'", types::format_date(*d)),
+            Consts::Timestamp(t) => write!(f, "TIMESTAMP '{}'", types::format_timestamp(*t)),
+            Consts::Decimal(v, scale) => {
+                write!(f, "DECIMAL '{}'", types::format_decimal(*v, *scale))
+            }
+            Consts::Bytes(b) => write!(f, "x'{}'", types::format_hex(b)),
+        }
+    }
+}
+
+impl Display for OrderDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderDirection::Asc => write!(f, "ASC"),
+            OrderDirection::Desc => write!(f, "DESC"),
+        }
+    }
+}
+
+// 两侧类型不同时，先统一成相同的类型
+// - 日期时间与字符串、日期与时间戳比较，如 d > '2024-01-01' 中的字符串会按照 d 的类型进行解析
+// - 定点数与整数、浮点数、字符串运算时，转换为定点数
+// - 浮点数与字符串比较，如 f = 'NaN'
+fn coerce_operands(lval: Value, rval: Value) -> RSDBResult<(Value, Value)> {
+    Ok(match (lval, rval) {
+        (l @ Value::Date(_), r @ (Value::String(_) | Value::Timestamp(_)))
+        | (l @ Value::Timestamp(_), r @ Value::String(_)) => {
+            let datatype = l.datatype().unwrap();
+            let r = r.coerce(&datatype)?;
+            match r.datatype() {
+                Some(DataType::Timestamp) => (l.coerce(&DataType::Timestamp)?, r),
+                _ => (l, r),
+            }
+        }
+        (l @ Value::Float(_), r @ Value::String(_)) => (l, r.coerce(&DataType::Float)?),
+        (l @ Value::Decimal(..), r @ (Value::Integer(_) | Value::Float(_) | Value::String(_))) => {
+            let (v, scale) = r.to_decimal()?;
+            (l, Value::Decimal(v, scale))
+        }
+        (l @ (Value::String(_) | Value::Timestamp(_)), r @ Value::Date(_))
+        | (l @ Value::String(_), r @ Value::Timestamp(_))
+        | (l @ Value::String(_), r @ Value::Float(_))
+        | (l @ (Value::Integer(_) | Value::Float(_) | Value::String(_)), r @ Value::Decimal(..)) => {
+            let (r, l) = coerce_operands(r, l)?;
+            (l, r)
+        }
+        (l, r) => (l, r),
+    })
+}
+
+// 查找列的位置，Join 的结果中列名以表名限定，如 t1.a
+// - 完全相同的列名优先
+// - 没有限定的列名 a 可以匹配任意表中唯一的 a，如 t1.a
+// - 限定的列名 t.a 可以匹配没有限定的列 a，如单表查询、USING 合并后的列
+pub fn column_position(columns: &[String], name: &str) -> RSDBResult<usize> {
+    if let Some(pos) = columns.iter().position(|c| c == name) {
+        return Ok(pos);
+    }
+    let bare = name.split_once('.').map(|(_, col)| col);
+    let mut found = columns.iter().enumerate().filter(|(_, c)| match bare {
+        Some(col) => *c == col,
+        None => {
+            c.len() > name.len()
+                && c.ends_with(name)
+                && c.as_bytes()[c.len() - name.len() - 1] == b'.'
+        }
+    });
+    match (found.next(), found.next()) {
+        (Some((pos, _)), None) => Ok(pos),
+        (Some(_), Some(_)) => Err(RSDBError::AmbiguousColumn(name.to_string())),
+        (None, _) => Err(RSDBError::Internal(format!(
+            "Column {} not found in table",
+            name
+        ))),
+    }
+}
+
+// 推断表达式结果的类型，与 evaluate_expr 的计算规则一致，无法确定时返回 None
+// 如比较运算的结果为 Boolean，整数之间的运算结果为 Integer，整数与浮点数运算的结果为 Float
+pub fn expression_type(
+    expr: &Expression,
+    columns: &[String],
+    types: &[Option<DataType>],
+) -> Option<DataType> {
+    match expr {
+        Expression::Field(col_name) => types[column_position(columns, col_name).ok()?].clone(),
+        Expression::Consts(_) => Value::from_expression(expr.clone()).datatype(),
+        Expression::Operation(operation) => match operation {
+            Operation::Equal(_, _)
+            | Operation::GreaterThan(_, _)
+            | Operation::LessThan(_, _)
+            | Operation::And(_, _)
+            | Operation::Or(_, _) => Some(DataType::Boolean),
+            Operation::Add(l, r)
+            | Operation::Subtract(l, r)
+            | Operation::Multiply(l, r)
+            | Operation::Divide(l, r)
+            | Operation::Modulo(l, r)
+            | Operation::Div(l, r) => match (
+                expression_type(l, columns, types)?,
+                expression_type(r, columns, types)?,
+            ) {
+                (DataType::Integer, DataType::Integer) => Some(DataType::Integer),
+                (DataType::Integer | DataType::Float, DataType::Integer | DataType::Float) => {
+                    Some(DataType::Float)
+                }
+                _ => None,
+            },
+        },
+        // ROW_NUMBER 和 RANK 的结果都是整数
+        Expression::Window(_) | Expression::Sequence(_, _) => Some(DataType::Integer),
+        Expression::Function(_, _) | Expression::Placeholder(_) => None,
+    }
 }
 
 pub fn evaluate_expr(
@@ -121,18 +754,7 @@ pub fn evaluate_expr(
     rrow: &Vec<Value>,
 ) -> RSDBResult<Value> {
     match expr {
-        Expression::Field(col_name) => {
-            let pos = match lcols.iter().position(|c| c == col_name) {
-                Some(pos) => pos,
-                None => {
-                    return Err(RSDBError::Internal(format!(
-                        "Column {} not found in table",
-                        col_name
-                    )));
-                }
-            };
-            Ok(lrow[pos].clone())
-        }
+        Expression::Field(col_name) => Ok(lrow[column_position(lcols, col_name)?].clone()),
 
         Expression::Consts(consts) => match consts {
             Consts::Null => Ok(Value::Null),
@@ -140,68 +762,277 @@ pub fn evaluate_expr(
             Consts::Integer(i) => Ok(Value::Integer(*i)),
             Consts::Float(f) => Ok(Value::Float(*f)),
             Consts::String(s) => Ok(Value::String(s.clone())),
+            Consts::Date(d) => Ok(Value::Date(*d)),
+            Consts::Timestamp(t) => Ok(Value::Timestamp(*t)),
+            Consts::Decimal(v, scale) => Ok(Value::Decimal(*v, *scale)),
+            Consts::Bytes(b) => Ok(Value::Bytes(b.clone())),
         },
 
         Expression::Operation(operation) => match operation {
             Operation::Equal(lexpr, rexpr) => {
                 let lval = evaluate_expr(&lexpr, lcols, lrow, rcols, rrow)?;
                 let rval = evaluate_expr(&rexpr, rcols, rrow, lcols, lrow)?;
-                Ok(match (lval, rval) {
+                Ok(match coerce_operands(lval, rval)? {
                     (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l == r),
                     (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l == r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 == r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l == r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l == r),
+                    (Value::Integer(l), Value::Float(r)) => {
+                        Value::Boolean(types::float_cmp(l as f64, r).is_eq())
+                    }
+                    (Value::Float(l), Value::Integer(r)) => {
+                        Value::Boolean(types::float_cmp(l, r as f64).is_eq())
+                    }
+                    (Value::Float(l), Value::Float(r)) => {
+                        Value::Boolean(types::float_cmp(l, r).is_eq())
+                    }
                     (Value::String(l), Value::String(r)) => Value::Boolean(l == r),
+                    (Value::Date(l), Value::Date(r)) => Value::Boolean(l == r),
+                    (Value::Timestamp(l), Value::Timestamp(r)) => Value::Boolean(l == r),
+                    (Value::Decimal(l, ls), Value::Decimal(r, rs)) => {
+                        Value::Boolean(types::cmp_decimal((l, ls), (r, rs)) == Ordering::Equal)
+                    }
+                    (Value::Bytes(l), Value::Bytes(r)) => Value::Boolean(l == r),
                     (Value::Null, _) => Value::Null,
                     (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(RSDBError::Internal(format!(
-                            "Can not compare expression: {:?} and {:?}",
-                            l, r
-                        )));
-                    }
+                    (l, r) => return Err(cannot_compare(&l, &r)),
                 })
             }
             Operation::GreaterThan(lexpr, rexpr) => {
                 let lval = evaluate_expr(&lexpr, lcols, lrow, rcols, rrow)?;
                 let rval = evaluate_expr(&rexpr, rcols, rrow, lcols, lrow)?;
-                Ok(match (lval, rval) {
+                Ok(match coerce_operands(lval, rval)? {
                     (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l > r),
                     (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l > r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 > r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l > r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l > r),
+                    (Value::Integer(l), Value::Float(r)) => {
+                        Value::Boolean(types::float_cmp(l as f64, r).is_gt())
+                    }
+                    (Value::Float(l), Value::Integer(r)) => {
+                        Value::Boolean(types::float_cmp(l, r as f64).is_gt())
+                    }
+                    (Value::Float(l), Value::Float(r)) => {
+                        Value::Boolean(types::float_cmp(l, r).is_gt())
+                    }
                     (Value::String(l), Value::String(r)) => Value::Boolean(l > r),
-                    (Value::Null, _) | (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(RSDBError::Internal(format!(
-                            "Can not compare expression: {:?} and {:?}",
-                            l, r
-                        )));
+                    (Value::Date(l), Value::Date(r)) => Value::Boolean(l > r),
+                    (Value::Timestamp(l), Value::Timestamp(r)) => Value::Boolean(l > r),
+                    (Value::Decimal(l, ls), Value::Decimal(r, rs)) => {
+                        Value::Boolean(types::cmp_decimal((l, ls), (r, rs)) > Ordering::Equal)
                     }
+                    (Value::Bytes(l), Value::Bytes(r)) => Value::Boolean(l > r),
+                    (Value::Null, _) | (_, Value::Null) => Value::Null,
+                    (l, r) => return Err(cannot_compare(&l, &r)),
                 })
             }
             Operation::LessThan(lexpr, rexpr) => {
                 let lval = evaluate_expr(&lexpr, lcols, lrow, rcols, rrow)?;
                 let rval = evaluate_expr(&rexpr, rcols, rrow, lcols, lrow)?;
-                Ok(match (lval, rval) {
+                Ok(match coerce_operands(lval, rval)? {
                     (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l < r),
                     (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l < r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean((l as f64) < r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l < r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l < r),
+                    (Value::Integer(l), Value::Float(r)) => {
+                        Value::Boolean(types::float_cmp(l as f64, r).is_lt())
+                    }
+                    (Value::Float(l), Value::Integer(r)) => {
+                        Value::Boolean(types::float_cmp(l, r as f64).is_lt())
+                    }
+                    (Value::Float(l), Value::Float(r)) => {
+                        Value::Boolean(types::float_cmp(l, r).is_lt())
+                    }
                     (Value::String(l), Value::String(r)) => Value::Boolean(l < r),
+                    (Value::Date(l), Value::Date(r)) => Value::Boolean(l < r),
+                    (Value::Timestamp(l), Value::Timestamp(r)) => Value::Boolean(l < r),
+                    (Value::Decimal(l, ls), Value::Decimal(r, rs)) => {
+                        Value::Boolean(types::cmp_decimal((l, ls), (r, rs)) < Ordering::Equal)
+                    }
+                    (Value::Bytes(l), Value::Bytes(r)) => Value::Boolean(l < r),
+                    (Value::Null, _) | (_, Value::Null) => Value::Null,
+                    (l, r) => return Err(cannot_compare(&l, &r)),
+                })
+            }
+            Operation::And(lexpr, rexpr) => {
+                let lval = evaluate_expr(lexpr, lcols, lrow, rcols, rrow)?;
+                let rval = evaluate_expr(rexpr, lcols, lrow, rcols, rrow)?;
+                Ok(match (lval, rval) {
+                    (Value::Boolean(false), _) | (_, Value::Boolean(false)) => {
+                        Value::Boolean(false)
+                    }
+                    (Value::Boolean(true), Value::Boolean(true)) => Value::Boolean(true),
+                    (Value::Null | Value::Boolean(true), Value::Null | Value::Boolean(true)) => {
+                        Value::Null
+                    }
+                    (l, r) => {
+                        return Err(RSDBError::Internal(format!(
+                            "Can not apply AND to expression: {:?} and {:?}",
+                            l, r
+                        )));
+                    }
+                })
+            }
+            Operation::Or(lexpr, rexpr) => {
+                let lval = evaluate_expr(lexpr, lcols, lrow, rcols, rrow)?;
+                let rval = evaluate_expr(rexpr, lcols, lrow, rcols, rrow)?;
+                Ok(match (lval, rval) {
+                    (Value::Boolean(true), _) | (_, Value::Boolean(true)) => Value::Boolean(true),
+                    (Value::Boolean(false), Value::Boolean(false)) => Value::Boolean(false),
+                    (Value::Null | Value::Boolean(false), Value::Null | Value::Boolean(false)) => {
+                        Value::Null
+                    }
+                    (l, r) => {
+                        return Err(RSDBError::Internal(format!(
+                            "Can not apply OR to expression: {:?} and {:?}",
+                            l, r
+                        )));
+                    }
+                })
+            }
+            // 整数运算溢出时报错，整数与浮点数运算的结果为浮点数，定点数按照小数位数对齐后运算
+            Operation::Add(lexpr, rexpr)
+            | Operation::Subtract(lexpr, rexpr)
+            | Operation::Multiply(lexpr, rexpr) => {
+                let symbol = match operation {
+                    Operation::Add(_, _) => '+',
+                    Operation::Subtract(_, _) => '-',
+                    _ => '*',
+                };
+                let lval = evaluate_expr(lexpr, lcols, lrow, rcols, rrow)?;
+                let rval = evaluate_expr(rexpr, lcols, lrow, rcols, rrow)?;
+                let overflow = || RSDBError::Internal(format!("Integer overflow: {}", expr));
+                Ok(match (symbol, coerce_operands(lval, rval)?) {
+                    (_, (Value::Null, _) | (_, Value::Null)) => Value::Null,
+                    ('+', (Value::Integer(l), Value::Integer(r))) => {
+                        Value::Integer(l.checked_add(r).ok_or_else(overflow)?)
+                    }
+                    ('-', (Value::Integer(l), Value::Integer(r))) => {
+                        Value::Integer(l.checked_sub(r).ok_or_else(overflow)?)
+                    }
+                    (_, (Value::Integer(l), Value::Integer(r))) => {
+                        Value::Integer(l.checked_mul(r).ok_or_else(overflow)?)
+                    }
+                    (_, (Value::Decimal(l, ls), Value::Decimal(r, rs))) => {
+                        let (v, scale) = match symbol {
+                            '+' => types::add_decimals((l, ls), (r, rs))?,
+                            '-' => types::add_decimals((l, ls), (-r, rs))?,
+                            _ => types::mul_decimals((l, ls), (r, rs))?,
+                        };
+                        Value::Decimal(v, scale)
+                    }
+                    (
+                        _,
+                        (
+                            l @ (Value::Integer(_) | Value::Float(_)),
+                            r @ (Value::Integer(_) | Value::Float(_)),
+                        ),
+                    ) => {
+                        let (l, r) = (to_f64(&l), to_f64(&r));
+                        Value::Float(match symbol {
+                            '+' => l + r,
+                            '-' => l - r,
+                            _ => l * r,
+                        })
+                    }
+                    (_, (l, r)) => {
+                        return Err(RSDBError::Internal(format!(
+                            "Can not apply {} to expression: {:?} and {:?}",
+                            symbol, l, r
+                        )));
+                    }
+                })
+            }
+            // 整数相除的结果向 0 截断，定点数的商增加 4 位小数
+            Operation::Divide(lexpr, rexpr) => {
+                let lval = evaluate_expr(lexpr, lcols, lrow, rcols, rrow)?;
+                let rval = evaluate_expr(rexpr, lcols, lrow, rcols, rrow)?;
+                Ok(match coerce_operands(lval, rval)? {
                     (Value::Null, _) | (_, Value::Null) => Value::Null,
+                    (Value::Decimal(_, _), Value::Decimal(0, _)) => {
+                        return Err(division_by_zero(expr));
+                    }
+                    (Value::Decimal(l, ls), Value::Decimal(r, rs)) => {
+                        let (v, scale) = types::div_decimals((l, ls), (r, rs))?;
+                        Value::Decimal(v, scale)
+                    }
+                    (Value::Integer(_), Value::Integer(0)) => return Err(division_by_zero(expr)),
+                    (Value::Integer(l), Value::Integer(r)) => {
+                        Value::Integer(l.checked_div(r).ok_or_else(|| {
+                            RSDBError::Internal(format!("Integer overflow: {}", expr))
+                        })?)
+                    }
+                    (
+                        l @ (Value::Integer(_) | Value::Float(_)),
+                        r @ (Value::Integer(_) | Value::Float(_)),
+                    ) => {
+                        let (l, r) = (to_f64(&l), to_f64(&r));
+                        if r == 0.0 {
+                            return Err(division_by_zero(expr));
+                        }
+                        Value::Float(l / r)
+                    }
                     (l, r) => {
                         return Err(RSDBError::Internal(format!(
-                            "Can not compare expression: {:?} and {:?}",
+                            "Can not apply / to expression: {:?} and {:?}",
+                            l, r
+                        )));
+                    }
+                })
+            }
+            // 取模的结果与被除数同号（截断语义），如 -7 % 2 = -1，7 % -2 = 1
+            // 浮点数使用 f64::rem，语义相同；定点数对齐小数位数后取模
+            Operation::Modulo(lexpr, rexpr) => {
+                let lval = evaluate_expr(lexpr, lcols, lrow, rcols, rrow)?;
+                let rval = evaluate_expr(rexpr, lcols, lrow, rcols, rrow)?;
+                Ok(match coerce_operands(lval, rval)? {
+                    (Value::Decimal(_, _), Value::Decimal(0, _)) => {
+                        return Err(division_by_zero(expr));
+                    }
+                    (Value::Decimal(l, ls), Value::Decimal(r, rs)) => {
+                        let (l, r, scale) = types::align_decimals((l, ls), (r, rs))?;
+                        Value::Decimal(l % r, scale)
+                    }
+                    (Value::Integer(_), Value::Integer(0)) => return Err(division_by_zero(expr)),
+                    (Value::Integer(l), Value::Integer(r)) => Value::Integer(l.wrapping_rem(r)),
+                    (Value::Integer(l), Value::Float(r)) => float_rem(l as f64, r, expr)?,
+                    (Value::Float(l), Value::Integer(r)) => float_rem(l, r as f64, expr)?,
+                    (Value::Float(l), Value::Float(r)) => float_rem(l, r, expr)?,
+                    (Value::Null, _) | (_, Value::Null) => Value::Null,
+                    (l, r) => {
+                        return Err(RSDBError::Internal(format!(
+                            "Can not apply % to expression: {:?} and {:?}",
+                            l, r
+                        )));
+                    }
+                })
+            }
+            // 整数除法向 0 截断，如 -7 div 2 = -3；浮点数、定点数的商同样截断为整数值
+            Operation::Div(lexpr, rexpr) => {
+                let lval = evaluate_expr(lexpr, lcols, lrow, rcols, rrow)?;
+                let rval = evaluate_expr(rexpr, lcols, lrow, rcols, rrow)?;
+                Ok(match coerce_operands(lval, rval)? {
+                    (Value::Decimal(_, _), Value::Decimal(0, _)) => {
+                        return Err(division_by_zero(expr));
+                    }
+                    (Value::Decimal(l, ls), Value::Decimal(r, rs)) => {
+                        let (l, r, _) = types::align_decimals((l, ls), (r, rs))?;
+                        Value::Decimal(l / r, 0)
+                    }
+                    (Value::Integer(_), Value::Integer(0)) => return Err(division_by_zero(expr)),
+                    (Value::Integer(l), Value::Integer(r)) => Value::Integer(l.wrapping_div(r)),
+                    (Value::Integer(l), Value::Float(r)) => float_div(l as f64, r, expr)?,
+                    (Value::Float(l), Value::Integer(r)) => float_div(l, r as f64, expr)?,
+                    (Value::Float(l), Value::Float(r)) => float_div(l, r, expr)?,
+                    (Value::Null, _) | (_, Value::Null) => Value::Null,
+                    (l, r) => {
+                        return Err(RSDBError::Internal(format!(
+                            "Can not apply DIV to expression: {:?} and {:?}",
                             l, r
                         )));
                     }
                 })
             }
         },
+        // 需要读写序列的计数器，由执行器在求值前替换为序列的值
+        Expression::Sequence(func, _) => Err(RSDBError::Internal(format!(
+            "{}() is only allowed in SELECT lists, INSERT VALUES, UPDATE SET and DEFAULT",
+            func
+        ))),
         _ => {
             return Err(RSDBError::Internal(format!(
                 "Unsupported expression type: {:?}",
@@ -210,3 +1041,33 @@ pub fn evaluate_expr(
         }
     }
 }
+
+fn to_f64(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Float(f) => *f,
+        _ => unreachable!(),
+    }
+}
+
+fn cannot_compare(l: &Value, r: &Value) -> RSDBError {
+    RSDBError::TypeMismatch(format!("can not compare {:?} with {:?}", l, r))
+}
+
+fn division_by_zero(expr: &Expression) -> RSDBError {
+    RSDBError::Internal(format!("Division by zero: {}", expr))
+}
+
+fn float_rem(l: f64, r: f64, expr: &Expression) -> RSDBResult<Value> {
+    if r == 0.0 {
+        return Err(division_by_zero(expr));
+    }
+    Ok(Value::Float(l.rem(r)))
+}
+
+fn float_div(l: f64, r: f64, expr: &Expression) -> RSDBResult<Value> {
+    if r == 0.0 {
+        return Err(division_by_zero(expr));
+    }
+    Ok(Value::Float((l / r).trunc()))
+}
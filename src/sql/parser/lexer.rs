@@ -1,24 +1,28 @@
 use std::{fmt::Display, iter::Peekable, str::Chars};
 
-use crate::error::{RSDBError, RSDBResult};
+use crate::error::{Location, RSDBError, RSDBResult};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Keyword(Keyword), // 关键字
     Ident(String),    // 其他类型的字符串Token，如表名、列名等
     String(String),   // 字符串类型
-    Number(String),   // 数值类型
-    OpenParen,        // 左括号 (
-    CloseParen,       // 右括号 )
-    Comma,            // 逗号 ，
-    Semicolon,        // 分号 ；
-    Asterisk,         // 星号 *
-    Plus,             // 加号 +
-    Minus,            // 减号 -
-    Slash,            // 斜杠 /
-    Equal,            // 等号 =
-    GreaterThan,      // 大于 >
-    LessThan,         // 小于 <
+    Number(String),
+    HexString(String), // 十六进制字符串 x'deadbeef'，只保存十六进制字符   // 数值类型
+    OpenParen,         // 左括号 (
+    CloseParen,        // 右括号 )
+    Comma,             // 逗号 ，
+    Period,            // 点 .，用于限定列名，如 t.a
+    Semicolon,         // 分号 ；
+    Asterisk,          // 星号 *
+    Plus,              // 加号 +
+    Minus,             // 减号 -
+    Slash,             // 斜杠 /
+    Percent,           // 百分号 %
+    Question,          // 问号 ?，参数占位符
+    Equal,             // 等号 =
+    GreaterThan,       // 大于 >
+    LessThan,          // 小于 <
 }
 
 impl Display for Token {
@@ -28,14 +32,18 @@ impl Display for Token {
             Token::Ident(ident) => ident,
             Token::String(s) => s,
             Token::Number(n) => n,
+            Token::HexString(h) => h,
             Token::OpenParen => "(",
             Token::CloseParen => ")",
             Token::Comma => ",",
+            Token::Period => ".",
             Token::Semicolon => ";",
             Token::Asterisk => "*",
             Token::Plus => "+",
             Token::Minus => "-",
             Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Question => "?",
             Token::Equal => "=",
             Token::GreaterThan => ">",
             Token::LessThan => "<",
@@ -49,6 +57,9 @@ pub enum Keyword {
     Table,
     Int,
     Integer,
+    Tinyint,
+    Smallint,
+    Bigint,
     Boolean,
     Bool,
     String,
@@ -56,6 +67,12 @@ pub enum Keyword {
     Varchar,
     Float,
     Double,
+    Date,
+    Timestamp,
+    Decimal,
+    Numeric,
+    Blob,
+    Bytea,
     Select,
     From,
     Insert,
@@ -83,6 +100,16 @@ pub enum Keyword {
     Join,
     Left,
     Right,
+    Full,
+    Using,
+    And,
+    Or,
+    In,
+    Div,
+    Read,
+    Only,
+    Of,
+    Version,
     On,
     Group,
     Having,
@@ -91,16 +118,27 @@ pub enum Keyword {
     Rollback,
     Index,
     Drop,
+    If,
+    Exists,
+    Explain,
+    Describe,
+    Show,
+    Analyze,
+    Over,
+    Partition,
 }
 
 impl Keyword {
     // 将字符串转换为对应的 Keyword 枚举
     pub fn from_str(ident: &str) -> Option<Self> {
-        Some(match ident.to_uppercase().as_ref() {
+        Some(match ident.to_ascii_uppercase().as_ref() {
             "CREATE" => Keyword::Create,
             "TABLE" => Keyword::Table,
             "INT" => Keyword::Int,
             "INTEGER" => Keyword::Integer,
+            "TINYINT" => Keyword::Tinyint,
+            "SMALLINT" => Keyword::Smallint,
+            "BIGINT" => Keyword::Bigint,
             "BOOLEAN" => Keyword::Boolean,
             "BOOL" => Keyword::Bool,
             "STRING" => Keyword::String,
@@ -108,6 +146,12 @@ impl Keyword {
             "VARCHAR" => Keyword::Varchar,
             "FLOAT" => Keyword::Float,
             "DOUBLE" => Keyword::Double,
+            "DATE" => Keyword::Date,
+            "TIMESTAMP" => Keyword::Timestamp,
+            "DECIMAL" => Keyword::Decimal,
+            "NUMERIC" => Keyword::Numeric,
+            "BLOB" => Keyword::Blob,
+            "BYTEA" => Keyword::Bytea,
             "SELECT" => Keyword::Select,
             "FROM" => Keyword::From,
             "INSERT" => Keyword::Insert,
@@ -135,6 +179,18 @@ impl Keyword {
             "JOIN" => Keyword::Join,
             "LEFT" => Keyword::Left,
             "RIGHT" => Keyword::Right,
+            "FULL" => Keyword::Full,
+            "USING" => Keyword::Using,
+            "AND" => Keyword::And,
+            "OR" => Keyword::Or,
+            "IN" => Keyword::In,
+            "IF" => Keyword::If,
+            "EXISTS" => Keyword::Exists,
+            "DIV" => Keyword::Div,
+            "READ" => Keyword::Read,
+            "ONLY" => Keyword::Only,
+            "OF" => Keyword::Of,
+            "VERSION" => Keyword::Version,
             "ON" => Keyword::On,
             "GROUP" => Keyword::Group,
             "HAVING" => Keyword::Having,
@@ -143,6 +199,12 @@ impl Keyword {
             "ROLLBACK" => Keyword::Rollback,
             "INDEX" => Keyword::Index,
             "DROP" => Keyword::Drop,
+            "EXPLAIN" => Keyword::Explain,
+            "DESCRIBE" => Keyword::Describe,
+            "SHOW" => Keyword::Show,
+            "ANALYZE" => Keyword::Analyze,
+            "OVER" => Keyword::Over,
+            "PARTITION" => Keyword::Partition,
             _ => return None,
         })
     }
@@ -153,6 +215,9 @@ impl Keyword {
             Keyword::Table => "TABLE",
             Keyword::Int => "INT",
             Keyword::Integer => "INTEGER",
+            Keyword::Tinyint => "TINYINT",
+            Keyword::Smallint => "SMALLINT",
+            Keyword::Bigint => "BIGINT",
             Keyword::Boolean => "BOOLEAN",
             Keyword::Bool => "BOOL",
             Keyword::String => "STRING",
@@ -160,6 +225,12 @@ impl Keyword {
             Keyword::Varchar => "VARCHAR",
             Keyword::Float => "FLOAT",
             Keyword::Double => "DOUBLE",
+            Keyword::Date => "DATE",
+            Keyword::Timestamp => "TIMESTAMP",
+            Keyword::Decimal => "DECIMAL",
+            Keyword::Numeric => "NUMERIC",
+            Keyword::Blob => "BLOB",
+            Keyword::Bytea => "BYTEA",
             Keyword::Select => "SELECT",
             Keyword::From => "FROM",
             Keyword::Insert => "INSERT",
@@ -187,6 +258,18 @@ impl Keyword {
             Keyword::Join => "JOIN",
             Keyword::Left => "LEFT",
             Keyword::Right => "RIGHT",
+            Keyword::Full => "FULL",
+            Keyword::Using => "USING",
+            Keyword::And => "AND",
+            Keyword::Or => "OR",
+            Keyword::In => "IN",
+            Keyword::If => "IF",
+            Keyword::Exists => "EXISTS",
+            Keyword::Div => "DIV",
+            Keyword::Read => "READ",
+            Keyword::Only => "ONLY",
+            Keyword::Of => "OF",
+            Keyword::Version => "VERSION",
             Keyword::On => "ON",
             Keyword::Group => "GROUP",
             Keyword::Having => "HAVING",
@@ -195,8 +278,53 @@ impl Keyword {
             Keyword::Rollback => "ROLLBACK",
             Keyword::Index => "INDEX",
             Keyword::Drop => "DROP",
+            Keyword::Explain => "EXPLAIN",
+            Keyword::Describe => "DESCRIBE",
+            Keyword::Show => "SHOW",
+            Keyword::Analyze => "ANALYZE",
+            Keyword::Over => "OVER",
+            Keyword::Partition => "PARTITION",
         }
     }
+
+    // 非保留关键字，在表名、列名的位置可以直接作为标识符使用
+    pub fn is_non_reserved(&self) -> bool {
+        matches!(
+            self,
+            Keyword::Int
+                | Keyword::Integer
+                | Keyword::Tinyint
+                | Keyword::Smallint
+                | Keyword::Bigint
+                | Keyword::Boolean
+                | Keyword::Bool
+                | Keyword::String
+                | Keyword::Text
+                | Keyword::Varchar
+                | Keyword::Float
+                | Keyword::Double
+                | Keyword::Date
+                | Keyword::Timestamp
+                | Keyword::Decimal
+                | Keyword::Numeric
+                | Keyword::Blob
+                | Keyword::Bytea
+                | Keyword::Key
+                | Keyword::Asc
+                | Keyword::Desc
+                | Keyword::Left
+                | Keyword::Right
+                | Keyword::Full
+                | Keyword::Index
+                | Keyword::Read
+                | Keyword::Only
+                | Keyword::Of
+                | Keyword::Version
+                | Keyword::Analyze
+                | Keyword::Over
+                | Keyword::Partition
+        )
+    }
 }
 
 impl Display for Keyword {
@@ -205,25 +333,93 @@ impl Display for Keyword {
     }
 }
 
+// Token 在 SQL 文本中的位置，start / end 为字节偏移，line / column 从 1 开始
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    // SQL 文本末尾的位置，用于报告 Unexpected end of input
+    pub fn end_of(input: &str) -> Self {
+        let line = input.matches('\n').count() + 1;
+        let column = input.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        Self {
+            start: input.len(),
+            end: input.len(),
+            line,
+            column,
+        }
+    }
+
+    // 生成带位置信息的解析错误，附带出错行以及 ^ 标注的片段
+    pub fn error(&self, input: &str, message: String) -> RSDBError {
+        let line_start = input[..self.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[self.start..]
+            .find('\n')
+            .map_or(input.len(), |i| self.start + i);
+        let line_text = &input[line_start..line_end];
+        let width = input[self.start..self.end.min(line_end)]
+            .chars()
+            .count()
+            .max(1);
+        RSDBError::Parse {
+            message,
+            location: Some(Location {
+                line: self.line,
+                column: self.column,
+                snippet: format!(
+                    "{}\n{}{}",
+                    line_text,
+                    " ".repeat(self.column - 1),
+                    "^".repeat(width)
+                ),
+            }),
+        }
+    }
+}
+
 // 词法分析 Lexer 定义
+#[derive(Clone)]
 pub struct Lexer<'a> {
+    input: &'a str,
     iter: Peekable<Chars<'a>>,
+    // 当前字符的字节偏移、行号、列号
+    offset: usize,
+    line: usize,
+    column: usize,
 }
 
-// 自定义迭代器，返回 Token
+// 自定义迭代器，返回 Token 及其位置
 impl<'a> Iterator for Lexer<'a> {
-    type Item = RSDBResult<Token>;
+    type Item = RSDBResult<(Token, Span)>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        // 先清除空白字符，记录 Token 的起始位置
+        self.erase_whitespace();
+        let (start, line, column) = (self.offset, self.line, self.column);
+        let span = |end| Span {
+            start,
+            end,
+            line,
+            column,
+        };
         match self.scan() {
-            Ok(Some(token)) => Some(Ok(token)),
+            Ok(Some(token)) => Some(Ok((token, span(self.offset)))),
             Ok(None) => self.iter.peek().map(|c| {
-                Err(RSDBError::Parse(format!(
-                    "[Lexer] Unexpected character: {}",
-                    c
-                )))
+                Err(span(start + c.len_utf8())
+                    .error(self.input, format!("[Lexer] Unexpected character: {}", c)))
             }),
-            Err(err) => Some(Err(err)),
+            Err(err) => Some(Err(match err {
+                RSDBError::Parse {
+                    message,
+                    location: None,
+                } => span(self.offset).error(self.input, message),
+                err => err,
+            })),
         }
     }
 }
@@ -231,8 +427,25 @@ impl<'a> Iterator for Lexer<'a> {
 impl<'a> Lexer<'a> {
     pub fn new(sql_text: &'a str) -> Self {
         Self {
+            input: sql_text,
             iter: sql_text.chars().peekable(),
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    // 跳转到下一个字符，并更新位置信息
+    fn bump(&mut self) -> Option<char> {
+        let c = self.iter.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
         }
+        Some(c)
     }
 
     // 清除空白字符
@@ -253,26 +466,28 @@ impl<'a> Lexer<'a> {
     // 如果满足条件，则跳转到下一个字符，并返回该字符
     fn next_if<F: Fn(char) -> bool>(&mut self, predicate: F) -> Option<char> {
         self.iter.peek().filter(|&c| predicate(*c))?;
-        self.iter.next()
+        self.bump()
     }
 
     // 只有是 Token 类型，才跳转到到下一个，并返回 Token
     fn next_if_token<F: Fn(char) -> Option<Token>>(&mut self, predicate: F) -> Option<Token> {
         let token = self.iter.peek().and_then(|c| predicate(*c))?;
-        self.iter.next();
+        self.bump();
         Some(token)
     }
 
     // 扫描拿到下一个Token
     fn scan(&mut self) -> RSDBResult<Option<Token>> {
-        // 清除空白字符
-        self.erase_whitespace();
         // 根据第一个字符判断
         match self.iter.peek() {
-            Some('\'') => self.scan_string(), // 扫描字符串
+            Some('\'') => self.scan_string(),      // 扫描字符串
+            Some('"') => self.scan_quoted_ident(), // 扫描双引号括起来的 Ident
+            Some('x' | 'X') if self.input[self.offset + 1..].starts_with('\'') => {
+                self.scan_hex_string() // 扫描十六进制字符串
+            }
             Some(c) if c.is_ascii_digit() => Ok(self.scan_number()), // 扫描数字
-            Some(c) if c.is_ascii_alphabetic() => Ok(self.scan_ident()), // 扫描 Ident 类型
-            Some(_) => Ok(self.scan_symbol()), // 扫描符号
+            Some(c) if c.is_alphabetic() || *c == '_' => Ok(self.scan_ident()), // 扫描 Ident 类型
+            Some(_) => Ok(self.scan_symbol()),                       // 扫描符号
             None => Ok(None),
         }
     }
@@ -285,20 +500,65 @@ impl<'a> Lexer<'a> {
         }
         let mut value = String::new();
         loop {
-            match self.iter.next() {
+            match self.bump() {
                 Some('\'') => break,      // 遇到单引号结束
                 Some(c) => value.push(c), // 其他字符加入到字符串中
                 None => {
-                    return Err(RSDBError::Parse(format!(
-                        "[Lexer] Unterminated string literal: {}",
-                        value
-                    )));
+                    return Err(RSDBError::Parse {
+                        message: format!("[Lexer] Unterminated string literal: {}", value),
+                        location: None,
+                    });
                 } // 如果没有遇到单引号，说明字符串没有结束
             }
         }
         Ok(Some(Token::String(value)))
     }
 
+    // 扫描十六进制字符串 x'deadbeef'，字符个数必须为偶数
+    fn scan_hex_string(&mut self) -> RSDBResult<Option<Token>> {
+        if self.next_if(|c| c == 'x' || c == 'X').is_none() {
+            return Ok(None);
+        }
+        let value = match self.scan_string()? {
+            Some(Token::String(value)) => value,
+            _ => return Ok(None),
+        };
+        if !value.len().is_multiple_of(2) || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(RSDBError::Parse {
+                message: format!("[Lexer] Invalid hex string: {}", value),
+                location: None,
+            });
+        }
+        Ok(Some(Token::HexString(value.to_lowercase())))
+    }
+
+    // 扫描双引号括起来的 Ident，不会被识别为关键字，并且保留大小写
+    fn scan_quoted_ident(&mut self) -> RSDBResult<Option<Token>> {
+        if self.next_if(|c| c == '"').is_none() {
+            return Ok(None);
+        }
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => break,
+                Some(c) => value.push(c),
+                None => {
+                    return Err(RSDBError::Parse {
+                        message: format!("[Lexer] Unterminated quoted identifier: {}", value),
+                        location: None,
+                    });
+                }
+            }
+        }
+        if value.is_empty() {
+            return Err(RSDBError::Parse {
+                message: "[Lexer] Empty quoted identifier".to_string(),
+                location: None,
+            });
+        }
+        Ok(Some(Token::Ident(value)))
+    }
+
     // 扫描数字
     fn scan_number(&mut self) -> Option<Token> {
         // 先扫描一部分
@@ -310,13 +570,29 @@ impl<'a> Lexer<'a> {
                 num.push(c);
             }
         }
+        // 科学计数法的指数部分，如 1e-7，e 后面必须是数字，或者正负号加数字
+        let mut ahead = self.iter.clone();
+        if matches!(ahead.next(), Some('e' | 'E')) {
+            let next = match ahead.next() {
+                Some('+' | '-') => ahead.next(),
+                c => c,
+            };
+            if next.is_some_and(|c| c.is_ascii_digit()) {
+                num.extend(self.bump());
+                num.extend(self.next_if(|c| c == '+' || c == '-'));
+                while let Some(c) = self.next_if(|c| c.is_ascii_digit()) {
+                    num.push(c);
+                }
+            }
+        }
         Some(Token::Number(num))
     }
 
     // 扫描 Ident 类型, 如表名、列名等，也有可能是关键字，true / false
+    // 以字母（包括非 ASCII 字母）或下划线开头
     fn scan_ident(&mut self) -> Option<Token> {
-        let mut value = self.next_if(|c| c.is_ascii_alphabetic())?.to_string();
-        while let Some(c) = self.next_if(|c| c.is_ascii_alphanumeric() || c == '_') {
+        let mut value = self.next_if(|c| c.is_alphabetic() || c == '_')?.to_string();
+        while let Some(c) = self.next_if(|c| c.is_alphanumeric() || c == '_') {
             value.push(c);
         }
         Some(Keyword::from_str(&value).map_or(Token::Ident(value.to_lowercase()), Token::Keyword))
@@ -329,10 +605,13 @@ impl<'a> Lexer<'a> {
             '(' => Some(Token::OpenParen),
             ')' => Some(Token::CloseParen),
             ',' => Some(Token::Comma),
+            '.' => Some(Token::Period),
             ';' => Some(Token::Semicolon),
             '+' => Some(Token::Plus),
             '-' => Some(Token::Minus),
             '/' => Some(Token::Slash),
+            '%' => Some(Token::Percent),
+            '?' => Some(Token::Question),
             '=' => Some(Token::Equal),
             '>' => Some(Token::GreaterThan),
             '<' => Some(Token::LessThan),
@@ -348,7 +627,7 @@ mod tests {
     use super::Lexer;
     use crate::{
         error::RSDBResult,
-        sql::parser::lexer::{Keyword, Token},
+        sql::parser::lexer::{Keyword, Span, Token},
     };
 
     #[test]
@@ -361,7 +640,7 @@ mod tests {
                 );
                 ",
         )
-        .peekable()
+        .map(|r| r.map(|(token, _)| token))
         .collect::<RSDBResult<Vec<_>>>()?;
 
         assert_eq!(
@@ -400,7 +679,7 @@ mod tests {
                         );
                         ",
         )
-        .peekable()
+        .map(|r| r.map(|(token, _)| token))
         .collect::<RSDBResult<Vec<_>>>()?;
 
         assert!(tokens2.len() > 0);
@@ -411,7 +690,7 @@ mod tests {
     #[test]
     fn test_lexer_insert_into() -> RSDBResult<()> {
         let tokens1 = Lexer::new("insert into tbl values (1, 2, '3', true, false, 4.55);")
-            .peekable()
+            .map(|r| r.map(|(token, _)| token))
             .collect::<RSDBResult<Vec<_>>>()?;
 
         assert_eq!(
@@ -439,7 +718,7 @@ mod tests {
         );
 
         let tokens2 = Lexer::new("INSERT INTO       tbl (id, name, age) values (100, 'db', 10);")
-            .peekable()
+            .map(|r| r.map(|(token, _)| token))
             .collect::<RSDBResult<Vec<_>>>()?;
 
         assert_eq!(
@@ -472,7 +751,7 @@ mod tests {
     #[test]
     fn test_lexer_select() -> RSDBResult<()> {
         let tokens1 = Lexer::new("select * from tbl;")
-            .peekable()
+            .map(|r| r.map(|(token, _)| token))
             .collect::<RSDBResult<Vec<_>>>()?;
 
         assert_eq!(
@@ -487,4 +766,160 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_lexer_span() -> RSDBResult<()> {
+        let spans = Lexer::new("select *\n  from tbl;")
+            .map(|r| r.map(|(_, span)| span))
+            .collect::<RSDBResult<Vec<_>>>()?;
+        assert_eq!(
+            spans,
+            vec![
+                Span {
+                    start: 0,
+                    end: 6,
+                    line: 1,
+                    column: 1
+                },
+                Span {
+                    start: 7,
+                    end: 8,
+                    line: 1,
+                    column: 8
+                },
+                Span {
+                    start: 11,
+                    end: 15,
+                    line: 2,
+                    column: 3
+                },
+                Span {
+                    start: 16,
+                    end: 19,
+                    line: 2,
+                    column: 8
+                },
+                Span {
+                    start: 19,
+                    end: 20,
+                    line: 2,
+                    column: 11
+                },
+            ]
+        );
+
+        // 非法字符
+        let err = Lexer::new("select a\nfrom tbl where a @ 1;")
+            .collect::<RSDBResult<Vec<_>>>()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parse error: [Lexer] Unexpected character: @ at line 2 column 18
+from tbl where a @ 1;
+                 ^"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_quoted_ident() -> RSDBResult<()> {
+        let tokens = Lexer::new("select \"Order\", key from \"select\";")
+            .map(|r| r.map(|(token, _)| token))
+            .collect::<RSDBResult<Vec<_>>>()?;
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Ident("Order".to_string()),
+                Token::Comma,
+                Token::Keyword(Keyword::Key),
+                Token::Keyword(Keyword::From),
+                Token::Ident("select".to_string()),
+                Token::Semicolon,
+            ]
+        );
+
+        assert!(
+            Lexer::new("select \"a from t;")
+                .collect::<RSDBResult<Vec<_>>>()
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_unicode_ident() -> RSDBResult<()> {
+        let tokens = Lexer::new("select _id, 名字, Café_1 from 用户;")
+            .map(|r| r.map(|(token, _)| token))
+            .collect::<RSDBResult<Vec<_>>>()?;
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Ident("_id".to_string()),
+                Token::Comma,
+                Token::Ident("名字".to_string()),
+                Token::Comma,
+                Token::Ident("café_1".to_string()),
+                Token::Keyword(Keyword::From),
+                Token::Ident("用户".to_string()),
+                Token::Semicolon,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_hex_string() -> RSDBResult<()> {
+        let tokens = Lexer::new("select x, X'DEad00', x'' from t;")
+            .map(|r| r.map(|(token, _)| token))
+            .collect::<RSDBResult<Vec<_>>>()?;
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Ident("x".to_string()),
+                Token::Comma,
+                Token::HexString("dead00".to_string()),
+                Token::Comma,
+                Token::HexString("".to_string()),
+                Token::Keyword(Keyword::From),
+                Token::Ident("t".to_string()),
+                Token::Semicolon,
+            ]
+        );
+
+        assert!(
+            Lexer::new("x'abc'")
+                .collect::<RSDBResult<Vec<_>>>()
+                .is_err()
+        );
+        assert!(Lexer::new("x'zz'").collect::<RSDBResult<Vec<_>>>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_exponent() -> RSDBResult<()> {
+        let tokens = Lexer::new("select 1e-7, 2.5E+10, 1e16, 3e from t;")
+            .map(|r| r.map(|(token, _)| token))
+            .collect::<RSDBResult<Vec<_>>>()?;
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Number("1e-7".to_string()),
+                Token::Comma,
+                Token::Number("2.5E+10".to_string()),
+                Token::Comma,
+                Token::Number("1e16".to_string()),
+                Token::Comma,
+                Token::Number("3".to_string()),
+                Token::Ident("e".to_string()),
+                Token::Keyword(Keyword::From),
+                Token::Ident("t".to_string()),
+                Token::Semicolon,
+            ]
+        );
+        Ok(())
+    }
 }
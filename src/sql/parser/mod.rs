@@ -1,9 +1,13 @@
-use std::{collections::BTreeMap, iter::Peekable};
+use std::{
+    collections::BTreeMap,
+    iter::Peekable,
+    num::{ParseFloatError, ParseIntError},
+};
 
 use ast::Column;
-use lexer::{Keyword, Lexer, Token};
+use lexer::{Keyword, Lexer, Span, Token};
 
-use super::types::DataType;
+use super::types::{self, DataType, IntegerWidth};
 use crate::{
     error::{RSDBError, RSDBResult},
     sql::parser::ast::{Expression, Operation, OrderDirection},
@@ -14,13 +18,29 @@ mod lexer;
 
 // 解析器
 pub struct Parser<'a> {
+    input: &'a str,
     lexer: Peekable<Lexer<'a>>,
+    // 最近一次取出的 Token 的位置，用于报告解析错误
+    span: Span,
+    // 已解析的参数占位符个数
+    placeholders: usize,
+    // 是否正在解析 WHERE 条件，其中不能使用聚集函数
+    in_where: bool,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
         Parser {
+            input,
             lexer: Lexer::new(input).peekable(),
+            span: Span {
+                start: 0,
+                end: 0,
+                line: 1,
+                column: 1,
+            },
+            placeholders: 0,
+            in_where: false,
         }
     }
 
@@ -31,14 +51,28 @@ impl<'a> Parser<'a> {
         self.next_expect(Token::Semicolon)?;
         // 分号后面不能有其他 Token
         if let Some(token) = self.peek()? {
-            return Err(RSDBError::Parse(format!(
-                "[Parse] Unexpected token after statement: {}",
-                token
-            )));
+            return Err(self.peek_span().error(
+                self.input,
+                format!("[Parse] Unexpected token after statement: {}", token),
+            ));
         }
         Ok(stmt)
     }
 
+    // 解析脚本中的下一条语句，每条语句以分号结尾，没有更多语句时返回 None
+    pub fn parse_next(&mut self) -> RSDBResult<Option<ast::Statement>> {
+        // 跳过空语句，如 ;;
+        while self.next_if_token(Token::Semicolon).is_some() {}
+        if self.peek()?.is_none() {
+            return Ok(None);
+        }
+        // 每条语句的占位符单独编号
+        self.placeholders = 0;
+        let stmt = self.parse_statement()?;
+        self.next_expect(Token::Semicolon)?;
+        Ok(Some(stmt))
+    }
+
     fn parse_statement(&mut self) -> RSDBResult<ast::Statement> {
         // 查看第一个 Token 类型
         match self.peek()? {
@@ -51,20 +85,69 @@ impl<'a> Parser<'a> {
             Some(Token::Keyword(Keyword::Begin)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Commit)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Rollback)) => self.parse_transaction(),
-            Some(t) => Err(RSDBError::Parse(format!("[Parse] Unexpected token {}", t))),
-            None => Err(RSDBError::Parse(format!("[Parse] Unexpected end of input"))),
+            Some(Token::Keyword(Keyword::Explain)) => self.parse_explain(),
+            Some(Token::Keyword(Keyword::Describe)) => self.parse_describe(),
+            Some(Token::Keyword(Keyword::Show)) => self.parse_show(),
+            Some(Token::Keyword(Keyword::Analyze)) => self.parse_analyze(),
+            Some(Token::Keyword(Keyword::Set)) => self.parse_set(),
+            Some(Token::Ident(s)) if s == "use" => {
+                self.next()?;
+                Ok(ast::Statement::UseDatabase {
+                    name: self.next_database_name()?,
+                })
+            }
+            Some(Token::Ident(s)) if s == "vacuum" => {
+                self.next()?;
+                Ok(ast::Statement::Vacuum)
+            }
+            Some(t) => Err(self
+                .peek_span()
+                .error(self.input, format!("[Parse] Unexpected token {}", t))),
+            None => Err(self
+                .peek_span()
+                .error(self.input, "[Parse] Unexpected end of input".to_string())),
         }
     }
 
     // 解析 DDL 语句
     fn parse_ddl(&mut self) -> RSDBResult<ast::Statement> {
         match self.next()? {
-            Token::Keyword(Keyword::Create) => self.parse_ddl_create_table(),
-            Token::Keyword(Keyword::Drop) => self.parse_ddl_drop_table(),
-            token => Err(RSDBError::Parse(format!(
-                "[Parse] Unexpected token {}",
-                token
-            ))),
+            Token::Keyword(Keyword::Create) => match self.peek()? {
+                Some(Token::Keyword(Keyword::Index)) => self.parse_ddl_create_index(),
+                Some(Token::Ident(s)) if s == "sequence" => {
+                    self.next()?;
+                    Ok(ast::Statement::CreateSequence {
+                        name: self.next_ident()?,
+                    })
+                }
+                Some(Token::Ident(s)) if s == "database" => {
+                    self.next()?;
+                    Ok(ast::Statement::CreateDatabase {
+                        name: self.next_database_name()?,
+                    })
+                }
+                _ => self.parse_ddl_create_table(),
+            },
+            Token::Keyword(Keyword::Drop) => match self.peek()? {
+                Some(Token::Ident(s)) if s == "sequence" => {
+                    self.next()?;
+                    let if_exists = self.parse_if_exists()?;
+                    Ok(ast::Statement::DropSequence {
+                        name: self.next_ident()?,
+                        if_exists,
+                    })
+                }
+                Some(Token::Ident(s)) if s == "database" => {
+                    self.next()?;
+                    let if_exists = self.parse_if_exists()?;
+                    Ok(ast::Statement::DropDatabase {
+                        name: self.next_database_name()?,
+                        if_exists,
+                    })
+                }
+                _ => self.parse_ddl_drop_table(),
+            },
+            token => Err(self.error(format!("[Parse] Unexpected token {}", token))),
         }
     }
 
@@ -94,6 +177,7 @@ impl<'a> Parser<'a> {
                     None
                 }
             },
+            as_of: self.parse_as_of()?,
         })
     }
 
@@ -114,10 +198,7 @@ impl<'a> Parser<'a> {
                     Token::CloseParen => break,
                     Token::Comma => continue,
                     token => {
-                        return Err(RSDBError::Parse(format!(
-                            "[Parse] Unexpected token {}",
-                            token
-                        )));
+                        return Err(self.error(format!("[Parse] Unexpected token {}", token)));
                     }
                 }
             }
@@ -138,10 +219,7 @@ impl<'a> Parser<'a> {
                     Token::CloseParen => break,
                     Token::Comma => continue,
                     token => {
-                        return Err(RSDBError::Parse(format!(
-                            "[Parse] Unexpected token {}",
-                            token
-                        )));
+                        return Err(self.error(format!("[Parse] Unexpected token {}", token)));
                     }
                 }
             }
@@ -166,13 +244,14 @@ impl<'a> Parser<'a> {
         let mut columns = BTreeMap::new();
         loop {
             let col = self.next_ident()?;
+            let col_span = self.span;
             self.next_expect(Token::Equal)?;
             let value = self.parse_expression()?;
             if columns.contains_key(&col) {
-                return Err(RSDBError::Parse(format!(
-                    "[Parse] Duplicate column name {} in update statement",
-                    col
-                )));
+                return Err(col_span.error(
+                    self.input,
+                    format!("[Parse] Duplicate column name {} in update statement", col),
+                ));
             }
             columns.insert(col, value);
             // 如果没有逗号，列解析完成
@@ -202,11 +281,11 @@ impl<'a> Parser<'a> {
     // 解析事务语句
     fn parse_transaction(&mut self) -> RSDBResult<ast::Statement> {
         Ok(match self.next()? {
-            Token::Keyword(Keyword::Begin) => ast::Statement::Begin,
+            Token::Keyword(Keyword::Begin) => self.parse_begin_options()?,
             Token::Keyword(Keyword::Commit) => ast::Statement::Commit,
             Token::Keyword(Keyword::Rollback) => ast::Statement::Rollback,
             token => {
-                return Err(RSDBError::Parse(format!(
+                return Err(self.error(format!(
                     "[Parse] Unexpected token {} in transaction statement",
                     token
                 )));
@@ -214,6 +293,147 @@ impl<'a> Parser<'a> {
         })
     }
 
+    // 解析 begin 后面的选项：begin [read only] [isolation level serializable | snapshot] [as of version n]
+    fn parse_begin_options(&mut self) -> RSDBResult<ast::Statement> {
+        let mut read_only = false;
+        if self.next_if_token(Token::Keyword(Keyword::Read)).is_some() {
+            self.next_expect(Token::Keyword(Keyword::Only))?;
+            read_only = true;
+        }
+        let mut serializable = false;
+        if self
+            .next_if_token(Token::Ident("isolation".to_string()))
+            .is_some()
+        {
+            self.next_expect(Token::Ident("level".to_string()))?;
+            serializable = match self.next()? {
+                Token::Ident(level) if level == "serializable" => true,
+                Token::Ident(level) if level == "snapshot" => false,
+                token => {
+                    return Err(self.error(format!(
+                        "[Parse] Expected isolation level SERIALIZABLE or SNAPSHOT, got token {}",
+                        token
+                    )));
+                }
+            };
+        }
+        let as_of = self.parse_as_of()?;
+        Ok(ast::Statement::Begin {
+            read_only,
+            as_of,
+            serializable,
+        })
+    }
+
+    // 解析 as of version n，读取版本号为 n 时的数据
+    fn parse_as_of(&mut self) -> RSDBResult<Option<u64>> {
+        if self.next_if_token(Token::Keyword(Keyword::As)).is_none() {
+            return Ok(None);
+        }
+        self.next_expect(Token::Keyword(Keyword::Of))?;
+        self.next_expect(Token::Keyword(Keyword::Version))?;
+        match self.next()? {
+            Token::Number(n) if n.chars().all(|c| c.is_ascii_digit()) => {
+                Ok(Some(n.parse().map_err(|e: ParseIntError| {
+                    self.error(format!("[Parse] {}", e))
+                })?))
+            }
+            token => Err(self.error(format!(
+                "[Parse] Expected version number, got token {}",
+                token
+            ))),
+        }
+    }
+
+    // 解析 Explain 语句
+    fn parse_explain(&mut self) -> RSDBResult<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Explain))?;
+        // explain analyze t 是对 ANALYZE 语句的 explain，ANALYZE 后面跟语句时才是 EXPLAIN ANALYZE
+        let mut analyze = false;
+        if let Some(Token::Keyword(Keyword::Analyze)) = self.peek()? {
+            self.next()?;
+            if let Some(Token::Ident(_)) = self.peek()? {
+                return Ok(ast::Statement::Explain {
+                    stmt: Box::new(ast::Statement::Analyze {
+                        table_name: self.next_ident()?,
+                    }),
+                    analyze: false,
+                });
+            }
+            analyze = true;
+        }
+        if let Some(Token::Keyword(Keyword::Explain)) = self.peek()? {
+            return Err(self.peek_span().error(
+                self.input,
+                "[Parse] Cannot explain an explain statement".to_string(),
+            ));
+        }
+        Ok(ast::Statement::Explain {
+            stmt: Box::new(self.parse_statement()?),
+            analyze,
+        })
+    }
+
+    // 解析 Describe 语句
+    fn parse_describe(&mut self) -> RSDBResult<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Describe))?;
+        Ok(ast::Statement::Describe {
+            table_name: self.next_ident()?,
+        })
+    }
+
+    // 解析 Analyze 语句
+    fn parse_analyze(&mut self) -> RSDBResult<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Analyze))?;
+        Ok(ast::Statement::Analyze {
+            table_name: self.next_ident()?,
+        })
+    }
+
+    // 解析 Show 语句
+    fn parse_show(&mut self) -> RSDBResult<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Show))?;
+        match self.next()? {
+            // show create table t 等价于 describe t
+            Token::Keyword(Keyword::Create) => {
+                self.next_expect(Token::Keyword(Keyword::Table))?;
+                Ok(ast::Statement::Describe {
+                    table_name: self.next_ident()?,
+                })
+            }
+            // show all 查看所有的设置，show <name> 查看单个设置
+            Token::Ident(name) if name == "all" => Ok(ast::Statement::ShowSettings { name: None }),
+            Token::Ident(name) if name == "transaction" => Ok(ast::Statement::ShowTransaction),
+            Token::Ident(name) if name == "status" => Ok(ast::Statement::ShowStatus),
+            // show tables [extended]，extended 需要扫描所有表的数据
+            Token::Ident(name) if name == "tables" => Ok(ast::Statement::ShowTables {
+                extended: self
+                    .next_if(|t| matches!(t, Token::Ident(name) if name == "extended"))
+                    .is_some(),
+            }),
+            Token::Ident(name) => Ok(ast::Statement::ShowSettings { name: Some(name) }),
+            token => Err(self.error(format!(
+                "[Parse] Unexpected token {} in show statement",
+                token
+            ))),
+        }
+    }
+
+    // 解析 Set 语句，如 set timeout = 5000
+    fn parse_set(&mut self) -> RSDBResult<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Set))?;
+        let name = self.next_ident()?;
+        self.next_expect(Token::Equal)?;
+        let value = self.parse_expression()?;
+        if !matches!(value, Expression::Consts(_)) {
+            return Err(self.error(format!(
+                "[Parse] Expected constant value for setting {}",
+                name
+            )));
+        }
+        Ok(ast::Statement::Set { name, value })
+    }
+
     fn parse_select_clause(&mut self) -> RSDBResult<Vec<(Expression, Option<String>)>> {
         self.next_expect(Token::Keyword(Keyword::Select))?;
         let mut select = Vec::new();
@@ -244,17 +464,36 @@ impl<'a> Parser<'a> {
             let left = Box::new(item);
             let right = Box::new(self.parse_from_table_clause()?);
             // 解析 Join 条件
+            let mut using = Vec::new();
             let predicate = match join_type {
                 ast::JoinType::Cross => None,
+                // using (a, b) 等价于 on a = a and b = b
+                _ if self.next_if_token(Token::Keyword(Keyword::Using)).is_some() => {
+                    self.next_expect(Token::OpenParen)?;
+                    loop {
+                        using.push(self.next_ident()?);
+                        if self.next_if_token(Token::Comma).is_none() {
+                            break;
+                        }
+                    }
+                    self.next_expect(Token::CloseParen)?;
+                    using
+                        .iter()
+                        .map(|col| {
+                            ast::Expression::Operation(Operation::Equal(
+                                Box::new(ast::Expression::Field(col.clone())),
+                                Box::new(ast::Expression::Field(col.clone())),
+                            ))
+                        })
+                        .reduce(|l, r| {
+                            ast::Expression::Operation(Operation::And(Box::new(l), Box::new(r)))
+                        })
+                }
                 _ => {
                     self.next_expect(Token::Keyword(Keyword::On))?;
                     let l = self.parse_expression()?;
                     self.next_expect(Token::Equal)?;
                     let r = self.parse_expression()?;
-                    let (l, r) = match join_type {
-                        ast::JoinType::Right => (r, l), // Right Join 时交换左右
-                        _ => (l, r),
-                    };
                     let cond = Operation::Equal(Box::new(l), Box::new(r));
                     Some(ast::Expression::Operation(cond))
                 }
@@ -265,27 +504,65 @@ impl<'a> Parser<'a> {
                 right,
                 join_type,
                 predicate,
+                using,
             };
         }
         Ok(item)
     }
 
-    fn parse_group_clause(&mut self) -> RSDBResult<Option<Expression>> {
+    // 解析 group by 子句，可以有多个分组列，如 group by a, b
+    fn parse_group_clause(&mut self) -> RSDBResult<Vec<Expression>> {
+        let mut group_by = Vec::new();
         if self.next_if_token(Token::Keyword(Keyword::Group)).is_none() {
-            return Ok(None);
+            return Ok(group_by);
         }
         self.next_expect(Token::Keyword(Keyword::By))?;
-        Ok(Some(self.parse_expression()?))
+        loop {
+            group_by.push(self.parse_expression()?);
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok(group_by)
     }
 
+    // 表名之后可以跟别名，如 employees e、employees as e
     fn parse_from_table_clause(&mut self) -> RSDBResult<ast::FromItem> {
+        // 带有数据库名的表 db.t，没有别名时以表名作为别名，列名可以用 t.a 限定
+        let mut default_alias = None;
+        let mut name = match self.peek_second()? {
+            Some(Token::Period) => self.next_database_name()?,
+            _ => self.next_ident()?,
+        };
+        if self.next_if_token(Token::Period).is_some() {
+            let table = self.next_ident()?;
+            name = format!("{}.{}", name, table);
+            default_alias = Some(table);
+        }
+        let alias = match self.peek()? {
+            // from t as of version n 中的 as 不是别名
+            Some(Token::Keyword(Keyword::As))
+                if matches!(self.peek_second()?, Some(Token::Keyword(Keyword::Of))) =>
+            {
+                None
+            }
+            Some(Token::Keyword(Keyword::As)) => {
+                self.next()?;
+                Some(self.next_ident()?)
+            }
+            Some(Token::Ident(_)) => Some(self.next_ident()?),
+            _ => None,
+        };
         Ok(ast::FromItem::Table {
-            name: self.next_ident()?,
+            name,
+            alias: alias.or(default_alias),
         })
     }
 
     fn parse_from_clause_join(&mut self) -> RSDBResult<Option<ast::JoinType>> {
-        if self.next_if_token(Token::Keyword(Keyword::Cross)).is_some() {
+        if self.next_if_token(Token::Comma).is_some() {
+            Ok(Some(ast::JoinType::Cross)) // from t1, t2 等价于 Cross Join
+        } else if self.next_if_token(Token::Keyword(Keyword::Cross)).is_some() {
             self.next_expect(Token::Keyword(Keyword::Join))?;
             Ok(Some(ast::JoinType::Cross)) // Cross Join
         } else if self.next_if_token(Token::Keyword(Keyword::Join)).is_some() {
@@ -296,6 +573,9 @@ impl<'a> Parser<'a> {
         } else if self.next_if_token(Token::Keyword(Keyword::Right)).is_some() {
             self.next_expect(Token::Keyword(Keyword::Join))?;
             Ok(Some(ast::JoinType::Right)) // Right Join
+        } else if self.next_if_token(Token::Keyword(Keyword::Full)).is_some() {
+            self.next_expect(Token::Keyword(Keyword::Join))?;
+            Ok(Some(ast::JoinType::Full)) // Full Join
         } else {
             Ok(None)
         }
@@ -305,7 +585,10 @@ impl<'a> Parser<'a> {
         if self.next_if_token(Token::Keyword(Keyword::Where)).is_none() {
             return Ok(None);
         }
-        Ok(Some(self.parse_operation_expr()?))
+        self.in_where = true;
+        let expr = self.parse_or_expr();
+        self.in_where = false;
+        Ok(Some(expr?))
     }
 
     fn parse_having_clause(&mut self) -> RSDBResult<Option<Expression>> {
@@ -315,11 +598,49 @@ impl<'a> Parser<'a> {
         {
             return Ok(None);
         }
-        Ok(Some(self.parse_operation_expr()?))
+        Ok(Some(self.parse_or_expr()?))
+    }
+
+    // 多个条件之间用 OR 连接，优先级低于 AND，如 a = 1 or b = 2 and c = 3
+    fn parse_or_expr(&mut self) -> RSDBResult<Expression> {
+        let mut expr = self.parse_and_expr()?;
+        while self.next_if_token(Token::Keyword(Keyword::Or)).is_some() {
+            expr = Expression::Operation(Operation::Or(
+                Box::new(expr),
+                Box::new(self.parse_and_expr()?),
+            ));
+        }
+        Ok(expr)
+    }
+
+    // 多个条件之间用 AND 连接，左结合，如 a > 1 and b = 2
+    fn parse_and_expr(&mut self) -> RSDBResult<Expression> {
+        let mut expr = self.parse_operation_expr()?;
+        while self.next_if_token(Token::Keyword(Keyword::And)).is_some() {
+            expr = Expression::Operation(Operation::And(
+                Box::new(expr),
+                Box::new(self.parse_operation_expr()?),
+            ));
+        }
+        Ok(expr)
     }
 
     fn parse_operation_expr(&mut self) -> RSDBResult<Expression> {
         let left = self.parse_expression()?;
+        // 单独的布尔常量，如 where true
+        if let Expression::Consts(ast::Consts::Boolean(_)) = left
+            && !matches!(
+                self.peek()?,
+                Some(
+                    Token::Equal
+                        | Token::GreaterThan
+                        | Token::LessThan
+                        | Token::Keyword(Keyword::In)
+                )
+            )
+        {
+            return Ok(left);
+        }
         Ok(match self.next()? {
             Token::Equal => Expression::Operation(Operation::Equal(
                 Box::new(left),
@@ -333,10 +654,33 @@ impl<'a> Parser<'a> {
                 Box::new(left),
                 Box::new(self.parse_expression()?),
             )),
-            _ => {
-                return Err(RSDBError::Parse(
-                    "Unexpected token in operation expression".to_string(),
-                ));
+            // b in (1, 2) 等价于 b = 1 or b = 2
+            Token::Keyword(Keyword::In) => {
+                self.next_expect(Token::OpenParen)?;
+                let mut expr = None;
+                loop {
+                    let eq = Expression::Operation(Operation::Equal(
+                        Box::new(left.clone()),
+                        Box::new(self.parse_expression()?),
+                    ));
+                    expr = Some(match expr {
+                        Some(prev) => {
+                            Expression::Operation(Operation::Or(Box::new(prev), Box::new(eq)))
+                        }
+                        None => eq,
+                    });
+                    if self.next_if_token(Token::Comma).is_none() {
+                        break;
+                    }
+                }
+                self.next_expect(Token::CloseParen)?;
+                expr.unwrap()
+            }
+            token => {
+                return Err(self.error(format!(
+                    "[Parse] Unexpected token {} in operation expression",
+                    token
+                )));
             }
         })
     }
@@ -348,7 +692,15 @@ impl<'a> Parser<'a> {
         }
         self.next_expect(Token::Keyword(Keyword::By))?;
         loop {
-            let col = self.next_ident()?;
+            let ident = self.next_ident()?;
+            // 聚集函数按照聚集输出的列名排序，如 count(a) 对应 COUNT(a)
+            let col = match self.next_if_token(Token::OpenParen) {
+                Some(_) => {
+                    let col_name = self.parse_function_arg()?;
+                    format!("{}({})", ident.to_uppercase(), col_name)
+                }
+                None => self.qualify_column(ident)?,
+            };
             let ord = match self.next_if(|t| {
                 matches!(
                     t,
@@ -369,6 +721,10 @@ impl<'a> Parser<'a> {
 
     // 解析 Create Table 语句
     fn parse_ddl_create_table(&mut self) -> RSDBResult<ast::Statement> {
+        // create temporary table 或 create temp table
+        let temporary = self
+            .next_if(|t| matches!(t, Token::Ident(s) if s == "temporary" || s == "temp"))
+            .is_some();
         self.next_expect(Token::Keyword(Keyword::Table))?;
         // 期待是 Table 名
         let table_name = self.next_ident()?;
@@ -387,21 +743,96 @@ impl<'a> Parser<'a> {
         Ok(ast::Statement::CreateTable {
             name: table_name,
             columns,
+            temporary,
+        })
+    }
+
+    // 解析 Create Index 语句，如 create index idx on t (a, b)
+    fn parse_ddl_create_index(&mut self) -> RSDBResult<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Index))?;
+        let name = self.next_ident()?;
+        self.next_expect(Token::Keyword(Keyword::On))?;
+        let table_name = self.next_ident()?;
+        self.next_expect(Token::OpenParen)?;
+        let mut columns = Vec::new();
+        loop {
+            columns.push(self.next_ident()?);
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        self.next_expect(Token::CloseParen)?;
+        Ok(ast::Statement::CreateIndex {
+            name,
+            table_name,
+            columns,
         })
     }
 
     // 解析 Drop Table 语句
     fn parse_ddl_drop_table(&mut self) -> RSDBResult<ast::Statement> {
         self.next_expect(Token::Keyword(Keyword::Table))?;
+        // DROP TABLE IF EXISTS，表不存在时不报错
+        let if_exists = self.parse_if_exists()?;
         Ok(ast::Statement::DropTable {
             name: self.next_ident()?,
+            if_exists,
+        })
+    }
+
+    fn parse_if_exists(&mut self) -> RSDBResult<bool> {
+        Ok(match self.next_if_token(Token::Keyword(Keyword::If)) {
+            Some(_) => {
+                self.next_expect(Token::Keyword(Keyword::Exists))?;
+                true
+            }
+            None => false,
         })
     }
 
+    // 解析定点数类型 DECIMAL[(precision[, scale])]，返回类型和精度，小数位数默认为 0
+    // 存储时统一使用 i128，没有指定精度时最多 38 位
+    fn parse_decimal_type(&mut self) -> RSDBResult<(DataType, Option<u32>)> {
+        if self.next_if_token(Token::OpenParen).is_none() {
+            return Ok((DataType::Decimal(0), None));
+        }
+        let precision = self.next_u32()?;
+        if precision == 0 || precision > types::MAX_DECIMAL_DIGITS {
+            return Err(self.error(format!(
+                "[Parse] Decimal precision must be between 1 and {}",
+                types::MAX_DECIMAL_DIGITS
+            )));
+        }
+        let scale = match self.next_if_token(Token::Comma) {
+            Some(_) => self.next_u32()?,
+            None => 0,
+        };
+        if scale > precision {
+            return Err(self.error(format!(
+                "[Parse] Decimal scale {} exceeds precision {}",
+                scale, precision
+            )));
+        }
+        self.next_expect(Token::CloseParen)?;
+        Ok((DataType::Decimal(scale), Some(precision)))
+    }
+
+    fn next_u32(&mut self) -> RSDBResult<u32> {
+        match self.next()? {
+            Token::Number(n) => n
+                .parse()
+                .map_err(|e: ParseIntError| self.error(format!("[Parse] {}", e))),
+            token => Err(self.error(format!("[Parse] Expected number, got token {}", token))),
+        }
+    }
+
     // 解析列信息
     fn parse_ddl_column(&mut self) -> RSDBResult<Column> {
+        let name = self.next_ident()?;
+        let mut int_width = None;
+        let mut precision = None;
         let mut column = Column {
-            name: self.next_ident()?,
+            name,
             datatype: match self.next()? {
                 Token::Keyword(Keyword::Bool) | Token::Keyword(Keyword::Boolean) => {
                     DataType::Boolean
@@ -409,22 +840,52 @@ impl<'a> Parser<'a> {
                 Token::Keyword(Keyword::Int) | Token::Keyword(Keyword::Integer) => {
                     DataType::Integer
                 }
+                // 不同宽度的整数都按 Integer 存储，记录声明的宽度
+                Token::Keyword(Keyword::Tinyint) => {
+                    int_width = Some(IntegerWidth::Tiny);
+                    DataType::Integer
+                }
+                Token::Keyword(Keyword::Smallint) => {
+                    int_width = Some(IntegerWidth::Small);
+                    DataType::Integer
+                }
+                Token::Keyword(Keyword::Bigint) => {
+                    int_width = Some(IntegerWidth::Big);
+                    DataType::Integer
+                }
                 Token::Keyword(Keyword::Float) | Token::Keyword(Keyword::Double) => DataType::Float,
                 Token::Keyword(Keyword::String)
                 | Token::Keyword(Keyword::Text)
                 | Token::Keyword(Keyword::Varchar) => DataType::String,
+                Token::Keyword(Keyword::Date) => DataType::Date,
+                Token::Keyword(Keyword::Timestamp) => DataType::Timestamp,
+                Token::Keyword(Keyword::Decimal) | Token::Keyword(Keyword::Numeric) => {
+                    let (datatype, p) = self.parse_decimal_type()?;
+                    precision = p;
+                    datatype
+                }
+                Token::Keyword(Keyword::Blob) | Token::Keyword(Keyword::Bytea) => DataType::Bytes,
                 token => {
-                    return Err(RSDBError::Parse(format!(
-                        "[Parse] Unexpected token {}",
-                        token
-                    )));
+                    return Err(self.error(format!("[Parse] Unexpected token {}", token)));
                 }
             },
+            max_length: None,
+            int_width,
+            precision,
             nullable: None,
             default: None,
             primary_key: false,
             index: false,
         };
+        // 字符串类型可以指定最大长度，如 varchar(32)
+        if column.datatype == DataType::String && self.next_if_token(Token::OpenParen).is_some() {
+            let max_length = self.next_u32()?;
+            if max_length == 0 {
+                return Err(self.error("[Parse] Length must be greater than 0".to_string()));
+            }
+            self.next_expect(Token::CloseParen)?;
+            column.max_length = Some(max_length as usize);
+        }
         // 解析列的默认值，以及是否可以为空
         while let Some(Token::Keyword(keyword)) = self.next_if_keyword() {
             match keyword {
@@ -440,44 +901,151 @@ impl<'a> Parser<'a> {
                 }
                 Keyword::Index => column.index = true,
                 k => {
-                    return Err(RSDBError::Parse(format!(
-                        "[Parse] Unexpected keyword {}",
-                        k
-                    )));
+                    return Err(self.error(format!("[Parse] Unexpected keyword {}", k)));
                 }
             }
         }
         Ok(column)
     }
 
-    // 解析表达式
+    // 解析表达式，+ 和 - 的优先级低于 *、/、% 和 div，左结合
     fn parse_expression(&mut self) -> RSDBResult<ast::Expression> {
+        let mut expr = self.parse_term()?;
+        loop {
+            expr = match self.next_if(|t| matches!(t, Token::Plus | Token::Minus)) {
+                Some(Token::Plus) => Expression::Operation(Operation::Add(
+                    Box::new(expr),
+                    Box::new(self.parse_term()?),
+                )),
+                Some(Token::Minus) => Expression::Operation(Operation::Subtract(
+                    Box::new(expr),
+                    Box::new(self.parse_term()?),
+                )),
+                _ => break,
+            };
+        }
+        Ok(expr)
+    }
+
+    // 解析乘除运算，% 和 div 的优先级与乘法相同，左结合
+    fn parse_term(&mut self) -> RSDBResult<ast::Expression> {
+        let mut expr = self.parse_expression_atom()?;
+        loop {
+            let op = match self.next_if(|t| {
+                matches!(
+                    t,
+                    Token::Asterisk | Token::Slash | Token::Percent | Token::Keyword(Keyword::Div)
+                )
+            }) {
+                Some(Token::Asterisk) => Operation::Multiply,
+                Some(Token::Slash) => Operation::Divide,
+                Some(Token::Percent) => Operation::Modulo,
+                Some(Token::Keyword(Keyword::Div)) => Operation::Div,
+                _ => break,
+            };
+            expr =
+                Expression::Operation(op(Box::new(expr), Box::new(self.parse_expression_atom()?)));
+        }
+        Ok(expr)
+    }
+
+    // 解析单个表达式，如列名、函数、常量
+    fn parse_expression_atom(&mut self) -> RSDBResult<ast::Expression> {
         Ok(match self.next()? {
             Token::Ident(ident) => {
                 // 函数
                 if self.next_if_token(Token::OpenParen).is_some() {
-                    let col_name = self.next_ident()?;
-                    self.next_expect(Token::CloseParen)?;
+                    // 序列函数，参数为序列名，如 nextval('s')
+                    let sequence = match ident.as_str() {
+                        "nextval" => Some(ast::SequenceFunction::NextVal),
+                        "currval" => Some(ast::SequenceFunction::CurrVal),
+                        _ => None,
+                    };
+                    if let Some(func) = sequence {
+                        let name = self.next_string()?;
+                        self.next_expect(Token::CloseParen)?;
+                        return Ok(ast::Expression::Sequence(func, name));
+                    }
+                    // 没有参数的函数是窗口函数，如 row_number() over (order by a)
+                    if self.next_if_token(Token::CloseParen).is_some() {
+                        if self.in_where {
+                            return Err(self.error(
+                                "[Parse] window functions are not allowed in WHERE".to_string(),
+                            ));
+                        }
+                        return self.parse_window(ident);
+                    }
+                    if self.in_where {
+                        return Err(self.error(
+                            "[Parse] aggregate functions are not allowed in WHERE, use HAVING instead"
+                                .to_string(),
+                        ));
+                    }
+                    let col_name = self.parse_function_arg()?;
                     ast::Expression::Function(ident, col_name)
                 } else {
                     // 列名
-                    ast::Expression::Field(ident)
-                }
-            }
-            Token::Number(n) => {
-                if n.chars().all(|c| c.is_ascii_digit()) {
-                    // 整数
-                    ast::Expression::Consts(ast::Consts::Integer(n.parse()?).into())
-                } else {
-                    // 浮点数
-                    ast::Expression::Consts(ast::Consts::Float(n.parse()?).into())
+                    ast::Expression::Field(self.qualify_column(ident)?)
                 }
             }
+            Token::Number(n) => self.parse_number(n)?,
+            // 负数
+            Token::Minus => match self.next()? {
+                Token::Number(n) => self.parse_number(format!("-{}", n))?,
+                t => return Err(self.error(format!("[Parse] Unexpected token {}", t))),
+            },
             Token::String(s) => ast::Consts::String(s).into(),
+            Token::HexString(h) => ast::Consts::Bytes(types::parse_hex(&h)?).into(),
+            // 参数占位符
+            Token::Question => {
+                self.placeholders += 1;
+                ast::Expression::Placeholder(self.placeholders - 1)
+            }
             Token::Keyword(Keyword::True) => ast::Consts::Boolean(true).into(),
             Token::Keyword(Keyword::False) => ast::Consts::Boolean(false).into(),
             Token::Keyword(Keyword::Null) => ast::Consts::Null.into(),
-            t => return Err(RSDBError::Parse(format!("[Parse] Unexpected token {}", t))),
+            // 日期时间字面量 DATE '2024-01-31'、TIMESTAMP '2024-01-31 12:00:00'
+            Token::Keyword(Keyword::Date) if matches!(self.peek()?, Some(Token::String(_))) => {
+                let s = self.next_string()?;
+                let d = types::parse_date(&s)
+                    .map_err(|_| self.error(format!("[Parse] Invalid date '{}'", s)))?;
+                ast::Consts::Date(d).into()
+            }
+            Token::Keyword(Keyword::Timestamp)
+                if matches!(self.peek()?, Some(Token::String(_))) =>
+            {
+                let s = self.next_string()?;
+                let t = types::parse_timestamp(&s)
+                    .map_err(|_| self.error(format!("[Parse] Invalid timestamp '{}'", s)))?;
+                ast::Consts::Timestamp(t).into()
+            }
+            Token::Keyword(Keyword::Decimal) if matches!(self.peek()?, Some(Token::String(_))) => {
+                let s = self.next_string()?;
+                let (v, scale) = types::parse_decimal(&s)
+                    .map_err(|_| self.error(format!("[Parse] Invalid decimal '{}'", s)))?;
+                ast::Consts::Decimal(v, scale).into()
+            }
+            // 非保留关键字作为列名
+            Token::Keyword(k) if k.is_non_reserved() => {
+                ast::Expression::Field(k.to_str().to_lowercase())
+            }
+            t => return Err(self.error(format!("[Parse] Unexpected token {}", t))),
+        })
+    }
+
+    fn parse_number(&self, n: String) -> RSDBResult<ast::Expression> {
+        Ok(if n.chars().all(|c| c.is_ascii_digit() || c == '-') {
+            // 整数
+            let i = n
+                .parse()
+                .map_err(|e: ParseIntError| self.error(format!("[Parse] {}", e)))?;
+            ast::Expression::Consts(ast::Consts::Integer(i).into())
+        } else {
+            // 浮点数
+            let f = n
+                .parse()
+                .map_err(|e: ParseFloatError| self.error(format!("[Parse] {}", e)))?;
+            ast::Expression::Consts(ast::Consts::Float(f).into())
         })
     }
 
@@ -497,29 +1065,118 @@ impl<'a> Parser<'a> {
     }
 
     fn peek(&mut self) -> RSDBResult<Option<Token>> {
-        self.lexer.peek().cloned().transpose()
+        self.lexer
+            .peek()
+            .cloned()
+            .transpose()
+            .map(|t| t.map(|(token, _)| token))
+    }
+
+    // 再下一个 Token，不会取出任何 Token
+    fn peek_second(&mut self) -> RSDBResult<Option<Token>> {
+        let mut lexer = self.lexer.clone();
+        lexer.next();
+        lexer.next().transpose().map(|t| t.map(|(token, _)| token))
+    }
+
+    // 下一个 Token 的位置，如果已经没有 Token，则为 SQL 文本末尾
+    fn peek_span(&mut self) -> Span {
+        match self.lexer.peek() {
+            Some(Ok((_, span))) => *span,
+            _ => Span::end_of(self.input),
+        }
     }
 
     fn next(&mut self) -> RSDBResult<Token> {
-        self.lexer
-            .next()
-            .unwrap_or_else(|| Err(RSDBError::Parse(format!("[Parse] Unexpected end of input"))))
+        let (token, span) = self.lexer.next().unwrap_or_else(|| {
+            Err(Span::end_of(self.input)
+                .error(self.input, "[Parse] Unexpected end of input".to_string()))
+        })?;
+        self.span = span;
+        Ok(token)
+    }
+
+    // 在最近一次取出的 Token 处生成解析错误
+    fn error(&self, message: String) -> RSDBError {
+        self.span.error(self.input, message)
     }
 
     fn next_ident(&mut self) -> RSDBResult<String> {
         match self.next()? {
             Token::Ident(ident) => Ok(ident),
-            token => Err(RSDBError::Parse(format!(
-                "[Parse] Expected ident, got token {}",
-                token
-            ))),
+            Token::Keyword(k) if k.is_non_reserved() => Ok(k.to_str().to_lowercase()),
+            token => Err(self.error(format!("[Parse] Expected ident, got token {}", token))),
+        }
+    }
+
+    // 窗口函数的 OVER 子句，如 over (partition by a order by b desc)
+    fn parse_window(&mut self, func: String) -> RSDBResult<ast::Expression> {
+        self.next_expect(Token::Keyword(Keyword::Over))?;
+        self.next_expect(Token::OpenParen)?;
+        let mut partition_by = Vec::new();
+        if self
+            .next_if_token(Token::Keyword(Keyword::Partition))
+            .is_some()
+        {
+            self.next_expect(Token::Keyword(Keyword::By))?;
+            loop {
+                partition_by.push(self.next_column()?);
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
+                }
+            }
+        }
+        let order_by = self.parse_order_clause()?;
+        self.next_expect(Token::CloseParen)?;
+        Ok(ast::Expression::Window(ast::WindowFunction {
+            func,
+            partition_by,
+            order_by,
+        }))
+    }
+
+    // 聚集函数的参数，只能是列名，包括右括号
+    fn parse_function_arg(&mut self) -> RSDBResult<String> {
+        let col_name = self.next_column()?;
+        if self.next_if_token(Token::OpenParen).is_some() {
+            return Err(self.error("[Parse] aggregate function calls cannot be nested".to_string()));
+        }
+        self.next_expect(Token::CloseParen)?;
+        Ok(col_name)
+    }
+
+    // 列名，可以使用表名限定，如 t.a
+    fn next_column(&mut self) -> RSDBResult<String> {
+        let ident = self.next_ident()?;
+        self.qualify_column(ident)
+    }
+
+    fn qualify_column(&mut self, ident: String) -> RSDBResult<String> {
+        match self.next_if_token(Token::Period) {
+            Some(_) => Ok(format!("{}.{}", ident, self.next_ident()?)),
+            None => Ok(ident),
+        }
+    }
+
+    // 数据库名，默认数据库的名字 default 是关键字，需要单独处理
+    fn next_database_name(&mut self) -> RSDBResult<String> {
+        match self.next_if_token(Token::Keyword(Keyword::Default)) {
+            Some(_) => Ok("default".to_string()),
+            None => self.next_ident(),
+        }
+    }
+
+    fn next_string(&mut self) -> RSDBResult<String> {
+        match self.next()? {
+            Token::String(s) => Ok(s),
+            token => Err(self.error(format!("[Parse] Expected string, got token {}", token))),
         }
     }
 
     fn next_expect(&mut self, expect: Token) -> RSDBResult<()> {
         let token = self.next()?;
         if token != expect {
-            return Err(RSDBError::Parse(format!(
+            return Err(self.error(format!(
                 "[Parse] Expected token {}, got token {}",
                 expect, token
             )));
@@ -532,8 +1189,11 @@ impl<'a> Parser<'a> {
 mod tests {
     use super::Parser;
     use crate::{
-        error::RSDBResult,
-        sql::parser::ast::{self, Consts, Expression},
+        error::{Location, RSDBError, RSDBResult},
+        sql::{
+            parser::ast::{self, Consts, Expression},
+            types::Value,
+        },
     };
 
     #[test]
@@ -569,41 +1229,176 @@ mod tests {
         ";
         let stm3 = Parser::new(sql3).parse();
         assert!(stm3.is_err());
+
+        // 临时表
+        let temporary = |sql: &str| -> RSDBResult<bool> {
+            match Parser::new(sql).parse()? {
+                ast::Statement::CreateTable { temporary, .. } => Ok(temporary),
+                _ => unreachable!(),
+            }
+        };
+        assert!(!temporary(sql1)?);
+        assert!(temporary(
+            "create temporary table tmp (a int primary key);"
+        )?);
+        assert!(temporary("CREATE TEMP TABLE tmp (a int primary key);")?);
+        assert!(
+            Parser::new("create temporary tmp (a int primary key);")
+                .parse()
+                .is_err()
+        );
         Ok(())
     }
 
     #[test]
-    fn test_parser_insert() -> RSDBResult<()> {
-        let sql1 = "
-            insert into tab1 values (1, 2, 3, 'a', true);
-        ";
-        let stm1 = Parser::new(sql1).parse()?;
+    fn test_parser_create_index() -> RSDBResult<()> {
+        let sql = "create index idx_ab on tbl1 (a, b);";
+        let stm = Parser::new(sql).parse()?;
         assert_eq!(
-            stm1,
-            ast::Statement::Insert {
-                table_name: "tab1".to_string(),
-                columns: None,
-                values: vec![vec![
-                    ast::Consts::Integer(1).into(),
-                    ast::Consts::Integer(2).into(),
-                    ast::Consts::Integer(3).into(),
-                    ast::Consts::String("a".to_string()).into(),
-                    ast::Consts::Boolean(true).into()
-                ]]
+            stm,
+            ast::Statement::CreateIndex {
+                name: "idx_ab".to_string(),
+                table_name: "tbl1".to_string(),
+                columns: vec!["a".to_string(), "b".to_string()],
             }
         );
 
-        let sql2 = "
-            insert into tab1 (c1, c2, c3) values (3, 'a', true), (4, 'b', false);
-        ";
-        let stm2 = Parser::new(sql2).parse()?;
+        assert!(
+            Parser::new("create index idx_ab on tbl1 ();")
+                .parse()
+                .is_err()
+        );
+        assert!(
+            Parser::new("create index idx_ab tbl1 (a);")
+                .parse()
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_database() -> RSDBResult<()> {
         assert_eq!(
-            stm2,
-            ast::Statement::Insert {
-                table_name: "tab1".to_string(),
-                columns: Some(vec!["c1".to_string(), "c2".to_string(), "c3".to_string()]),
-                values: vec![
-                    vec![
+            Parser::new("create database app;").parse()?,
+            ast::Statement::CreateDatabase {
+                name: "app".to_string()
+            }
+        );
+        assert_eq!(
+            Parser::new("drop database if exists app;").parse()?,
+            ast::Statement::DropDatabase {
+                name: "app".to_string(),
+                if_exists: true,
+            }
+        );
+        assert_eq!(
+            Parser::new("USE app;").parse()?,
+            ast::Statement::UseDatabase {
+                name: "app".to_string()
+            }
+        );
+        assert_eq!(Parser::new("VACUUM;").parse()?, ast::Statement::Vacuum);
+        assert!(Parser::new("vacuum t;").parse().is_err());
+        let from = |sql: &str| -> RSDBResult<ast::FromItem> {
+            match Parser::new(sql).parse()? {
+                ast::Statement::Select { from, .. } => Ok(from),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(
+            from("select * from app.users;")?,
+            ast::FromItem::Table {
+                name: "app.users".to_string(),
+                alias: Some("users".to_string()),
+            }
+        );
+        assert_eq!(
+            from("select * from app.users u;")?,
+            ast::FromItem::Table {
+                name: "app.users".to_string(),
+                alias: Some("u".to_string()),
+            }
+        );
+        assert_eq!(
+            from("select * from default.users;")?,
+            ast::FromItem::Table {
+                name: "default.users".to_string(),
+                alias: Some("users".to_string()),
+            }
+        );
+        assert!(Parser::new("select * from app.;").parse().is_err());
+        assert!(Parser::new("select * from default;").parse().is_err());
+        assert!(Parser::new("use;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_sequence() -> RSDBResult<()> {
+        assert_eq!(
+            Parser::new("create sequence seq1;").parse()?,
+            ast::Statement::CreateSequence {
+                name: "seq1".to_string()
+            }
+        );
+        assert_eq!(
+            Parser::new("DROP SEQUENCE IF EXISTS seq1;").parse()?,
+            ast::Statement::DropSequence {
+                name: "seq1".to_string(),
+                if_exists: true,
+            }
+        );
+        assert_eq!(
+            Parser::new("insert into tbl1 values (nextval('seq1'), currval('seq1'));").parse()?,
+            ast::Statement::Insert {
+                table_name: "tbl1".to_string(),
+                columns: None,
+                values: vec![vec![
+                    ast::Expression::Sequence(ast::SequenceFunction::NextVal, "seq1".to_string()),
+                    ast::Expression::Sequence(ast::SequenceFunction::CurrVal, "seq1".to_string()),
+                ]],
+            }
+        );
+        assert!(Parser::new("create sequence;").parse().is_err());
+        assert!(
+            Parser::new("insert into tbl1 values (nextval(seq1));")
+                .parse()
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_insert() -> RSDBResult<()> {
+        let sql1 = "
+            insert into tab1 values (1, 2, 3, 'a', true);
+        ";
+        let stm1 = Parser::new(sql1).parse()?;
+        assert_eq!(
+            stm1,
+            ast::Statement::Insert {
+                table_name: "tab1".to_string(),
+                columns: None,
+                values: vec![vec![
+                    ast::Consts::Integer(1).into(),
+                    ast::Consts::Integer(2).into(),
+                    ast::Consts::Integer(3).into(),
+                    ast::Consts::String("a".to_string()).into(),
+                    ast::Consts::Boolean(true).into()
+                ]]
+            }
+        );
+
+        let sql2 = "
+            insert into tab1 (c1, c2, c3) values (3, 'a', true), (4, 'b', false);
+        ";
+        let stm2 = Parser::new(sql2).parse()?;
+        assert_eq!(
+            stm2,
+            ast::Statement::Insert {
+                table_name: "tab1".to_string(),
+                columns: Some(vec!["c1".to_string(), "c2".to_string(), "c3".to_string()]),
+                values: vec![
+                    vec![
                         ast::Consts::Integer(3).into(),
                         ast::Consts::String("a".to_string()).into(),
                         ast::Consts::Boolean(true).into()
@@ -628,17 +1423,19 @@ mod tests {
             ast::Statement::Select {
                 select: vec![],
                 from: ast::FromItem::Table {
-                    name: "tab1".to_string()
+                    name: "tab1".to_string(),
+                    alias: None,
                 },
                 where_clause: Some(ast::Expression::Operation(ast::Operation::GreaterThan(
                     Box::new(ast::Expression::Field("a".to_string())),
                     Box::new(ast::Expression::Consts(Consts::Integer(10).into()))
                 ))),
-                group_by: None,
+                group_by: vec![],
                 having: None,
                 order_by: vec![],
                 limit: None,
                 offset: None,
+                as_of: None,
             }
         );
 
@@ -654,10 +1451,11 @@ mod tests {
                     (Expression::Field("c".to_string()), None),
                 ],
                 from: ast::FromItem::Table {
-                    name: "tbl1".to_string()
+                    name: "tbl1".to_string(),
+                    alias: None,
                 },
                 where_clause: None,
-                group_by: None,
+                group_by: vec![],
                 having: None,
                 order_by: vec![
                     ("a".to_string(), ast::OrderDirection::Asc),
@@ -666,6 +1464,7 @@ mod tests {
                 ],
                 limit: Some(Expression::Consts(Consts::Integer(1))),
                 offset: Some(Expression::Consts(Consts::Integer(2))),
+                as_of: None,
             }
         );
 
@@ -678,26 +1477,32 @@ mod tests {
                 from: ast::FromItem::Join {
                     left: Box::new(ast::FromItem::Join {
                         left: Box::new(ast::FromItem::Table {
-                            name: "tbl1".to_string()
+                            name: "tbl1".to_string(),
+                            alias: None,
                         }),
                         right: Box::new(ast::FromItem::Table {
-                            name: "tbl2".to_string()
+                            name: "tbl2".to_string(),
+                            alias: None,
                         }),
                         join_type: ast::JoinType::Cross,
                         predicate: None,
+                        using: vec![],
                     }),
                     right: Box::new(ast::FromItem::Table {
-                        name: "tbl3".to_string()
+                        name: "tbl3".to_string(),
+                        alias: None,
                     }),
                     join_type: ast::JoinType::Cross,
                     predicate: None,
+                    using: vec![],
                 },
                 where_clause: None,
-                group_by: None,
+                group_by: vec![],
                 having: None,
                 order_by: vec![],
                 limit: None,
                 offset: None,
+                as_of: None,
             }
         );
 
@@ -721,14 +1526,16 @@ mod tests {
                     ),
                 ],
                 from: ast::FromItem::Table {
-                    name: "tbl1".to_string()
+                    name: "tbl1".to_string(),
+                    alias: None,
                 },
                 where_clause: None,
-                group_by: None,
+                group_by: vec![],
                 having: None,
                 order_by: vec![],
                 limit: None,
                 offset: None,
+                as_of: None,
             }
         );
 
@@ -753,10 +1560,11 @@ mod tests {
                     ),
                 ],
                 from: ast::FromItem::Table {
-                    name: "tbl1".to_string()
+                    name: "tbl1".to_string(),
+                    alias: None,
                 },
                 where_clause: None,
-                group_by: Some(Expression::Field("a".to_string())),
+                group_by: vec![Expression::Field("a".to_string())],
                 having: Some(Expression::Operation(ast::Operation::Equal(
                     Box::new(Expression::Field("max_c".to_string())),
                     Box::new(Expression::Consts(Consts::Integer(10).into()))
@@ -764,9 +1572,393 @@ mod tests {
                 order_by: vec![],
                 limit: None,
                 offset: None,
+                as_of: None,
             }
         );
 
+        let sql = "select a, b, count(c) from tbl1 group by a, b;";
+        match Parser::new(sql).parse()? {
+            ast::Statement::Select { group_by, .. } => assert_eq!(
+                group_by,
+                vec![
+                    Expression::Field("a".to_string()),
+                    Expression::Field("b".to_string())
+                ]
+            ),
+            _ => unreachable!(),
+        }
+        assert!(
+            Parser::new("select a from tbl1 group by a,;")
+                .parse()
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_implicit_cross_join() -> RSDBResult<()> {
+        let sql = "select * from tbl1, tbl2, tbl3;";
+        let stm = Parser::new(sql).parse()?;
+        let sql = "select * from tbl1 cross join tbl2 cross join tbl3;";
+        assert_eq!(stm, Parser::new(sql).parse()?);
+
+        let sql = "select * from tbl1, tbl2 where a = b;";
+        let stm = Parser::new(sql).parse()?;
+        assert_eq!(
+            stm,
+            ast::Statement::Select {
+                select: vec![],
+                from: ast::FromItem::Join {
+                    left: Box::new(ast::FromItem::Table {
+                        name: "tbl1".to_string(),
+                        alias: None,
+                    }),
+                    right: Box::new(ast::FromItem::Table {
+                        name: "tbl2".to_string(),
+                        alias: None,
+                    }),
+                    join_type: ast::JoinType::Cross,
+                    predicate: None,
+                    using: vec![],
+                },
+                where_clause: Some(Expression::Operation(ast::Operation::Equal(
+                    Box::new(Expression::Field("a".to_string())),
+                    Box::new(Expression::Field("b".to_string())),
+                ))),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+                as_of: None,
+            }
+        );
+
+        let sql = "select * from tbl1, ;";
+        assert!(Parser::new(sql).parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_qualified_column() -> RSDBResult<()> {
+        let sql = "select t1.a, max(t2.b) from t1 join t2 on t1.a = t2.a group by t1.a order by t1.a desc, max(t2.b);";
+        match Parser::new(sql).parse()? {
+            ast::Statement::Select {
+                select,
+                from: ast::FromItem::Join { predicate, .. },
+                group_by,
+                order_by,
+                ..
+            } => {
+                assert_eq!(
+                    select,
+                    vec![
+                        (Expression::Field("t1.a".to_string()), None),
+                        (
+                            Expression::Function("max".to_string(), "t2.b".to_string()),
+                            None
+                        ),
+                    ]
+                );
+                assert_eq!(
+                    predicate,
+                    Some(Expression::Operation(ast::Operation::Equal(
+                        Box::new(Expression::Field("t1.a".to_string())),
+                        Box::new(Expression::Field("t2.a".to_string())),
+                    )))
+                );
+                assert_eq!(group_by, vec![Expression::Field("t1.a".to_string())]);
+                assert_eq!(
+                    order_by,
+                    vec![
+                        ("t1.a".to_string(), ast::OrderDirection::Desc),
+                        ("MAX(t2.b)".to_string(), ast::OrderDirection::Asc)
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        assert!(Parser::new("select t1. from t1;").parse().is_err());
+        assert!(Parser::new("select t1.a.b from t1;").parse().is_err());
+
+        // 表的别名，可以省略 AS
+        match Parser::new("select e.a from tbl e join tbl as m on e.b = m.a;").parse()? {
+            ast::Statement::Select {
+                from: ast::FromItem::Join { left, right, .. },
+                ..
+            } => {
+                assert_eq!(
+                    *left,
+                    ast::FromItem::Table {
+                        name: "tbl".to_string(),
+                        alias: Some("e".to_string()),
+                    }
+                );
+                assert_eq!(
+                    *right,
+                    ast::FromItem::Table {
+                        name: "tbl".to_string(),
+                        alias: Some("m".to_string()),
+                    }
+                );
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_join_using() -> RSDBResult<()> {
+        let sql = "select * from tbl1 join tbl2 using (a, b);";
+        let stm = Parser::new(sql).parse()?;
+        let field = |name: &str| Box::new(Expression::Field(name.to_string()));
+        assert_eq!(
+            stm,
+            ast::Statement::Select {
+                select: vec![],
+                from: ast::FromItem::Join {
+                    left: Box::new(ast::FromItem::Table {
+                        name: "tbl1".to_string(),
+                        alias: None,
+                    }),
+                    right: Box::new(ast::FromItem::Table {
+                        name: "tbl2".to_string(),
+                        alias: None,
+                    }),
+                    join_type: ast::JoinType::Inner,
+                    predicate: Some(Expression::Operation(ast::Operation::And(
+                        Box::new(Expression::Operation(ast::Operation::Equal(
+                            field("a"),
+                            field("a")
+                        ))),
+                        Box::new(Expression::Operation(ast::Operation::Equal(
+                            field("b"),
+                            field("b")
+                        ))),
+                    ))),
+                    using: vec!["a".to_string(), "b".to_string()],
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+                as_of: None,
+            }
+        );
+
+        let sql = "select * from tbl1 join tbl2 using ();";
+        assert!(Parser::new(sql).parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_modulo() -> RSDBResult<()> {
+        let sql = "select * from tbl1 where a % 2 div -3 = 0;";
+        let stm = Parser::new(sql).parse()?;
+        assert_eq!(
+            stm,
+            ast::Statement::Select {
+                select: vec![],
+                from: ast::FromItem::Table {
+                    name: "tbl1".to_string(),
+                    alias: None,
+                },
+                where_clause: Some(Expression::Operation(ast::Operation::Equal(
+                    Box::new(Expression::Operation(ast::Operation::Div(
+                        Box::new(Expression::Operation(ast::Operation::Modulo(
+                            Box::new(Expression::Field("a".to_string())),
+                            Box::new(Consts::Integer(2).into()),
+                        ))),
+                        Box::new(Consts::Integer(-3).into()),
+                    ))),
+                    Box::new(Consts::Integer(0).into()),
+                ))),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+                as_of: None,
+            }
+        );
+
+        let sql = "select * from tbl1 where a % = 0;";
+        assert!(Parser::new(sql).parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_begin() -> RSDBResult<()> {
+        assert_eq!(
+            Parser::new("begin;").parse()?,
+            ast::Statement::Begin {
+                read_only: false,
+                as_of: None,
+                serializable: false,
+            }
+        );
+        assert_eq!(
+            Parser::new("begin read only;").parse()?,
+            ast::Statement::Begin {
+                read_only: true,
+                as_of: None,
+                serializable: false,
+            }
+        );
+        assert_eq!(
+            Parser::new("begin as of version 12;").parse()?,
+            ast::Statement::Begin {
+                read_only: false,
+                as_of: Some(12),
+                serializable: false,
+            }
+        );
+        assert_eq!(
+            Parser::new("begin isolation level serializable;").parse()?,
+            ast::Statement::Begin {
+                read_only: false,
+                as_of: None,
+                serializable: true,
+            }
+        );
+        assert_eq!(
+            Parser::new("BEGIN READ ONLY ISOLATION LEVEL SNAPSHOT AS OF VERSION 3;").parse()?,
+            ast::Statement::Begin {
+                read_only: true,
+                as_of: Some(3),
+                serializable: false,
+            }
+        );
+        assert!(
+            Parser::new("begin isolation serializable;")
+                .parse()
+                .is_err()
+        );
+        assert!(
+            Parser::new("begin isolation level read committed;")
+                .parse()
+                .is_err()
+        );
+        assert!(Parser::new("begin read;").parse().is_err());
+        assert!(Parser::new("begin as of version -1;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_placeholder() -> RSDBResult<()> {
+        let sql = "update tbl set a = ? where b = ?;";
+        let mut stm = Parser::new(sql).parse()?;
+        assert_eq!(
+            stm,
+            ast::Statement::Update {
+                table_name: "tbl".into(),
+                columns: vec![("a".into(), Expression::Placeholder(0))]
+                    .into_iter()
+                    .collect(),
+                where_clause: Some(Expression::Operation(ast::Operation::Equal(
+                    Box::new(Expression::Field("b".to_string())),
+                    Box::new(Expression::Placeholder(1)),
+                ))),
+            }
+        );
+
+        stm.bind(&[Value::Integer(1), Value::String("x".to_string())])?;
+        assert_eq!(
+            stm,
+            ast::Statement::Update {
+                table_name: "tbl".into(),
+                columns: vec![("a".into(), Consts::Integer(1).into())]
+                    .into_iter()
+                    .collect(),
+                where_clause: Some(Expression::Operation(ast::Operation::Equal(
+                    Box::new(Expression::Field("b".to_string())),
+                    Box::new(Consts::String("x".to_string()).into()),
+                ))),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_explain() -> RSDBResult<()> {
+        let sql = "explain select * from tbl1;";
+        let stm = Parser::new(sql).parse()?;
+        assert_eq!(
+            stm,
+            ast::Statement::Explain {
+                stmt: Box::new(ast::Statement::Select {
+                    select: vec![],
+                    from: ast::FromItem::Table {
+                        name: "tbl1".to_string(),
+                        alias: None,
+                    },
+                    where_clause: None,
+                    group_by: vec![],
+                    having: None,
+                    order_by: vec![],
+                    limit: None,
+                    offset: None,
+                    as_of: None,
+                }),
+                analyze: false,
+            }
+        );
+
+        let sql = "explain analyze select * from tbl1;";
+        assert!(matches!(
+            Parser::new(sql).parse()?,
+            ast::Statement::Explain { analyze: true, .. }
+        ));
+        let sql = "explain analyze tbl1;";
+        assert_eq!(
+            Parser::new(sql).parse()?,
+            ast::Statement::Explain {
+                stmt: Box::new(ast::Statement::Analyze {
+                    table_name: "tbl1".to_string()
+                }),
+                analyze: false,
+            }
+        );
+
+        let sql = "explain explain select * from tbl1;";
+        assert!(Parser::new(sql).parse().is_err());
+        let sql = "explain analyze explain select * from tbl1;";
+        assert!(Parser::new(sql).parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_describe() -> RSDBResult<()> {
+        let sql = "describe tbl1;";
+        let stm = Parser::new(sql).parse()?;
+        assert_eq!(
+            stm,
+            ast::Statement::Describe {
+                table_name: "tbl1".to_string()
+            }
+        );
+
+        let sql = "show create table tbl1;";
+        let stm = Parser::new(sql).parse()?;
+        assert_eq!(
+            stm,
+            ast::Statement::Describe {
+                table_name: "tbl1".to_string()
+            }
+        );
+
+        // show 后面的其他名称查看 session 的设置，执行时检查名称是否存在
+        let sql = "show tbl1;";
+        assert_eq!(
+            Parser::new(sql).parse()?,
+            ast::Statement::ShowSettings {
+                name: Some("tbl1".to_string())
+            }
+        );
+        assert!(Parser::new("show 1;").parse().is_err());
         Ok(())
     }
 
@@ -794,4 +1986,232 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_parser_error_location() -> RSDBResult<()> {
+        // 多行 create table 中的类型拼写错误
+        let sql = "create table tbl (
+    a int primary key,
+    b integr not null
+);";
+        let err = Parser::new(sql).parse().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parse error: [Parse] Unexpected token integr at line 3 column 7
+    b integr not null
+      ^^^^^^"
+        );
+
+        // 缺少分号
+        let err = Parser::new("select * from tbl").parse().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parse error: [Parse] Unexpected end of input at line 1 column 18
+select * from tbl
+                 ^"
+        );
+
+        // insert 的值之间缺少逗号
+        let err = Parser::new("insert into tbl values (1 2);")
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RSDBError::Parse {
+                message: "[Parse] Unexpected token 2".to_string(),
+                location: Some(Location {
+                    line: 1,
+                    column: 27,
+                    snippet: "insert into tbl values (1 2);\n                          ^"
+                        .to_string(),
+                }),
+            }
+        );
+
+        // 字符串没有结束
+        let err = Parser::new("select * from tbl\nwhere a = 'abc;")
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parse error: [Lexer] Unterminated string literal: abc; at line 2 column 11
+where a = 'abc;
+          ^^^^^"
+        );
+
+        // 语句结束后还有其他 Token
+        let err = Parser::new("select * from tbl; select")
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parse error: [Parse] Unexpected token after statement: SELECT at line 1 column 20
+select * from tbl; select
+                   ^^^^^^"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_aggregate_errors() -> RSDBResult<()> {
+        let message = |sql: &str| match Parser::new(sql).parse() {
+            Err(RSDBError::Parse { message, .. }) => message,
+            r => panic!("unexpected result {:?}", r),
+        };
+        // 聚集函数不能嵌套
+        for sql in [
+            "select sum(count(a)) from tbl;",
+            "select a from tbl group by a order by max(min(b));",
+        ] {
+            assert_eq!(
+                message(sql),
+                "[Parse] aggregate function calls cannot be nested"
+            );
+        }
+        // WHERE 中不能使用聚集函数
+        for sql in [
+            "select a from tbl where count(a) > 1;",
+            "update tbl set a = 1 where b = 1 and max(b) = 2;",
+            "delete from tbl where sum(a) = 1;",
+        ] {
+            assert_eq!(
+                message(sql),
+                "[Parse] aggregate functions are not allowed in WHERE, use HAVING instead"
+            );
+        }
+        // HAVING 和 WHERE 之后的子句中可以使用
+        Parser::new("select a, count(b) from tbl where a > 1 group by a having count(b) > 1;")
+            .parse()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_window_function() -> RSDBResult<()> {
+        let stmt = Parser::new(
+            "select a, rank() over (partition by t.b, c order by d desc, e) as r from t;",
+        )
+        .parse()?;
+        let ast::Statement::Select { select, .. } = stmt else {
+            unreachable!()
+        };
+        assert_eq!(
+            select[1],
+            (
+                Expression::Window(ast::WindowFunction {
+                    func: "rank".to_string(),
+                    partition_by: vec!["t.b".to_string(), "c".to_string()],
+                    order_by: vec![
+                        ("d".to_string(), ast::OrderDirection::Desc),
+                        ("e".to_string(), ast::OrderDirection::Asc),
+                    ],
+                }),
+                Some("r".to_string())
+            )
+        );
+        assert_eq!(
+            select[1].0.to_string(),
+            "RANK() OVER (PARTITION BY t.b, c ORDER BY d DESC, e ASC)"
+        );
+        let stmt = Parser::new("select row_number() over () from t;").parse()?;
+        let ast::Statement::Select { select, .. } = stmt else {
+            unreachable!()
+        };
+        assert_eq!(select[0].0.to_string(), "ROW_NUMBER() OVER ()");
+
+        // 窗口函数必须有 OVER 子句，且不能出现在 WHERE 中
+        assert!(Parser::new("select row_number() from t;").parse().is_err());
+        match Parser::new("select a from t where row_number() over () = 1;").parse() {
+            Err(RSDBError::Parse { message, .. }) => {
+                assert_eq!(message, "[Parse] window functions are not allowed in WHERE")
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_set_show() -> RSDBResult<()> {
+        assert_eq!(
+            Parser::new("set output_format = 'json';").parse()?,
+            ast::Statement::Set {
+                name: "output_format".to_string(),
+                value: ast::Consts::String("json".to_string()).into(),
+            }
+        );
+        assert_eq!(
+            Parser::new("SET Timeout = 5000;").parse()?,
+            ast::Statement::Set {
+                name: "timeout".to_string(),
+                value: ast::Consts::Integer(5000).into(),
+            }
+        );
+        assert_eq!(
+            Parser::new("show timeout;").parse()?,
+            ast::Statement::ShowSettings {
+                name: Some("timeout".to_string())
+            }
+        );
+        assert_eq!(
+            Parser::new("show all;").parse()?,
+            ast::Statement::ShowSettings { name: None }
+        );
+        // 值只能是常量
+        assert!(Parser::new("set timeout = a;").parse().is_err());
+        assert!(Parser::new("set timeout = ?;").parse().is_err());
+        assert!(Parser::new("set timeout 5000;").parse().is_err());
+
+        assert_eq!(
+            Parser::new("SHOW TRANSACTION;").parse()?,
+            ast::Statement::ShowTransaction
+        );
+        assert_eq!(
+            Parser::new("show status;").parse()?,
+            ast::Statement::ShowStatus
+        );
+        assert_eq!(
+            Parser::new("show tables;").parse()?,
+            ast::Statement::ShowTables { extended: false }
+        );
+        assert_eq!(
+            Parser::new("SHOW TABLES EXTENDED;").parse()?,
+            ast::Statement::ShowTables { extended: true }
+        );
+        assert!(Parser::new("show tables extended t;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_select_as_of() -> RSDBResult<()> {
+        let as_of = |sql: &str| -> RSDBResult<(ast::FromItem, Option<u64>)> {
+            match Parser::new(sql).parse()? {
+                ast::Statement::Select { from, as_of, .. } => Ok((from, as_of)),
+                _ => unreachable!(),
+            }
+        };
+        let table = |alias: Option<&str>| ast::FromItem::Table {
+            name: "t".to_string(),
+            alias: alias.map(|a| a.to_string()),
+        };
+        assert_eq!(as_of("select * from t;")?, (table(None), None));
+        // 表名后面的 as of 不是别名
+        assert_eq!(
+            as_of("select * from t as of version 5;")?,
+            (table(None), Some(5))
+        );
+        assert_eq!(
+            as_of("select a from t as x where a = 1 order by a limit 1 as of version 12;")?,
+            (table(Some("x")), Some(12))
+        );
+        assert!(
+            Parser::new("select * from t as of version;")
+                .parse()
+                .is_err()
+        );
+        assert!(
+            Parser::new("select * from t as of version -1;")
+                .parse()
+                .is_err()
+        );
+        Ok(())
+    }
 }
@@ -1,13 +1,17 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    error::RSDBResult,
+    error::{RSDBError, RSDBResult},
     sql::{
         engine::Transaction,
-        executor::{Executor, ResultSet},
-        parser::ast::{self, Expression, OrderDirection},
+        executor::{Executor, Output, ResultSet},
+        parser::ast::{self, Expression, JoinType, OrderDirection, WindowFunction},
         plan::planner::Planner,
-        schema::Table,
+        schema::{Index, Table},
         types::Value,
     },
 };
@@ -25,6 +29,50 @@ pub enum Node {
     // 删除表
     DropTable {
         table_name: String,
+        if_exists: bool,
+    },
+
+    // 创建索引
+    CreateIndex {
+        table_name: String,
+        index: Index,
+    },
+
+    // 创建序列
+    CreateSequence {
+        name: String,
+    },
+
+    // 删除序列
+    DropSequence {
+        name: String,
+        if_exists: bool,
+    },
+
+    // 创建数据库
+    CreateDatabase {
+        name: String,
+    },
+
+    // 删除数据库，以及其中所有的表
+    DropDatabase {
+        name: String,
+        if_exists: bool,
+    },
+
+    // 查看表结构
+    DescribeTable {
+        table_name: String,
+    },
+
+    // 列出所有的表
+    ShowTables {
+        extended: bool,
+    },
+
+    // 收集表的统计信息
+    Analyze {
+        table_name: String,
     },
 
     // 插入数据
@@ -38,6 +86,12 @@ pub enum Node {
     Scan {
         table_name: String,
         filter: Option<Expression>,
+        // 需要输出的列，None 表示输出所有列
+        columns: Option<Vec<String>>,
+        // 由 LIMIT 下推的行数上限，扫描到足够的行后不再读取，None 表示扫描全部
+        limit: Option<usize>,
+        // 按主键倒序扫描，ORDER BY 主键 DESC 时不需要再排序
+        reverse: bool,
     },
 
     // 更新节点
@@ -82,14 +136,23 @@ pub enum Node {
         left: Box<Node>,
         right: Box<Node>,
         predicate: Option<Expression>,
-        outer: bool,
+        join_type: JoinType,
+        using: Vec<String>, // 是否交换两侧的执行顺序，交换后右侧作为外层循环或左侧作为哈希表
+        swapped: bool,
+        tables: JoinTables,
     },
 
     // 聚集节点
     Aggregate {
         source: Box<Node>,
         exprs: Vec<(Expression, Option<String>)>,
-        group_by: Option<Expression>,
+        group_by: Vec<Expression>,
+    },
+
+    // 窗口函数节点，在每一行后面追加窗口函数的结果列，列名为别名或函数的文本
+    Window {
+        source: Box<Node>,
+        functions: Vec<(WindowFunction, String)>,
     },
 
     // 过滤节点
@@ -98,11 +161,24 @@ pub enum Node {
         predicate: Expression,
     },
 
-    // 索引查询节点
+    // 去重节点，保留第一次出现的行
+    Distinct {
+        source: Box<Node>,
+    },
+
+    // 索引查询节点，多列索引时 fields 为有等值条件的前缀列
     IndexScan {
+        table_name: String,
+        index: String,
+        fields: Vec<String>,
+        values: Vec<Value>,
+    },
+
+    // 多值索引查询节点，如 b in ('a', 'c')
+    MultiIndexScan {
         table_name: String,
         field: String,
-        value: Value,
+        values: Vec<Value>,
     },
 
     // 主键查询节点
@@ -111,42 +187,393 @@ pub enum Node {
         value: Value,
     },
 
+    // 空结果节点，过滤条件恒为假时不需要读取数据
+    Nothing {
+        columns: Vec<String>,
+    },
+
     // 哈希 Join 节点
     HashJoin {
         left: Box<Node>,
         right: Box<Node>,
         predicate: Option<Expression>,
-        outer: bool,
+        join_type: JoinType,
+        using: Vec<String>, // 是否交换两侧的执行顺序，交换后右侧作为外层循环或左侧作为哈希表
+        swapped: bool,
+        tables: JoinTables,
     },
 }
 
+// Join 两侧为单表时的表名，用于限定输出的列名，如 t1.a
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct JoinTables {
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+impl Node {
+    // 以缩进树的形式展示执行节点，每一层子节点缩进两个空格
+    fn format(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        let indent = "  ".repeat(depth);
+        match self {
+            Node::CreateTable { schema } => writeln!(f, "{}CreateTable: {}", indent, schema.name),
+            Node::DropTable {
+                table_name,
+                if_exists,
+            } => {
+                write!(f, "{}DropTable: {}", indent, table_name)?;
+                if *if_exists {
+                    write!(f, " (if exists)")?;
+                }
+                writeln!(f)
+            }
+            Node::CreateIndex { table_name, index } => writeln!(
+                f,
+                "{}CreateIndex: {} ON {} ({})",
+                indent,
+                index.name,
+                table_name,
+                index.columns.join(", ")
+            ),
+            Node::CreateSequence { name } => writeln!(f, "{}CreateSequence: {}", indent, name),
+            Node::DropSequence { name, .. } => writeln!(f, "{}DropSequence: {}", indent, name),
+            Node::CreateDatabase { name } => writeln!(f, "{}CreateDatabase: {}", indent, name),
+            Node::DropDatabase { name, .. } => writeln!(f, "{}DropDatabase: {}", indent, name),
+            Node::DescribeTable { table_name } => {
+                writeln!(f, "{}DescribeTable: {}", indent, table_name)
+            }
+            Node::ShowTables { extended } => match extended {
+                true => writeln!(f, "{}ShowTables: extended", indent),
+                false => writeln!(f, "{}ShowTables", indent),
+            },
+            Node::Analyze { table_name } => writeln!(f, "{}Analyze: {}", indent, table_name),
+            Node::Insert {
+                table_name, values, ..
+            } => writeln!(
+                f,
+                "{}Insert: {} ({} rows)",
+                indent,
+                table_name,
+                values.len()
+            ),
+            Node::Scan {
+                table_name,
+                filter,
+                columns,
+                limit,
+                reverse,
+            } => {
+                write!(f, "{}Scan: {}", indent, table_name)?;
+                if let Some(columns) = columns {
+                    write!(f, " [{}]", columns.join(", "))?;
+                }
+                if let Some(expr) = filter {
+                    write!(f, " (filter: {})", expr)?;
+                }
+                if *reverse {
+                    write!(f, " (reverse)")?;
+                }
+                match limit {
+                    Some(limit) => writeln!(f, " (limit {})", limit),
+                    None => writeln!(f),
+                }
+            }
+            Node::Update {
+                table_name,
+                source,
+                columns,
+            } => {
+                let sets = columns
+                    .iter()
+                    .map(|(col, expr)| format!("{} = {}", col, expr))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                writeln!(f, "{}Update: {} (set {})", indent, table_name, sets)?;
+                source.format(f, depth + 1)
+            }
+            Node::Delete { table_name, source } => {
+                writeln!(f, "{}Delete: {}", indent, table_name)?;
+                source.format(f, depth + 1)
+            }
+            Node::Order { source, order_by } => {
+                let orders = order_by
+                    .iter()
+                    .map(|(col, dir)| format!("{} {}", col, dir))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                writeln!(f, "{}Order: {}", indent, orders)?;
+                source.format(f, depth + 1)
+            }
+            Node::Limit { source, limit } => {
+                writeln!(f, "{}Limit: {}", indent, limit)?;
+                source.format(f, depth + 1)
+            }
+            Node::Offset { source, offset } => {
+                writeln!(f, "{}Offset: {}", indent, offset)?;
+                source.format(f, depth + 1)
+            }
+            Node::Projection { source, exprs } => {
+                writeln!(f, "{}Projection: {}", indent, format_exprs(exprs))?;
+                source.format(f, depth + 1)
+            }
+            Node::NestLoopJoin {
+                left,
+                right,
+                predicate,
+                join_type,
+                using,
+                swapped,
+                ..
+            } => {
+                writeln!(
+                    f,
+                    "{}NestLoopJoin: {}",
+                    indent,
+                    format_join(predicate, join_type, using, *swapped)
+                )?;
+                left.format(f, depth + 1)?;
+                right.format(f, depth + 1)
+            }
+            Node::Aggregate {
+                source,
+                exprs,
+                group_by,
+            } => {
+                match group_by.is_empty() {
+                    false => writeln!(
+                        f,
+                        "{}Aggregate: {} (group by: {})",
+                        indent,
+                        format_exprs(exprs),
+                        group_by
+                            .iter()
+                            .map(|expr| expr.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )?,
+                    true => writeln!(f, "{}Aggregate: {}", indent, format_exprs(exprs))?,
+                }
+                source.format(f, depth + 1)
+            }
+            Node::Window { source, functions } => {
+                let functions = functions
+                    .iter()
+                    .map(|(window, name)| match window.to_string() {
+                        text if text == *name => text,
+                        text => format!("{} as {}", text, name),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                writeln!(f, "{}Window: {}", indent, functions)?;
+                source.format(f, depth + 1)
+            }
+            Node::Filter { source, predicate } => {
+                writeln!(f, "{}Filter: {}", indent, predicate)?;
+                source.format(f, depth + 1)
+            }
+            Node::Distinct { source } => {
+                writeln!(f, "{}Distinct", indent)?;
+                source.format(f, depth + 1)
+            }
+            Node::IndexScan {
+                table_name,
+                index,
+                fields,
+                values,
+            } => {
+                let conds = fields
+                    .iter()
+                    .zip(values)
+                    .map(|(field, value)| format!("{} = {}", field, value))
+                    .collect::<Vec<_>>()
+                    .join(" AND ");
+                // 单列索引的索引名即列名，不再重复显示
+                if fields.len() == 1 && &fields[0] == index {
+                    writeln!(f, "{}IndexScan: {} ({})", indent, table_name, conds)
+                } else {
+                    writeln!(
+                        f,
+                        "{}IndexScan: {} USING {} ({})",
+                        indent, table_name, index, conds
+                    )
+                }
+            }
+            Node::MultiIndexScan {
+                table_name,
+                field,
+                values,
+            } => writeln!(
+                f,
+                "{}MultiIndexScan: {} ({} IN ({}))",
+                indent,
+                table_name,
+                field,
+                values
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Node::PrimaryKeyScan { table_name, value } => {
+                writeln!(f, "{}PrimaryKeyScan: {} ({})", indent, table_name, value)
+            }
+            Node::Nothing { columns } => writeln!(f, "{}Nothing: {}", indent, columns.join(", ")),
+            Node::HashJoin {
+                left,
+                right,
+                predicate,
+                join_type,
+                using,
+                swapped,
+                ..
+            } => {
+                writeln!(
+                    f,
+                    "{}HashJoin: {}",
+                    indent,
+                    format_join(predicate, join_type, using, *swapped)
+                )?;
+                left.format(f, depth + 1)?;
+                right.format(f, depth + 1)
+            }
+        }
+    }
+}
+
+fn format_exprs(exprs: &[(Expression, Option<String>)]) -> String {
+    exprs
+        .iter()
+        .map(|(expr, alias)| match alias {
+            Some(a) => format!("{} as {}", expr, a),
+            None => expr.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_join(
+    predicate: &Option<Expression>,
+    join_type: &JoinType,
+    using: &[String],
+    swapped: bool,
+) -> String {
+    let join_type = match join_type {
+        JoinType::Cross => "cross",
+        JoinType::Inner => "inner",
+        JoinType::Left => "left outer",
+        JoinType::Right => "right outer",
+        JoinType::Full => "full outer",
+    };
+    let join = match predicate {
+        Some(_) if !using.is_empty() => format!("{} using ({})", join_type, using.join(", ")),
+        Some(expr) => format!("{} on {}", join_type, expr),
+        None => join_type.to_string(),
+    };
+    match swapped {
+        true => format!("{} (swapped)", join),
+        false => join,
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.format(f, 0)
+    }
+}
+
 // 执行计划定义，底层是不同类型执行节点
 #[derive(Debug, PartialEq)]
 pub struct Plan(pub Node);
 
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl Plan {
     pub fn build<T: Transaction>(stmt: ast::Statement, txn: &mut T) -> RSDBResult<Self> {
         Ok(Planner::new(txn).build(stmt)?)
     }
 
     pub fn execute<T: Transaction + 'static>(self, txn: &mut T) -> RSDBResult<ResultSet> {
-        <dyn Executor<T>>::build(self.0).execute(txn)
+        self.execute_with_timeout(txn, None)
+    }
+
+    // 执行计划，超时后不再拉取新的行，返回超时错误
+    // INSERT 等不输出行的语句以及排序等需要读取全部数据的节点，在执行结束后检查是否超时
+    pub fn execute_with_timeout<T: Transaction + 'static>(
+        self,
+        txn: &mut T,
+        timeout: Option<Duration>,
+    ) -> RSDBResult<ResultSet> {
+        let Some(timeout) = timeout else {
+            return <dyn Executor<T>>::build(self.0)
+                .execute(txn)?
+                .into_result_set();
+        };
+        let deadline = Instant::now() + timeout;
+        let check = move || match Instant::now() > deadline {
+            true => Err(RSDBError::StatementTimeout(timeout.as_millis() as u64)),
+            false => Ok(()),
+        };
+        let output = match <dyn Executor<T>>::build(self.0).execute(txn)? {
+            Output::Rows {
+                columns,
+                types,
+                rows,
+            } => Output::Rows {
+                columns,
+                types,
+                rows: Box::new(rows.map(move |row| check().and(row))),
+            },
+            output => output,
+        };
+        let result = output.into_result_set()?;
+        check()?;
+        Ok(result)
+    }
+
+    // 实际执行计划，在每个节点后面标注输出的行数和耗时
+    pub fn explain_analyze<T: Transaction + 'static>(self, txn: &mut T) -> RSDBResult<String> {
+        let text = self.to_string();
+        let mut stats = Vec::new();
+        <dyn Executor<T>>::build_profiled(self.0, &mut stats)
+            .execute(txn)?
+            .into_result_set()?;
+        // 每个节点占一行，输出顺序与构建执行器的顺序一致，都是先序遍历
+        Ok(text
+            .lines()
+            .zip(stats)
+            .map(|(line, stats)| {
+                let stats = stats.borrow();
+                format!(
+                    "{} (rows={}, time={:.3}ms)",
+                    line,
+                    stats.rows,
+                    stats.elapsed.as_secs_f64() * 1000.0
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        error::RSDBResult,
+        error::{RSDBError, RSDBResult},
         sql::{
-            engine::{Engine, kv::KVEngine},
+            engine::{Engine, Transaction, kv::KVEngine},
+            executor::ResultSet,
             parser::{
                 Parser,
-                ast::{self, Expression},
+                ast::{self, Expression, JoinType},
             },
-            plan::{Node, Plan},
+            plan::{JoinTables, Node, Plan},
+            types::Value,
         },
-        storage::disk::DiskEngine,
+        storage::{disk::DiskEngine, memory::MemoryEngine},
     };
 
     #[test]
@@ -235,6 +662,9 @@ mod tests {
     fn test_plan_select() -> RSDBResult<()> {
         let p = tempfile::tempdir()?.keep().join("rsdb-log");
         let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        kvengine
+            .session()?
+            .execute("create table tbl1 (a int primary key);")?;
         let mut txn = kvengine.begin()?;
 
         let sql = "select * from tbl1;";
@@ -245,9 +675,380 @@ mod tests {
             Plan(Node::Scan {
                 table_name: "tbl1".to_string(),
                 filter: None,
+                columns: None,
+                limit: None,
+                reverse: false,
             })
         );
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
+
+    #[test]
+    fn test_plan_hash_join() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, x int);")?;
+        s.execute("create table t2 (b int primary key, y int);")?;
+        let values = |f: &dyn Fn(i64) -> String| {
+            (0..2000)
+                .map(|i| format!("({}, {})", i, f(i)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        // 连接列有重复值和 NULL
+        s.execute(&format!(
+            "insert into t1 values {};",
+            values(&|i| if i % 7 == 0 {
+                "null".to_string()
+            } else {
+                (i % 50).to_string()
+            })
+        ))?;
+        s.execute(&format!(
+            "insert into t2 values {};",
+            values(&|i| if i % 9 == 0 {
+                "null".to_string()
+            } else {
+                (i % 40).to_string()
+            })
+        ))?;
+
+        let mut txn = kvengine.begin()?;
+        for (sql, join_type) in [
+            ("select * from t1 join t2 on x = y;", JoinType::Inner),
+            ("select * from t1 left join t2 on x = y;", JoinType::Left),
+            ("select * from t1 right join t2 on x = y;", JoinType::Right),
+            ("select * from t1 full join t2 on x = y;", JoinType::Full),
+        ] {
+            let plan = Plan::build(Parser::new(sql).parse()?, &mut txn)?;
+            let scan = |table_name: &str| {
+                Box::new(Node::Scan {
+                    table_name: table_name.to_string(),
+                    filter: None,
+                    columns: None,
+                    limit: None,
+                    reverse: false,
+                })
+            };
+            let tables = || JoinTables {
+                left: Some("t1".to_string()),
+                right: Some("t2".to_string()),
+            };
+            let predicate = Some(Expression::Operation(ast::Operation::Equal(
+                Box::new(Expression::Field("x".to_string())),
+                Box::new(Expression::Field("y".to_string())),
+            )));
+            assert_eq!(
+                plan,
+                Plan(Node::HashJoin {
+                    left: scan("t1"),
+                    right: scan("t2"),
+                    predicate: predicate.clone(),
+                    join_type: join_type.clone(),
+                    using: vec![],
+                    swapped: false,
+                    tables: tables(),
+                })
+            );
+            // 与 NestLoopJoin 的结果一致
+            let nest_loop = Plan(Node::NestLoopJoin {
+                left: scan("t1"),
+                right: scan("t2"),
+                predicate,
+                join_type,
+                using: vec![],
+                swapped: false,
+                tables: tables(),
+            });
+            let expected = nest_loop.execute(&mut txn)?;
+            let result = plan.execute(&mut txn)?;
+            match &result {
+                ResultSet::Scan { rows, .. } => assert!(rows.len() > 2000),
+                _ => unreachable!(),
+            }
+            assert_eq!(result, expected);
+        }
+
+        // 非等值条件、cross join 仍然使用 NestLoopJoin
+        let plan = Plan::build(Parser::new("select * from t1, t2;").parse()?, &mut txn)?;
+        assert!(matches!(plan.0, Node::NestLoopJoin { .. }));
+        let stmt = Parser::new("select * from t1 join t2 on x = 1;").parse()?;
+        let plan = Plan::build(stmt, &mut txn)?;
+        assert!(matches!(plan.0, Node::NestLoopJoin { .. }));
+        txn.rollback()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_fold_constants() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c float);")?;
+        s.execute("insert into t values (1, 10, 1.5), (2, 20, 2.5), (3, 30, null);")?;
+
+        let mut txn = kvengine.begin()?;
+        fn build(sql: &str, txn: &mut impl Transaction) -> RSDBResult<Plan> {
+            Plan::build(Parser::new(sql).parse()?, txn)
+        }
+        for (sql, folded) in [
+            (
+                "select * from t where b = 1 + 2 * 3 limit 5 * 2 offset 4 - 3;",
+                "select * from t where b = 7 limit 10 offset 1;",
+            ),
+            (
+                "select * from t where a = 4 div 2;",
+                "select * from t where a = 2;",
+            ),
+            (
+                "select * from t where c > 1 / 2.0 and b = 7 % 4;",
+                "select * from t where c > 0.5 and b = 3;",
+            ),
+            (
+                "select * from t where b = null + 1;",
+                "select * from t where b = null;",
+            ),
+            (
+                "update t set b = 2 * 5 where a = 1 + 0;",
+                "update t set b = 10 where a = 1;",
+            ),
+            (
+                "delete from t where b > 10 - 1;",
+                "delete from t where b > 9;",
+            ),
+        ] {
+            assert_eq!(build(sql, &mut txn)?, build(folded, &mut txn)?);
+        }
+
+        // 包含列的子表达式不折叠
+        let plan = build("select * from t where b = a + 1 * 2;", &mut txn)?;
+        assert_eq!(
+            plan,
+            Plan(Node::Scan {
+                table_name: "t".to_string(),
+                filter: Some(Expression::Operation(ast::Operation::Equal(
+                    Box::new(Expression::Field("b".to_string())),
+                    Box::new(Expression::Operation(ast::Operation::Add(
+                        Box::new(Expression::Field("a".to_string())),
+                        Box::new(Expression::Consts(ast::Consts::Integer(2))),
+                    ))),
+                ))),
+                columns: None,
+                limit: None,
+                reverse: false,
+            })
+        );
+
+        // 除数为 0 时保留原表达式，执行时才报错
+        let plan = build("select * from t where b = 1 / 0;", &mut txn)?;
+        assert!(matches!(
+            &plan.0,
+            Node::Scan { filter: Some(Expression::Operation(ast::Operation::Equal(_, r))), .. }
+                if matches!(r.as_ref(), Expression::Operation(ast::Operation::Divide(_, _)))
+        ));
+        assert!(plan.execute(&mut txn).is_err());
+
+        // LIMIT 必须是非负整数常量
+        assert!(build("select * from t limit 1 - 2;", &mut txn).is_err());
+        assert!(build("select * from t limit 1 / 0;", &mut txn).is_err());
+        txn.rollback()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_join_order() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table big (a int primary key, x int);")?;
+        s.execute("create table small (b int primary key, y int);")?;
+        let values = (0..50)
+            .map(|i| {
+                format!(
+                    "({}, {})",
+                    i,
+                    if i % 7 == 0 {
+                        "null".to_string()
+                    } else {
+                        (i % 10).to_string()
+                    }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        s.execute(&format!("insert into big values {};", values))?;
+        s.execute("insert into small values (1, 1), (2, 3), (3, null), (4, 11);")?;
+
+        let mut txn = kvengine.begin()?;
+        let sorted = |rs: ResultSet| match rs {
+            ResultSet::Scan {
+                columns, mut rows, ..
+            } => {
+                rows.sort_by(|a, b| {
+                    a.iter()
+                        .zip(b.iter())
+                        .map(|(x, y)| x.total_cmp(y))
+                        .find(|o| o.is_ne())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                (columns, rows)
+            }
+            _ => unreachable!(),
+        };
+        // 交换执行顺序后的结果应与不交换时一致，包括外连接中填充 NULL 的行
+        for (sql, swapped) in [
+            ("select * from big join small on x = y;", false),
+            ("select * from small join big on y = x;", true),
+            ("select * from big left join small on x = y;", false),
+            ("select * from small left join big on y = x;", true),
+            ("select * from big right join small on x = y;", false),
+            ("select * from small right join big on y = x;", true),
+            ("select * from big full join small on x = y;", false),
+            ("select * from small full join big on y = x;", true),
+            ("select * from big left join small on x = 3;", true),
+            ("select * from small left join big on y = 3;", false),
+            ("select * from big full join small on x = 3;", true),
+            ("select * from big, small;", true),
+        ] {
+            let plan = Plan::build(Parser::new(sql).parse()?, &mut txn)?;
+            let mut unswapped = Plan::build(Parser::new(sql).parse()?, &mut txn)?;
+            match &mut unswapped.0 {
+                Node::HashJoin { swapped: s, .. } | Node::NestLoopJoin { swapped: s, .. } => {
+                    assert_eq!(*s, swapped, "{}", sql);
+                    *s = false;
+                }
+                _ => unreachable!(),
+            }
+            assert_eq!(
+                sorted(plan.execute(&mut txn)?),
+                sorted(unswapped.execute(&mut txn)?),
+                "{}",
+                sql
+            );
+        }
+        txn.rollback()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_check_columns() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (id int primary key, a int, x text);")?;
+        s.execute("create table t2 (id int primary key, b int, x text);")?;
+        s.execute("insert into t1 values (1, 10, 'a');")?;
+
+        let mut txn = kvengine.begin()?;
+        let not_found = |column: &str, table: &str| RSDBError::ColumnNotFound {
+            column: column.to_string(),
+            table: table.to_string(),
+        };
+        for (sql, err) in [
+            ("select xyz from t1;", not_found("xyz", "t1")),
+            ("select * from t1 where xyz = 1;", not_found("xyz", "t1")),
+            ("select count(xyz) from t1;", not_found("xyz", "t1")),
+            (
+                "select a, count(id) from t1 group by xyz;",
+                not_found("xyz", "t1"),
+            ),
+            (
+                "select count(id) from t1 group by a having xyz > 1;",
+                not_found("xyz", "t1"),
+            ),
+            ("select a from t1 order by xyz;", not_found("xyz", "t1")),
+            ("update t1 set xyz = 1;", not_found("xyz", "t1")),
+            ("update t1 set a = 1 where xyz = 1;", not_found("xyz", "t1")),
+            ("delete from t1 where xyz = 1;", not_found("xyz", "t1")),
+            (
+                "select * from t1 join t2 on a = c;",
+                not_found("c", "t1, t2"),
+            ),
+            (
+                "select * from t1, t2 where c = 1;",
+                not_found("c", "t1, t2"),
+            ),
+            (
+                "select x from t1 join t2 on a = b;",
+                RSDBError::AmbiguousColumn("x".to_string()),
+            ),
+            (
+                "select * from t1 join t2 using (id) where x = 'a';",
+                RSDBError::AmbiguousColumn("x".to_string()),
+            ),
+        ] {
+            let stmt = Parser::new(sql).parse()?;
+            assert_eq!(Plan::build(stmt, &mut txn), Err(err), "{}", sql);
+        }
+
+        // 别名、USING 合并后的列、Join 条件两侧的同名列都可以引用
+        for sql in [
+            "select a as v from t1 order by v;",
+            "select a, count(id) as n from t1 group by a having n > 0;",
+            "select id, a, b from t1 join t2 using (id) where id = 1;",
+            "select a, b from t1 join t2 on id = id;",
+        ] {
+            Plan::build(Parser::new(sql).parse()?, &mut txn)?;
+        }
+        // 出错时没有执行任何操作
+        assert!(s.execute("update t1 set a = 0 where xyz = 1;").is_err());
+        match s.execute("select a from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(10)]]),
+            _ => unreachable!(),
+        }
+        txn.rollback()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_check_group_by() -> RSDBResult<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        kvengine
+            .session()?
+            .execute("create table t (a int primary key, b int, c int);")?;
+        let mut txn = kvengine.begin()?;
+        let not_grouped = |col: &str| {
+            RSDBError::Internal(format!(
+                "{} must appear in the GROUP BY clause or be used in an aggregate function",
+                col
+            ))
+        };
+        for (sql, err) in [
+            // SELECT 中的列不在 GROUP BY 中
+            ("select a, b, count(c) from t group by b;", not_grouped("a")),
+            (
+                "select a, b, count(c) from t group by c, b;",
+                not_grouped("a"),
+            ),
+            ("select b, a from t group by b;", not_grouped("a")),
+            // 没有 GROUP BY 时不能和聚集函数一起查询普通列
+            ("select a, count(c) from t;", not_grouped("a")),
+            // HAVING 中的列不在 GROUP BY 中
+            (
+                "select b, count(c) from t group by b having a > 1;",
+                not_grouped("a"),
+            ),
+            // 没有 GROUP BY 和聚集函数时不能使用 HAVING
+            (
+                "select a from t having a > 1;",
+                RSDBError::Internal(
+                    "HAVING requires GROUP BY or an aggregate function".to_string(),
+                ),
+            ),
+        ] {
+            let stmt = Parser::new(sql).parse()?;
+            assert_eq!(Plan::build(stmt, &mut txn), Err(err), "{}", sql);
+        }
+
+        for sql in [
+            "select b, count(c) from t group by b;",
+            "select c, b, count(a) from t group by b, c having c > 1;",
+            "select b as x, max(a) from t group by b having x > 1;",
+            "select b, sum(c) as s from t group by b having s > 1;",
+            "select count(a), min(b) from t;",
+            "select count(a) as n from t having n > 0;",
+        ] {
+            Plan::build(Parser::new(sql).parse()?, &mut txn)?;
+        }
+        txn.rollback()?;
+        Ok(())
+    }
 }
@@ -1,11 +1,13 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::{
     error::{RSDBError, RSDBResult},
     sql::{
         engine::Transaction,
-        parser::ast::{self, Expression},
-        plan::{Node, Plan},
-        schema::{self, Table},
-        types::Value,
+        parser::ast::{self, Expression, SequenceFunction},
+        plan::{JoinTables, Node, Plan},
+        schema::{self, Table, TableStats},
+        types::{DataType, Value},
     },
 };
 
@@ -24,31 +26,81 @@ impl<'a, T: Transaction> Planner<'a, T> {
 
     fn build_statement(&self, stmt: ast::Statement) -> RSDBResult<Node> {
         let node = match stmt {
-            ast::Statement::CreateTable { name, columns } => Node::CreateTable {
+            ast::Statement::CreateTable { name, columns, .. } => Node::CreateTable {
                 schema: Table {
                     name,
                     columns: columns
                         .into_iter()
                         .map(|c| {
                             let nullable = c.nullable.unwrap_or(!c.primary_key);
-                            let default = match c.default {
-                                Some(expr) => Some(Value::from_expression(expr)),
-                                None if nullable => Some(Value::Null),
-                                None => None,
+                            // 默认值为 nextval('s') 时在插入时分配，只记录序列名
+                            let (default, default_sequence) = match c.default {
+                                Some(Expression::Sequence(SequenceFunction::NextVal, name)) => {
+                                    (None, Some(name))
+                                }
+                                Some(Expression::Sequence(func, _)) => {
+                                    return Err(RSDBError::Internal(format!(
+                                        "{}() is not allowed in DEFAULT",
+                                        func
+                                    )));
+                                }
+                                Some(expr) => (
+                                    Some(Value::from_expression(expr).coerce(&c.datatype)?),
+                                    None,
+                                ),
+                                None if nullable => (Some(Value::Null), None),
+                                None => (None, None),
                             };
-                            schema::Column {
+                            Ok(schema::Column {
                                 name: c.name,
                                 datatype: c.datatype,
+                                max_length: c.max_length,
+                                int_width: c.int_width,
+                                precision: c.precision,
                                 nullable,
                                 default,
+                                default_sequence,
                                 primary_key: c.primary_key,
                                 index: c.index && !c.primary_key,
-                            }
+                            })
                         })
-                        .collect(),
+                        .collect::<RSDBResult<_>>()?,
+                    indexes: Vec::new(),
                 },
             },
-            ast::Statement::DropTable { name } => Node::DropTable { table_name: name },
+            ast::Statement::DropTable { name, if_exists } => Node::DropTable {
+                table_name: name,
+                if_exists,
+            },
+            ast::Statement::CreateIndex {
+                name,
+                table_name,
+                columns,
+            } => {
+                let table_columns = self.table_column_counts(&table_name, &None)?;
+                for col in &columns {
+                    let field = Expression::Field(col.clone());
+                    Self::check_columns(&field, &table_columns, &[], &table_name)?;
+                }
+                Node::CreateIndex {
+                    table_name,
+                    index: schema::Index { name, columns },
+                }
+            }
+            ast::Statement::CreateSequence { name } => Node::CreateSequence { name },
+            ast::Statement::DropSequence { name, if_exists } => {
+                Node::DropSequence { name, if_exists }
+            }
+            ast::Statement::CreateDatabase { name } => Node::CreateDatabase { name },
+            ast::Statement::DropDatabase { name, if_exists } => {
+                Node::DropDatabase { name, if_exists }
+            }
+            ast::Statement::Describe { table_name } => Node::DescribeTable { table_name },
+            ast::Statement::ShowTables { extended } => Node::ShowTables { extended },
+            ast::Statement::Analyze { table_name } => {
+                self.txn.must_get_table(table_name.clone())?;
+                Node::Analyze { table_name }
+            }
             ast::Statement::Insert {
                 table_name,
                 columns,
@@ -56,7 +108,10 @@ impl<'a, T: Transaction> Planner<'a, T> {
             } => Node::Insert {
                 table_name,
                 columns: columns.unwrap_or_default(),
-                values,
+                values: values
+                    .iter()
+                    .map(|row| row.iter().map(Self::fold_constants).collect())
+                    .collect(),
             },
             ast::Statement::Select {
                 select,
@@ -67,10 +122,111 @@ impl<'a, T: Transaction> Planner<'a, T> {
                 order_by,
                 limit,
                 offset,
+                // 历史版本在开启事务时处理
+                as_of: _,
             } => {
+                // 先对各个子句做常量折叠
+                let mut select: Vec<_> = select
+                    .iter()
+                    .map(|(expr, alias)| (Self::fold_constants(expr), alias.clone()))
+                    .collect();
+                let where_clause = where_clause.as_ref().map(Self::fold_constants);
+                let having = having.as_ref().map(Self::fold_constants);
+                // 在构建执行节点之前检查引用的列是否存在，HAVING 和 ORDER BY 还可以引用别名
+                let from_columns = self.item_column_counts(&from)?;
+                let from_name = Self::item_name(&from);
+                let aliases = select
+                    .iter()
+                    .filter_map(|(_, alias)| alias.clone())
+                    .collect::<Vec<_>>();
+                for expr in select
+                    .iter()
+                    .map(|(expr, _)| expr)
+                    .chain(where_clause.iter())
+                    .chain(group_by.iter())
+                {
+                    Self::check_columns(expr, &from_columns, &[], &from_name)?;
+                }
+                if let Some(expr) = &having {
+                    Self::check_columns(expr, &from_columns, &aliases, &from_name)?;
+                }
+                for (col, _) in &order_by {
+                    let expr = Self::order_aggregate(col).unwrap_or(Expression::Field(col.clone()));
+                    Self::check_columns(&expr, &from_columns, &aliases, &from_name)?;
+                }
+                let has_window = Self::check_windows(&select, &group_by, &having)?;
+                Self::check_group_by(&select, &group_by, &having, &aliases)?;
+                if !select
+                    .iter()
+                    .any(|(expr, _)| matches!(expr, Expression::Function(_, _)))
+                    && group_by.is_empty()
+                    && let Some((col, _)) = order_by
+                        .iter()
+                        .find(|(col, _)| Self::order_aggregate(col).is_some())
+                {
+                    return Err(RSDBError::Internal(format!(
+                        "ORDER BY {} requires GROUP BY or an aggregate function",
+                        col
+                    )));
+                }
+                let single_table = matches!(from, ast::FromItem::Table { .. });
                 // from
-                let mut node = self.build_from_item(from, &where_clause)?;
+                let mut node = self.build_from_item(from, where_clause)?;
+                // 单表查询只输出用到的列，select * 需要完整的行
+                if single_table
+                    && !select.is_empty()
+                    && let Node::Scan {
+                        table_name,
+                        columns,
+                        ..
+                    } = &mut node
+                {
+                    let mut fields = Vec::new();
+                    select
+                        .iter()
+                        .map(|(expr, _)| expr)
+                        .chain(group_by.iter())
+                        .chain(having.iter())
+                        .for_each(|expr| Self::collect_fields(expr, &mut fields));
+                    for (col, _) in &order_by {
+                        match Self::order_aggregate(col) {
+                            Some(expr) => Self::collect_fields(&expr, &mut fields),
+                            None => fields.push(col.clone()),
+                        }
+                    }
+                    let table = self.txn.must_get_table(table_name.clone())?;
+                    *columns = Some(
+                        table
+                            .columns
+                            .into_iter()
+                            .map(|c| c.name)
+                            .filter(|name| {
+                                fields.iter().any(|f| {
+                                    f == name || f.split_once('.').is_some_and(|(_, c)| c == name)
+                                })
+                            })
+                            .collect(),
+                    );
+                }
+                // window，窗口函数的结果作为新的列追加在行的后面，SELECT 中改为引用这些列
+                if has_window {
+                    let mut functions = Vec::new();
+                    for (expr, alias) in select.iter_mut() {
+                        if let Expression::Window(window) = expr {
+                            let name = alias.take().unwrap_or_else(|| window.to_string());
+                            functions.push((window.clone(), name.clone()));
+                            *expr = Expression::Field(name);
+                        }
+                    }
+                    node = Node::Window {
+                        source: Box::new(node),
+                        functions,
+                    };
+                }
                 // aggregate, group by
+                let mut having = having;
+                let mut order_by = order_by;
+                let mut has_hidden = false;
                 let mut has_agg = false;
                 if !select.is_empty() {
                     for (expr, _) in select.iter() {
@@ -79,25 +235,74 @@ impl<'a, T: Transaction> Planner<'a, T> {
                             break;
                         }
                     }
-                    if group_by.is_some() {
+                    if !group_by.is_empty() {
                         has_agg = true;
                     }
                     if has_agg {
+                        // HAVING 和 ORDER BY 中的聚集函数引用聚集输出的列，
+                        // 不在 SELECT 中的聚集函数作为隐藏列加入聚集，最后再投影去掉
+                        let mut exprs = select.clone();
+                        let mut output_name = |func: &String, col: &String| {
+                            let pos = exprs.iter().position(|(expr, _)| {
+                                matches!(expr, Expression::Function(f, c)
+                                    if f.eq_ignore_ascii_case(func) && c == col)
+                            });
+                            let pos = pos.unwrap_or_else(|| {
+                                let expr = Expression::Function(func.clone(), col.clone());
+                                exprs.push((expr, None));
+                                exprs.len() - 1
+                            });
+                            Self::aggregate_columns(&exprs[pos..=pos]).remove(0)
+                        };
+                        having = having.map(|expr| {
+                            expr.map_functions(&mut |func, col| {
+                                Expression::Field(output_name(func, col))
+                            })
+                        });
+                        for (col, _) in order_by.iter_mut() {
+                            if let Some(Expression::Function(func, arg)) =
+                                Self::order_aggregate(col)
+                            {
+                                *col = output_name(&func, &arg);
+                            }
+                        }
+                        has_hidden = exprs.len() > select.len();
                         node = Node::Aggregate {
                             source: Box::new(node),
-                            exprs: select.clone(),
+                            exprs,
                             group_by,
                         }
                     }
                 }
                 // having
-                if let Some(expr) = having {
-                    node = Node::Filter {
-                        source: Box::new(node),
-                        predicate: expr,
+                match Self::prune_filter(having) {
+                    Some(Some(expr)) => {
+                        node = Node::Filter {
+                            source: Box::new(node),
+                            predicate: expr,
+                        }
+                    }
+                    Some(None) => {}
+                    None => {
+                        node = match node {
+                            Node::Aggregate { exprs, .. } => Node::Nothing {
+                                columns: Self::aggregate_columns(&exprs),
+                            },
+                            node => Node::Filter {
+                                source: Box::new(node),
+                                predicate: Expression::Consts(ast::Consts::Boolean(false)),
+                            },
+                        }
                     }
                 }
                 // order by
+                // 扫描按主键的顺序返回，只按主键排序时不需要 Order 节点，降序时倒序扫描
+                if let Some(desc) = self.primary_key_order(&node, &order_by, &aliases)?
+                    && let Node::Scan { reverse, .. } = &mut node
+                {
+                    *reverse = desc;
+                    order_by.clear();
+                }
                 if !order_by.is_empty() {
                     node = Node::Order {
                         source: Box::new(node),
@@ -108,28 +313,16 @@ impl<'a, T: Transaction> Planner<'a, T> {
                 if let Some(expr) = offset {
                     node = Node::Offset {
                         source: Box::new(node),
-                        offset: match Value::from_expression(expr) {
-                            Value::Integer(i) => i as usize,
-                            _ => {
-                                return Err(RSDBError::Internal(
-                                    "invalid offset expression".to_string(),
-                                ));
-                            }
-                        },
+                        offset: Self::build_count(&expr, "offset")?,
                     }
                 }
                 // limit
                 if let Some(expr) = limit {
+                    let limit = Self::build_count(&expr, "limit")?;
+                    Self::push_limit(&mut node, limit);
                     node = Node::Limit {
                         source: Box::new(node),
-                        limit: match Value::from_expression(expr) {
-                            Value::Integer(i) => i as usize,
-                            _ => {
-                                return Err(RSDBError::Internal(
-                                    "invalid limit expression".to_string(),
-                                ));
-                            }
-                        },
+                        limit,
                     }
                 }
                 // projection
@@ -139,6 +332,15 @@ impl<'a, T: Transaction> Planner<'a, T> {
                         source: Box::new(node),
                         exprs: select,
                     }
+                } else if has_hidden {
+                    // 去掉只在 HAVING 或 ORDER BY 中使用的聚集列
+                    node = Node::Projection {
+                        source: Box::new(node),
+                        exprs: Self::aggregate_columns(&select)
+                            .into_iter()
+                            .map(|col| (Expression::Field(col), None))
+                            .collect(),
+                    }
                 }
                 node
             }
@@ -146,119 +348,987 @@ impl<'a, T: Transaction> Planner<'a, T> {
                 table_name,
                 columns,
                 where_clause,
-            } => Node::Update {
-                table_name: table_name.clone(),
-                source: Box::new(self.build_scan(table_name.clone(), where_clause)?),
-                columns: columns.into_iter().collect(),
-            },
+            } => {
+                let table_columns = self.table_column_counts(&table_name, &None)?;
+                for (col, expr) in &columns {
+                    let field = Expression::Field(col.clone());
+                    Self::check_columns(&field, &table_columns, &[], &table_name)?;
+                    Self::check_columns(expr, &table_columns, &[], &table_name)?;
+                }
+                if let Some(expr) = &where_clause {
+                    Self::check_columns(expr, &table_columns, &[], &table_name)?;
+                }
+                Node::Update {
+                    table_name: table_name.clone(),
+                    source: Box::new(self.build_scan(
+                        table_name.clone(),
+                        where_clause.as_ref().map(Self::fold_constants),
+                    )?),
+                    columns: columns
+                        .iter()
+                        .map(|(col, expr)| (col.clone(), Self::fold_constants(expr)))
+                        .collect(),
+                }
+            }
             ast::Statement::Delete {
                 table_name,
                 where_clause,
-            } => Node::Delete {
-                table_name: table_name.clone(),
-                source: Box::new(self.build_scan(table_name.clone(), where_clause)?),
-            },
-            ast::Statement::Begin | ast::Statement::Commit | ast::Statement::Rollback => {
+            } => {
+                if let Some(expr) = &where_clause {
+                    let table_columns = self.table_column_counts(&table_name, &None)?;
+                    Self::check_columns(expr, &table_columns, &[], &table_name)?;
+                }
+                Node::Delete {
+                    table_name: table_name.clone(),
+                    source: Box::new(self.build_scan(
+                        table_name.clone(),
+                        where_clause.as_ref().map(Self::fold_constants),
+                    )?),
+                }
+            }
+            ast::Statement::Begin { .. } | ast::Statement::Commit | ast::Statement::Rollback => {
                 return Err(RSDBError::Internal(
                     "transaction statements are not supported in planner".to_string(),
                 ));
             }
+            ast::Statement::Explain { .. } => {
+                return Err(RSDBError::Internal(
+                    "explain statement is not supported in planner".to_string(),
+                ));
+            }
+            ast::Statement::Set { .. }
+            | ast::Statement::ShowSettings { .. }
+            | ast::Statement::ShowTransaction
+            | ast::Statement::ShowStatus
+            | ast::Statement::UseDatabase { .. }
+            | ast::Statement::Vacuum => {
+                return Err(RSDBError::Internal(
+                    "session statements are not supported in planner".to_string(),
+                ));
+            }
         };
         Ok(node)
     }
 
-    fn build_from_item(
+    // 常量折叠，将只包含常量的子表达式在构建计划时求值
+    // 求值出错（如除数为 0）时保留原表达式，使错误仍在执行阶段出现
+    pub fn fold_constants(expr: &Expression) -> Expression {
+        let Expression::Operation(operation) = expr else {
+            return expr.clone();
+        };
+        type Ctor = fn(Box<Expression>, Box<Expression>) -> ast::Operation;
+        let (ctor, l, r): (Ctor, _, _) = match operation {
+            ast::Operation::Equal(l, r) => (ast::Operation::Equal, l, r),
+            ast::Operation::GreaterThan(l, r) => (ast::Operation::GreaterThan, l, r),
+            ast::Operation::LessThan(l, r) => (ast::Operation::LessThan, l, r),
+            ast::Operation::And(l, r) => (ast::Operation::And, l, r),
+            ast::Operation::Or(l, r) => (ast::Operation::Or, l, r),
+            ast::Operation::Add(l, r) => (ast::Operation::Add, l, r),
+            ast::Operation::Subtract(l, r) => (ast::Operation::Subtract, l, r),
+            ast::Operation::Multiply(l, r) => (ast::Operation::Multiply, l, r),
+            ast::Operation::Divide(l, r) => (ast::Operation::Divide, l, r),
+            ast::Operation::Modulo(l, r) => (ast::Operation::Modulo, l, r),
+            ast::Operation::Div(l, r) => (ast::Operation::Div, l, r),
+        };
+        let folded = Expression::Operation(ctor(
+            Box::new(Self::fold_constants(l)),
+            Box::new(Self::fold_constants(r)),
+        ));
+        if let Expression::Operation(
+            ast::Operation::Equal(l, r)
+            | ast::Operation::GreaterThan(l, r)
+            | ast::Operation::LessThan(l, r)
+            | ast::Operation::And(l, r)
+            | ast::Operation::Or(l, r)
+            | ast::Operation::Add(l, r)
+            | ast::Operation::Subtract(l, r)
+            | ast::Operation::Multiply(l, r)
+            | ast::Operation::Divide(l, r)
+            | ast::Operation::Modulo(l, r)
+            | ast::Operation::Div(l, r),
+        ) = &folded
+            && matches!(
+                (l.as_ref(), r.as_ref()),
+                (Expression::Consts(_), Expression::Consts(_))
+            )
+            && let Ok(value) = ast::evaluate_expr(&folded, &vec![], &vec![], &vec![], &vec![])
+        {
+            return value.into();
+        }
+        folded
+    }
+
+    // ORDER BY 只有一列且为扫描的表的主键时，返回是否为降序，其他情况返回 None
+    // 多列排序时即使第一列是主键也不处理，排序列为 SELECT 中的别名时可能不是原来的列
+    // 只有编码后的字节序与值的大小一致时，存储的顺序才是主键的顺序：
+    // 整数和日期时间按照大端字节序编码，负数排在正数之后；定点数的小数位数不同时字节序与大小不一致
+    fn primary_key_order(
         &self,
-        item: ast::FromItem,
-        filter: &Option<Expression>,
-    ) -> RSDBResult<Node> {
+        node: &Node,
+        order_by: &[(String, ast::OrderDirection)],
+        aliases: &[String],
+    ) -> RSDBResult<Option<bool>> {
+        let (Node::Scan { table_name, .. }, [(col, direction)]) = (node, order_by) else {
+            return Ok(None);
+        };
+        if aliases.contains(col) {
+            return Ok(None);
+        }
+        let table = self.txn.must_get_table(table_name.clone())?;
+        let name = col.rsplit('.').next().unwrap_or(col);
+        Ok(match table.columns.iter().find(|c| c.primary_key) {
+            Some(pk)
+                if pk.name == name
+                    && matches!(
+                        pk.datatype,
+                        DataType::Boolean | DataType::Float | DataType::String | DataType::Bytes
+                    ) =>
+            {
+                Some(matches!(direction, ast::OrderDirection::Desc))
+            }
+            _ => None,
+        })
+    }
+
+    // 将 LIMIT 需要的行数下推到扫描节点，扫描到足够的行后提前结束
+    // 中间有排序、聚集、过滤、Join 等节点时，扫描的行数与输出的行数无关，不能下推
+    fn push_limit(node: &mut Node, rows: usize) {
+        match node {
+            Node::Offset { source, offset } => {
+                Self::push_limit(source, rows.saturating_add(*offset))
+            }
+            Node::Scan { limit, .. } => *limit = Some(rows),
+            _ => {}
+        }
+    }
+
+    // 解析 LIMIT/OFFSET 的值，必须是非负整数常量
+    fn build_count(expr: &Expression, name: &str) -> RSDBResult<usize> {
+        match Self::fold_constants(expr) {
+            Expression::Consts(ast::Consts::Integer(i)) if i >= 0 => Ok(i as usize),
+            _ => Err(RSDBError::Internal(format!("invalid {} expression", name))),
+        }
+    }
+
+    fn build_from_item(&self, item: ast::FromItem, filter: Option<Expression>) -> RSDBResult<Node> {
         let node = match item {
-            ast::FromItem::Table { name } => self.build_scan(name, filter.clone())?,
+            // 别名限定的列名在扫描时去掉别名，如 e.a 改为 a
+            ast::FromItem::Table { name, alias } => {
+                let filter = match alias {
+                    Some(alias) => filter.map(|expr| Self::unqualify(&expr, &alias)),
+                    None => filter,
+                };
+                self.build_scan(name, filter)?
+            }
             ast::FromItem::Join {
                 left,
                 right,
                 join_type,
                 predicate,
+                using,
             } => {
-                let (left, right) = match join_type {
-                    ast::JoinType::Right => (right, left),
-                    _ => (left, right),
+                let (keep_left, keep_right) = join_type.keeps();
+                // Join 条件的左右两边分别在两侧计算，同名列不算歧义，只检查是否存在
+                let predicate = match predicate {
+                    Some(expr) => {
+                        let (left_cols, right_cols) =
+                            (self.item_columns(&left)?, self.item_columns(&right)?);
+                        let counts = left_cols
+                            .union(&right_cols)
+                            .map(|c| (c.clone(), 1))
+                            .collect();
+                        let name =
+                            format!("{}, {}", Self::item_name(&left), Self::item_name(&right));
+                        Self::check_columns(&expr, &counts, &[], &name)?;
+                        Some(Self::orient_join_predicate(expr, &left_cols, &right_cols))
+                    }
+                    None => None,
+                };
+                // WHERE 条件按 AND 拆分，只涉及一侧的列的条件下推到该侧，其余的在 Join 之后过滤
+                // 外连接中会填充 NULL 的一侧不能下推，否则会多出填充 NULL 的行
+                let (left_filter, right_filter, filter) = match Self::prune_filter(filter) {
+                    Some(filter) => {
+                        self.split_join_filter(&left, &right, filter, !keep_right, !keep_left)?
+                    }
+                    // 恒为假的条件下推到所有的表，Join 的结果为空，不需要读取数据
+                    None => {
+                        let never = Some(Expression::Consts(ast::Consts::Boolean(false)));
+                        (never.clone(), never, None)
+                    }
+                };
+                let table = |item: &ast::FromItem| match item {
+                    ast::FromItem::Table { name, alias } => {
+                        Some(alias.as_ref().unwrap_or(name).clone())
+                    }
+                    ast::FromItem::Join { .. } => None,
+                };
+                let tables = JoinTables {
+                    left: table(&left),
+                    right: table(&right),
                 };
-                let outer = match join_type {
-                    ast::JoinType::Cross | ast::JoinType::Inner => false,
-                    _ => true,
+                let left = Box::new(self.build_from_item(*left, left_filter)?);
+                let right = Box::new(self.build_from_item(*right, right_filter)?);
+                // 两个列的等值条件使用 HashJoin，其余的使用 NestLoopJoin
+                let hash_join = match &predicate {
+                    Some(Expression::Operation(ast::Operation::Equal(l, r))) => {
+                        matches!(
+                            (l.as_ref(), r.as_ref()),
+                            (Expression::Field(_), Expression::Field(_))
+                        )
+                    }
+                    _ => false,
                 };
-                if join_type == ast::JoinType::Cross {
+                // 根据估算的行数决定执行顺序：NestLoopJoin 以较小的一侧作为外层循环，
+                // HashJoin 以较小的一侧构建哈希表
+                let (left_rows, right_rows) =
+                    (self.estimate_rows(&left)?, self.estimate_rows(&right)?);
+                let node = if join_type == ast::JoinType::Cross || !hash_join {
                     Node::NestLoopJoin {
-                        left: Box::new(self.build_from_item(*left, filter)?),
-                        right: Box::new(self.build_from_item(*right, filter)?),
+                        left,
+                        right,
                         predicate,
-                        outer,
+                        join_type,
+                        using,
+                        swapped: right_rows < left_rows,
+                        tables,
                     }
                 } else {
                     Node::HashJoin {
-                        left: Box::new(self.build_from_item(*left, filter)?),
-                        right: Box::new(self.build_from_item(*right, filter)?),
+                        left,
+                        right,
                         predicate,
-                        outer,
+                        join_type,
+                        using,
+                        swapped: left_rows < right_rows,
+                        tables,
                     }
+                };
+                match filter {
+                    Some(predicate) => Node::Filter {
+                        source: Box::new(node),
+                        predicate,
+                    },
+                    None => node,
                 }
             }
         };
         Ok(node)
     }
 
-    fn build_scan(&self, table_name: String, filter: Option<Expression>) -> RSDBResult<Node> {
-        let node = match Self::parse_scan_filter(filter.clone()) {
-            Some((field, value)) => {
-                let table = self.txn.must_get_table(table_name.clone())?;
-                // 判断是否是主键
-                if table
-                    .columns
+    // 去掉恒为真的条件，如 where true、where 1 = 1
+    // 存在恒为假或者为 NULL 的条件时，结果一定为空，返回 None
+    fn prune_filter(filter: Option<Expression>) -> Option<Option<Expression>> {
+        let Some(filter) = filter else {
+            return Some(None);
+        };
+        let mut exprs = Vec::new();
+        for expr in Self::split_conjunction(filter) {
+            match expr {
+                Expression::Consts(ast::Consts::Boolean(true)) => {}
+                Expression::Consts(ast::Consts::Boolean(false) | ast::Consts::Null) => return None,
+                expr => exprs.push(expr),
+            }
+        }
+        Some(Self::join_conjunction(exprs))
+    }
+
+    // 聚集节点输出的列名，与 Aggregate 执行器的命名一致
+    fn aggregate_columns(exprs: &[(Expression, Option<String>)]) -> Vec<String> {
+        exprs
+            .iter()
+            .map(|(expr, alias)| match (expr, alias) {
+                (_, Some(alias)) => alias.clone(),
+                (Expression::Function(func_name, col_name), None) => {
+                    format!("{}({})", func_name.to_uppercase(), col_name)
+                }
+                (expr, None) => expr.to_string(),
+            })
+            .collect()
+    }
+
+    // ORDER BY 中的聚集函数，如 COUNT(a)
+    fn order_aggregate(col: &str) -> Option<Expression> {
+        let (func, arg) = col.strip_suffix(')')?.split_once('(')?;
+        Some(Expression::Function(func.to_lowercase(), arg.to_string()))
+    }
+
+    // 估算执行节点输出的行数
+    fn estimate_rows(&self, node: &Node) -> RSDBResult<usize> {
+        Ok(match node {
+            // 优先使用 ANALYZE 收集的统计信息，避免每次都扫描表
+            Node::Scan { table_name, .. } => match self.txn.get_stats(table_name)? {
+                Some(stats) => stats.row_count,
+                None => self
+                    .txn
+                    .count_rows(&self.txn.must_get_table(table_name.clone())?)?,
+            },
+            Node::PrimaryKeyScan { .. } => 1,
+            Node::Nothing { .. } => 0,
+            Node::IndexScan {
+                table_name,
+                index,
+                values,
+                ..
+            } => self.txn.load_index(table_name, index, values)?.len(),
+            Node::MultiIndexScan {
+                table_name,
+                field,
+                values,
+            } => {
+                let mut rows = 0;
+                for value in values {
+                    rows += self
+                        .txn
+                        .load_index(table_name, field, std::slice::from_ref(value))?
+                        .len();
+                }
+                rows
+            }
+            Node::Filter { source, .. } | Node::Distinct { source } => {
+                self.estimate_rows(source)?
+            }
+            Node::NestLoopJoin {
+                left,
+                right,
+                predicate,
+                ..
+            }
+            | Node::HashJoin {
+                left,
+                right,
+                predicate,
+                ..
+            } => {
+                let (l, r) = (self.estimate_rows(left)?, self.estimate_rows(right)?);
+                match predicate {
+                    Some(_) => l.max(r),
+                    None => l.saturating_mul(r),
+                }
+            }
+            _ => usize::MAX,
+        })
+    }
+
+    // 将 Join 的过滤条件拆分为左侧、右侧以及 Join 之后的三部分
+    // 两侧有同名列时，Join 之后按照左侧的列计算，因此只能下推到左侧
+    fn split_join_filter(
+        &self,
+        left: &ast::FromItem,
+        right: &ast::FromItem,
+        filter: Option<Expression>,
+        push_left: bool,
+        push_right: bool,
+    ) -> RSDBResult<(Option<Expression>, Option<Expression>, Option<Expression>)> {
+        let Some(filter) = filter else {
+            return Ok((None, None, None));
+        };
+        let left_cols = self.item_columns(left)?;
+        let right_cols = self.item_columns(right)?;
+        let (mut left_exprs, mut right_exprs, mut exprs) = (Vec::new(), Vec::new(), Vec::new());
+        for expr in Self::split_conjunction(filter) {
+            let mut fields = Vec::new();
+            Self::collect_fields(&expr, &mut fields);
+            if fields.is_empty() {
+                exprs.push(expr);
+            } else if push_left && fields.iter().all(|f| left_cols.contains(f)) {
+                left_exprs.push(expr);
+            } else if push_right
+                && fields
+                    .iter()
+                    .all(|f| right_cols.contains(f) && !left_cols.contains(f))
+            {
+                right_exprs.push(expr);
+            } else {
+                exprs.push(expr);
+            }
+        }
+        Ok((
+            Self::join_conjunction(left_exprs),
+            Self::join_conjunction(right_exprs),
+            Self::join_conjunction(exprs),
+        ))
+    }
+
+    // FROM 中每个列名出现的次数，USING 的列合并后只算一次
+    fn item_column_counts(&self, item: &ast::FromItem) -> RSDBResult<HashMap<String, usize>> {
+        Ok(match item {
+            ast::FromItem::Table { name, alias } => self.table_column_counts(name, alias)?,
+            ast::FromItem::Join {
+                left, right, using, ..
+            } => {
+                let mut counts = self.item_column_counts(left)?;
+                for (col, n) in self.item_column_counts(right)? {
+                    *counts.entry(col).or_default() += n;
+                }
+                for col in using {
+                    counts.insert(col.clone(), 1);
+                }
+                counts
+            }
+        })
+    }
+
+    // 列名既可以直接引用，也可以用表名限定，如 a、t.a，有别名时只能用别名限定
+    fn table_column_counts(
+        &self,
+        table_name: &str,
+        alias: &Option<String>,
+    ) -> RSDBResult<HashMap<String, usize>> {
+        Ok(self
+            .table_columns(table_name, alias)?
+            .into_iter()
+            .map(|c| (c, 1))
+            .collect())
+    }
+
+    fn table_columns(&self, table_name: &str, alias: &Option<String>) -> RSDBResult<Vec<String>> {
+        let columns = self.txn.must_get_table(table_name.to_string())?.columns;
+        let qualifier = alias.as_deref().unwrap_or(table_name);
+        Ok(columns
+            .iter()
+            .map(|c| c.name.clone())
+            .chain(columns.iter().map(|c| format!("{}.{}", qualifier, c.name)))
+            .collect())
+    }
+
+    // FROM 中的表名，用于错误信息
+    fn item_name(item: &ast::FromItem) -> String {
+        match item {
+            ast::FromItem::Table { name, .. } => name.clone(),
+            ast::FromItem::Join { left, right, .. } => {
+                format!("{}, {}", Self::item_name(left), Self::item_name(right))
+            }
+        }
+    }
+
+    // 检查表达式中引用的列是否存在，多张表中都存在的同名列有歧义
+    fn check_columns(
+        expr: &Expression,
+        columns: &HashMap<String, usize>,
+        aliases: &[String],
+        table: &str,
+    ) -> RSDBResult<()> {
+        let mut fields = Vec::new();
+        Self::collect_fields(expr, &mut fields);
+        for field in fields.into_iter().filter(|f| !aliases.contains(f)) {
+            match columns.get(&field) {
+                None => {
+                    return Err(RSDBError::ColumnNotFound {
+                        column: field,
+                        table: table.to_string(),
+                    });
+                }
+                Some(n) if *n > 1 => return Err(RSDBError::AmbiguousColumn(field)),
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    // 聚集查询中，SELECT 和 HAVING 里不在聚集函数中的列必须出现在 GROUP BY 中
+    fn check_group_by(
+        select: &[(Expression, Option<String>)],
+        group_by: &[Expression],
+        having: &Option<Expression>,
+        aliases: &[String],
+    ) -> RSDBResult<()> {
+        let has_agg = select
+            .iter()
+            .any(|(expr, _)| matches!(expr, Expression::Function(_, _)));
+        if !has_agg && group_by.is_empty() {
+            return match having {
+                Some(_) => Err(RSDBError::Internal(
+                    "HAVING requires GROUP BY or an aggregate function".to_string(),
+                )),
+                None => Ok(()),
+            };
+        }
+        let mut fields = Vec::new();
+        for (expr, _) in select {
+            Self::collect_plain_fields(expr, &mut fields);
+        }
+        if let Some(expr) = having {
+            let mut having_fields = Vec::new();
+            Self::collect_plain_fields(expr, &mut having_fields);
+            // HAVING 中可以引用 SELECT 中的别名
+            fields.extend(having_fields.into_iter().filter(|f| !aliases.contains(f)));
+        }
+        for field in fields {
+            if !group_by
+                .iter()
+                .any(|expr| matches!(expr, Expression::Field(col) if *col == field))
+            {
+                return Err(RSDBError::Internal(format!(
+                    "{} must appear in the GROUP BY clause or be used in an aggregate function",
+                    field
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // 窗口函数只能直接作为 SELECT 中的一列，暂不支持与 GROUP BY、聚集函数一起使用
+    // 返回 SELECT 中是否有窗口函数
+    fn check_windows(
+        select: &[(Expression, Option<String>)],
+        group_by: &[Expression],
+        having: &Option<Expression>,
+    ) -> RSDBResult<bool> {
+        let nested = select
+            .iter()
+            .any(|(expr, _)| !matches!(expr, Expression::Window(_)) && Self::contains_window(expr));
+        if nested
+            || group_by
+                .iter()
+                .chain(having.iter())
+                .any(Self::contains_window)
+        {
+            return Err(RSDBError::Internal(
+                "window functions are only allowed as select columns".to_string(),
+            ));
+        }
+        let windows = select
+            .iter()
+            .filter_map(|(expr, _)| match expr {
+                Expression::Window(window) => Some(window),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if let Some(window) = windows.iter().find(|w| {
+            !w.func.eq_ignore_ascii_case("row_number") && !w.func.eq_ignore_ascii_case("rank")
+        }) {
+            return Err(RSDBError::Internal(format!(
+                "unsupported window function {}",
+                window.func
+            )));
+        }
+        let has_window = !windows.is_empty();
+        if has_window
+            && (!group_by.is_empty()
+                || having.is_some()
+                || select
                     .iter()
-                    .position(|c| c.name == field && c.primary_key)
-                    .is_some()
+                    .any(|(expr, _)| matches!(expr, Expression::Function(_, _))))
+        {
+            return Err(RSDBError::Internal(
+                "window functions cannot be used with GROUP BY or aggregate functions".to_string(),
+            ));
+        }
+        Ok(has_window)
+    }
+
+    fn contains_window(expr: &Expression) -> bool {
+        match expr {
+            Expression::Window(_) => true,
+            Expression::Operation(
+                ast::Operation::Equal(l, r)
+                | ast::Operation::GreaterThan(l, r)
+                | ast::Operation::LessThan(l, r)
+                | ast::Operation::And(l, r)
+                | ast::Operation::Or(l, r)
+                | ast::Operation::Add(l, r)
+                | ast::Operation::Subtract(l, r)
+                | ast::Operation::Multiply(l, r)
+                | ast::Operation::Divide(l, r)
+                | ast::Operation::Modulo(l, r)
+                | ast::Operation::Div(l, r),
+            ) => Self::contains_window(l) || Self::contains_window(r),
+            _ => false,
+        }
+    }
+
+    // 表达式中不在聚集函数中的列名
+    fn collect_plain_fields(expr: &Expression, fields: &mut Vec<String>) {
+        match expr {
+            Expression::Function(_, _) => {}
+            Expression::Operation(
+                ast::Operation::Equal(l, r)
+                | ast::Operation::GreaterThan(l, r)
+                | ast::Operation::LessThan(l, r)
+                | ast::Operation::And(l, r)
+                | ast::Operation::Or(l, r)
+                | ast::Operation::Add(l, r)
+                | ast::Operation::Subtract(l, r)
+                | ast::Operation::Multiply(l, r)
+                | ast::Operation::Divide(l, r)
+                | ast::Operation::Modulo(l, r)
+                | ast::Operation::Div(l, r),
+            ) => {
+                Self::collect_plain_fields(l, fields);
+                Self::collect_plain_fields(r, fields);
+            }
+            expr => Self::collect_fields(expr, fields),
+        }
+    }
+
+    // FROM 中所有表的列名
+    fn item_columns(&self, item: &ast::FromItem) -> RSDBResult<HashSet<String>> {
+        Ok(match item {
+            ast::FromItem::Table { name, alias } => {
+                self.table_columns(name, alias)?.into_iter().collect()
+            }
+            ast::FromItem::Join { left, right, .. } => {
+                let mut cols = self.item_columns(left)?;
+                cols.extend(self.item_columns(right)?);
+                cols
+            }
+        })
+    }
+
+    // 按 AND 拆分条件，如 a > 1 and b = 2 拆分为 a > 1、b = 2
+    fn split_conjunction(expr: Expression) -> Vec<Expression> {
+        match expr {
+            Expression::Operation(ast::Operation::And(l, r)) => {
+                let mut exprs = Self::split_conjunction(*l);
+                exprs.extend(Self::split_conjunction(*r));
+                exprs
+            }
+            expr => vec![expr],
+        }
+    }
+
+    fn join_conjunction(exprs: Vec<Expression>) -> Option<Expression> {
+        exprs
+            .into_iter()
+            .reduce(|l, r| Expression::Operation(ast::Operation::And(Box::new(l), Box::new(r))))
+    }
+
+    // 等值条件的左边在左侧计算、右边在右侧计算，如 on t2.b = t1.a 交换为 on t1.a = t2.b
+    fn orient_join_predicate(
+        expr: Expression,
+        left_cols: &HashSet<String>,
+        right_cols: &HashSet<String>,
+    ) -> Expression {
+        let only_in = |expr: &Expression, cols: &HashSet<String>, other: &HashSet<String>| {
+            let mut fields = Vec::new();
+            Self::collect_fields(expr, &mut fields);
+            !fields.is_empty()
+                && fields
+                    .iter()
+                    .all(|f| cols.contains(f) && !other.contains(f))
+        };
+        let exprs = Self::split_conjunction(expr)
+            .into_iter()
+            .map(|expr| match expr {
+                Expression::Operation(ast::Operation::Equal(l, r))
+                    if only_in(&l, right_cols, left_cols) && only_in(&r, left_cols, right_cols) =>
                 {
-                    return Ok(Node::PrimaryKeyScan { table_name, value });
+                    Expression::Operation(ast::Operation::Equal(r, l))
                 }
+                expr => expr,
+            })
+            .collect();
+        Self::join_conjunction(exprs).unwrap()
+    }
+
+    // 去掉以 table 限定的列名中的表名
+    fn unqualify(expr: &Expression, table: &str) -> Expression {
+        expr.map_fields(&|col| match col.split_once('.') {
+            Some((t, c)) if t == table => c.to_string(),
+            _ => col.clone(),
+        })
+    }
 
-                match table
+    // 表达式中引用的所有列名
+    fn collect_fields(expr: &Expression, fields: &mut Vec<String>) {
+        match expr {
+            Expression::Field(f) => fields.push(f.clone()),
+            Expression::Function(_, col_name) => fields.push(col_name.clone()),
+            Expression::Operation(
+                ast::Operation::Equal(l, r)
+                | ast::Operation::GreaterThan(l, r)
+                | ast::Operation::LessThan(l, r)
+                | ast::Operation::And(l, r)
+                | ast::Operation::Or(l, r)
+                | ast::Operation::Add(l, r)
+                | ast::Operation::Subtract(l, r)
+                | ast::Operation::Multiply(l, r)
+                | ast::Operation::Divide(l, r)
+                | ast::Operation::Modulo(l, r)
+                | ast::Operation::Div(l, r),
+            ) => {
+                Self::collect_fields(l, fields);
+                Self::collect_fields(r, fields);
+            }
+            Expression::Window(window) => {
+                fields.extend(window.partition_by.iter().cloned());
+                fields.extend(window.order_by.iter().map(|(col, _)| col.clone()));
+            }
+            Expression::Consts(_) | Expression::Placeholder(_) | Expression::Sequence(_, _) => {}
+        }
+    }
+
+    fn build_scan(&self, table_name: String, filter: Option<Expression>) -> RSDBResult<Node> {
+        // 扫描单表时去掉列名中的表名，如 t.a 改为 a
+        let filter = filter.map(|expr| Self::unqualify(&expr, &table_name));
+        let Some(filter) = Self::prune_filter(filter) else {
+            let table = self.txn.must_get_table(table_name)?;
+            return Ok(Node::Nothing {
+                columns: table.columns.into_iter().map(|c| c.name).collect(),
+            });
+        };
+        let Some(expr) = &filter else {
+            return Ok(Node::Scan {
+                table_name,
+                filter,
+                columns: None,
+                limit: None,
+                reverse: false,
+            });
+        };
+        // 在执行前检查过滤条件中列与常量的类型是否可以比较
+        let table = self.txn.must_get_table(table_name.clone())?;
+        Self::check_filter_types(&table, expr)?;
+        // AND 连接的多个条件中，选出可以走索引的条件，剩余的条件在索引扫描之后过滤
+        let stats = self.txn.get_stats(&table_name)?;
+        let mut conjuncts = Self::split_conjunction(expr.clone());
+        let mut candidates = Vec::new();
+        for (i, conjunct) in conjuncts.iter().enumerate() {
+            if let Some((node, rows)) = Self::build_index_scan(&table, stats.as_ref(), conjunct)? {
+                candidates.push((vec![i], node, rows));
+            }
+        }
+        // 多列索引使用有等值条件的最长前缀列
+        let equals = conjuncts
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| Self::parse_scan_filter(Some(c.clone())).map(|(f, v)| (i, f, v)))
+            .collect::<Vec<_>>();
+        for index in &table.indexes {
+            let (mut used, mut fields, mut values) = (Vec::new(), Vec::new(), Vec::new());
+            for col_name in &index.columns {
+                let col = &table.columns[table.get_col_index(col_name)?];
+                let mut found = None;
+                for (i, field, value) in &equals {
+                    if field == col_name
+                        && let Some(value) = Self::index_value(col, value.clone())?
+                    {
+                        found = Some((*i, value));
+                        break;
+                    }
+                }
+                let Some((i, value)) = found else {
+                    break;
+                };
+                used.push(i);
+                fields.push(col_name.clone());
+                values.push(value);
+            }
+            if fields.is_empty() {
+                continue;
+            }
+            let rows = Self::estimate_index_rows(stats.as_ref(), &fields, 1);
+            let node = Node::IndexScan {
+                table_name: table_name.clone(),
+                index: index.name.clone(),
+                fields,
+                values,
+            };
+            candidates.push((used, node, rows));
+        }
+        // 选择估算行数最少的，相同时选择覆盖条件最多的，再相同时选择第一个
+        let mut best: Option<(Vec<usize>, Node, usize)> = None;
+        for (used, node, rows) in candidates {
+            if best.as_ref().is_none_or(|(best_used, _, best_rows)| {
+                rows < *best_rows || (rows == *best_rows && used.len() > best_used.len())
+            }) {
+                best = Some((used, node, rows));
+            }
+        }
+        let Some((used, node, _)) = best else {
+            return Ok(Node::Scan {
+                table_name,
+                filter,
+                columns: None,
+                limit: None,
+                reverse: false,
+            });
+        };
+        let mut i = 0;
+        conjuncts.retain(|_| {
+            i += 1;
+            !used.contains(&(i - 1))
+        });
+        Ok(match Self::join_conjunction(conjuncts) {
+            Some(predicate) => Node::Filter {
+                source: Box::new(node),
+                predicate,
+            },
+            None => node,
+        })
+    }
+
+    // 估算索引扫描的行数，有统计信息时按 行数 / 各列不同值个数的乘积 估算，否则视为全表
+    fn estimate_index_rows(stats: Option<&TableStats>, fields: &[String], keys: usize) -> usize {
+        let Some(stats) = stats else {
+            return usize::MAX;
+        };
+        let distinct = fields
+            .iter()
+            .map(|field| {
+                stats
                     .columns
                     .iter()
-                    .position(|c| c.name == field && c.index)
-                {
-                    Some(_) => Node::IndexScan {
-                        table_name,
+                    .find(|c| &c.name == field)
+                    .map_or(1, |c| c.distinct.max(1))
+            })
+            .fold(1usize, |acc, d| acc.saturating_mul(d));
+        (stats.row_count / distinct).max(1).saturating_mul(keys)
+    }
+
+    // 将等值条件中的常量转换成列的类型，如 DATE 列的 '2024-01-31'
+    // 无法转换（如 INT 列 a = 1.0）或为 NULL 时不能走索引，NULL 与任何值都不相等，但索引中保存了 NULL 值的行
+    fn index_value(col: &schema::Column, value: Value) -> RSDBResult<Option<Value>> {
+        let value = value.coerce(&col.datatype)?;
+        Ok(match value.datatype() {
+            Some(datatype) if datatype == col.datatype => Some(value),
+            _ => None,
+        })
+    }
+
+    // 根据单个条件构建主键或单列索引扫描节点，同时返回估算的行数，主键扫描最多一行
+    fn build_index_scan(
+        table: &Table,
+        stats: Option<&TableStats>,
+        expr: &Expression,
+    ) -> RSDBResult<Option<(Node, usize)>> {
+        // 同一个索引列的多个等值条件，如 b = 'a' or b = 'c'、b in ('a', 'c')
+        if let Some((field, values)) = Self::parse_scan_filter_in(expr)
+            && let Some(col) = table.columns.iter().find(|c| c.name == field && c.index)
+        {
+            let mut coerced = Vec::new();
+            for value in values {
+                // NULL 与任何值都不相等，重复的值只查找一次
+                let value = value.coerce(&col.datatype)?;
+                if value != Value::Null && !coerced.contains(&value) {
+                    coerced.push(value);
+                }
+            }
+            if coerced
+                .iter()
+                .all(|v| v.datatype().as_ref() == Some(&col.datatype))
+            {
+                let rows =
+                    Self::estimate_index_rows(stats, std::slice::from_ref(&field), coerced.len());
+                return Ok(Some((
+                    Node::MultiIndexScan {
+                        table_name: table.name.clone(),
                         field,
-                        value,
+                        values: coerced,
                     },
-                    None => Node::Scan { table_name, filter },
-                }
+                    rows,
+                )));
             }
-            None => Node::Scan { table_name, filter },
+        }
+        let Some((field, value)) = Self::parse_scan_filter(Some(expr.clone())) else {
+            return Ok(None);
         };
-        Ok(node)
+        let Some(col) = table
+            .columns
+            .iter()
+            .find(|c| c.name == field && (c.primary_key || c.index))
+        else {
+            return Ok(None);
+        };
+        let Some(value) = Self::index_value(col, value)? else {
+            return Ok(None);
+        };
+        if col.primary_key {
+            return Ok(Some((
+                Node::PrimaryKeyScan {
+                    table_name: table.name.clone(),
+                    value,
+                },
+                1,
+            )));
+        }
+        let rows = Self::estimate_index_rows(stats, std::slice::from_ref(&field), 1);
+        Ok(Some((
+            Node::IndexScan {
+                table_name: table.name.clone(),
+                index: field.clone(),
+                fields: vec![field],
+                values: vec![value],
+            },
+            rows,
+        )))
     }
 
-    fn parse_scan_filter(filter: Option<Expression>) -> Option<(String, Value)> {
-        match filter {
-            Some(expr) => match expr {
-                Expression::Field(f) => Some((f, Value::Null)),
-                Expression::Consts(c) => Some((
-                    "".to_string(),
-                    Value::from_expression(Expression::Consts(c)),
-                )),
-                Expression::Operation(operation) => match operation {
-                    ast::Operation::Equal(l, r) => {
-                        let lv = Self::parse_scan_filter(Some(*l));
-                        let rv = Self::parse_scan_filter(Some(*r));
-                        Some((lv.unwrap().0, rv.unwrap().1))
+    // 检查比较运算中列和常量的类型，如 BOOL 列 d = 1
+    // 不属于该表的列跳过，如 join 时另一张表的列
+    fn check_filter_types(table: &Table, expr: &Expression) -> RSDBResult<()> {
+        let Expression::Operation(operation) = expr else {
+            return Ok(());
+        };
+        match operation {
+            ast::Operation::Equal(l, r)
+            | ast::Operation::GreaterThan(l, r)
+            | ast::Operation::LessThan(l, r) => match (l.as_ref(), r.as_ref()) {
+                (Expression::Field(field), Expression::Consts(c))
+                | (Expression::Consts(c), Expression::Field(field)) => {
+                    let Some(col) = table.columns.iter().find(|col| &col.name == field) else {
+                        return Ok(());
+                    };
+                    let value = Value::from_expression(Expression::Consts(c.clone()));
+                    match value.datatype() {
+                        Some(datatype) if !col.datatype.is_comparable(&datatype) => {
+                            Err(RSDBError::TypeMismatch(format!(
+                                "column {} of type {} can not be compared with {}",
+                                col.name,
+                                col.datatype_desc(),
+                                value
+                            )))
+                        }
+                        _ => Ok(()),
                     }
-                    _ => None,
-                },
-                _ => None,
+                }
+                _ => Ok(()),
             },
-            None => None,
+            ast::Operation::And(l, r) | ast::Operation::Or(l, r) => {
+                Self::check_filter_types(table, l)?;
+                Self::check_filter_types(table, r)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // 解析 OR 连接的同一列的等值条件，返回列名和所有的值
+    fn parse_scan_filter_in(expr: &Expression) -> Option<(String, Vec<Value>)> {
+        let Expression::Operation(ast::Operation::Or(l, r)) = expr else {
+            return None;
+        };
+        let mut fields = Vec::new();
+        for expr in [l.as_ref(), r.as_ref()] {
+            match expr {
+                Expression::Operation(ast::Operation::Or(_, _)) => {
+                    fields.push(Self::parse_scan_filter_in(expr)?)
+                }
+                expr => {
+                    let (field, value) = Self::parse_scan_filter(Some(expr.clone()))?;
+                    fields.push((field, vec![value]))
+                }
+            }
+        }
+        let (rfield, rvalues) = fields.pop()?;
+        let (field, mut values) = fields.pop()?;
+        if field != rfield {
+            return None;
+        }
+        values.extend(rvalues);
+        Some((field, values))
+    }
+
+    // 解析列与常量的等值条件，列可以在等号的任意一侧，如 a = 2、2 = a
+    fn parse_scan_filter(filter: Option<Expression>) -> Option<(String, Value)> {
+        let Some(Expression::Operation(ast::Operation::Equal(l, r))) = filter else {
+            return None;
+        };
+        match (*l, *r) {
+            (Expression::Field(f), Expression::Consts(c))
+            | (Expression::Consts(c), Expression::Field(f)) => {
+                Some((f, Value::from_expression(Expression::Consts(c))))
+            }
+            _ => None,
         }
     }
 }
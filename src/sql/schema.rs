@@ -4,13 +4,45 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{RSDBError, RSDBResult},
-    sql::types::{DataType, Row, Value},
+    sql::types::{DataType, IntegerWidth, MAX_DECIMAL_DIGITS, Row, Value},
 };
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+// 表的统计信息，由 ANALYZE 语句收集，供优化器估算代价
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableStats {
+    pub row_count: usize,
+    pub columns: Vec<ColumnStats>,
+}
+
+// 列的统计信息，min/max 不包含 NULL，全部为 NULL 时为 NULL
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnStats {
+    pub name: String,
+    pub distinct: usize,
+    pub min: Value,
+    pub max: Value,
+}
+
+// 表占用的空间，由 show tables extended 扫描得到，不会持久化
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableSize {
+    pub rows: usize,
+    // 行和索引序列化后的字节数之和，不包含 key 和历史版本，只是近似值
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
+    // 通过 CREATE INDEX 创建的索引，可以包含多列
+    pub indexes: Vec<Index>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    pub name: String,
+    pub columns: Vec<String>,
 }
 
 impl Table {
@@ -62,6 +94,41 @@ impl Table {
                     None => {}
                 }
             }
+            // 校验默认值的长度、取值范围
+            if let Some(default_val) = &col.default {
+                col.validate_value(default_val)?;
+            }
+            // 序列的值都是整数
+            if col.default_sequence.is_some() && col.datatype != DataType::Integer {
+                return Err(RSDBError::Internal(format!(
+                    "Default value for column {} in table {} does not match its datatype",
+                    col.name, self.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // 校验一行数据是否满足列的约束：是否为空、数据类型、字符串长度、整数范围
+    pub fn validate_row(&self, row: &Row) -> RSDBResult<()> {
+        for (i, col) in self.columns.iter().enumerate() {
+            match row[i].datatype() {
+                None if col.nullable => continue,
+                None => {
+                    return Err(RSDBError::NotNullViolation {
+                        column: col.name.clone(),
+                    });
+                }
+                Some(dt) if dt != col.datatype => {
+                    return Err(RSDBError::ColumnTypeMismatch {
+                        column: col.name.clone(),
+                        datatype: col.datatype_desc(),
+                        value: row[i].to_string(),
+                    });
+                }
+                _ => {}
+            }
+            col.validate_value(&row[i])?;
         }
         Ok(())
     }
@@ -75,6 +142,27 @@ impl Table {
         Ok(row[pos].clone())
     }
 
+    // 一行数据在所有索引中对应的 key，即索引名和索引列的值
+    // 单列索引以列名作为索引名；多列索引的每个前缀都单独保存，如 (a, b) 保存 [a] 和 [a, b]
+    // 这样只有前缀列上有等值条件时也可以直接查找
+    pub fn index_keys(&self, row: &Row) -> RSDBResult<Vec<(String, Vec<Value>)>> {
+        let mut keys = self
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, col)| col.index)
+            .map(|(i, col)| (col.name.clone(), vec![row[i].clone()]))
+            .collect::<Vec<_>>();
+        for index in &self.indexes {
+            let mut values = Vec::new();
+            for col_name in &index.columns {
+                values.push(row[self.get_col_index(col_name)?].clone());
+                keys.push((index.name.clone(), values.clone()));
+            }
+        }
+        Ok(keys)
+    }
+
     pub fn get_col_index(&self, col_name: &str) -> RSDBResult<usize> {
         self.columns
             .iter()
@@ -94,30 +182,107 @@ impl Display for Table {
             .map(|col| format!("{}", col))
             .collect::<Vec<_>>()
             .join(",\n");
-        write!(f, "CREATE TABLE {} (\n{}\n)", self.name, col_desc)
+        write!(f, "CREATE TABLE {} (\n{}\n)", self.name, col_desc)?;
+        for index in &self.indexes {
+            write!(
+                f,
+                "\nCREATE INDEX {} ON {} ({})",
+                index.name,
+                self.name,
+                index.columns.join(", ")
+            )?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Column {
     pub name: String,
     pub datatype: DataType,
+    // 字符串的最大字符个数，如 varchar(32)，超出长度时直接报错，不做截断
+    pub max_length: Option<usize>,
+    // 整数声明的宽度，如 smallint，超出范围时报错
+    pub int_width: Option<IntegerWidth>,
+    // 定点数声明的精度，即最多的有效数字位数，如 decimal(5, 2)，超出时报错
+    pub precision: Option<u32>,
     pub nullable: bool,
     pub default: Option<Value>,
+    // 默认值为 nextval('s') 时的序列名，插入时每次分配序列的下一个值
+    pub default_sequence: Option<String>,
     pub primary_key: bool,
     pub index: bool,
 }
 
+impl Column {
+    // 列类型的描述，可以直接用于建表语句，如 Varchar(32)、Decimal(38, 2)、Smallint
+    pub fn datatype_desc(&self) -> String {
+        match (&self.datatype, self.max_length, self.int_width) {
+            (DataType::String, Some(max_length), _) => format!("Varchar({})", max_length),
+            (DataType::Integer, _, Some(IntegerWidth::Tiny)) => "Tinyint".to_string(),
+            (DataType::Integer, _, Some(IntegerWidth::Small)) => "Smallint".to_string(),
+            (DataType::Integer, _, Some(IntegerWidth::Big)) => "Bigint".to_string(),
+            (DataType::Decimal(scale), _, _) => format!(
+                "Decimal({}, {})",
+                self.precision.unwrap_or(MAX_DECIMAL_DIGITS),
+                scale
+            ),
+            (DataType::Bytes, _, _) => "Bytea".to_string(),
+            (datatype, _, _) => format!("{:?}", datatype),
+        }
+    }
+
+    // 校验值的长度和取值范围，字符串按字符个数计算长度，定点数按有效数字的位数计算精度
+    fn validate_value(&self, value: &Value) -> RSDBResult<()> {
+        match (value, self.max_length, self.int_width) {
+            (Value::String(s), Some(max_length), _) => {
+                let length = s.chars().count();
+                if length > max_length {
+                    return Err(RSDBError::ValueTooLong {
+                        column: self.name.clone(),
+                        length,
+                        max_length,
+                    });
+                }
+            }
+            (Value::Integer(i), _, Some(width)) => {
+                let (min, max) = width.range();
+                if *i < min || *i > max {
+                    return Err(RSDBError::ValueOutOfRange {
+                        column: self.name.clone(),
+                        value: i.to_string(),
+                        datatype: self.datatype_desc(),
+                    });
+                }
+            }
+            (Value::Decimal(v, _), _, _) if let Some(precision) = self.precision => {
+                let digits = v.unsigned_abs().checked_ilog10().map_or(1, |d| d + 1);
+                if digits > precision {
+                    return Err(RSDBError::ValueOutOfRange {
+                        column: self.name.clone(),
+                        value: value.to_string(),
+                        datatype: self.datatype_desc(),
+                    });
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
 impl Display for Column {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut col_desc = format!("    {} {:?}", self.name, self.datatype);
+        let mut col_desc = format!("    {} {}", self.name, self.datatype_desc());
         if self.primary_key {
             col_desc += " PRIMARY KEY";
         }
         if !self.nullable && !self.primary_key {
             col_desc += " NOT NULL";
         }
-        if let Some(v) = &self.default {
+        if let Some(name) = &self.default_sequence {
+            col_desc += &format!(" DEFAULT nextval('{}')", name);
+        } else if let Some(v) = &self.default {
             col_desc += &format!(" DEFAULT {}", v.to_string());
         }
         write!(f, "{}", col_desc)
@@ -2,23 +2,71 @@ use std::{cmp::Ordering, fmt::Display, hash::Hash};
 
 use serde::{Deserialize, Serialize};
 
-use crate::sql::parser::ast::{Consts, Expression};
+use crate::{
+    error::{RSDBError, RSDBResult},
+    sql::parser::ast::{Consts, Expression},
+};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DataType {
     Boolean,
     Integer,
     Float,
     String,
+    Date,
+    Timestamp,
+    Decimal(u32), // 小数位数
+    Bytes,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+impl DataType {
+    // 两种类型的值能否比较，与 evaluate_expr 中的类型转换规则一致
+    // 数值类型之间、日期时间与字符串之间、浮点数和定点数与字符串之间可以比较
+    pub fn is_comparable(&self, other: &DataType) -> bool {
+        use DataType::*;
+        matches!(
+            (self, other),
+            (Boolean, Boolean)
+                | (String, String)
+                | (Bytes, Bytes)
+                | (Integer | Float | Decimal(_), Integer | Float | Decimal(_))
+                | (Date | Timestamp, Date | Timestamp | String)
+                | (String, Date | Timestamp | Float | Decimal(_))
+                | (Float | Decimal(_), String)
+        )
+    }
+}
+
+// 整数类型声明的宽度，存储时都是 Integer，写入时校验取值范围
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum IntegerWidth {
+    Tiny,
+    Small,
+    Big,
+}
+
+impl IntegerWidth {
+    // 取值范围，闭区间
+    pub fn range(&self) -> (i64, i64) {
+        match self {
+            Self::Tiny => (i8::MIN as i64, i8::MAX as i64),
+            Self::Small => (i16::MIN as i64, i16::MAX as i64),
+            Self::Big => (i64::MIN, i64::MAX),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Value {
     Null,
     Boolean(bool),
     Integer(i64),
     Float(f64),
     String(String),
+    Date(i64),          // 距 1970-01-01 的天数
+    Timestamp(i64),     // 距 1970-01-01 00:00:00 的微秒数
+    Decimal(i128, u32), // 定点数，值为 v / 10^scale，如 12.34 为 Decimal(1234, 2)
+    Bytes(#[serde(with = "serde_bytes")] Vec<u8>),
 }
 
 impl Value {
@@ -29,6 +77,10 @@ impl Value {
             Expression::Consts(Consts::Integer(i)) => Self::Integer(i),
             Expression::Consts(Consts::Float(f)) => Self::Float(f),
             Expression::Consts(Consts::String(s)) => Self::String(s),
+            Expression::Consts(Consts::Date(d)) => Self::Date(d),
+            Expression::Consts(Consts::Timestamp(t)) => Self::Timestamp(t),
+            Expression::Consts(Consts::Decimal(v, scale)) => Self::Decimal(v, scale),
+            Expression::Consts(Consts::Bytes(b)) => Self::Bytes(b),
             _ => unreachable!(),
         }
     }
@@ -40,8 +92,394 @@ impl Value {
             Self::Integer(_) => Some(DataType::Integer),
             Self::Float(_) => Some(DataType::Float),
             Self::String(_) => Some(DataType::String),
+            Self::Date(_) => Some(DataType::Date),
+            Self::Timestamp(_) => Some(DataType::Timestamp),
+            Self::Decimal(_, scale) => Some(DataType::Decimal(*scale)),
+            Self::Bytes(_) => Some(DataType::Bytes),
         }
     }
+
+    // 将值转换为列的数据类型，目前只处理日期时间和定点数类型
+    // 如 '2024-01-31' 写入 DATE 列时转换为 Value::Date，其余情况原样返回，由调用方校验类型
+    pub fn coerce(self, datatype: &DataType) -> RSDBResult<Value> {
+        Ok(match (self, datatype) {
+            (Self::String(s), DataType::Date) => Self::Date(parse_date(&s)?),
+            (Self::String(s), DataType::Timestamp) => Self::Timestamp(parse_timestamp(&s)?),
+            (Self::Date(d), DataType::Timestamp) => Self::Timestamp(d * MICROS_PER_DAY),
+            (Self::String(s), DataType::Float) => Self::Float(parse_float(&s)?),
+            // 整数转换为浮点数，无法精确表示的整数（绝对值超过 2^53 的部分值）报错
+            (Self::Integer(i), DataType::Float) => {
+                let f = i as f64;
+                if f as i128 != i as i128 {
+                    return Err(RSDBError::Internal(format!(
+                        "can not convert {} to float without losing precision",
+                        i
+                    )));
+                }
+                Self::Float(f)
+            }
+            (
+                v @ (Self::Integer(_) | Self::Float(_) | Self::String(_)),
+                DataType::Decimal(scale),
+            ) => {
+                let (value, from) = v.to_decimal()?;
+                Self::Decimal(rescale_decimal(value, from, *scale)?, *scale)
+            }
+            (Self::Decimal(v, from), DataType::Decimal(scale)) => {
+                Self::Decimal(rescale_decimal(v, from, *scale)?, *scale)
+            }
+            (v, _) => v,
+        })
+    }
+
+    // 转换为定点数，返回值和小数位数，浮点数按照其最短的十进制表示转换，如 0.1 转换为 (1, 1)
+    pub fn to_decimal(&self) -> RSDBResult<(i128, u32)> {
+        match self {
+            Self::Integer(i) => Ok((*i as i128, 0)),
+            Self::Float(f) => parse_decimal(&f.to_string()),
+            Self::String(s) => parse_decimal(s),
+            Self::Decimal(v, scale) => Ok((*v, *scale)),
+            v => Err(RSDBError::Internal(format!(
+                "can not convert {} to decimal",
+                v
+            ))),
+        }
+    }
+}
+
+// 全序比较，可用于排序、MIN / MAX 等
+// 除了 partial_cmp 的规则外，不同类型之间按照类型排序
+impl Value {
+    pub fn total_cmp(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other)
+            .unwrap_or_else(|| self.type_order().cmp(&other.type_order()))
+    }
+
+    fn type_order(&self) -> u8 {
+        match self {
+            Self::Null => 0,
+            Self::Boolean(_) => 1,
+            Self::Integer(_) => 2,
+            Self::Float(_) => 3,
+            Self::String(_) => 4,
+            Self::Date(_) => 5,
+            Self::Timestamp(_) => 6,
+            Self::Decimal(_, _) => 7,
+            Self::Bytes(_) => 8,
+        }
+    }
+}
+
+// 浮点数的全序，与 Postgres 一致：NaN 等于 NaN，并且大于所有其他值（包括 Infinity），-0.0 等于 0.0
+pub fn float_cmp(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => a.partial_cmp(&b).unwrap(),
+    }
+}
+
+// 相等的浮点数统一成相同的表示，所有 NaN 统一为 f64::NAN，-0.0 统一为 0.0，用于 Hash 以及编码
+pub fn canonical_float(f: f64) -> f64 {
+    if f.is_nan() {
+        f64::NAN
+    } else if f == 0.0 {
+        0.0
+    } else {
+        f
+    }
+}
+
+// 解析浮点数，支持 'NaN'、'Infinity'、'-Infinity'，不区分大小写
+pub fn parse_float(s: &str) -> RSDBResult<f64> {
+    s.trim()
+        .parse()
+        .map_err(|_| RSDBError::Internal(format!("invalid float '{}'", s)))
+}
+
+// 浮点数按照最短的可还原表示展示，整数值保留 .0 以区别于整数，如 1.0、0.1
+// 绝对值过大或过小时使用科学计数法，如 1e16、1e-7
+pub fn format_float(f: f64) -> String {
+    if f.is_nan() {
+        return "NaN".to_string();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let abs = f.abs();
+    if abs != 0.0 && !(1e-4..1e16).contains(&abs) {
+        return format!("{:e}", f);
+    }
+    let s = f.to_string();
+    if s.contains('.') { s } else { s + ".0" }
+}
+
+// 解析十六进制字符串，如 'deadbeef'
+pub fn parse_hex(s: &str) -> RSDBResult<Vec<u8>> {
+    let err = || RSDBError::Internal(format!("invalid hex string '{}'", s));
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return Err(err());
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| err()))
+        .collect()
+}
+
+pub fn format_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// 定点数最多 38 位有效数字，保证能用 i128 表示
+pub const MAX_DECIMAL_DIGITS: u32 = 38;
+
+// 解析十进制数字符串，返回值和小数位数，如 '-12.340' 返回 (-12340, 3)
+pub fn parse_decimal(s: &str) -> RSDBResult<(i128, u32)> {
+    let err = || RSDBError::Internal(format!("invalid decimal '{}'", s));
+    let t = s.trim();
+    let (negative, t) = match t.strip_prefix('-') {
+        Some(t) => (true, t),
+        None => (false, t.strip_prefix('+').unwrap_or(t)),
+    };
+    let (int, frac) = t.split_once('.').unwrap_or((t, ""));
+    if int.is_empty() && frac.is_empty()
+        || !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit())
+    {
+        return Err(err());
+    }
+    let digits = int.trim_start_matches('0').len() + frac.len();
+    if digits > MAX_DECIMAL_DIGITS as usize {
+        return Err(RSDBError::Internal(format!("decimal '{}' out of range", s)));
+    }
+    let mut value: i128 = 0;
+    for c in int.chars().chain(frac.chars()) {
+        value = value * 10 + c.to_digit(10).unwrap() as i128;
+    }
+    Ok((if negative { -value } else { value }, frac.len() as u32))
+}
+
+pub fn format_decimal(value: i128, scale: u32) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let digits = format!("{:0>width$}", digits, width = scale as usize + 1);
+    let (int, frac) = digits.split_at(digits.len() - scale as usize);
+    let sign = if value < 0 { "-" } else { "" };
+    if scale == 0 {
+        format!("{}{}", sign, int)
+    } else {
+        format!("{}{}.{}", sign, int, frac)
+    }
+}
+
+// 调整小数位数，减少位数时四舍五入（远离 0）
+pub fn rescale_decimal(value: i128, from: u32, to: u32) -> RSDBResult<i128> {
+    let overflow = || {
+        RSDBError::Internal(format!(
+            "decimal {} out of range",
+            format_decimal(value, from)
+        ))
+    };
+    if to >= from {
+        10i128
+            .checked_pow(to - from)
+            .and_then(|p| value.checked_mul(p))
+            .ok_or_else(overflow)
+    } else {
+        let p = 10i128.checked_pow(from - to).ok_or_else(overflow)?;
+        let (q, r) = (value / p, value % p);
+        Ok(if r.abs() * 2 >= p {
+            q + value.signum()
+        } else {
+            q
+        })
+    }
+}
+
+// 将两个定点数调整为相同的小数位数
+pub fn align_decimals(l: (i128, u32), r: (i128, u32)) -> RSDBResult<(i128, i128, u32)> {
+    let scale = l.1.max(r.1);
+    Ok((
+        rescale_decimal(l.0, l.1, scale)?,
+        rescale_decimal(r.0, r.1, scale)?,
+        scale,
+    ))
+}
+
+// 定点数相加，结果的小数位数取两者中较大的
+pub fn add_decimals(l: (i128, u32), r: (i128, u32)) -> RSDBResult<(i128, u32)> {
+    let (lv, rv, scale) = align_decimals(l, r)?;
+    let v = lv.checked_add(rv).ok_or_else(|| {
+        RSDBError::Internal(format!(
+            "decimal {} + {} out of range",
+            format_decimal(l.0, l.1),
+            format_decimal(r.0, r.1)
+        ))
+    })?;
+    Ok((v, scale))
+}
+
+// 定点数相乘，结果的小数位数为两者之和
+pub fn mul_decimals(l: (i128, u32), r: (i128, u32)) -> RSDBResult<(i128, u32)> {
+    let scale = l.1 + r.1;
+    match l.0.checked_mul(r.0) {
+        Some(v) if scale <= MAX_DECIMAL_DIGITS => Ok((v, scale)),
+        _ => Err(RSDBError::Internal(format!(
+            "decimal {} * {} out of range",
+            format_decimal(l.0, l.1),
+            format_decimal(r.0, r.1)
+        ))),
+    }
+}
+
+// 定点数相除，结果的小数位数比两者中较大的多 4 位，四舍五入，除数不能为 0
+pub fn div_decimals(l: (i128, u32), r: (i128, u32)) -> RSDBResult<(i128, u32)> {
+    let scale = (l.1.max(r.1) + 4).min(MAX_DECIMAL_DIGITS);
+    // 多计算一位小数用于四舍五入
+    let v = rescale_decimal(l.0, l.1, scale + 1 + r.1)? / r.0;
+    Ok((rescale_decimal(v, scale + 1, scale)?, scale))
+}
+
+pub fn cmp_decimal(l: (i128, u32), r: (i128, u32)) -> Ordering {
+    match align_decimals(l, r) {
+        Ok((l, r, _)) => l.cmp(&r),
+        // 溢出说明小数位数少的一方绝对值更大
+        Err(_) if l.1 < r.1 => l.0.signum().cmp(&0),
+        Err(_) => 0.cmp(&r.0.signum()),
+    }
+}
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+// 解析 YYYY-MM-DD 格式的日期，返回距 1970-01-01 的天数
+pub fn parse_date(s: &str) -> RSDBResult<i64> {
+    let err = || RSDBError::Internal(format!("invalid date '{}'", s));
+    let mut parts = s.trim().splitn(3, '-');
+    let mut next = || -> RSDBResult<i64> {
+        let part = parts.next().ok_or_else(err)?;
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return Err(err());
+        }
+        part.parse().map_err(|_| err())
+    };
+    let (year, month, day) = (next()?, next()?, next()?);
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(err());
+    }
+    Ok(days_from_civil(year, month, day))
+}
+
+// 解析 YYYY-MM-DD[ HH:MM:SS[.ffffff]] 格式的时间戳，返回距 1970-01-01 00:00:00 的微秒数
+// 日期和时间之间也可以用 T 分隔
+pub fn parse_timestamp(s: &str) -> RSDBResult<i64> {
+    let err = || RSDBError::Internal(format!("invalid timestamp '{}'", s));
+    let s = s.trim();
+    let (date, time) = match s.find([' ', 'T']) {
+        Some(i) => (&s[..i], Some(s[i + 1..].trim())),
+        None => (s, None),
+    };
+    let days = parse_date(date).map_err(|_| err())?;
+    let micros = match time {
+        Some(time) => {
+            let (hms, frac) = match time.split_once('.') {
+                Some((hms, frac)) => (hms, Some(frac)),
+                None => (time, None),
+            };
+            let parts = hms.split(':').collect::<Vec<_>>();
+            if parts.len() != 3
+                || parts
+                    .iter()
+                    .any(|p| p.len() != 2 || !p.chars().all(|c| c.is_ascii_digit()))
+            {
+                return Err(err());
+            }
+            let (h, m, sec): (i64, i64, i64) = (
+                parts[0].parse().map_err(|_| err())?,
+                parts[1].parse().map_err(|_| err())?,
+                parts[2].parse().map_err(|_| err())?,
+            );
+            if h > 23 || m > 59 || sec > 59 {
+                return Err(err());
+            }
+            // 小数部分最多精确到微秒
+            let frac = match frac {
+                Some(f)
+                    if f.is_empty() || f.len() > 6 || !f.chars().all(|c| c.is_ascii_digit()) =>
+                {
+                    return Err(err());
+                }
+                Some(f) => format!("{:0<6}", f).parse::<i64>().map_err(|_| err())?,
+                None => 0,
+            };
+            (h * 3600 + m * 60 + sec) * MICROS_PER_SECOND + frac
+        }
+        None => 0,
+    };
+    Ok(days * MICROS_PER_DAY + micros)
+}
+
+pub fn format_date(days: i64) -> String {
+    let (y, m, d) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+pub fn format_timestamp(micros: i64) -> String {
+    let days = micros.div_euclid(MICROS_PER_DAY);
+    let rem = micros.rem_euclid(MICROS_PER_DAY);
+    let secs = rem / MICROS_PER_SECOND;
+    let frac = rem % MICROS_PER_SECOND;
+    let mut s = format!(
+        "{} {:02}:{:02}:{:02}",
+        format_date(days),
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    );
+    if frac != 0 {
+        s += format!(".{:06}", frac).trim_end_matches('0');
+    }
+    s
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// 公历日期与天数的互相转换，算法参考 http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = if month <= 2 {
+        yoe + era * 400 + 1
+    } else {
+        yoe + era * 400
+    };
+    (year, month, day)
 }
 
 impl Display for Value {
@@ -51,8 +489,30 @@ impl Display for Value {
             Self::Boolean(b) if *b => write!(f, "TRUE"),
             Self::Boolean(_) => write!(f, "FALSE"),
             Self::Integer(i) => write!(f, "{}", i),
-            Self::Float(fl) => write!(f, "{}", fl),
+            Self::Float(fl) => write!(f, "{}", format_float(*fl)),
             Self::String(s) => write!(f, "'{}'", s),
+            Self::Date(d) => write!(f, "{}", format_date(*d)),
+            Self::Timestamp(t) => write!(f, "{}", format_timestamp(*t)),
+            Self::Decimal(v, scale) => write!(f, "{}", format_decimal(*v, *scale)),
+            Self::Bytes(b) => write!(f, "x'{}'", format_hex(b)),
+        }
+    }
+}
+
+// 浮点数按照 float_cmp 判断相等，其余类型要求类型和值都相同
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Null, Self::Null) => true,
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::Integer(a), Self::Integer(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => float_cmp(*a, *b).is_eq(),
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Date(a), Self::Date(b)) => a == b,
+            (Self::Timestamp(a), Self::Timestamp(b)) => a == b,
+            (Self::Decimal(a, sa), Self::Decimal(b, sb)) => a == b && sa == sb,
+            (Self::Bytes(a), Self::Bytes(b)) => a == b,
+            _ => false,
         }
     }
 }
@@ -65,10 +525,22 @@ impl PartialOrd for Value {
             (_, Self::Null) => Some(Ordering::Greater),
             (Self::Boolean(a), Self::Boolean(b)) => a.partial_cmp(b),
             (Self::Integer(a), Self::Integer(b)) => a.partial_cmp(b),
-            (Self::Integer(a), Self::Float(b)) => (*a as f64).partial_cmp(b),
-            (Self::Float(a), Self::Integer(b)) => a.partial_cmp(&(*b as f64)),
-            (Self::Float(a), Self::Float(b)) => a.partial_cmp(b),
+            (Self::Integer(a), Self::Float(b)) => Some(float_cmp(*a as f64, *b)),
+            (Self::Float(a), Self::Integer(b)) => Some(float_cmp(*a, *b as f64)),
+            (Self::Float(a), Self::Float(b)) => Some(float_cmp(*a, *b)),
             (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            (Self::Date(a), Self::Date(b)) => a.partial_cmp(b),
+            (Self::Date(a), Self::Timestamp(b)) => (a * MICROS_PER_DAY).partial_cmp(b),
+            (Self::Timestamp(a), Self::Date(b)) => a.partial_cmp(&(b * MICROS_PER_DAY)),
+            (Self::Timestamp(a), Self::Timestamp(b)) => a.partial_cmp(b),
+            (Self::Decimal(a, sa), Self::Decimal(b, sb)) => Some(cmp_decimal((*a, *sa), (*b, *sb))),
+            (Self::Decimal(a, sa), Self::Integer(b)) => {
+                Some(cmp_decimal((*a, *sa), (*b as i128, 0)))
+            }
+            (Self::Integer(a), Self::Decimal(b, sb)) => {
+                Some(cmp_decimal((*a as i128, 0), (*b, *sb)))
+            }
+            (Self::Bytes(a), Self::Bytes(b)) => a.partial_cmp(b),
             _ => None, // 不同类型之间不支持比较
         }
     }
@@ -88,12 +560,29 @@ impl Hash for Value {
             }
             Self::Float(fl) => {
                 state.write_u8(3);
-                fl.to_be_bytes().hash(state);
+                canonical_float(*fl).to_be_bytes().hash(state);
             }
             Self::String(s) => {
                 state.write_u8(4);
                 s.hash(state);
             }
+            Self::Date(d) => {
+                state.write_u8(5);
+                d.hash(state);
+            }
+            Self::Timestamp(t) => {
+                state.write_u8(6);
+                t.hash(state);
+            }
+            Self::Decimal(v, scale) => {
+                state.write_u8(7);
+                v.hash(state);
+                scale.hash(state);
+            }
+            Self::Bytes(b) => {
+                state.write_u8(8);
+                b.hash(state);
+            }
         }
     }
 }
@@ -101,3 +590,154 @@ impl Hash for Value {
 impl Eq for Value {}
 
 pub type Row = Vec<Value>;
+
+// 按需拉取的行，执行器之间以此传递数据，不需要一次性物化所有的行
+pub type Rows = Box<dyn Iterator<Item = RSDBResult<Row>>>;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cmp::Ordering,
+        hash::{DefaultHasher, Hash, Hasher},
+    };
+
+    use super::{
+        DataType, Value, cmp_decimal, format_date, format_decimal, format_float, format_timestamp,
+        parse_date, parse_decimal, parse_timestamp, rescale_decimal,
+    };
+    use crate::error::RSDBResult;
+
+    #[test]
+    fn test_date() -> RSDBResult<()> {
+        assert_eq!(parse_date("1970-01-01")?, 0);
+        assert_eq!(parse_date("2024-01-31")?, 19753);
+        assert_eq!(parse_date("1969-12-31")?, -1);
+        assert_eq!(format_date(19753), "2024-01-31");
+        assert_eq!(format_date(-1), "1969-12-31");
+        assert_eq!(format_date(parse_date("2000-02-29")?), "2000-02-29");
+
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("2024-01").is_err());
+        assert!(parse_date("2024-01-x1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp() -> RSDBResult<()> {
+        assert_eq!(parse_timestamp("1970-01-01 00:00:01")?, 1_000_000);
+        assert_eq!(parse_timestamp("2024-01-31")?, 19753 * 86_400_000_000);
+        assert_eq!(
+            parse_timestamp("2024-01-31T12:00:00.5")?,
+            parse_timestamp("2024-01-31 12:00:00.500000")?
+        );
+        assert_eq!(
+            format_timestamp(parse_timestamp("2024-01-31 12:34:56")?),
+            "2024-01-31 12:34:56"
+        );
+        assert_eq!(
+            format_timestamp(parse_timestamp("1969-12-31 23:59:59.25")?),
+            "1969-12-31 23:59:59.25"
+        );
+
+        assert!(parse_timestamp("2024-01-31 24:00:00").is_err());
+        assert!(parse_timestamp("2024-01-31 12:00").is_err());
+        assert!(parse_timestamp("2024-01-31 12:00:00.1234567").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimal() -> RSDBResult<()> {
+        assert_eq!(parse_decimal("12.340")?, (12340, 3));
+        assert_eq!(parse_decimal("-0.5")?, (-5, 1));
+        assert_eq!(parse_decimal(".5")?, (5, 1));
+        assert_eq!(parse_decimal("7")?, (7, 0));
+        assert!(parse_decimal("1.2.3").is_err());
+        assert!(parse_decimal("-").is_err());
+        assert!(parse_decimal(&"9".repeat(39)).is_err());
+
+        assert_eq!(format_decimal(1234, 2), "12.34");
+        assert_eq!(format_decimal(-5, 2), "-0.05");
+        assert_eq!(format_decimal(7, 0), "7");
+
+        // 四舍五入
+        assert_eq!(rescale_decimal(1235, 3, 2)?, 124);
+        assert_eq!(rescale_decimal(-1235, 3, 2)?, -124);
+        assert_eq!(rescale_decimal(1234, 3, 2)?, 123);
+        assert_eq!(rescale_decimal(12, 1, 3)?, 1200);
+        assert!(rescale_decimal(i128::MAX, 0, 1).is_err());
+
+        assert_eq!(cmp_decimal((150, 2), (15, 1)), Ordering::Equal);
+        assert_eq!(cmp_decimal((-1, 0), (i128::MAX, 38)), Ordering::Less);
+        assert_eq!(cmp_decimal((i128::MAX, 0), (1, 38)), Ordering::Greater);
+
+        // 浮点数按照最短的十进制表示转换
+        assert_eq!(
+            Value::Float(0.1).coerce(&DataType::Decimal(2))?,
+            Value::Decimal(10, 2)
+        );
+        assert_eq!(
+            Value::String("0.125".into()).coerce(&DataType::Decimal(2))?,
+            Value::Decimal(13, 2)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_float_total_order() {
+        let hash = |v: &Value| {
+            let mut hasher = DefaultHasher::new();
+            v.hash(&mut hasher);
+            hasher.finish()
+        };
+        let nan = Value::Float(f64::NAN);
+        let other_nan = Value::Float(-f64::NAN);
+        assert_eq!(nan, other_nan);
+        assert_eq!(hash(&nan), hash(&other_nan));
+        assert_eq!(Value::Float(0.0), Value::Float(-0.0));
+        assert_eq!(hash(&Value::Float(0.0)), hash(&Value::Float(-0.0)));
+
+        let mut values = vec![
+            nan.clone(),
+            Value::Float(f64::INFINITY),
+            Value::Integer(1),
+            Value::Null,
+            Value::Float(f64::NEG_INFINITY),
+            Value::Float(0.5),
+        ];
+        values.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(
+            values.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+            vec!["NULL", "-Infinity", "0.5", "1", "Infinity", "NaN"]
+        );
+
+        // 不同类型之间按照类型排序
+        assert_eq!(
+            Value::Integer(10).total_cmp(&Value::String("a".into())),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_float_display() {
+        let display = |f: f64| Value::Float(f).to_string();
+        assert_eq!(display(1.0), "1.0");
+        assert_eq!(display(-3.0), "-3.0");
+        assert_eq!(display(0.0), "0.0");
+        assert_eq!(display(0.1), "0.1");
+        assert_eq!(display(13.0 / 3.0), "4.333333333333333");
+        assert_eq!(display(0.0001), "0.0001");
+        assert_eq!(display(1e-7), "1e-7");
+        assert_eq!(display(-2.5e-10), "-2.5e-10");
+        assert_eq!(display(123456789012345.6), "123456789012345.6");
+        assert_eq!(display(1e16), "1e16");
+        assert_eq!(display(f64::MAX), "1.7976931348623157e308");
+        assert_eq!(display(f64::NEG_INFINITY), "-Infinity");
+        assert_eq!(Value::Integer(1).to_string(), "1");
+
+        // 展示的结果可以还原为原来的值
+        for f in [0.1, 1e-7, 1e16, f64::MAX, f64::MIN_POSITIVE, 13.0 / 3.0] {
+            assert_eq!(format_float(f).parse::<f64>(), Ok(f));
+        }
+    }
+}
@@ -1,59 +1,200 @@
 use std::{
-    collections::{BTreeMap, btree_map},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, btree_map},
     fs::{File, OpenOptions},
     io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
 };
 
+use chacha20poly1305::{
+    AeadCore, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, OsRng, Payload, rand_core::RngCore},
+};
 use fs4::FileExt;
 
-use crate::{error::RSDBResult, storage};
+use crate::{
+    error::{RSDBError, RSDBResult},
+    storage::{
+        self,
+        engine::{Durability, EngineStatus},
+    },
+};
 
-pub type KeyDir = BTreeMap<Vec<u8>, (u64, u32)>; // (offset, size)
+pub type KeyDir = BTreeMap<Vec<u8>, (u64, u64, u32, bool)>; // (segment_id, offset, size, flagged)
 const LOG_HEADER_SIZE: u32 = 8;
+// key 长度的最高位表示 value 之前有一个字节的压缩方式，没有这个标记的 value 没有压缩
+const VALUE_FLAG: u32 = 1 << 31;
+const CODEC_NONE: u8 = 0;
+const CODEC_LZ4: u8 = 1;
+// 单个日志文件默认的大小上限
+const DEFAULT_MAX_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+// 最多同时打开的只读日志文件的个数
+const SEGMENT_CACHE_SIZE: usize = 8;
+// 密钥文件：magic(8) | 口令派生密钥的迭代次数(4) | 盐(16) | 加密后的校验数据
+const KEY_FILE_MAGIC: &[u8; 8] = b"RSDBKEY1";
+// 迭代次数记录在密钥文件中，测试时减少迭代次数加快测试
+const KDF_ROUNDS: u32 = if cfg!(test) { 1_000 } else { 100_000 };
+const KDF_SALT_SIZE: usize = 16;
+// 使用正确的密钥才能解密出这个校验数据
+const KEY_CHECK: &[u8] = b"rsdb key check";
+// 每次加密在数据之前增加 24 字节的随机 nonce，之后增加 16 字节的认证标签
+const NONCE_SIZE: usize = 24;
+const CIPHER_OVERHEAD: usize = NONCE_SIZE + 16;
+// 加密索引文件使用的附加数据，和日志文件中的记录区分开
+const HINT_AAD: &[u8] = b"rsdb hint";
+
+// 磁盘存储引擎的配置
+#[derive(Clone)]
+pub struct DiskOptions {
+    pub durability: Durability,
+    // 单个日志文件的大小上限，写满之后切换到新的日志文件，超过上限的单条记录独占一个日志文件
+    pub max_segment_size: u64,
+    // 使用 LZ4 压缩不小于这个字节数的 value，压缩后没有变小时仍然保存原始数据，None 表示不压缩
+    pub compress_threshold: Option<usize>,
+    // 加密日志文件和索引文件使用的口令，None 表示不加密，加密的数据库必须提供正确的口令才能打开
+    pub passphrase: Option<String>,
+}
+
+impl Default for DiskOptions {
+    // 默认不主动落盘
+    fn default() -> Self {
+        Self {
+            durability: Durability::Never,
+            max_segment_size: DEFAULT_MAX_SEGMENT_SIZE,
+            compress_threshold: None,
+            passphrase: None,
+        }
+    }
+}
 
 // 磁盘存储引擎定义
+// 数据按照写入顺序分布在编号递增的多个日志文件中，只有编号最大的日志文件可以写入
 pub struct DiskEngine {
     keydir: KeyDir,
-    log: Log,
+    segments: Segments,
+    durability: Durability,
+    compress_threshold: Option<usize>,
+    // Interval 模式下后台线程落盘的文件，切换或者 compact 之后替换为新的日志文件，销毁时置为 None
+    flusher: Option<Arc<Mutex<Option<File>>>>,
+    // 放在最后，其他字段销毁之后才释放锁
+    _lock: LockFile,
 }
 
 impl DiskEngine {
     pub fn new(file_path: PathBuf) -> RSDBResult<Self> {
-        let log = Log::new(file_path)?;
-        // 从 log 中加载 keydir
-        let keydir = log.build_keydir()?;
-        Ok(Self { keydir, log })
+        Self::new_with_options(file_path, DiskOptions::default())
+    }
+
+    pub fn new_with_durability(file_path: PathBuf, durability: Durability) -> RSDBResult<Self> {
+        Self::new_with_options(
+            file_path,
+            DiskOptions {
+                durability,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn new_with_options(file_path: PathBuf, options: DiskOptions) -> RSDBResult<Self> {
+        // 先加锁，保证同时只有一个存储引擎在使用数据文件
+        let lock = LockFile::acquire(sidecar_path(&file_path, "lock"))?;
+        // 从所有的日志文件中加载 keydir
+        let mut keydir = KeyDir::new();
+        let segments = Segments::open(file_path, &options, &mut keydir)?;
+        let flusher = match options.durability {
+            Durability::Interval(ms) => {
+                Some(Self::spawn_flusher(segments.active.file.try_clone()?, ms))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            keydir,
+            segments,
+            durability: options.durability,
+            compress_threshold: options.compress_threshold,
+            flusher,
+            _lock: lock,
+        })
+    }
+
+    // 后台线程每隔 ms 毫秒落盘一次，存储引擎销毁后线程退出
+    // 后台线程中无法返回错误，落盘失败时等待下一次重试
+    fn spawn_flusher(file: File, ms: u64) -> Arc<Mutex<Option<File>>> {
+        let file = Arc::new(Mutex::new(Some(file)));
+        let weak = Arc::downgrade(&file);
+        thread::spawn(move || {
+            loop {
+                thread::sleep(Duration::from_millis(ms));
+                let Some(file) = weak.upgrade() else {
+                    break;
+                };
+                let Ok(file) = file.lock() else {
+                    break;
+                };
+                match file.as_ref() {
+                    Some(file) => {
+                        let _ = file.sync_all();
+                    }
+                    None => break,
+                }
+            }
+        });
+        file
     }
 
     pub fn new_compact(file_path: PathBuf) -> RSDBResult<Self> {
         let mut eng = Self::new(file_path)?;
-        eng.compact()?;
+        storage::engine::Engine::compact(&mut eng)?;
         Ok(eng)
     }
 
-    fn compact(&mut self) -> RSDBResult<()> {
-        // 新打开一个临时日志文件
-        let mut new_path = self.log.file_path.clone();
-        new_path.set_extension("compact");
-
-        let mut new_log = Log::new(new_path)?;
-        let mut new_keydir = KeyDir::new();
-        // 重写数据到临时文件中
-        for (key, (offset, val_size)) in &self.keydir {
-            // 读取 value
-            let value = self.log.read_value(*offset, *val_size)?;
-            let (new_offset, new_size) = new_log.write_entry(key, Some(&value))?;
-            new_keydir.insert(
-                key.clone(),
-                (new_offset + new_size as u64 - *val_size as u64, *val_size),
-            );
+    // 打开时为了构建 keydir 读取的字节数，包括索引文件和重放的日志
+    pub fn loaded_bytes(&self) -> u64 {
+        self.segments.loaded_bytes
+    }
+
+    // 压缩 value，返回写入日志文件的数据以及是否带有压缩方式的标记
+    fn encode_value(&self, value: Vec<u8>) -> (Vec<u8>, bool) {
+        match self.compress_threshold {
+            Some(threshold) if value.len() >= threshold => {
+                let compressed = lz4_flex::compress_prepend_size(&value);
+                if compressed.len() + 1 >= value.len() {
+                    return (value, false);
+                }
+                let mut buf = Vec::with_capacity(compressed.len() + 1);
+                buf.push(CODEC_LZ4);
+                buf.extend(compressed);
+                (buf, true)
+            }
+            _ => (value, false),
+        }
+    }
+
+    // 当前日志文件写不下这条记录时，先切换到新的日志文件
+    fn write_entry(
+        &mut self,
+        key: &Vec<u8>,
+        value: Option<&Vec<u8>>,
+        flagged: bool,
+    ) -> RSDBResult<(u64, u64, u32)> {
+        let size = self.segments.entry_size(key.len(), value.map(|v| v.len()));
+        if self.segments.active_size > 0
+            && self.segments.active_size + size > self.segments.max_size
+        {
+            self.segments.roll()?;
+            self.update_flusher()?;
+        }
+        self.segments.write_entry(key, value, flagged)
+    }
+
+    // 写入的日志文件发生变化后，后台线程改为落盘新的日志文件
+    fn update_flusher(&self) -> RSDBResult<()> {
+        if let Some(flusher) = &self.flusher {
+            *flusher.lock()? = Some(self.segments.active.file.try_clone()?);
         }
-        // 重命名临时文件为正式日志文件
-        std::fs::rename(&new_log.file_path, &self.log.file_path)?;
-        new_log.file_path = self.log.file_path.clone();
-        self.keydir = new_keydir;
-        self.log = new_log;
         Ok(())
     }
 }
@@ -63,26 +204,25 @@ impl storage::engine::Engine for DiskEngine {
 
     fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> RSDBResult<()> {
         // 先写日志
-        let (offset, size) = self.log.write_entry(&key, Some(&value))?;
+        let (value, flagged) = self.encode_value(value);
+        let (id, offset, val_size) = self.write_entry(&key, Some(&value), flagged)?;
         // 更新内存索引
-        let val_size = value.len() as u32;
-        self.keydir
-            .insert(key, (offset + size as u64 - val_size as u64, val_size));
+        self.keydir.insert(key, (id, offset, val_size, flagged));
         Ok(())
     }
 
     fn get(&mut self, key: Vec<u8>) -> RSDBResult<Option<Vec<u8>>> {
         match self.keydir.get(&key) {
-            Some((offset, val_size)) => {
-                let val = self.log.read_value(*offset, *val_size)?;
-                Ok(Some(val))
+            Some((id, offset, val_size, flagged)) => {
+                let val = self.segments.read_value(*id, &key, *offset, *val_size)?;
+                Ok(Some(decode_value(val, *flagged)?))
             }
             None => Ok(None),
         }
     }
 
     fn delete(&mut self, key: Vec<u8>) -> RSDBResult<()> {
-        self.log.write_entry(&key, None)?;
+        self.write_entry(&key, None, false)?;
         self.keydir.remove(&key);
         Ok(())
     }
@@ -90,14 +230,110 @@ impl storage::engine::Engine for DiskEngine {
     fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
         DiskEngineIterator {
             inner: self.keydir.range(range),
-            log: &mut self.log,
+            segments: &mut self.segments,
+        }
+    }
+
+    // 按编号顺序依次处理每个日志文件，将其中有效的数据按 key 的顺序写入临时文件，写完并落盘后再替换原来的文件
+    // 替换之前进程退出时，原来的文件不受影响，残留的临时文件在下一次 compact 时被覆盖
+    // 处理到某个日志文件时，之前的日志文件中只剩有效的数据，删除标记不再需要覆盖任何数据，可以全部丢弃
+    // 没有任何有效数据的只读日志文件直接删除，没有垃圾的日志文件不需要重写
+    fn compact(&mut self) -> RSDBResult<()> {
+        self.segments.remove_hint()?;
+        // 每个日志文件中有效的数据
+        let mut lives = BTreeMap::<u64, Vec<_>>::new();
+        for (key, (id, offset, val_size, flagged)) in &self.keydir {
+            lives
+                .entry(*id)
+                .or_default()
+                .push((key.clone(), *offset, *val_size, *flagged));
+        }
+        for id in self.segments.ids.clone() {
+            let path = self.segments.path(id);
+            let file_size = std::fs::metadata(&path)?.len();
+            let live = lives.remove(&id).unwrap_or_default();
+            let size = live
+                .iter()
+                .map(|(key, _, val_size, _)| {
+                    LOG_HEADER_SIZE as u64
+                        + self.segments.encrypted_len(key.len())
+                        + *val_size as u64
+                })
+                .sum::<u64>();
+            if size == file_size {
+                continue;
+            }
+            if size == 0 && id != self.segments.active_id {
+                self.segments.remove(id)?;
+                continue;
+            }
+
+            // 新打开一个临时日志文件
+            let mut new_path = self.segments.file_path.clone();
+            new_path.set_extension("compact");
+            let mut new_log = Log::new(new_path, self.segments.cipher.clone())?;
+            new_log.file.set_len(0)?;
+            // 重写数据到临时文件中，压缩过的数据不需要解压，替换成功之后才更新内存索引
+            let mut new_keydir = Vec::with_capacity(live.len());
+            for (key, offset, val_size, flagged) in live {
+                let value = self.segments.read_value(id, &key, offset, val_size)?;
+                let (new_offset, new_size) = new_log.write_entry(&key, Some(&value), flagged)?;
+                let new_offset = new_offset + new_size as u64 - val_size as u64;
+                new_keydir.push((key, (id, new_offset, val_size, flagged)));
+            }
+            new_log.file.sync_all()?;
+            // 重命名临时文件为正式日志文件，rename 是原子的，目录落盘后重命名才持久化
+            std::fs::rename(&new_log.file_path, &path)?;
+            sync_dir(&path)?;
+            new_log.file_path = path;
+            self.keydir.extend(new_keydir);
+            self.segments.replace(id, new_log, size);
+            if id == self.segments.active_id {
+                self.update_flusher()?;
+            }
+        }
+        self.segments.write_hint(&self.keydir)
+    }
+
+    fn flush(&mut self) -> RSDBResult<()> {
+        self.segments.active.file.sync_all()?;
+        Ok(())
+    }
+
+    fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    // 数据文件中只追加写入，disk_size 与 size 的差值为 compact 可以回收的空间
+    fn status(&mut self) -> RSDBResult<EngineStatus> {
+        Ok(EngineStatus {
+            keys: self.keydir.len() as u64,
+            size: self
+                .keydir
+                .iter()
+                .map(|(k, (_, _, val_size, _))| k.len() as u64 + *val_size as u64)
+                .sum(),
+            disk_size: self.segments.disk_size()?,
+        })
+    }
+}
+
+// 正常关闭时写入索引文件，下次打开时不需要重放所有的日志文件
+// 后台线程持有的文件会一直持有文件锁，销毁时立即关闭，之后可以马上重新打开数据文件
+impl Drop for DiskEngine {
+    fn drop(&mut self) {
+        let _ = self.segments.write_hint(&self.keydir);
+        if let Some(flusher) = &self.flusher
+            && let Ok(mut file) = flusher.lock()
+        {
+            file.take();
         }
     }
 }
 
 pub struct DiskEngineIterator<'a> {
-    inner: btree_map::Range<'a, Vec<u8>, (u64, u32)>,
-    log: &'a mut Log,
+    inner: btree_map::Range<'a, Vec<u8>, (u64, u64, u32, bool)>,
+    segments: &'a mut Segments,
 }
 
 impl<'a> super::engine::EngineIterator for DiskEngineIterator<'a> {}
@@ -106,29 +342,553 @@ impl<'a> Iterator for DiskEngineIterator<'a> {
     type Item = RSDBResult<(Vec<u8>, Vec<u8>)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|(key, (offset, val_size))| {
-            let value = self.log.read_value(*offset, *val_size)?;
-            Ok((key.clone(), value))
-        })
+        self.inner
+            .next()
+            .map(|(key, (id, offset, val_size, flagged))| {
+                let value = self.segments.read_value(*id, key, *offset, *val_size)?;
+                Ok((key.clone(), decode_value(value, *flagged)?))
+            })
     }
 }
 
 impl<'a> DoubleEndedIterator for DiskEngineIterator<'a> {
     fn next_back(&mut self) -> Option<Self::Item> {
-        self.inner.next_back().map(|(key, (offset, val_size))| {
-            let value = self.log.read_value(*offset, *val_size)?;
-            Ok((key.clone(), value))
+        self.inner
+            .next_back()
+            .map(|(key, (id, offset, val_size, flagged))| {
+                let value = self.segments.read_value(*id, key, *offset, *val_size)?;
+                Ok((key.clone(), decode_value(value, *flagged)?))
+            })
+    }
+}
+
+// 所有的日志文件，编号为 0 的日志文件就是 file_path，兼容只有一个日志文件的数据，其余的日志文件为 file_path.编号
+struct Segments {
+    file_path: PathBuf,
+    max_size: u64,
+    // 所有日志文件的编号
+    ids: BTreeSet<u64>,
+    // 正在写入的日志文件，编号最大
+    active_id: u64,
+    active: Log,
+    active_size: u64,
+    // 最近读取过的只读日志文件，最近使用的放在最后
+    cache: Vec<(u64, File)>,
+    // 打开时为了构建 keydir 读取的字节数
+    loaded_bytes: u64,
+    // 加密日志文件和索引文件的密钥，None 表示不加密
+    cipher: Option<Cipher>,
+}
+
+impl Segments {
+    // 按编号顺序重放所有的日志文件，构建内存索引
+    fn open(file_path: PathBuf, options: &DiskOptions, keydir: &mut KeyDir) -> RSDBResult<Self> {
+        let mut ids = BTreeSet::new();
+        if let Some(name) = file_path.file_name().and_then(|name| name.to_str()) {
+            let prefix = format!("{}.", name);
+            for entry in std::fs::read_dir(parent_dir(&file_path))? {
+                let entry = entry?;
+                let Some(entry_name) = entry.file_name().to_str().map(|n| n.to_string()) else {
+                    continue;
+                };
+                if entry_name == name {
+                    ids.insert(0);
+                } else if let Some(id) = entry_name.strip_prefix(&prefix)
+                    && !id.is_empty()
+                    && id.bytes().all(|b| b.is_ascii_digit())
+                {
+                    ids.insert(id.parse()?);
+                }
+            }
+        }
+        if ids.is_empty() {
+            ids.insert(0);
+        }
+        let cipher = Self::cipher(&file_path, &ids, options.passphrase.as_deref())?;
+
+        // 每个日志文件从哪里开始重放，索引文件有效时从索引文件中加载 keydir，只重放之后写入的数据
+        let mut starts = ids.iter().map(|id| (*id, 0)).collect::<BTreeMap<_, _>>();
+        let mut loaded_bytes = 0;
+        if let Some((hint_segments, hint_keydir, hint_size)) =
+            Self::load_hint(&file_path, cipher.as_ref())
+            && let Some(hint_starts) = Self::hint_starts(&file_path, &ids, &hint_segments)
+        {
+            *keydir = hint_keydir;
+            starts = hint_starts;
+            loaded_bytes += hint_size;
+        }
+
+        let active_id = *ids.last().unwrap_or(&0);
+        let mut active = None;
+        for (id, start) in starts {
+            let log = Log::new(Self::segment_path(&file_path, id), cipher.clone())?;
+            let (size, read) = log.build_keydir(id, start, keydir)?;
+            loaded_bytes += read;
+            if id == active_id {
+                active = Some((log, size));
+            }
+        }
+        let Some((active, active_size)) = active else {
+            return Err(RSDBError::Internal("no active log segment".to_string()));
+        };
+        Ok(Self {
+            file_path,
+            max_size: options.max_segment_size,
+            ids,
+            active_id,
+            active,
+            active_size,
+            cache: Vec::new(),
+            loaded_bytes,
+            cipher,
         })
     }
+
+    // 存在密钥文件时数据库是加密的，必须提供口令；已经写入过数据的未加密数据库不能再使用口令打开
+    fn cipher(
+        file_path: &Path,
+        ids: &BTreeSet<u64>,
+        passphrase: Option<&str>,
+    ) -> RSDBResult<Option<Cipher>> {
+        let key_path = Self::key_path(file_path);
+        match (passphrase, key_path.exists()) {
+            (Some(passphrase), true) => Ok(Some(Cipher::load(&key_path, passphrase)?)),
+            (Some(passphrase), false) => {
+                let written = ids.iter().any(|id| {
+                    std::fs::metadata(Self::segment_path(file_path, *id)).is_ok_and(|m| m.len() > 0)
+                });
+                if written {
+                    return Err(RSDBError::Internal(
+                        "database is not encrypted, can not open it with a passphrase".to_string(),
+                    ));
+                }
+                Ok(Some(Cipher::create(&key_path, passphrase)?))
+            }
+            (None, true) => Err(RSDBError::Internal(
+                "database is encrypted, passphrase is required".to_string(),
+            )),
+            (None, false) => Ok(None),
+        }
+    }
+
+    // 加密之后的长度，每次加密增加 nonce 和认证标签
+    fn encrypted_len(&self, len: usize) -> u64 {
+        match self.cipher {
+            Some(_) => (len + CIPHER_OVERHEAD) as u64,
+            None => len as u64,
+        }
+    }
+
+    // 记录在日志文件中占用的字节数
+    fn entry_size(&self, key_len: usize, value_len: Option<usize>) -> u64 {
+        LOG_HEADER_SIZE as u64
+            + self.encrypted_len(key_len)
+            + value_len.map_or(0, |len| self.encrypted_len(len))
+    }
+
+    fn hint_path(file_path: &Path) -> PathBuf {
+        sidecar_path(file_path, "hint")
+    }
+
+    fn key_path(file_path: &Path) -> PathBuf {
+        sidecar_path(file_path, "key")
+    }
+
+    // 读取索引文件，文件不存在、不完整或者校验失败时返回 None，之后重放所有的日志文件
+    fn load_hint(file_path: &Path, cipher: Option<&Cipher>) -> Option<(HintSegments, KeyDir, u64)> {
+        let buf = std::fs::read(Self::hint_path(file_path)).ok()?;
+        if buf.len() < 4 {
+            return None;
+        }
+        let (checksum, data) = buf.split_at(4);
+        if u32::from_be_bytes(checksum.try_into().ok()?) != crc32(data) {
+            return None;
+        }
+        let (segments, keydir) = match cipher {
+            Some(cipher) => bincode::deserialize(&cipher.decrypt(data, HINT_AAD).ok()?).ok()?,
+            None => bincode::deserialize(data).ok()?,
+        };
+        Some((segments, keydir, buf.len() as u64))
+    }
+
+    // 写入索引文件之后日志文件只会追加写入：之前的日志文件大小不变，最后一个日志文件只会变大，之后可能有新的日志文件
+    // 满足这些条件时返回需要重放的日志文件以及重放的起始位置，否则索引文件已经过期，返回 None
+    fn hint_starts(
+        file_path: &Path,
+        ids: &BTreeSet<u64>,
+        hint_segments: &HintSegments,
+    ) -> Option<BTreeMap<u64, u64>> {
+        let (last, _) = hint_segments.last()?;
+        if hint_segments.iter().any(|(id, _)| !ids.contains(id)) {
+            return None;
+        }
+        let mut starts = BTreeMap::new();
+        for id in ids {
+            let size = std::fs::metadata(Self::segment_path(file_path, *id)).map_or(0, |m| m.len());
+            match hint_segments.iter().find(|(hint_id, _)| hint_id == id) {
+                Some((_, hint_size)) if id == last && size >= *hint_size => {
+                    starts.insert(*id, *hint_size);
+                }
+                Some((_, hint_size)) if id != last && size == *hint_size => {}
+                None if id > last => {
+                    starts.insert(*id, 0);
+                }
+                _ => return None,
+            }
+        }
+        Some(starts)
+    }
+
+    // 日志文件落盘之后，写入临时文件再重命名为索引文件
+    fn write_hint(&mut self, keydir: &KeyDir) -> RSDBResult<()> {
+        self.active.file.sync_all()?;
+        let mut segments = HintSegments::new();
+        for id in &self.ids {
+            let size = match *id == self.active_id {
+                true => self.active_size,
+                false => std::fs::metadata(self.path(*id))?.len(),
+            };
+            segments.push((*id, size));
+        }
+        let mut data = bincode::serialize(&(&segments, keydir))?;
+        if let Some(cipher) = &self.cipher {
+            data = cipher.encrypt(&data, HINT_AAD)?;
+        }
+        let path = Self::hint_path(&self.file_path);
+        let tmp_path = path.with_extension("hint.tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&crc32(&data).to_be_bytes())?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &path)?;
+        sync_dir(&path)?;
+        Ok(())
+    }
+
+    // 重写日志文件之前删除索引文件，重写过程中进程退出时不会使用过期的索引文件
+    fn remove_hint(&self) -> RSDBResult<()> {
+        let path = Self::hint_path(&self.file_path);
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+            sync_dir(&path)?;
+        }
+        Ok(())
+    }
+
+    fn segment_path(file_path: &Path, id: u64) -> PathBuf {
+        if id == 0 {
+            return file_path.to_path_buf();
+        }
+        sidecar_path(file_path, &format!("{:06}", id))
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        Self::segment_path(&self.file_path, id)
+    }
+
+    fn write_entry(
+        &mut self,
+        key: &Vec<u8>,
+        value: Option<&Vec<u8>>,
+        flagged: bool,
+    ) -> RSDBResult<(u64, u64, u32)> {
+        let (offset, val_size) = self.active.write_entry(key, value, flagged)?;
+        self.active_size = offset + val_size as u64;
+        Ok((self.active_id, offset, val_size))
+    }
+
+    // 读取 key 对应的 value，加密时解密并校验
+    fn read_value(
+        &mut self,
+        id: u64,
+        key: &[u8],
+        offset: u64,
+        val_size: u32,
+    ) -> RSDBResult<Vec<u8>> {
+        let value = match id == self.active_id {
+            true => Log::read_value(&mut self.active.file, offset, val_size)?,
+            false => Log::read_value(self.reader(id)?, offset, val_size)?,
+        };
+        match &self.cipher {
+            Some(cipher) => cipher.decrypt(&value, key),
+            None => Ok(value),
+        }
+    }
+
+    // 获取只读日志文件，不在缓存中时打开文件，缓存满了之后关闭最久没有使用的文件
+    fn reader(&mut self, id: u64) -> RSDBResult<&mut File> {
+        match self.cache.iter().position(|(i, _)| *i == id) {
+            Some(pos) => {
+                let entry = self.cache.remove(pos);
+                self.cache.push(entry);
+            }
+            None => {
+                if self.cache.len() >= SEGMENT_CACHE_SIZE {
+                    self.cache.remove(0);
+                }
+                self.cache.push((id, File::open(self.path(id))?));
+            }
+        }
+        match self.cache.last_mut() {
+            Some((_, file)) => Ok(file),
+            None => Err(RSDBError::Internal(format!("log segment {} not found", id))),
+        }
+    }
+
+    // 当前日志文件落盘之后切换到新的日志文件，之后当前日志文件不再修改
+    fn roll(&mut self) -> RSDBResult<()> {
+        self.active.file.sync_all()?;
+        let id = self.active_id + 1;
+        self.active = Log::new(self.path(id), self.cipher.clone())?;
+        sync_dir(&self.file_path)?;
+        self.ids.insert(id);
+        self.active_id = id;
+        self.active_size = 0;
+        Ok(())
+    }
+
+    // compact 之后替换日志文件，只读日志文件下次读取时重新打开
+    fn replace(&mut self, id: u64, log: Log, size: u64) {
+        self.cache.retain(|(i, _)| *i != id);
+        if id == self.active_id {
+            self.active = log;
+            self.active_size = size;
+        }
+    }
+
+    fn remove(&mut self, id: u64) -> RSDBResult<()> {
+        self.cache.retain(|(i, _)| *i != id);
+        let path = self.path(id);
+        std::fs::remove_file(&path)?;
+        sync_dir(&path)?;
+        self.ids.remove(&id);
+        Ok(())
+    }
+
+    fn disk_size(&self) -> RSDBResult<u64> {
+        let mut size = 0;
+        for id in &self.ids {
+            size += std::fs::metadata(self.path(*id))?.len();
+        }
+        Ok(size)
+    }
+}
+
+// 解压带有压缩方式标记的 value
+fn decode_value(value: Vec<u8>, flagged: bool) -> RSDBResult<Vec<u8>> {
+    if !flagged {
+        return Ok(value);
+    }
+    match value.split_first() {
+        Some((&CODEC_NONE, data)) => Ok(data.to_vec()),
+        Some((&CODEC_LZ4, data)) => lz4_flex::decompress_size_prepended(data)
+            .map_err(|e| RSDBError::Internal(format!("decompress value failed: {}", e))),
+        _ => Err(RSDBError::Internal(format!(
+            "unknown value codec {:?}",
+            value.first()
+        ))),
+    }
+}
+
+// 索引文件中保存的每个日志文件的编号和大小
+type HintSegments = Vec<(u64, u64)>;
+
+// CRC-32（IEEE），用于检查索引文件是否完整
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+// 记录读取的字节数
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for CountingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+// 文件所在的目录，相对路径中没有目录时为当前目录
+fn parent_dir(file_path: &Path) -> &Path {
+    match file_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+// 数据文件对应的其他文件，在完整的文件名之后追加后缀，如 data.db 的锁文件为 data.db.lock
+// 替换扩展名时同一目录下的 data.db 和 data.log 会使用同一个文件
+fn sidecar_path(file_path: &Path, suffix: &str) -> PathBuf {
+    let mut path = file_path.as_os_str().to_os_string();
+    path.push(format!(".{}", suffix));
+    PathBuf::from(path)
+}
+
+// 创建、重命名和删除文件之后，目录落盘后才持久化
+pub(super) fn sync_dir(file_path: &Path) -> RSDBResult<()> {
+    File::open(parent_dir(file_path))?.sync_all()?;
+    Ok(())
+}
+
+// 数据文件旁边的锁文件，存储引擎存在期间持有它的排他锁，文件中记录持有锁的进程号
+// 锁文件不会被删除，删除之后其他进程可能锁住一个新创建的同名文件，两个进程就会同时使用数据文件
+struct LockFile {
+    file: File,
+}
+
+impl LockFile {
+    fn acquire(file_path: PathBuf) -> RSDBResult<Self> {
+        if let Some(dir) = file_path.parent()
+            && !dir.exists()
+        {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&file_path)?;
+        if let Err(err) = file.try_lock_exclusive() {
+            if err.raw_os_error() != fs4::lock_contended_error().raw_os_error() {
+                return Err(err.into());
+            }
+            let mut pid = String::new();
+            file.read_to_string(&mut pid)?;
+            return Err(RSDBError::Internal(format!(
+                "database is locked by another process (pid {})",
+                pid.trim()
+            )));
+        }
+        file.set_len(0)?;
+        file.write_all(std::process::id().to_string().as_bytes())?;
+        file.flush()?;
+        Ok(Self { file })
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+// 使用口令派生的密钥加密日志文件中的 key 和 value 以及索引文件，每次加密使用新的随机 nonce
+#[derive(Clone)]
+struct Cipher {
+    aead: XChaCha20Poly1305,
+}
+
+impl Cipher {
+    // 第一次使用口令时生成随机的盐，和校验数据一起写入密钥文件
+    fn create(key_path: &Path, passphrase: &str) -> RSDBResult<Self> {
+        let mut salt = [0; KDF_SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = Self::derive(passphrase, &salt, KDF_ROUNDS);
+        let mut buf = KEY_FILE_MAGIC.to_vec();
+        buf.extend(KDF_ROUNDS.to_be_bytes());
+        buf.extend(salt);
+        buf.extend(cipher.encrypt(KEY_CHECK, KEY_FILE_MAGIC)?);
+        // 写入临时文件再重命名，进程退出时不会留下不完整的密钥文件
+        let tmp_path = key_path.with_extension("key.tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, key_path)?;
+        sync_dir(key_path)?;
+        Ok(cipher)
+    }
+
+    // 从密钥文件中读取盐派生密钥，校验数据解密失败说明口令错误
+    fn load(key_path: &Path, passphrase: &str) -> RSDBResult<Self> {
+        let buf = std::fs::read(key_path)?;
+        let header_size = KEY_FILE_MAGIC.len() + 4 + KDF_SALT_SIZE;
+        if buf.len() < header_size || !buf.starts_with(KEY_FILE_MAGIC) {
+            return Err(RSDBError::Internal(format!(
+                "invalid key file {}",
+                key_path.display()
+            )));
+        }
+        let rounds = u32::from_be_bytes(buf[8..12].try_into()?);
+        let cipher = Self::derive(passphrase, &buf[12..header_size], rounds);
+        match cipher.decrypt(&buf[header_size..], KEY_FILE_MAGIC) {
+            Ok(check) if check == KEY_CHECK => Ok(cipher),
+            _ => Err(RSDBError::Internal(
+                "wrong passphrase for encrypted database".to_string(),
+            )),
+        }
+    }
+
+    fn derive(passphrase: &str, salt: &[u8], rounds: u32) -> Self {
+        let mut key = [0; 32];
+        pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), salt, rounds, &mut key);
+        Self {
+            aead: XChaCha20Poly1305::new(&key.into()),
+        }
+    }
+
+    // 返回 nonce + 密文 + 认证标签，aad 不加密但是参与认证
+    fn encrypt(&self, data: &[u8], aad: &[u8]) -> RSDBResult<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, Payload { msg: data, aad })
+            .map_err(|_| RSDBError::Internal("encrypt failed".to_string()))?;
+        let mut buf = nonce.to_vec();
+        buf.extend(ciphertext);
+        Ok(buf)
+    }
+
+    // 认证失败说明密钥错误或者数据被损坏、篡改
+    fn decrypt(&self, data: &[u8], aad: &[u8]) -> RSDBResult<Vec<u8>> {
+        if data.len() < CIPHER_OVERHEAD {
+            return Err(RSDBError::Internal(
+                "encrypted data is corrupted".to_string(),
+            ));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+        self.aead
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| {
+                RSDBError::Internal(
+                    "decrypt failed, wrong passphrase or corrupted data".to_string(),
+                )
+            })
+    }
 }
 
 struct Log {
     file: std::fs::File,
     file_path: PathBuf,
+    // 加密 key 和 value 使用的密钥，None 表示不加密
+    cipher: Option<Cipher>,
 }
 
 impl Log {
-    pub fn new(file_path: PathBuf) -> RSDBResult<Self> {
+    pub fn new(file_path: PathBuf, cipher: Option<Cipher>) -> RSDBResult<Self> {
         // 如果目录不存在的话则创建
         if let Some(dir) = file_path.parent() {
             if !dir.exists() {
@@ -143,21 +903,34 @@ impl Log {
             .open(&file_path)?;
         // 加文件锁，保证同时只能有一个服务去使用这个文件
         file.try_lock_exclusive()?;
-        Ok(Self { file, file_path })
+        Ok(Self {
+            file,
+            file_path,
+            cipher,
+        })
     }
 
-    // 遍历数据文件，构建内存索引
-    fn build_keydir(&self) -> RSDBResult<KeyDir> {
-        let mut keydir = KeyDir::new();
+    // 从 start 开始遍历数据文件，更新内存索引，返回截断之后文件的大小以及读取的字节数
+    // 写入过程中进程退出时，最后一条记录可能不完整，从它开始截断文件，之后的写入追加在完整的记录之后
+    fn build_keydir(&self, id: u64, start: u64, keydir: &mut KeyDir) -> RSDBResult<(u64, u64)> {
         let file_size = self.file.metadata()?.len();
-        let mut buf_reader = BufReader::new(&self.file);
-        let mut offset = 0;
-        loop {
-            if offset >= file_size {
-                break;
-            }
-            let (key, val_size) = Self::read_entry(&mut buf_reader, offset)?;
+        let mut reader = CountingReader {
+            inner: BufReader::new(&self.file),
+            count: 0,
+        };
+        let mut offset = start;
+        while offset < file_size {
+            let Some((key, val_size, flagged)) = Self::read_entry(&mut reader, offset, file_size)?
+            else {
+                self.file.set_len(offset)?;
+                return Ok((offset, reader.count));
+            };
+            // 加密的 key 认证失败时说明密钥错误或者数据损坏，不能继续构建索引
             let key_size = key.len() as u32;
+            let key = match &self.cipher {
+                Some(cipher) => cipher.decrypt(&key, &[])?,
+                None => key,
+            };
             if val_size == -1 {
                 keydir.remove(&key);
                 offset += LOG_HEADER_SIZE as u64 + key_size as u64;
@@ -165,67 +938,107 @@ impl Log {
                 keydir.insert(
                     key,
                     (
+                        id,
                         offset + LOG_HEADER_SIZE as u64 + key_size as u64,
                         val_size as u32,
+                        flagged,
                     ),
                 );
                 offset += LOG_HEADER_SIZE as u64 + key_size as u64 + val_size as u64;
             }
         }
-        Ok(keydir)
+        Ok((file_size, reader.count))
     }
 
     // +-------------+-------------+----------------+----------------+
     // | key len(4)    val len(4)     key(varint)       val(varint)  |
     // +-------------+-------------+----------------+----------------+
-    fn write_entry(&mut self, key: &Vec<u8>, value: Option<&Vec<u8>>) -> RSDBResult<(u64, u32)> {
+    // flagged 时 key len 的最高位为 1，val 的第一个字节为压缩方式
+    // 加密时 key 和 val 分别加密为 nonce + 密文 + 认证标签，key len 和 val len 为加密后的长度
+    // 返回 value 在文件中的位置和长度
+    fn write_entry(
+        &mut self,
+        key: &Vec<u8>,
+        value: Option<&Vec<u8>>,
+        flagged: bool,
+    ) -> RSDBResult<(u64, u32)> {
+        // value 使用 key 作为附加数据，不能被替换为其他 key 的 value
+        let (key, value) = match &self.cipher {
+            Some(cipher) => (
+                Cow::Owned(cipher.encrypt(key, &[])?),
+                value
+                    .map(|v| cipher.encrypt(v, key))
+                    .transpose()?
+                    .map(Cow::Owned),
+            ),
+            None => (Cow::Borrowed(key), value.map(Cow::Borrowed)),
+        };
         // 首先将文件偏移到末尾
         let offset = self.file.seek(SeekFrom::End(0))?;
         let key_size = key.len() as u32;
-        let value_size = value.map_or(0, |v| v.len() as u32);
+        let value_size = value.as_ref().map_or(0, |v| v.len() as u32);
         let total_size = LOG_HEADER_SIZE + key_size + value_size;
         // 写入 key_size, value_size，key，value
         let mut writer = BufWriter::with_capacity(total_size as usize, &self.file);
-        writer.write_all(&key_size.to_be_bytes())?;
-        writer.write_all(&value.map_or(-1, |v| v.len() as i32).to_be_bytes())?;
+        let flag = if flagged { VALUE_FLAG } else { 0 };
+        writer.write_all(&(key_size | flag).to_be_bytes())?;
+        writer.write_all(&value.as_ref().map_or(-1, |v| v.len() as i32).to_be_bytes())?;
         writer.write_all(&key)?;
-        if let Some(value) = value {
+        if let Some(value) = &value {
             writer.write_all(value)?;
         }
         writer.flush()?;
-        Ok((offset, total_size))
+        Ok((offset + (LOG_HEADER_SIZE + key_size) as u64, value_size))
     }
 
-    fn read_value(&mut self, offset: u64, val_size: u32) -> RSDBResult<Vec<u8>> {
-        self.file.seek(SeekFrom::Start(offset))?;
+    fn read_value(file: &mut File, offset: u64, val_size: u32) -> RSDBResult<Vec<u8>> {
+        file.seek(SeekFrom::Start(offset))?;
         let mut buf = vec![0; val_size as usize];
-        self.file.read_exact(&mut buf)?;
+        file.read_exact(&mut buf)?;
         Ok(buf)
     }
 
-    fn read_entry(buf_reader: &mut BufReader<&File>, offset: u64) -> RSDBResult<(Vec<u8>, i32)> {
+    // 读取 offset 处的记录，记录不完整（头部、key 或 value 超出文件末尾）或者长度不合法时返回 None
+    fn read_entry<R: Read + Seek>(
+        buf_reader: &mut R,
+        offset: u64,
+        file_size: u64,
+    ) -> RSDBResult<Option<(Vec<u8>, i32, bool)>> {
+        if offset + LOG_HEADER_SIZE as u64 > file_size {
+            return Ok(None);
+        }
         buf_reader.seek(SeekFrom::Start(offset))?;
         let mut len_buf = [0; 4];
-        // 读取 key_size
+        // 读取 key_size 以及 value 是否带有压缩方式的标记
         buf_reader.read_exact(&mut len_buf)?;
         let key_size = u32::from_be_bytes(len_buf);
-        // 读取 value_size
+        let flagged = key_size & VALUE_FLAG != 0;
+        let key_size = key_size & !VALUE_FLAG;
+        // 读取 value_size，-1 表示删除
         buf_reader.read_exact(&mut len_buf)?;
         let val_size = i32::from_be_bytes(len_buf);
+        let entry_size = LOG_HEADER_SIZE as u64 + key_size as u64 + val_size.max(0) as u64;
+        if val_size < -1 || (flagged && val_size < 1) || offset + entry_size > file_size {
+            return Ok(None);
+        }
         // 读取 key
         let mut key = vec![0; key_size as usize];
         buf_reader.read_exact(&mut key)?;
-        Ok((key, val_size))
+        Ok(Some((key, val_size, flagged)))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::{ops::Bound, path::PathBuf};
 
     use crate::{
-        error::RSDBResult,
-        storage::{disk::DiskEngine, engine::Engine},
+        error::{RSDBError, RSDBResult},
+        storage::{
+            disk::{DiskEngine, DiskOptions, KeyDir, SEGMENT_CACHE_SIZE, Segments},
+            engine::{Durability, Engine},
+            memory::MemoryEngine,
+        },
     };
 
     #[test]
@@ -261,4 +1074,608 @@ mod tests {
         std::fs::remove_dir_all("/tmp/rsdb")?;
         Ok(())
     }
+
+    // 同样的写入在内存存储引擎和磁盘存储引擎中的扫描结果一致，包括重新打开和 compact 之后
+    #[test]
+    fn test_scan_parity() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let mut disk = DiskEngine::new(p.clone())?;
+        let mut memory = MemoryEngine::new();
+        // 固定种子的线性同余生成器，key 中包含 0 和 255，value 可能为空
+        let mut seed: u64 = 42;
+        let mut next = |n: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        let alphabet = [0u8, 1, b'a', b'b', 254, 255];
+        for _ in 0..2000 {
+            let len = next(4) as usize;
+            let key = (0..len)
+                .map(|_| alphabet[next(alphabet.len() as u64) as usize])
+                .collect::<Vec<_>>();
+            if next(4) == 0 {
+                disk.delete(key.clone())?;
+                memory.delete(key)?;
+            } else {
+                let value = vec![next(256) as u8; next(3) as usize];
+                disk.set(key.clone(), value.clone())?;
+                memory.set(key, value)?;
+            }
+        }
+
+        let bounds = [
+            Bound::Unbounded,
+            Bound::Included(vec![]),
+            Bound::Included(vec![1]),
+            Bound::Excluded(vec![1]),
+            Bound::Included(vec![b'a', 255]),
+            Bound::Excluded(vec![b'b', 0]),
+            Bound::Included(vec![255, 255, 255]),
+        ];
+        let compare = |disk: &mut DiskEngine, memory: &mut MemoryEngine| -> RSDBResult<()> {
+            for start in &bounds {
+                for end in &bounds {
+                    if let (
+                        Bound::Included(s) | Bound::Excluded(s),
+                        Bound::Included(e) | Bound::Excluded(e),
+                    ) = (start, end)
+                        && s >= e
+                    {
+                        continue;
+                    }
+                    let range = (start.clone(), end.clone());
+                    let expected = memory.scan(range.clone()).collect::<RSDBResult<Vec<_>>>()?;
+                    let forward = disk.scan(range.clone()).collect::<RSDBResult<Vec<_>>>()?;
+                    assert_eq!(forward, expected, "{:?}", range);
+                    let mut reverse = disk
+                        .scan(range.clone())
+                        .rev()
+                        .collect::<RSDBResult<Vec<_>>>()?;
+                    reverse.reverse();
+                    assert_eq!(reverse, expected, "{:?}", range);
+                    // 两端交替读取，在中间相遇后结束
+                    let mut iter = disk.scan(range.clone());
+                    let (mut front, mut back) = (Vec::new(), Vec::new());
+                    loop {
+                        match iter.next().transpose()? {
+                            Some(item) => front.push(item),
+                            None => break,
+                        }
+                        match iter.next_back().transpose()? {
+                            Some(item) => back.push(item),
+                            None => break,
+                        }
+                    }
+                    back.reverse();
+                    front.extend(back);
+                    assert_eq!(front, expected, "{:?}", range);
+                }
+            }
+            Ok(())
+        };
+        compare(&mut disk, &mut memory)?;
+        drop(disk);
+        compare(&mut DiskEngine::new(p.clone())?, &mut memory)?;
+        compare(&mut DiskEngine::new_compact(p.clone())?, &mut memory)?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 最后一条记录写入一部分时进程退出，重新打开后截断不完整的记录，之前的记录都可见
+    #[test]
+    fn test_torn_write() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        eng.delete(b"key1".to_vec())?;
+        drop(eng);
+        let complete = std::fs::metadata(&p)?.len();
+        let expected = vec![(b"key2".to_vec(), b"value2".to_vec())];
+
+        // 最后一条记录分别在头部、key、value 中间被截断
+        let last = 8 + 4 + 6;
+        for torn in [1, 4, 8, 10, 12, last - 1] {
+            let mut eng = DiskEngine::new(p.clone())?;
+            eng.set(b"key3".to_vec(), b"value3".to_vec())?;
+            drop(eng);
+            let file = std::fs::OpenOptions::new().write(true).open(&p)?;
+            file.set_len(complete + torn)?;
+            drop(file);
+
+            let mut eng = DiskEngine::new(p.clone())?;
+            assert_eq!(std::fs::metadata(&p)?.len(), complete, "torn at {}", torn);
+            assert_eq!(eng.scan(..).collect::<RSDBResult<Vec<_>>>()?, expected);
+            assert_eq!(eng.get(b"key3".to_vec())?, None);
+        }
+
+        // 长度不合法的记录同样被截断
+        let mut file = std::fs::OpenOptions::new().append(true).open(&p)?;
+        std::io::Write::write_all(&mut file, &[0, 0, 0, 1, 255, 255, 255, 0, b'k'])?;
+        drop(file);
+        let mut eng = DiskEngine::new(p.clone())?;
+        assert_eq!(std::fs::metadata(&p)?.len(), complete);
+
+        // 之后的写入追加在完整的记录之后，重新打开后仍然可见
+        eng.set(b"key3".to_vec(), b"value3".to_vec())?;
+        eng.delete(b"key2".to_vec())?;
+        drop(eng);
+        let mut eng = DiskEngine::new(p.clone())?;
+        assert_eq!(
+            eng.scan(..).collect::<RSDBResult<Vec<_>>>()?,
+            vec![(b"key3".to_vec(), b"value3".to_vec())]
+        );
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 各种持久化方式下读写的结果都相同，重新打开后数据不变
+    #[test]
+    fn test_durability() -> RSDBResult<()> {
+        assert_eq!("Always".parse::<Durability>()?, Durability::Always);
+        assert_eq!("never".parse::<Durability>()?, Durability::Never);
+        assert_eq!("100ms".parse::<Durability>()?, Durability::Interval(100));
+        for s in ["", "0ms", "100", "sometimes"] {
+            assert!(s.parse::<Durability>().is_err(), "{}", s);
+        }
+
+        for durability in [
+            Durability::Always,
+            Durability::Interval(10),
+            Durability::Never,
+        ] {
+            let p = tempfile::tempdir()?.keep().join("rsdb-log");
+            let mut eng = DiskEngine::new_with_durability(p.clone(), durability)?;
+            assert_eq!(eng.durability(), durability);
+            eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+            eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+            eng.delete(b"key1".to_vec())?;
+            eng.flush()?;
+            // compact 之后后台线程落盘新的日志文件
+            eng.compact()?;
+            eng.set(b"key3".to_vec(), b"value3".to_vec())?;
+            std::thread::sleep(std::time::Duration::from_millis(30));
+            eng.flush()?;
+            drop(eng);
+
+            let mut eng = DiskEngine::new(p.clone())?;
+            assert_eq!(eng.get(b"key1".to_vec())?, None);
+            assert_eq!(eng.get(b"key2".to_vec())?, Some(b"value2".to_vec()));
+            assert_eq!(eng.get(b"key3".to_vec())?, Some(b"value3".to_vec()));
+            drop(eng);
+            std::fs::remove_dir_all(p.parent().unwrap())?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        // 同一个进程中再次打开也会失败
+        for _ in 0..2 {
+            assert_eq!(
+                DiskEngine::new(p.clone()).err(),
+                Some(RSDBError::Internal(format!(
+                    "database is locked by another process (pid {})",
+                    std::process::id()
+                )))
+            );
+        }
+        // 打开失败不影响已经打开的存储引擎
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        // compact 之后仍然持有锁
+        eng.compact()?;
+        assert!(DiskEngine::new(p.clone()).is_err());
+
+        // 销毁之后可以重新打开
+        drop(eng);
+        let mut eng = DiskEngine::new(p.clone())?;
+        assert_eq!(eng.get(b"key2".to_vec())?, Some(b"value2".to_vec()));
+        drop(eng);
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        // 锁文件名为完整的文件名加上后缀，同一目录下文件名相同、扩展名不同的数据文件互不影响
+        let dir = tempfile::tempdir()?.keep();
+        let db = DiskEngine::new(dir.join("data.db"))?;
+        let log = DiskEngine::new(dir.join("data.log"))?;
+        assert!(dir.join("data.db.lock").exists());
+        assert!(dir.join("data.log.lock").exists());
+        assert!(DiskEngine::new(dir.join("data.db")).is_err());
+        drop(db);
+        drop(log);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    // 日志文件写满之后切换到新的日志文件，读取、重新打开和 compact 都能跨越多个日志文件
+    #[test]
+    fn test_segments() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let options = DiskOptions {
+            max_segment_size: 1024,
+            ..Default::default()
+        };
+        let mut eng = DiskEngine::new_with_options(p.clone(), options.clone())?;
+        let mut memory = MemoryEngine::new();
+        // 最早的日志文件中的 key 一直保留，同一个日志文件中之后被删除的 key 在重新打开和 compact 之后不能再出现
+        eng.set(b"first".to_vec(), b"value".to_vec())?;
+        memory.set(b"first".to_vec(), b"value".to_vec())?;
+        eng.set(b"deleted".to_vec(), b"value".to_vec())?;
+        for i in 0..500 {
+            let key = format!("key{:03}", i % 200).into_bytes();
+            let value = format!("value{:03}-{}", i, "x".repeat(i % 30)).into_bytes();
+            eng.set(key.clone(), value.clone())?;
+            memory.set(key, value)?;
+        }
+        eng.delete(b"deleted".to_vec())?;
+        for i in (0..200).step_by(3) {
+            let key = format!("key{:03}", i).into_bytes();
+            eng.delete(key.clone())?;
+            memory.delete(key)?;
+        }
+
+        let check = |eng: &mut DiskEngine, memory: &mut MemoryEngine| -> RSDBResult<()> {
+            let expected = memory.scan(..).collect::<RSDBResult<Vec<_>>>()?;
+            assert_eq!(eng.scan(..).collect::<RSDBResult<Vec<_>>>()?, expected);
+            let mut reverse = eng.scan(..).rev().collect::<RSDBResult<Vec<_>>>()?;
+            reverse.reverse();
+            assert_eq!(reverse, expected);
+            for (key, value) in expected {
+                assert_eq!(eng.get(key)?, Some(value));
+            }
+            assert_eq!(eng.get(b"deleted".to_vec())?, None);
+            // 只有超过上限的记录才会独占一个日志文件
+            for id in &eng.segments.ids {
+                let size = std::fs::metadata(eng.segments.path(*id))?.len();
+                assert!(size <= options.max_segment_size, "{} {}", id, size);
+            }
+            Ok(())
+        };
+        // 日志文件的个数超过了打开文件的缓存
+        let segments = eng.segments.ids.len();
+        assert!(segments > SEGMENT_CACHE_SIZE * 2, "{}", segments);
+        check(&mut eng, &mut memory)?;
+        drop(eng);
+
+        let mut eng = DiskEngine::new_with_options(p.clone(), options.clone())?;
+        assert_eq!(eng.segments.ids.len(), segments);
+        check(&mut eng, &mut memory)?;
+        // 重新打开之后继续写入编号最大的日志文件
+        eng.set(b"last".to_vec(), b"value".to_vec())?;
+        memory.set(b"last".to_vec(), b"value".to_vec())?;
+        assert_eq!(eng.keydir[&b"last".to_vec()].0, segments as u64 - 1);
+
+        // 没有有效数据的日志文件被删除，保留的日志文件中只剩有效数据
+        let disk_size = eng.status()?.disk_size;
+        eng.compact()?;
+        check(&mut eng, &mut memory)?;
+        let status = eng.status()?;
+        assert!(status.disk_size < disk_size / 2, "{:?}", status);
+        assert!(eng.segments.ids.len() < segments, "{:?}", eng.segments.ids);
+        assert!(eng.segments.ids.contains(&0));
+        drop(eng);
+        let mut eng = DiskEngine::new_with_options(p.clone(), options.clone())?;
+        check(&mut eng, &mut memory)?;
+
+        // 全部删除之后 compact，只剩一个空的日志文件
+        let keys = memory
+            .scan(..)
+            .map(|item| item.map(|(key, _)| key))
+            .collect::<RSDBResult<Vec<_>>>()?;
+        for key in keys {
+            eng.delete(key.clone())?;
+            memory.delete(key)?;
+        }
+        eng.compact()?;
+        assert_eq!(eng.status()?.disk_size, 0);
+        assert_eq!(eng.segments.ids.len(), 1);
+        drop(eng);
+        let mut eng = DiskEngine::new_with_options(p.clone(), options.clone())?;
+        check(&mut eng, &mut memory)?;
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 使用索引文件打开时只需要读取很少的数据，构建的 keydir 与完整重放的结果相同
+    // 索引文件之后追加的数据会被重放，索引文件损坏或者过期时完整重放
+    #[test]
+    fn test_hint() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let hint = Segments::hint_path(&p);
+        let options = DiskOptions {
+            max_segment_size: 64 * 1024,
+            ..Default::default()
+        };
+        let open = |p: &PathBuf| -> RSDBResult<(KeyDir, u64)> {
+            let eng = DiskEngine::new_with_options(p.clone(), options.clone())?;
+            Ok((eng.keydir.clone(), eng.loaded_bytes()))
+        };
+        // 删除索引文件之后打开，重放所有的日志文件，关闭时重新写入索引文件
+        let replay = |p: &PathBuf| -> RSDBResult<(KeyDir, u64)> {
+            std::fs::remove_file(Segments::hint_path(p))?;
+            open(p)
+        };
+
+        // 每条记录重放时读取头部和 key，共 8 + 6 字节
+        let mut eng = DiskEngine::new_with_options(p.clone(), options.clone())?;
+        for i in 0..20000 {
+            eng.set(format!("key{:03}", i % 200).into_bytes(), vec![i as u8; 10])?;
+        }
+        for i in (0..200).step_by(7) {
+            eng.delete(format!("key{:03}", i).into_bytes())?;
+        }
+        assert!(eng.segments.ids.len() > 1);
+        drop(eng);
+        let saved = std::fs::read(&hint)?;
+
+        let (keydir, loaded) = open(&p)?;
+        assert_eq!(loaded, saved.len() as u64);
+        let (replayed, replay_loaded) = replay(&p)?;
+        assert_eq!(replayed, keydir);
+        assert_eq!(replay_loaded, (20000 + 29) * 14);
+        assert!(loaded * 20 < replay_loaded, "{} {}", loaded, replay_loaded);
+
+        // 索引文件之后追加的数据，包括切换到新的日志文件
+        let mut eng = DiskEngine::new_with_options(p.clone(), options.clone())?;
+        let segments = eng.segments.ids.len();
+        for i in 0..5000 {
+            eng.set(format!("key{:03}", i % 300).into_bytes(), vec![i as u8; 10])?;
+        }
+        eng.delete(b"key001".to_vec())?;
+        assert!(eng.segments.ids.len() > segments);
+        drop(eng);
+        std::fs::write(&hint, &saved)?;
+        let (keydir, loaded) = open(&p)?;
+        assert_eq!(loaded, saved.len() as u64 + 5001 * 14);
+        let (replayed, replay_loaded) = replay(&p)?;
+        assert_eq!(replayed, keydir);
+
+        // 索引文件损坏
+        let saved = std::fs::read(&hint)?;
+        let mut corrupt = saved.clone();
+        corrupt[saved.len() / 2] ^= 1;
+        for data in [
+            corrupt,
+            saved[..2].to_vec(),
+            saved[..saved.len() - 1].to_vec(),
+        ] {
+            std::fs::write(&hint, &data)?;
+            let (corrupt_keydir, loaded) = open(&p)?;
+            assert_eq!(loaded, replay_loaded);
+            assert_eq!(corrupt_keydir, keydir);
+        }
+
+        // compact 之后重新写入索引文件，旧的索引文件已经过期
+        let saved = std::fs::read(&hint)?;
+        let mut eng = DiskEngine::new_with_options(p.clone(), options.clone())?;
+        eng.compact()?;
+        let compacted = eng.keydir.clone();
+        drop(eng);
+        let (keydir, loaded) = open(&p)?;
+        assert_eq!(keydir, compacted);
+        assert_eq!(loaded, std::fs::metadata(&hint)?.len());
+        // compact 之后只剩 299 个 key 的记录
+        std::fs::write(&hint, &saved)?;
+        let (keydir, loaded) = open(&p)?;
+        assert_eq!(loaded, 299 * 14);
+        assert_eq!(keydir, compacted);
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        // 文件名相同、扩展名不同的数据文件各自使用自己的索引文件
+        let dir = tempfile::tempdir()?.keep();
+        for (name, key) in [("data.db", b"db"), ("data.log", b"lg")] {
+            let mut eng = DiskEngine::new(dir.join(name))?;
+            eng.set(key.to_vec(), key.to_vec())?;
+        }
+        assert!(dir.join("data.db.hint").exists());
+        assert!(dir.join("data.log.hint").exists());
+        for (name, key, other) in [("data.db", b"db", b"lg"), ("data.log", b"lg", b"db")] {
+            let mut eng = DiskEngine::new(dir.join(name))?;
+            assert!(eng.loaded_bytes() > 0);
+            assert_eq!(eng.get(key.to_vec())?, Some(key.to_vec()));
+            assert_eq!(eng.get(other.to_vec())?, None);
+        }
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    // 压缩前后的 value 相同，没有压缩的旧数据和压缩的数据可以混合在同一个日志文件中
+    #[test]
+    fn test_compression() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let compressed = DiskOptions {
+            compress_threshold: Some(64),
+            ..Default::default()
+        };
+        let mut seed: u64 = 42;
+        let random = (0..1000)
+            .map(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (seed >> 33) as u8
+            })
+            .collect::<Vec<_>>();
+        let text = "hello rsdb ".repeat(100).into_bytes();
+
+        // 没有开启压缩时写入的数据
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"old-text".to_vec(), text.clone())?;
+        eng.set(b"old-random".to_vec(), random.clone())?;
+        eng.set(b"old-deleted".to_vec(), text.clone())?;
+        drop(eng);
+
+        let mut eng = DiskEngine::new_with_options(p.clone(), compressed.clone())?;
+        let disk_size = eng.status()?.disk_size;
+        eng.set(b"text".to_vec(), text.clone())?;
+        // 可以压缩的数据只占用很少的空间
+        assert!(eng.status()?.disk_size - disk_size < 100);
+        eng.set(b"random".to_vec(), random.clone())?;
+        eng.set(b"short".to_vec(), b"hello".to_vec())?;
+        eng.set(b"empty".to_vec(), vec![])?;
+        eng.delete(b"old-deleted".to_vec())?;
+        assert!(eng.keydir[&b"text".to_vec()].3);
+        assert!(!eng.keydir[&b"random".to_vec()].3);
+        assert!(!eng.keydir[&b"short".to_vec()].3);
+        assert!(!eng.keydir[&b"old-text".to_vec()].3);
+
+        let expected = vec![
+            (b"empty".to_vec(), vec![]),
+            (b"old-random".to_vec(), random.clone()),
+            (b"old-text".to_vec(), text.clone()),
+            (b"random".to_vec(), random.clone()),
+            (b"short".to_vec(), b"hello".to_vec()),
+            (b"text".to_vec(), text.clone()),
+        ];
+        let check = |eng: &mut DiskEngine| -> RSDBResult<()> {
+            assert_eq!(eng.scan(..).collect::<RSDBResult<Vec<_>>>()?, expected);
+            let mut reverse = eng.scan(..).rev().collect::<RSDBResult<Vec<_>>>()?;
+            reverse.reverse();
+            assert_eq!(reverse, expected);
+            for (key, value) in &expected {
+                assert_eq!(eng.get(key.clone())?, Some(value.clone()));
+            }
+            assert_eq!(eng.get(b"old-deleted".to_vec())?, None);
+            Ok(())
+        };
+        check(&mut eng)?;
+        drop(eng);
+
+        // 重新打开后，不管是否开启压缩都能读取所有的数据
+        check(&mut DiskEngine::new(p.clone())?)?;
+        std::fs::remove_file(Segments::hint_path(&p))?;
+        check(&mut DiskEngine::new_with_options(
+            p.clone(),
+            compressed.clone(),
+        )?)?;
+        check(&mut DiskEngine::new_compact(p.clone())?)?;
+        std::fs::remove_file(Segments::hint_path(&p))?;
+        check(&mut DiskEngine::new(p.clone())?)?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_encryption() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let encrypted = DiskOptions {
+            max_segment_size: 4096,
+            compress_threshold: Some(64),
+            passphrase: Some("correct horse".to_string()),
+            ..Default::default()
+        };
+        let wrong = DiskOptions {
+            passphrase: Some("wrong horse".to_string()),
+            ..encrypted.clone()
+        };
+        let contains = |data: &[u8], needle: &[u8]| data.windows(needle.len()).any(|w| w == needle);
+
+        let mut eng = DiskEngine::new_with_options(p.clone(), encrypted.clone())?;
+        let mut expected = Vec::new();
+        for i in 0..200 {
+            let key = format!("secret-key-{:03}", i).into_bytes();
+            let value = format!("secret-value-{:03}", i)
+                .repeat(i % 3 + 1)
+                .into_bytes();
+            eng.set(key.clone(), value.clone())?;
+            expected.push((key, value));
+        }
+        let text = "secret text ".repeat(100).into_bytes();
+        eng.set(b"secret-text".to_vec(), text.clone())?;
+        eng.delete(b"secret-key-000".to_vec())?;
+        expected.remove(0);
+        expected.push((b"secret-text".to_vec(), text));
+        assert!(eng.segments.ids.len() > 1);
+
+        let check = |eng: &mut DiskEngine| -> RSDBResult<()> {
+            assert_eq!(eng.scan(..).collect::<RSDBResult<Vec<_>>>()?, expected);
+            let mut reverse = eng.scan(..).rev().collect::<RSDBResult<Vec<_>>>()?;
+            reverse.reverse();
+            assert_eq!(reverse, expected);
+            assert_eq!(eng.get(b"secret-key-000".to_vec())?, None);
+            Ok(())
+        };
+        check(&mut eng)?;
+        drop(eng);
+
+        // 日志文件和索引文件中都没有明文的 key 和 value
+        let files = std::fs::read_dir(p.parent().unwrap())?
+            .map(|entry| Ok(std::fs::read(entry?.path())?))
+            .collect::<RSDBResult<Vec<_>>>()?;
+        for data in &files {
+            assert!(!contains(data, b"secret"));
+        }
+
+        // 口令错误或者没有口令时无法打开
+        assert!(DiskEngine::new_with_options(p.clone(), wrong.clone()).is_err());
+        assert!(DiskEngine::new(p.clone()).is_err());
+
+        // 使用正确的口令从索引文件或者日志文件恢复，compact 之后数据仍然加密
+        check(&mut DiskEngine::new_with_options(
+            p.clone(),
+            encrypted.clone(),
+        )?)?;
+        std::fs::remove_file(Segments::hint_path(&p))?;
+        check(&mut DiskEngine::new_with_options(
+            p.clone(),
+            encrypted.clone(),
+        )?)?;
+        let mut eng = DiskEngine::new_with_options(p.clone(), encrypted.clone())?;
+        eng.compact()?;
+        check(&mut eng)?;
+        drop(eng);
+        std::fs::remove_file(Segments::hint_path(&p))?;
+        check(&mut DiskEngine::new_with_options(
+            p.clone(),
+            encrypted.clone(),
+        )?)?;
+        assert!(!contains(&std::fs::read(&p)?, b"secret"));
+
+        // 密钥文件被替换时，其他口令派生的密钥无法认证已有的记录，重放日志文件时失败
+        let key_path = Segments::key_path(&p);
+        std::fs::remove_file(&key_path)?;
+        let empty = p.parent().unwrap().join("rsdb-empty");
+        drop(DiskEngine::new_with_options(empty.clone(), wrong.clone())?);
+        std::fs::copy(Segments::key_path(&empty), &key_path)?;
+        assert!(DiskEngine::new_with_options(p.clone(), wrong.clone()).is_err());
+        assert!(DiskEngine::new_with_options(p.clone(), encrypted.clone()).is_err());
+
+        // 已经写入数据的未加密数据库不能使用口令打开
+        let plain = p.parent().unwrap().join("rsdb-plain");
+        let mut eng = DiskEngine::new(plain.clone())?;
+        eng.set(b"key".to_vec(), b"value".to_vec())?;
+        drop(eng);
+        assert!(DiskEngine::new_with_options(plain.clone(), encrypted.clone()).is_err());
+        assert_eq!(
+            DiskEngine::new(plain)?.get(b"key".to_vec())?,
+            Some(b"value".to_vec())
+        );
+
+        // 文件名相同、扩展名不同的数据文件各自使用自己的密钥文件，口令可以不同
+        let dir = p.parent().unwrap();
+        for (name, options) in [("data.db", &encrypted), ("data.log", &wrong)] {
+            let mut eng = DiskEngine::new_with_options(dir.join(name), options.clone())?;
+            eng.set(b"key".to_vec(), name.as_bytes().to_vec())?;
+        }
+        assert!(dir.join("data.db.key").exists());
+        assert!(dir.join("data.log.key").exists());
+        for (name, options) in [("data.db", &encrypted), ("data.log", &wrong)] {
+            let mut eng = DiskEngine::new_with_options(dir.join(name), options.clone())?;
+            assert_eq!(eng.get(b"key".to_vec())?, Some(name.as_bytes().to_vec()));
+        }
+        assert!(DiskEngine::new_with_options(dir.join("data.db"), wrong).is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
 }
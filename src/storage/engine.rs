@@ -1,6 +1,9 @@
-use std::ops::{Bound, RangeBounds};
+use std::{
+    ops::{Bound, RangeBounds},
+    str::FromStr,
+};
 
-use crate::error::RSDBResult;
+use crate::error::{RSDBError, RSDBResult};
 
 // 抽象存储引擎接口定义，接入不同的存储引擎，目前支持内存和简单的磁盘 KV 存储
 pub trait Engine {
@@ -22,21 +25,87 @@ pub trait Engine {
 
     // 前缀扫描
     fn scan_prefix(&mut self, prefix: Vec<u8>) -> Self::EngineIterator<'_> {
-        let start = Bound::Included(prefix.clone());
-        let mut bound_prefix = prefix.clone();
-        // 前缀包含了 255
-        // 10 2 3 255, 右边界就是 10 2 4
-        // 如果全都是 255，右边界就是 Unbounded
-        let end = match bound_prefix.iter_mut().rposition(|b| *b != 255) {
-            Some(pos) => {
-                bound_prefix[pos] += 1;
-                bound_prefix.truncate(pos + 1);
-                Bound::Excluded(bound_prefix)
-            }
-            None => Bound::Unbounded,
-        };
-        self.scan((start, end))
+        self.scan(prefix_range(prefix))
     }
+
+    // 存储引擎的统计信息
+    fn status(&mut self) -> RSDBResult<EngineStatus>;
+
+    // 回收已经删除或覆盖的数据占用的空间，默认不需要处理
+    fn compact(&mut self) -> RSDBResult<()> {
+        Ok(())
+    }
+
+    // 将已经写入的数据持久化，默认不需要处理
+    fn flush(&mut self) -> RSDBResult<()> {
+        Ok(())
+    }
+
+    // 写入数据的持久化方式，事务提交时根据它决定是否调用 flush
+    fn durability(&self) -> Durability {
+        Durability::Never
+    }
+}
+
+// 写入数据的持久化方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Durability {
+    // 每次事务提交时都落盘，提交成功的事务不会因为断电丢失
+    Always,
+    // 后台每隔一段时间（毫秒）落盘一次，断电时可能丢失最近一段时间内提交的事务
+    Interval(u64),
+    // 由操作系统决定何时落盘
+    Never,
+}
+
+impl FromStr for Durability {
+    type Err = RSDBError;
+
+    // always、never，或者 interval 的毫秒数，如 100ms
+    fn from_str(s: &str) -> RSDBResult<Self> {
+        match s.to_lowercase().as_str() {
+            "always" => Ok(Durability::Always),
+            "never" => Ok(Durability::Never),
+            v => v
+                .strip_suffix("ms")
+                .and_then(|ms| ms.parse().ok())
+                .filter(|ms| *ms > 0)
+                .map(Durability::Interval)
+                .ok_or(RSDBError::Internal(format!(
+                    "invalid durability {}, expected always, never or interval like 100ms",
+                    s
+                ))),
+        }
+    }
+}
+
+// 存储引擎的统计信息
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EngineStatus {
+    // key 的个数
+    pub keys: u64,
+    // 所有 key 和 value 的字节数之和，不包括存储引擎额外的开销
+    pub size: u64,
+    // 数据文件的字节数，包括已经删除或覆盖的数据，内存存储引擎为 0
+    pub disk_size: u64,
+}
+
+// 前缀对应的扫描范围
+pub fn prefix_range(prefix: Vec<u8>) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    let start = Bound::Included(prefix.clone());
+    let mut bound_prefix = prefix;
+    // 前缀包含了 255
+    // 10 2 3 255, 右边界就是 10 2 4
+    // 如果全都是 255，右边界就是 Unbounded
+    let end = match bound_prefix.iter_mut().rposition(|b| *b != 255) {
+        Some(pos) => {
+            bound_prefix[pos] += 1;
+            bound_prefix.truncate(pos + 1);
+            Bound::Excluded(bound_prefix)
+        }
+        None => Bound::Unbounded,
+    };
+    (start, end)
 }
 
 pub trait EngineIterator: DoubleEndedIterator<Item = RSDBResult<(Vec<u8>, Vec<u8>)>> {}
@@ -133,11 +202,33 @@ mod tests {
         Ok(())
     }
 
+    // 测试统计信息，返回最后的 disk_size，由调用方按照存储引擎检查
+    fn test_status(mut eng: impl Engine) -> RSDBResult<u64> {
+        assert_eq!(eng.status()?.keys, 0);
+        assert_eq!(eng.status()?.size, 0);
+
+        eng.set(b"aa".to_vec(), vec![1, 2, 3])?;
+        eng.set(b"bbb".to_vec(), vec![4])?;
+        let status = eng.status()?;
+        assert_eq!(status.keys, 2);
+        assert_eq!(status.size, 9);
+
+        // 覆盖和删除之后只统计最新的数据
+        eng.set(b"aa".to_vec(), vec![])?;
+        eng.delete(b"bbb".to_vec())?;
+        eng.delete(b"not exist".to_vec())?;
+        let status = eng.status()?;
+        assert_eq!(status.keys, 1);
+        assert_eq!(status.size, 2);
+        Ok(status.disk_size)
+    }
+
     #[test]
     fn test_memory() -> RSDBResult<()> {
         test_point_opt(MemoryEngine::new())?;
         test_scan(MemoryEngine::new())?;
         test_scan_prefix(MemoryEngine::new())?;
+        assert_eq!(test_status(MemoryEngine::new())?, 0);
         Ok(())
     }
 
@@ -152,6 +243,14 @@ mod tests {
         test_scan_prefix(DiskEngine::new(PathBuf::from("/tmp/rsdb3/test.log"))?)?;
         std::fs::remove_dir_all(PathBuf::from("/tmp/rsdb3"))?;
 
+        // 数据文件中保留了所有写入和删除的记录，每条记录有 8 字节的头部
+        let disk_size = test_status(DiskEngine::new(PathBuf::from("/tmp/rsdb4/test.log"))?)?;
+        assert_eq!(disk_size, (8 + 5) + (8 + 4) + (8 + 2) + (8 + 3) + (8 + 9));
+        let mut eng = DiskEngine::new_compact(PathBuf::from("/tmp/rsdb4/test.log"))?;
+        assert_eq!(eng.status()?.disk_size, 8 + 2);
+        drop(eng);
+        std::fs::remove_dir_all(PathBuf::from("/tmp/rsdb4"))?;
+
         Ok(())
     }
 }
@@ -3,7 +3,10 @@ use serde::{
     ser,
 };
 
-use crate::error::{RSDBError, RSDBResult};
+use crate::{
+    error::{RSDBError, RSDBResult},
+    sql::types::canonical_float,
+};
 
 pub fn serialize_key<T: serde::Serialize>(key: &T) -> RSDBResult<Vec<u8>> {
     let mut ser = Serializer { output: Vec::new() };
@@ -61,6 +64,12 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         Ok(())
     }
 
+    // 翻转符号位，使负数排在正数之前，编码后的字节序与数值大小一致
+    fn serialize_i128(self, v: i128) -> RSDBResult<()> {
+        self.output.extend(((v as u128) ^ (1 << 127)).to_be_bytes());
+        Ok(())
+    }
+
     fn serialize_u8(self, _v: u8) -> RSDBResult<()> {
         todo!()
     }
@@ -69,8 +78,9 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         todo!()
     }
 
-    fn serialize_u32(self, _v: u32) -> RSDBResult<()> {
-        todo!()
+    fn serialize_u32(self, v: u32) -> RSDBResult<()> {
+        self.output.extend(v.to_be_bytes());
+        Ok(())
     }
 
     fn serialize_u64(self, v: u64) -> RSDBResult<()> {
@@ -82,8 +92,16 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         todo!()
     }
 
+    // 与 Value 的 Hash 一样先统一为 canonical_float 的表示，相等的浮点数编码相同
+    // 正数翻转符号位，负数翻转所有位，编码后的字节序与 float_cmp 的顺序一致，NaN 最大
     fn serialize_f64(self, v: f64) -> RSDBResult<()> {
-        self.output.extend(v.to_be_bytes());
+        let bits = canonical_float(v).to_bits();
+        let bits = if bits >> 63 == 0 {
+            bits ^ (1 << 63)
+        } else {
+            !bits
+        };
+        self.output.extend(bits.to_be_bytes());
         Ok(())
     }
 
@@ -91,9 +109,9 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         todo!()
     }
 
+    // 字符串与字节数组的编码相同，带有结尾标记，这样 "t" 编码后不是 "tt" 的前缀
     fn serialize_str(self, v: &str) -> RSDBResult<()> {
-        self.output.extend(v.as_bytes());
-        Ok(())
+        self.serialize_bytes(v.as_bytes())
     }
 
     // 原始值           编码后
@@ -274,10 +292,14 @@ pub struct Deserializer<'de> {
 }
 
 impl<'de> Deserializer<'de> {
-    fn take_bytes(&mut self, len: usize) -> &[u8] {
+    // 剩余的字节不足时返回错误，解析的 key 可能是其他格式
+    fn take_bytes(&mut self, len: usize) -> RSDBResult<&[u8]> {
+        if self.input.len() < len {
+            return Err(RSDBError::Internal("unexpected input".into()));
+        }
         let bytes = &self.input[..len];
         self.input = &self.input[len..];
-        bytes
+        Ok(bytes)
     }
 
     // - 如果这个 0 之后的值是 255，说明是原始字符串中的 0，则继续解析
@@ -315,7 +337,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let v = self.take_bytes(1)[0];
+        let v = self.take_bytes(1)?[0];
         // v == 0 => false
         // 否则为 true
         visitor.visit_bool(v != 0)
@@ -346,11 +368,20 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let bytes = self.take_bytes(8);
+        let bytes = self.take_bytes(8)?;
         let v = i64::from_be_bytes(bytes.try_into()?);
         visitor.visit_i64(v)
     }
 
+    fn deserialize_i128<V>(self, visitor: V) -> RSDBResult<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        let bytes = self.take_bytes(16)?;
+        let v = u128::from_be_bytes(bytes.try_into()?) ^ (1 << 127);
+        visitor.visit_i128(v as i128)
+    }
+
     fn deserialize_u8<V>(self, _visitor: V) -> RSDBResult<V::Value>
     where
         V: de::Visitor<'de>,
@@ -365,11 +396,13 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         todo!()
     }
 
-    fn deserialize_u32<V>(self, _visitor: V) -> RSDBResult<V::Value>
+    fn deserialize_u32<V>(self, visitor: V) -> RSDBResult<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let bytes = self.take_bytes(4)?;
+        let v = u32::from_be_bytes(bytes.try_into()?);
+        visitor.visit_u32(v)
     }
 
     // &[u8] -> Vec<u8>
@@ -378,7 +411,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let bytes = self.take_bytes(8);
+        let bytes = self.take_bytes(8)?;
         let v = u64::from_be_bytes(bytes.try_into()?);
         visitor.visit_u64(v)
     }
@@ -394,9 +427,14 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let bytes = self.take_bytes(8);
-        let v = f64::from_be_bytes(bytes.try_into()?);
-        visitor.visit_f64(v)
+        let bytes = self.take_bytes(8)?;
+        let bits = u64::from_be_bytes(bytes.try_into()?);
+        let bits = if bits >> 63 == 1 {
+            bits ^ (1 << 63)
+        } else {
+            !bits
+        };
+        visitor.visit_f64(f64::from_bits(bits))
     }
 
     fn deserialize_char<V>(self, _visitor: V) -> RSDBResult<V::Value>
@@ -414,11 +452,12 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_str(&String::from_utf8(bytes)?)
     }
 
-    fn deserialize_string<V>(self, _visitor: V) -> RSDBResult<V::Value>
+    fn deserialize_string<V>(self, visitor: V) -> RSDBResult<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let bytes = self.next_bytes()?;
+        visitor.visit_string(String::from_utf8(bytes)?)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> RSDBResult<V::Value>
@@ -555,7 +594,7 @@ impl<'de, 'a> de::EnumAccess<'de> for &mut Deserializer<'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        let index = self.take_bytes(1)[0] as u32;
+        let index = self.take_bytes(1)?[0] as u32;
         let varint_index: RSDBResult<_> = seed.deserialize(index.into_deserializer());
         Ok((varint_index?, self))
     }
@@ -596,9 +635,12 @@ impl<'de, 'a> de::VariantAccess<'de> for &mut Deserializer<'de> {
 
 #[cfg(test)]
 mod tests {
-    use crate::storage::{
-        keycode::{deserialize_key, serialize_key},
-        mvcc::{MvccKey, MvccKeyPrefix},
+    use crate::{
+        sql::types::Value,
+        storage::{
+            keycode::{deserialize_key, serialize_key},
+            mvcc::{MvccKey, MvccKeyPrefix},
+        },
     };
 
     #[test]
@@ -636,6 +678,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_encode_str() {
+        let t = serialize_key(&"t").unwrap();
+        let tt = serialize_key(&"tt").unwrap();
+        assert_eq!(t, vec![116, 0, 0]);
+        assert!(!tt.starts_with(&t));
+        assert_eq!(serialize_key(&"a\0b").unwrap(), vec![97, 0, 255, 98, 0, 0]);
+        let s: String = deserialize_key(&serialize_key(&"a\0b").unwrap()).unwrap();
+        assert_eq!(s, "a\0b");
+    }
+
     #[test]
     fn test_decode() {
         let der_cmp = |k: MvccKey, v: Vec<u8>| {
@@ -655,6 +708,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_decimal_order() {
+        let values = [
+            Value::Decimal(i128::MIN, 2),
+            Value::Decimal(-1001, 2),
+            Value::Decimal(-5, 2),
+            Value::Decimal(0, 2),
+            Value::Decimal(30, 2),
+            Value::Decimal(1001, 2),
+            Value::Decimal(i128::MAX, 2),
+        ];
+        let encoded = values
+            .iter()
+            .map(|v| serialize_key(v).unwrap())
+            .collect::<Vec<_>>();
+        // 编码后的字节序与数值大小一致
+        assert!(encoded.windows(2).all(|w| w[0] < w[1]));
+        for (v, e) in values.iter().zip(encoded.iter()) {
+            assert_eq!(&deserialize_key::<Value>(e).unwrap(), v);
+        }
+    }
+
+    #[test]
+    fn test_float_order() {
+        let values = [
+            f64::NEG_INFINITY,
+            -1.5,
+            -f64::MIN_POSITIVE,
+            0.0,
+            1e-300,
+            2.5,
+            f64::INFINITY,
+            f64::NAN,
+        ];
+        let encoded = values
+            .iter()
+            .map(|v| serialize_key(&Value::Float(*v)).unwrap())
+            .collect::<Vec<_>>();
+        // 编码后的字节序与 float_cmp 的顺序一致
+        assert!(encoded.windows(2).all(|w| w[0] < w[1]));
+        for (v, e) in values.iter().zip(encoded.iter()) {
+            assert_eq!(deserialize_key::<Value>(e).unwrap(), Value::Float(*v));
+        }
+        // 相等的浮点数编码相同
+        assert_eq!(
+            serialize_key(&Value::Float(-0.0)).unwrap(),
+            serialize_key(&Value::Float(0.0)).unwrap()
+        );
+        assert_eq!(
+            serialize_key(&Value::Float(-f64::NAN)).unwrap(),
+            serialize_key(&Value::Float(f64::NAN)).unwrap()
+        );
+    }
+
     // #[test]
     // fn test_u8_convert() {
     //     let v = [1 as u8, 2, 3];
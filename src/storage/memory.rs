@@ -1,9 +1,21 @@
 use std::{
     collections::{BTreeMap, btree_map},
+    fs::File,
+    io::{BufWriter, Write},
     ops::RangeBounds,
+    path::Path,
 };
 
-use crate::{error::RSDBResult, storage};
+use crate::{
+    error::{RSDBError, RSDBResult},
+    storage::{self, disk::sync_dir, engine::EngineStatus},
+};
+
+// 快照文件：magic(8) | 版本(4) | 记录个数(8) | 记录 * 个数 | 之后的数据忽略
+// 每条记录为 长度(4) | bincode 编码的 (key, value)，之后的版本可以在记录末尾或者文件末尾追加数据
+const SNAPSHOT_MAGIC: &[u8; 8] = b"RSDBSNAP";
+const SNAPSHOT_VERSION: u32 = 1;
+const SNAPSHOT_HEADER_SIZE: usize = 8 + 4 + 8;
 
 // 内存存储引擎定义
 pub struct MemoryEngine {
@@ -16,6 +28,70 @@ impl MemoryEngine {
             data: BTreeMap::new(),
         }
     }
+
+    // 将所有的 key/value 写入快照文件，返回写入的个数
+    // 先写入临时文件再重命名，写入过程中进程退出时不会破坏之前的快照
+    pub fn dump_to(&self, path: &Path) -> RSDBResult<usize> {
+        let tmp_path = path.with_extension("tmp");
+        let file = File::create(&tmp_path)?;
+        let mut writer = BufWriter::new(&file);
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_be_bytes())?;
+        writer.write_all(&(self.data.len() as u64).to_be_bytes())?;
+        for entry in &self.data {
+            let record = bincode::serialize(&entry)?;
+            writer.write_all(&(record.len() as u32).to_be_bytes())?;
+            writer.write_all(&record)?;
+        }
+        writer.flush()?;
+        drop(writer);
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        sync_dir(path)?;
+        Ok(self.data.len())
+    }
+
+    // 从快照文件中加载 key/value，返回加载的个数
+    // merge 为 false 时只能加载到空的存储引擎中，否则返回错误；为 true 时合并到已有的数据中，相同的 key 使用快照中的 value
+    // 快照文件不合法时返回错误，不会加载其中的任何数据
+    pub fn load_from(&mut self, path: &Path, merge: bool) -> RSDBResult<usize> {
+        if !merge && !self.data.is_empty() {
+            return Err(RSDBError::Internal(
+                "can not load snapshot into a non-empty engine without merge".to_string(),
+            ));
+        }
+        let buf = std::fs::read(path)?;
+        if buf.len() < SNAPSHOT_HEADER_SIZE || !buf.starts_with(SNAPSHOT_MAGIC) {
+            return Err(RSDBError::Internal(format!(
+                "invalid snapshot file {}",
+                path.display()
+            )));
+        }
+        let version = u32::from_be_bytes(buf[8..12].try_into()?);
+        if version > SNAPSHOT_VERSION {
+            return Err(RSDBError::Internal(format!(
+                "unsupported snapshot version {}",
+                version
+            )));
+        }
+        let count = u64::from_be_bytes(buf[12..SNAPSHOT_HEADER_SIZE].try_into()?);
+        let mut entries = Vec::new();
+        let mut rest = &buf[SNAPSHOT_HEADER_SIZE..];
+        for _ in 0..count {
+            let truncated = || RSDBError::Internal("snapshot file is truncated".to_string());
+            let (len, data) = rest.split_first_chunk::<4>().ok_or_else(truncated)?;
+            let len = u32::from_be_bytes(*len) as usize;
+            if data.len() < len {
+                return Err(truncated());
+            }
+            // 忽略记录末尾无法识别的数据
+            let (record, data) = data.split_at(len);
+            entries.push(bincode::deserialize::<(Vec<u8>, Vec<u8>)>(record)?);
+            rest = data;
+        }
+        self.data.extend(entries);
+        Ok(count as usize)
+    }
 }
 
 impl storage::engine::Engine for MemoryEngine {
@@ -41,6 +117,18 @@ impl storage::engine::Engine for MemoryEngine {
             inner: self.data.range(range),
         }
     }
+
+    fn status(&mut self) -> RSDBResult<EngineStatus> {
+        Ok(EngineStatus {
+            keys: self.data.len() as u64,
+            size: self
+                .data
+                .iter()
+                .map(|(k, v)| (k.len() + v.len()) as u64)
+                .sum(),
+            disk_size: 0,
+        })
+    }
 }
 
 // 内存存储引擎迭代器
@@ -65,3 +153,92 @@ impl<'a> DoubleEndedIterator for MemoryEngineIterator<'a> {
             .map(|(k, v)| Ok((k.clone(), v.clone())))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryEngine, SNAPSHOT_HEADER_SIZE, SNAPSHOT_MAGIC, SNAPSHOT_VERSION};
+    use crate::{error::RSDBResult, storage::engine::Engine};
+
+    #[test]
+    fn test_snapshot() -> RSDBResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snapshot");
+        let mut eng = MemoryEngine::new();
+        let pairs = vec![
+            (vec![], b"empty key".to_vec()),
+            (vec![0], vec![0, 0, 0]),
+            (vec![0, 1, 0], vec![]),
+            (b"aa\0bb".to_vec(), b"value\0with zero".to_vec()),
+            (b"key".to_vec(), vec![0xff; 1000]),
+        ];
+        for (key, value) in &pairs {
+            eng.set(key.clone(), value.clone())?;
+        }
+        assert_eq!(eng.dump_to(&path)?, pairs.len());
+
+        let mut loaded = MemoryEngine::new();
+        assert_eq!(loaded.load_from(&path, false)?, pairs.len());
+        assert_eq!(loaded.scan(..).collect::<RSDBResult<Vec<_>>>()?, pairs);
+
+        // 非空的存储引擎只能合并加载，快照中的 value 覆盖已有的 value
+        loaded.set(b"key".to_vec(), b"new".to_vec())?;
+        loaded.set(b"other".to_vec(), b"other".to_vec())?;
+        assert!(loaded.load_from(&path, false).is_err());
+        assert_eq!(loaded.get(b"key".to_vec())?, Some(b"new".to_vec()));
+        assert_eq!(loaded.load_from(&path, true)?, pairs.len());
+        assert_eq!(loaded.get(b"key".to_vec())?, Some(vec![0xff; 1000]));
+        assert_eq!(loaded.get(b"other".to_vec())?, Some(b"other".to_vec()));
+
+        // 空的存储引擎也可以导出和加载
+        MemoryEngine::new().dump_to(&path)?;
+        let mut empty = MemoryEngine::new();
+        assert_eq!(empty.load_from(&path, false)?, 0);
+        assert_eq!(empty.status()?.keys, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_format() -> RSDBResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snapshot");
+        let mut eng = MemoryEngine::new();
+        eng.set(b"a\0".to_vec(), b"1".to_vec())?;
+        eng.set(b"b".to_vec(), b"2".to_vec())?;
+        eng.dump_to(&path)?;
+        let buf = std::fs::read(&path)?;
+        let expected = eng.scan(..).collect::<RSDBResult<Vec<_>>>()?;
+
+        // 之后的版本在记录末尾以及文件末尾追加的数据被忽略
+        let mut newer = buf[..SNAPSHOT_HEADER_SIZE].to_vec();
+        for (key, value) in &expected {
+            let mut record = bincode::serialize(&(key, value))?;
+            record.extend(b"extra field");
+            newer.extend((record.len() as u32).to_be_bytes());
+            newer.extend(record);
+        }
+        newer.extend(b"unknown trailer");
+        std::fs::write(&path, &newer)?;
+        let mut loaded = MemoryEngine::new();
+        assert_eq!(loaded.load_from(&path, false)?, 2);
+        assert_eq!(loaded.scan(..).collect::<RSDBResult<Vec<_>>>()?, expected);
+
+        // 不完整的快照、无法识别的文件以及更高的版本返回错误，不加载任何数据
+        for invalid in [
+            buf[..buf.len() - 1].to_vec(),
+            buf[..SNAPSHOT_HEADER_SIZE - 1].to_vec(),
+            [b"NOTSNAP!".as_slice(), &buf[SNAPSHOT_MAGIC.len()..]].concat(),
+            [
+                SNAPSHOT_MAGIC.as_slice(),
+                &(SNAPSHOT_VERSION + 1).to_be_bytes(),
+                &buf[12..],
+            ]
+            .concat(),
+        ] {
+            std::fs::write(&path, invalid)?;
+            let mut loaded = MemoryEngine::new();
+            assert!(loaded.load_from(&path, false).is_err());
+            assert_eq!(loaded.status()?.keys, 0);
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,10 @@
 use std::{
-    collections::{BTreeMap, HashSet},
-    sync::{Arc, Mutex, MutexGuard},
+    collections::{BTreeMap, HashSet, VecDeque},
+    ops::Bound,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+    },
     u64, vec,
 };
 
@@ -9,21 +13,38 @@ use serde::{Deserialize, Serialize};
 use crate::{
     error::{RSDBError, RSDBResult},
     storage::{
-        engine::Engine,
+        engine::{Durability, Engine, EngineStatus, prefix_range},
         keycode::{deserialize_key, serialize_key},
     },
 };
 
 type Version = u64;
 
+// 存储引擎中编码后的 key 的范围
+type KeyRange = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
+// 扫描时每次加锁读取的原始 key 的个数，两批之间释放锁，其他事务可以读写
+// 第一批较小，带有 LIMIT 的查询只需要读取少量的 key，之后每批翻倍直到上限
+const SCAN_MIN_BATCH_SIZE: usize = 8;
+const SCAN_MAX_BATCH_SIZE: usize = 1024;
+
+// 只读事务的快照不会写入存储引擎，在内存中记录每个快照能看到所有更早版本的最小版本号及其个数
+type Readers = Arc<Mutex<BTreeMap<Version, usize>>>;
+
 pub struct Mvcc<E: Engine> {
     engine: Arc<Mutex<E>>,
+    // 是否已经清理过上次进程退出时遗留的活跃事务，所有的 clone 共享
+    recovered: Arc<AtomicBool>,
+    // 正在进行的只读事务，清理旧版本时不能删除它们还能读到的版本
+    readers: Readers,
 }
 
 impl<E: Engine> Clone for Mvcc<E> {
     fn clone(&self) -> Self {
         Self {
             engine: self.engine.clone(),
+            recovered: self.recovered.clone(),
+            readers: self.readers.clone(),
         }
     }
 }
@@ -32,22 +53,182 @@ impl<E: Engine> Mvcc<E> {
     pub fn new(eng: E) -> Self {
         Self {
             engine: Arc::new(Mutex::new(eng)),
+            recovered: Arc::new(AtomicBool::new(false)),
+            readers: Readers::default(),
         }
     }
 
     pub fn begin(&self) -> RSDBResult<MvccTransaction<E>> {
-        MvccTransaction::begin(self.engine.clone())
+        self.begin_with(TransactionOptions::default())
+    }
+
+    pub fn begin_with(&self, opts: TransactionOptions) -> RSDBResult<MvccTransaction<E>> {
+        self.recover()?;
+        MvccTransaction::begin_with(self.engine.clone(), self.readers.clone(), opts)
+    }
+
+    // 清理所有快照都不再需要的旧版本，返回删除的版本的个数
+    // 所有快照都能看到版本号小于 horizon 的已提交版本，对于每个 key，这些版本中只有最新的一个会被读到：
+    // - 更早的版本都可以删除
+    // - 最新的版本是删除标记时，它本身也可以删除，key 不再有任何版本
+    // 清理后 AS OF 不能再查询 horizon 之前的版本，清理期间持有存储引擎的锁
+    pub fn gc(&self) -> RSDBResult<usize> {
+        self.recover()?;
+        let mut engine = self.engine.lock()?;
+        let mut horizon = match engine.get(MvccKey::NextVersion.encode()?)? {
+            Some(value) => bincode::deserialize(&value)?,
+            None => 0,
+        };
+        let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnActive.encode()?);
+        while let Some((_, value)) = iter.next().transpose()? {
+            // 旧版本的活跃事务记录中没有保存快照，保守地不清理任何版本
+            let low = match value.is_empty() {
+                true => 0,
+                false => bincode::deserialize(&value)?,
+            };
+            horizon = horizon.min(low);
+        }
+        drop(iter);
+        if let Some(low) = self.readers.lock()?.keys().next() {
+            horizon = horizon.min(*low);
+        }
+
+        let mut prefix = MvccKeyPrefix::Version(vec![]).encode()?;
+        prefix.truncate(prefix.len() - 2);
+        let mut deletes = Vec::new();
+        // 当前 key 在 horizon 之前的版本，以及最新的一个是否为删除标记
+        let mut current: Option<(Vec<u8>, Vec<Vec<u8>>, bool)> = None;
+        let mut iter = engine.scan_prefix(prefix);
+        while let Some((key, value)) = iter.next().transpose()? {
+            let MvccKey::Version(raw_key, version) = MvccKey::decode(key.clone())? else {
+                return Err(RSDBError::Internal(format!(
+                    "unexpected key: {:?}",
+                    String::from_utf8(key)
+                )));
+            };
+            if version >= horizon {
+                continue;
+            }
+            let deleted = bincode::deserialize::<Option<Vec<u8>>>(&value)?.is_none();
+            match &mut current {
+                Some((k, versions, tombstone)) if *k == raw_key => {
+                    versions.push(key);
+                    *tombstone = deleted;
+                }
+                _ => {
+                    if let Some((_, versions, tombstone)) =
+                        current.replace((raw_key, vec![key], deleted))
+                    {
+                        Self::collect_garbage(versions, tombstone, &mut deletes);
+                    }
+                }
+            }
+        }
+        drop(iter);
+        if let Some((_, versions, tombstone)) = current {
+            Self::collect_garbage(versions, tombstone, &mut deletes);
+        }
+        for key in &deletes {
+            engine.delete(key.clone())?;
+        }
+        engine.set(MvccKey::GcHorizon.encode()?, bincode::serialize(&horizon)?)?;
+        Ok(deletes.len())
     }
+
+    // 清理旧版本之后压缩存储引擎，回收删除的版本占用的空间
+    pub fn vacuum(&self) -> RSDBResult<VacuumStats> {
+        // 清理旧版本时会追加删除记录，在清理之前获取原始的大小
+        let disk_size_before = self.engine.lock()?.status()?.disk_size;
+        let versions = self.gc()?;
+        let mut engine = self.engine.lock()?;
+        engine.compact()?;
+        Ok(VacuumStats {
+            versions,
+            disk_size_before,
+            disk_size_after: engine.status()?.disk_size,
+        })
+    }
+
+    // 一个 key 在 horizon 之前按版本从小到大排列的所有版本中，需要删除的版本
+    fn collect_garbage(mut versions: Vec<Vec<u8>>, tombstone: bool, deletes: &mut Vec<Vec<u8>>) {
+        if !tombstone {
+            versions.pop();
+        }
+        deletes.extend(versions);
+    }
+
+    // 进程在事务提交或回滚之前退出时，事务的 TxnActive 记录会一直留在存储引擎中
+    // 之后的事务都会认为它仍然活跃，它的 TxnWrite 和 Version 记录也不会被清理
+    // 第一次开启事务之前，本进程中还没有任何事务，所有的活跃事务都是遗留的，将它们回滚
+    fn recover(&self) -> RSDBResult<()> {
+        if self.recovered.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        let mut engine = self.engine.lock()?;
+        // 其他线程可能已经在持有锁时完成了清理
+        if self.recovered.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        for version in MvccTransaction::scan_active(&mut engine)? {
+            MvccTransaction::rollback_version(&mut engine, version)?;
+        }
+        self.recovered.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    // MVCC 和存储引擎的统计信息
+    pub fn status(&self) -> RSDBResult<MvccStatus> {
+        let mut engine = self.engine.lock()?;
+        MvccTransaction::status_locked(&mut engine)
+    }
+
+    // 持有存储引擎的锁调用 f，期间没有其他事务读写，如导出内存存储引擎的快照
+    // 快照中未提交的事务在加载后第一次开启事务时回滚
+    pub fn with_engine<T>(&self, f: impl FnOnce(&mut E) -> RSDBResult<T>) -> RSDBResult<T> {
+        let mut engine = self.engine.lock()?;
+        f(&mut engine)
+    }
+
+    // 开启只读事务，不分配版本号，写入时返回 ReadOnlyTransaction 错误
+    pub fn begin_read_only(&self) -> RSDBResult<MvccTransaction<E>> {
+        self.begin_with(TransactionOptions {
+            read_only: true,
+            as_of: None,
+            ..Default::default()
+        })
+    }
+}
+
+// 开启事务的选项
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionOptions {
+    // 只读事务，不分配新的版本号，也不会加入活跃事务列表
+    pub read_only: bool,
+    // 历史版本事务，可见性与版本号为 as_of 的事务开始时相同，隐含只读
+    pub as_of: Option<Version>,
+    // 可串行化隔离级别，记录读取过的 key，提交时检查是否被其他已提交的事务修改
+    // 默认为快照隔离，不能避免写偏斜；只读事务不会写入，忽略这个选项
+    pub serializable: bool,
 }
 
 pub struct MvccTransaction<E: Engine> {
     engine: Arc<Mutex<E>>,
-    state: TransactionState, // 事务状态：当前事务的版本号和活跃事务列表
+    state: TransactionState, // 事务状态：当前事务的版本号、活跃事务列表以及是否只读
+    // 可串行化事务读取过的 key 的范围，包括所有版本，其他隔离级别为 None
+    reads: Option<Mutex<Vec<KeyRange>>>,
+    // 只读事务在 Mvcc 中的登记，事务结束后注销，扫描的迭代器同样持有
+    reader: Option<Arc<ReaderGuard>>,
+    // 提交时删除的不受 MVCC 管理的 key，回滚后保留
+    unversioned_deletes: Mutex<Vec<Vec<u8>>>,
 }
 
 impl<E: Engine> MvccTransaction<E> {
-    // 开启事务
-    pub fn begin(eng: Arc<Mutex<E>>) -> RSDBResult<Self> {
+    // 根据选项开启事务
+    pub fn begin_with(
+        eng: Arc<Mutex<E>>,
+        readers: Readers,
+        opts: TransactionOptions,
+    ) -> RSDBResult<Self> {
         // 获取存储引擎
         let mut engine = eng.lock()?;
         // 获取最新的版本号
@@ -55,21 +236,67 @@ impl<E: Engine> MvccTransaction<E> {
             Some(value) => bincode::deserialize(&value)?,
             None => 0,
         };
+        // 只读事务直接使用当前的版本号，看不到之后开启的事务的写入
+        if opts.read_only || opts.as_of.is_some() {
+            let version = match opts.as_of {
+                Some(version) if version > next_version => {
+                    return Err(RSDBError::Internal(format!(
+                        "version {} does not exist, latest version is {}",
+                        version, next_version
+                    )));
+                }
+                Some(version) => {
+                    let horizon = match engine.get(MvccKey::GcHorizon.encode()?)? {
+                        Some(value) => bincode::deserialize(&value)?,
+                        None => 0,
+                    };
+                    if version < horizon {
+                        return Err(RSDBError::Internal(format!(
+                            "version {} has been garbage collected, oldest version is {}",
+                            version, horizon
+                        )));
+                    }
+                    version
+                }
+                None => next_version,
+            };
+            let state = TransactionState {
+                version,
+                active_versions: Self::scan_active(&mut engine)?,
+                read_only: true,
+            };
+            // 持有存储引擎的锁时登记，清理旧版本时一定能看到这个快照
+            let reader = ReaderGuard::new(readers, state.low_watermark())?;
+            return Ok(Self {
+                engine: eng.clone(),
+                state,
+                reads: None,
+                reader: Some(Arc::new(reader)),
+                unversioned_deletes: Mutex::default(),
+            });
+        }
         // 保存下一个 version
         engine.set(
             MvccKey::NextVersion.encode()?,
             bincode::serialize(&(next_version + 1))?,
         )?;
         // 获取当前活跃事务版本列表
-        let active_versions = Self::scan_active(&mut engine)?;
-        // 将当前事务加入到的活跃事务列表中
-        engine.set(MvccKey::TxnActive(next_version).encode()?, vec![])?;
+        let state = TransactionState {
+            version: next_version,
+            active_versions: Self::scan_active(&mut engine)?,
+            read_only: false,
+        };
+        // 将当前事务加入到的活跃事务列表中，同时记录快照，清理旧版本时需要保留它能读到的版本
+        engine.set(
+            MvccKey::TxnActive(next_version).encode()?,
+            bincode::serialize(&state.low_watermark())?,
+        )?;
         Ok(Self {
             engine: eng.clone(),
-            state: TransactionState {
-                version: next_version,
-                active_versions,
-            },
+            state,
+            reads: opts.serializable.then(Mutex::default),
+            reader: None,
+            unversioned_deletes: Mutex::default(),
         })
     }
 
@@ -78,8 +305,87 @@ impl<E: Engine> MvccTransaction<E> {
         self.state.version
     }
 
+    // 是否是只读事务
+    pub fn read_only(&self) -> bool {
+        self.state.read_only
+    }
+
+    // 原子地将不受 MVCC 管理的计数器加上 delta，返回相加之后的值，不存在时从 0 开始
+    // 不会与其他事务冲突，事务回滚后也不会撤销
+    pub fn fetch_add_unversioned(&self, key: Vec<u8>, delta: i64) -> RSDBResult<i64> {
+        if self.state.read_only {
+            return Err(RSDBError::ReadOnlyTransaction);
+        }
+        let mut engine = self.engine.lock()?;
+        let key = MvccKey::Unversioned(key).encode()?;
+        let value = match engine.get(key.clone())? {
+            Some(value) => bincode::deserialize::<i64>(&value)? + delta,
+            None => delta,
+        };
+        engine.set(key, bincode::serialize(&value)?)?;
+        Ok(value)
+    }
+
+    // 删除不受 MVCC 管理的计数器，立即生效
+    pub fn delete_unversioned(&self, key: Vec<u8>) -> RSDBResult<()> {
+        if self.state.read_only {
+            return Err(RSDBError::ReadOnlyTransaction);
+        }
+        let mut engine = self.engine.lock()?;
+        engine.delete(MvccKey::Unversioned(key).encode()?)
+    }
+
+    // 事务提交时删除不受 MVCC 管理的计数器，与提交在同一次加锁中完成，回滚后保留
+    pub fn delete_unversioned_on_commit(&self, key: Vec<u8>) -> RSDBResult<()> {
+        if self.state.read_only {
+            return Err(RSDBError::ReadOnlyTransaction);
+        }
+        self.unversioned_deletes
+            .lock()?
+            .push(MvccKey::Unversioned(key).encode()?);
+        Ok(())
+    }
+
+    // MVCC 和存储引擎的统计信息，与事务的快照无关，总是最新的值
+    pub fn status(&self) -> RSDBResult<MvccStatus> {
+        let mut engine = self.engine.lock()?;
+        Self::status_locked(&mut engine)
+    }
+
+    // 当前事务写入的 key 的个数，同一个 key 写入多次只计算一次
+    pub fn write_count(&self) -> RSDBResult<usize> {
+        if self.state.read_only {
+            return Ok(0);
+        }
+        let mut engine = self.engine.lock()?;
+        let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(self.state.version).encode()?);
+        let mut count = 0;
+        while iter.next().transpose()?.is_some() {
+            count += 1;
+        }
+        Ok(count)
+    }
+
     pub fn commit(&self) -> RSDBResult<()> {
+        // 只读事务没有写入，也没有加入活跃事务列表
+        // 并且它的版本号可能已经分配给了其他事务，不能删除对应的记录
+        if self.state.read_only {
+            return Ok(());
+        }
         let mut engine = self.engine.lock()?;
+        if let Some(reads) = &self.reads
+            && let Err(err) = self.validate_reads(&mut engine, &reads.lock()?)
+        {
+            Self::rollback_version(&mut engine, self.state.version)?;
+            return Err(err);
+        }
+        // 先删除活跃状态，之后的事务就能看到当前事务的写入
+        // 如果在删除写入记录的过程中进程退出，事务仍然是已提交的，只会留下无用的写入记录
+        // 反过来的顺序下，重启后的清理会将部分写入已经删除的事务回滚，只撤销剩余的一部分写入
+        engine.delete(MvccKey::TxnActive(self.state.version).encode()?)?;
+        for key in self.unversioned_deletes.lock()?.drain(..) {
+            engine.delete(key)?;
+        }
         let mut txnwrite_keys = Vec::new();
         // 找到当前事务的 TxnWrite 信息
         let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(self.state.version).encode()?);
@@ -91,20 +397,31 @@ impl<E: Engine> MvccTransaction<E> {
         for key in txnwrite_keys {
             engine.delete(key)?;
         }
-        // 删除当前事务的活跃状态
-        engine.delete(MvccKey::TxnActive(self.state.version).encode()?)
+        // Always 模式下落盘之后才返回提交成功
+        if engine.durability() == Durability::Always {
+            engine.flush()?;
+        }
+        Ok(())
     }
 
     pub fn rollback(&self) -> RSDBResult<()> {
+        if self.state.read_only {
+            return Ok(());
+        }
         let mut engine = self.engine.lock()?;
+        Self::rollback_version(&mut engine, self.state.version)
+    }
+
+    // 删除某个版本的事务的所有写入以及活跃状态，用于回滚和启动时清理遗留的事务
+    fn rollback_version(engine: &mut MutexGuard<E>, version: Version) -> RSDBResult<()> {
         let mut txnwrite_keys = Vec::new();
         let mut version_keys = Vec::new();
-        let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(self.state.version).encode()?);
+        let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(version).encode()?);
         while let Some((key, _)) = iter.next().transpose()? {
             txnwrite_keys.push(key.clone());
             match MvccKey::decode(key.clone())? {
                 MvccKey::TxnWrite(_, raw_key) => {
-                    version_keys.push(MvccKey::Version(raw_key, self.state.version).encode()?);
+                    version_keys.push(MvccKey::Version(raw_key, version).encode()?);
                 }
                 _ => {
                     return Err(RSDBError::Internal(format!(
@@ -123,7 +440,7 @@ impl<E: Engine> MvccTransaction<E> {
         for key in version_keys {
             engine.delete(key)?;
         }
-        engine.delete(MvccKey::TxnActive(self.state.version).encode()?)
+        engine.delete(MvccKey::TxnActive(version).encode()?)
     }
 
     pub fn set(&self, key: Vec<u8>, value: Vec<u8>) -> RSDBResult<()> {
@@ -134,11 +451,28 @@ impl<E: Engine> MvccTransaction<E> {
         self.write_inner(key, None)
     }
 
+    // 批量写入，value 为 None 表示删除，只获取一次存储引擎的锁
+    pub fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> RSDBResult<()> {
+        if self.state.read_only {
+            return Err(RSDBError::ReadOnlyTransaction);
+        }
+        let mut engine = self.engine.lock()?;
+        for (key, value) in writes {
+            self.write_locked(&mut engine, key, value)?;
+        }
+        Ok(())
+    }
+
     pub fn get(&self, key: Vec<u8>) -> RSDBResult<Option<Vec<u8>>> {
         let mut engine = self.engine.lock()?;
         let from = MvccKey::Version(key.clone(), 0).encode()?;
+        self.record_read((
+            Bound::Included(from.clone()),
+            Bound::Included(MvccKey::Version(key.clone(), u64::MAX).encode()?),
+        ))?;
+        // 包含当前版本，事务需要能读到自己的写入
         let to = MvccKey::Version(key.clone(), self.state.version).encode()?;
-        let mut iter = engine.scan(from..to).rev();
+        let mut iter = engine.scan(from..=to).rev();
         // 从最新的版本开始查找，找到第一个可见的版本
         while let Some((key, value)) = iter.next().transpose()? {
             match MvccKey::decode(key.clone())? {
@@ -159,43 +493,65 @@ impl<E: Engine> MvccTransaction<E> {
     }
 
     pub fn scan_prefix(&self, prefix: Vec<u8>) -> RSDBResult<Vec<ScanResult>> {
-        let mut eng = self.engine.lock()?;
+        self.scan(prefix, false)?.collect()
+    }
+
+    // 按 key 的顺序依次处理前缀下每个可见的 key，回调返回 false 时停止扫描
+    pub fn scan_prefix_with<F>(&self, prefix: Vec<u8>, mut f: F) -> RSDBResult<()>
+    where
+        F: FnMut(ScanResult) -> RSDBResult<bool>,
+    {
+        for result in self.scan(prefix, false)? {
+            if !f(result?)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // 前缀扫描的迭代器，按需从存储引擎中分批读取，不会将所有的数据读入内存
+    // reverse 为 true 时按 key 从大到小的顺序返回
+    pub fn scan(&self, prefix: Vec<u8>, reverse: bool) -> RSDBResult<MvccScan<E>> {
         let mut enc_prefix = MvccKeyPrefix::Version(prefix).encode()?;
         // 原始值           编码后
         // 97 98 99     -> 97 98 99 0 0
         // 前缀原始值        前缀编码后         去掉最后的 [0, 0] 后缀
         // 97 98        -> 97 98 0 0         -> 97 98
         enc_prefix.truncate(enc_prefix.len() - 2);
-        let mut iter = eng.scan_prefix(enc_prefix);
-        let mut results = BTreeMap::new();
-        while let Some((key, value)) = iter.next().transpose()? {
-            match MvccKey::decode(key.clone())? {
-                MvccKey::Version(raw_key, version) => {
-                    if self.state.is_visible(version) {
-                        match bincode::deserialize(&value)? {
-                            Some(raw_value) => results.insert(raw_key, raw_value),
-                            None => results.remove(&raw_key),
-                        };
-                    }
-                }
-                _ => {
-                    return Err(RSDBError::Internal(format!(
-                        "unexpected key: {:?}",
-                        String::from_utf8(key)
-                    )));
-                }
-            }
-        }
-        Ok(results
-            .into_iter()
-            .map(|(key, value)| ScanResult { key, value })
-            .collect())
+        let (start, end) = prefix_range(enc_prefix);
+        // 扫描可能因为 LIMIT 提前结束，仍然记录整个前缀，提交时的检查更严格但不会遗漏
+        self.record_read((start.clone(), end.clone()))?;
+        Ok(MvccScan {
+            engine: self.engine.clone(),
+            state: self.state.clone(),
+            _reader: self.reader.clone(),
+            start,
+            end,
+            buffer: VecDeque::new(),
+            current: None,
+            batch_size: SCAN_MIN_BATCH_SIZE,
+            reverse,
+            done: false,
+        })
     }
 
     // 更新 / 删除 数据
     fn write_inner(&self, key: Vec<u8>, value: Option<Vec<u8>>) -> RSDBResult<()> {
+        if self.state.read_only {
+            return Err(RSDBError::ReadOnlyTransaction);
+        }
         // 获取存储引擎
         let mut engine = self.engine.lock()?;
+        self.write_locked(&mut engine, key, value)
+    }
+
+    // 在已经持有存储引擎锁的情况下写入一个 key
+    fn write_locked(
+        &self,
+        engine: &mut MutexGuard<E>,
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    ) -> RSDBResult<()> {
         // 检测冲突
         let from = MvccKey::Version(
             key.clone(),
@@ -237,6 +593,77 @@ impl<E: Engine> MvccTransaction<E> {
         Ok(())
     }
 
+    // 记录可串行化事务读取的范围，其他隔离级别不需要记录
+    fn record_read(&self, range: KeyRange) -> RSDBResult<()> {
+        if let Some(reads) = &self.reads {
+            reads.lock()?.push(range);
+        }
+        Ok(())
+    }
+
+    // 检查读取过的范围中是否有对当前事务不可见、并且已经提交的版本
+    // 这样的版本在当前事务的快照之后提交，当前事务读到的是旧值，提交后可能出现写偏斜等异常
+    // 仍然活跃的事务的写入在它自己提交时检查，两个事务中最多只有一个能提交
+    fn validate_reads(&self, engine: &mut MutexGuard<E>, reads: &[KeyRange]) -> RSDBResult<()> {
+        let active_versions = Self::scan_active(engine)?;
+        for range in reads {
+            let mut iter = engine.scan(range.clone());
+            while let Some((key, _)) = iter.next().transpose()? {
+                match MvccKey::decode(key.clone())? {
+                    MvccKey::Version(_, version) => {
+                        if !self.state.is_visible(version) && !active_versions.contains(&version) {
+                            return Err(RSDBError::SerializationFailure);
+                        }
+                    }
+                    _ => {
+                        return Err(RSDBError::Internal(format!(
+                            "unexpected key: {:?}",
+                            String::from_utf8(key)
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 统计需要扫描所有的版本，期间持有存储引擎的锁
+    fn status_locked(engine: &mut MutexGuard<E>) -> RSDBResult<MvccStatus> {
+        let next_version = match engine.get(MvccKey::NextVersion.encode()?)? {
+            Some(value) => bincode::deserialize(&value)?,
+            None => 0,
+        };
+        let active_txns = Self::scan_active(engine)?.len() as u64;
+        // 所有版本的 key 的公共前缀，去掉空 key 编码后的 [0, 0] 后缀
+        let mut prefix = MvccKeyPrefix::Version(vec![]).encode()?;
+        prefix.truncate(prefix.len() - 2);
+        let (mut total_keys, mut total_versions) = (0, 0);
+        let mut last_key = None;
+        let mut iter = engine.scan_prefix(prefix);
+        while let Some((key, _)) = iter.next().transpose()? {
+            let MvccKey::Version(raw_key, _) = MvccKey::decode(key.clone())? else {
+                return Err(RSDBError::Internal(format!(
+                    "unexpected key: {:?}",
+                    String::from_utf8(key)
+                )));
+            };
+            // 同一个 key 的多个版本是相邻的
+            if last_key.as_ref() != Some(&raw_key) {
+                total_keys += 1;
+                last_key = Some(raw_key);
+            }
+            total_versions += 1;
+        }
+        drop(iter);
+        Ok(MvccStatus {
+            active_txns,
+            next_version,
+            total_keys,
+            total_versions,
+            engine: engine.status()?,
+        })
+    }
+
     // 扫描获取当前活跃事务列表
     fn scan_active(engine: &mut MutexGuard<E>) -> RSDBResult<HashSet<Version>> {
         let mut active_versions = HashSet::new();
@@ -259,26 +686,195 @@ impl<E: Engine> MvccTransaction<E> {
     }
 }
 
+// MVCC 的统计信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct MvccStatus {
+    // 活跃的读写事务的个数，只读事务不会加入活跃事务列表
+    pub active_txns: u64,
+    // 下一个读写事务的版本号
+    pub next_version: Version,
+    // 有版本记录的 key 的个数，包括最新版本为删除的 key
+    pub total_keys: u64,
+    // 所有 key 的版本的个数，旧版本不会被清理，total_versions / total_keys 反映了版本的累积
+    pub total_versions: u64,
+    // 存储引擎的统计信息，包括 MVCC 内部使用的 key
+    pub engine: EngineStatus,
+}
+
+// VACUUM 的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct VacuumStats {
+    // 删除的版本的个数
+    pub versions: usize,
+    // 压缩前后数据文件的字节数，内存存储引擎都为 0
+    pub disk_size_before: u64,
+    pub disk_size_after: u64,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct ScanResult {
     pub key: Vec<u8>,
     pub value: Vec<u8>,
 }
 
+// 前缀扫描的迭代器，每批加锁读取一定数量的原始 key，处理完后释放锁
+// 其他事务在两批之间的写入版本号更大或仍然活跃，对本事务不可见，不影响扫描结果
+pub struct MvccScan<E: Engine> {
+    engine: Arc<Mutex<E>>,
+    state: TransactionState,
+    // 迭代器可能比事务存活得更久，只读事务的快照需要保留到扫描结束
+    _reader: Option<Arc<ReaderGuard>>,
+    // 下一批的扫描范围，不包括上一批已经读取的原始 key
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    // 已经确定的结果
+    buffer: VecDeque<ScanResult>,
+    // 同一个 key 的多个版本是相邻的，且按版本从小到大排列，最后一个可见的版本即为当前值
+    // 遇到下一个 key 时，上一个 key 的值才确定，可能跨越两批
+    // 倒序扫描时第一个可见的版本即为当前值，这里记录已经确定的 key，跳过它更早的版本
+    current: Option<(Vec<u8>, Option<Vec<u8>>)>,
+    batch_size: usize,
+    reverse: bool,
+    done: bool,
+}
+
+impl<E: Engine> MvccScan<E> {
+    // 读取下一批原始 key，将已经确定的结果放入 buffer
+    fn fill(&mut self) -> RSDBResult<()> {
+        let mut eng = self.engine.lock()?;
+        let mut iter = eng.scan((self.start.clone(), self.end.clone()));
+        for _ in 0..self.batch_size {
+            let item = match self.reverse {
+                true => iter.next_back(),
+                false => iter.next(),
+            };
+            let Some((key, value)) = item.transpose()? else {
+                self.done = true;
+                break;
+            };
+            match self.reverse {
+                true => self.end = Bound::Excluded(key.clone()),
+                false => self.start = Bound::Excluded(key.clone()),
+            }
+            let MvccKey::Version(raw_key, version) = MvccKey::decode(key.clone())? else {
+                return Err(RSDBError::Internal(format!(
+                    "unexpected key: {:?}",
+                    String::from_utf8(key)
+                )));
+            };
+            if !self.state.is_visible(version) {
+                continue;
+            }
+            if self.reverse {
+                if matches!(&self.current, Some((k, _)) if *k == raw_key) {
+                    continue;
+                }
+                let value: Option<Vec<u8>> = bincode::deserialize(&value)?;
+                self.current = Some((raw_key.clone(), None));
+                if let Some(value) = value {
+                    self.buffer.push_back(ScanResult {
+                        key: raw_key,
+                        value,
+                    });
+                }
+                continue;
+            }
+            let value: Option<Vec<u8>> = bincode::deserialize(&value)?;
+            match &mut self.current {
+                Some((k, v)) if *k == raw_key => *v = value,
+                _ => {
+                    if let Some((key, Some(value))) = self.current.replace((raw_key, value)) {
+                        self.buffer.push_back(ScanResult { key, value });
+                    }
+                }
+            }
+        }
+        self.batch_size = (self.batch_size * 2).min(SCAN_MAX_BATCH_SIZE);
+        if self.done
+            && let Some((key, Some(value))) = self.current.take()
+        {
+            self.buffer.push_back(ScanResult { key, value });
+        }
+        Ok(())
+    }
+}
+
+impl<E: Engine> Iterator for MvccScan<E> {
+    type Item = RSDBResult<ScanResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.buffer.pop_front() {
+                return Some(Ok(result));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(err) = self.fill() {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
 // 事务状态
+#[derive(Clone)]
 pub struct TransactionState {
     // 当前事务的版本号
     pub version: Version,
     // 当前活跃事务版本列表
     pub active_versions: HashSet<Version>,
+    // 只读事务的版本号没有分配给自己，之后开启的读写事务可能会使用同一个版本号
+    pub read_only: bool,
 }
 
 impl TransactionState {
+    // 版本号小于这个值的已提交版本都是可见的
+    fn low_watermark(&self) -> Version {
+        self.active_versions
+            .iter()
+            .copied()
+            .fold(self.version, Version::min)
+    }
+
     fn is_visible(&self, version: Version) -> bool {
         if self.active_versions.contains(&version) {
             return false; // 如果版本在活跃事务列表中，则不可见
         }
-        return version < self.version; // 如果版本小于当前事务版本，则可见
+        if self.read_only {
+            return version < self.version; // 只读事务只能看到之前版本的数据
+        }
+        return version <= self.version; // 如果版本小于当前事务版本，或者是自己的写入，则可见
+    }
+}
+
+// 只读事务在 Mvcc 中的登记，drop 时注销
+struct ReaderGuard {
+    readers: Readers,
+    low_watermark: Version,
+}
+
+impl ReaderGuard {
+    fn new(readers: Readers, low_watermark: Version) -> RSDBResult<Self> {
+        *readers.lock()?.entry(low_watermark).or_default() += 1;
+        Ok(Self {
+            readers,
+            low_watermark,
+        })
+    }
+}
+
+impl Drop for ReaderGuard {
+    fn drop(&mut self) {
+        if let Ok(mut readers) = self.readers.lock()
+            && let Some(count) = readers.get_mut(&self.low_watermark)
+        {
+            *count -= 1;
+            if *count == 0 {
+                readers.remove(&self.low_watermark);
+            }
+        }
     }
 }
 
@@ -288,6 +884,10 @@ pub enum MvccKey {
     TxnActive(Version),
     TxnWrite(Version, #[serde(with = "serde_bytes")] Vec<u8>),
     Version(#[serde(with = "serde_bytes")] Vec<u8>, Version),
+    // 不受 MVCC 管理的 key，直接读写存储引擎，事务回滚后也不会撤销，如序列的计数器
+    Unversioned(#[serde(with = "serde_bytes")] Vec<u8>),
+    // 上一次清理旧版本时的 horizon，AS OF 不能查询更早的版本
+    GcHorizon,
 }
 
 impl MvccKey {
@@ -316,12 +916,24 @@ impl MvccKeyPrefix {
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        ops::RangeBounds,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
     use crate::{
-        error::RSDBResult,
-        storage::{disk::DiskEngine, engine::Engine, memory::MemoryEngine},
+        error::{RSDBError, RSDBResult},
+        storage::{
+            disk::DiskEngine,
+            engine::{Durability, Engine, EngineStatus},
+            memory::MemoryEngine,
+        },
     };
 
-    use super::Mvcc;
+    use super::{Mvcc, MvccKey, MvccKeyPrefix, MvccTransaction, ScanResult, TransactionOptions};
 
     // 1. Get
     fn get(eng: impl Engine) -> RSDBResult<()> {
@@ -707,6 +1319,51 @@ mod tests {
         Ok(())
     }
 
+    // 批量写入
+    fn write_batch(eng: impl Engine) -> RSDBResult<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin()?;
+        let tx2 = mvcc.begin()?;
+        tx1.write_batch(vec![
+            (b"key1".to_vec(), None),
+            (b"key2".to_vec(), Some(b"val2".to_vec())),
+            (b"key3".to_vec(), Some(b"val3".to_vec())),
+        ])?;
+        assert_eq!(tx1.get(b"key1".to_vec())?, None);
+        assert_eq!(tx1.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        // 批量写入同样检测冲突
+        assert_eq!(
+            tx2.write_batch(vec![(b"key3".to_vec(), Some(b"val3-1".to_vec()))]),
+            Err(RSDBError::WriteConflict)
+        );
+        tx1.commit()?;
+
+        let tx3 = mvcc.begin_with(TransactionOptions {
+            read_only: true,
+            ..Default::default()
+        })?;
+        assert_eq!(
+            tx3.write_batch(vec![(b"key4".to_vec(), None)]),
+            Err(RSDBError::ReadOnlyTransaction)
+        );
+        assert_eq!(tx3.get(b"key3".to_vec())?, Some(b"val3".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_batch() -> RSDBResult<()> {
+        write_batch(MemoryEngine::new())?;
+
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        write_batch(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
     // 9. dirty read
     fn dirty_read(eng: impl Engine) -> RSDBResult<()> {
         let mvcc = Mvcc::new(eng);
@@ -863,4 +1520,614 @@ mod tests {
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
+
+    // 13. read only
+    fn read_only(eng: impl Engine) -> RSDBResult<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin_read_only()?;
+        assert_eq!(
+            tx1.set(b"key1".to_vec(), b"val2".to_vec()),
+            Err(RSDBError::ReadOnlyTransaction)
+        );
+        assert_eq!(
+            tx1.delete(b"key1".to_vec()),
+            Err(RSDBError::ReadOnlyTransaction)
+        );
+
+        // 只读事务不会和其他事务冲突，并且保持一致的快照
+        let tx2 = mvcc.begin()?;
+        assert_eq!(tx2.version(), tx1.version());
+        tx2.set(b"key1".to_vec(), b"val2".to_vec())?;
+        tx2.set(b"key2".to_vec(), b"val3".to_vec())?;
+        tx2.set(b"key2".to_vec(), b"val3".to_vec())?;
+        // 写入的 key 只属于 tx2，同一个 key 只计算一次
+        assert_eq!(tx2.write_count()?, 2);
+        assert_eq!(tx1.write_count()?, 0);
+        // 只读事务提交不能影响版本号相同的 tx2
+        tx1.commit()?;
+        tx2.commit()?;
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx1.get(b"key2".to_vec())?, None);
+
+        let tx3 = mvcc.begin()?;
+        assert_eq!(tx3.get(b"key1".to_vec())?, Some(b"val2".to_vec()));
+        assert_eq!(tx3.get(b"key2".to_vec())?, Some(b"val3".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only() -> RSDBResult<()> {
+        read_only(MemoryEngine::new())?;
+
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        read_only(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 14. as of version
+    fn as_of(eng: impl Engine) -> RSDBResult<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val2".to_vec())?;
+        tx1.commit()?;
+
+        let tx2 = mvcc.begin()?;
+        tx2.delete(b"key1".to_vec())?;
+        tx2.commit()?;
+
+        let as_of = |version| {
+            mvcc.begin_with(TransactionOptions {
+                read_only: false,
+                as_of: Some(version),
+                ..Default::default()
+            })
+        };
+        // 可见性与对应版本的事务开始时相同
+        assert_eq!(as_of(tx.version())?.get(b"key1".to_vec())?, None);
+        assert_eq!(
+            as_of(tx1.version())?.get(b"key1".to_vec())?,
+            Some(b"val1".to_vec())
+        );
+        assert_eq!(
+            as_of(tx2.version())?.get(b"key1".to_vec())?,
+            Some(b"val2".to_vec())
+        );
+        assert_eq!(as_of(tx2.version() + 1)?.get(b"key1".to_vec())?, None);
+        // 历史版本事务是只读的
+        assert_eq!(
+            as_of(tx1.version())?.set(b"key1".to_vec(), b"val3".to_vec()),
+            Err(RSDBError::ReadOnlyTransaction)
+        );
+        // 不存在的版本
+        assert!(as_of(tx2.version() + 2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_of() -> RSDBResult<()> {
+        as_of(MemoryEngine::new())?;
+
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        as_of(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 15. read own writes
+    fn read_own_writes(eng: impl Engine) -> RSDBResult<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        // 存在其他活跃事务时，重复写同一个 key 不应该冲突
+        let tx1 = mvcc.begin()?;
+        let tx2 = mvcc.begin()?;
+        tx2.set(b"key1".to_vec(), b"val2".to_vec())?;
+        tx2.set(b"key1".to_vec(), b"val3".to_vec())?;
+        tx2.set(b"key2".to_vec(), b"val4".to_vec())?;
+        tx2.delete(b"key2".to_vec())?;
+        // 事务能读到自己的写入
+        assert_eq!(tx2.get(b"key1".to_vec())?, Some(b"val3".to_vec()));
+        assert_eq!(tx2.get(b"key2".to_vec())?, None);
+        assert_eq!(
+            tx2.scan_prefix(b"key".to_vec())?,
+            vec![ScanResult {
+                key: b"key1".to_vec(),
+                value: b"val3".to_vec(),
+            }]
+        );
+        // 其他事务看不到
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        tx1.commit()?;
+        tx2.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_own_writes() -> RSDBResult<()> {
+        read_own_writes(MemoryEngine::new())?;
+
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        read_own_writes(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 流式扫描之前的实现，加锁后一次性扫描所有的数据，作为流式扫描结果的参照
+    fn scan_prefix_locked<E: Engine>(
+        txn: &MvccTransaction<E>,
+        prefix: Vec<u8>,
+    ) -> RSDBResult<Vec<ScanResult>> {
+        let mut eng = txn.engine.lock()?;
+        let mut enc_prefix = MvccKeyPrefix::Version(prefix).encode()?;
+        enc_prefix.truncate(enc_prefix.len() - 2);
+        let mut results = Vec::new();
+        let mut current: Option<(Vec<u8>, Option<Vec<u8>>)> = None;
+        for item in eng.scan_prefix(enc_prefix) {
+            let (key, value) = item?;
+            let MvccKey::Version(raw_key, version) = MvccKey::decode(key)? else {
+                unreachable!()
+            };
+            if !txn.state.is_visible(version) {
+                continue;
+            }
+            let value: Option<Vec<u8>> = bincode::deserialize(&value)?;
+            match &mut current {
+                Some((k, v)) if *k == raw_key => *v = value,
+                _ => {
+                    if let Some((key, Some(value))) = current.replace((raw_key, value)) {
+                        results.push(ScanResult { key, value });
+                    }
+                }
+            }
+        }
+        if let Some((key, Some(value))) = current {
+            results.push(ScanResult { key, value });
+        }
+        Ok(results)
+    }
+
+    // 16. streaming scan
+    fn scan_streaming(eng: impl Engine) -> RSDBResult<()> {
+        let mvcc = Mvcc::new(eng);
+        let key = |i: u32| format!("key{:04}", i).into_bytes();
+        // 每个 key 有多个版本，部分版本为删除，数量足够跨越多个批次
+        for round in 0..3 {
+            let tx = mvcc.begin()?;
+            for i in 0..1000 {
+                match (i + round) % 7 {
+                    0 => tx.delete(key(i))?,
+                    _ => tx.set(key(i), format!("val{}_{}", i, round).into_bytes())?,
+                }
+            }
+            tx.commit()?;
+        }
+        // 未提交的写入、回滚的写入，以及事务自己的写入
+        let uncommitted = mvcc.begin()?;
+        let rolled_back = mvcc.begin()?;
+        for i in (0..1000).step_by(3) {
+            uncommitted.set(key(i), b"uncommitted".to_vec())?;
+            rolled_back.delete(key(i + 1))?;
+        }
+        rolled_back.rollback()?;
+        let tx = mvcc.begin()?;
+        for i in (2..1000).step_by(6) {
+            tx.set(key(i), b"own".to_vec())?;
+        }
+        let read_only = mvcc.begin_read_only()?;
+        let as_of = mvcc.begin_with(TransactionOptions {
+            read_only: true,
+            as_of: Some(2),
+            ..Default::default()
+        })?;
+
+        for txn in [&uncommitted, &tx, &read_only, &as_of] {
+            for prefix in ["", "key", "key00", "key05", "key0999", "none"] {
+                let prefix = prefix.as_bytes().to_vec();
+                let expected = scan_prefix_locked(txn, prefix.clone())?;
+                let results = txn
+                    .scan(prefix.clone(), false)?
+                    .collect::<RSDBResult<Vec<_>>>()?;
+                assert_eq!(results, expected);
+                assert_eq!(txn.scan_prefix(prefix.clone())?, expected);
+                // 倒序扫描的结果与正序相反
+                let mut results = txn.scan(prefix, true)?.collect::<RSDBResult<Vec<_>>>()?;
+                results.reverse();
+                assert_eq!(results, expected);
+            }
+        }
+
+        // 扫描期间不持有存储引擎的锁，其他事务可以读写，之后的写入对扫描不可见
+        let expected = scan_prefix_locked(&read_only, b"key".to_vec())?;
+        let mut iter = read_only.scan(b"key".to_vec(), false)?;
+        let mut results = vec![iter.next().unwrap()?];
+        let writer = mvcc.begin()?;
+        writer.set(key(1000), b"new".to_vec())?;
+        writer.commit()?;
+        for result in iter {
+            results.push(result?);
+        }
+        assert_eq!(results, expected);
+
+        uncommitted.commit()?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_streaming() -> RSDBResult<()> {
+        scan_streaming(MemoryEngine::new())?;
+
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        scan_streaming(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 写偏斜：两个事务都读取了 x 和 y，分别修改其中一个
+    fn write_skew(eng: impl Engine) -> RSDBResult<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"x".to_vec(), b"1".to_vec())?;
+        tx.set(b"y".to_vec(), b"1".to_vec())?;
+        tx.commit()?;
+
+        let begin = |serializable: bool| {
+            mvcc.begin_with(TransactionOptions {
+                serializable,
+                ..Default::default()
+            })
+        };
+        let skew = |tx1: &MvccTransaction<_>, tx2: &MvccTransaction<_>| -> RSDBResult<()> {
+            for tx in [tx1, tx2] {
+                assert_eq!(tx.get(b"x".to_vec())?, Some(b"1".to_vec()));
+                assert_eq!(tx.get(b"y".to_vec())?, Some(b"1".to_vec()));
+            }
+            tx1.set(b"x".to_vec(), b"0".to_vec())?;
+            tx2.set(b"y".to_vec(), b"0".to_vec())
+        };
+
+        // 快照隔离下两个事务都能提交
+        let (tx1, tx2) = (begin(false)?, begin(false)?);
+        skew(&tx1, &tx2)?;
+        tx1.commit()?;
+        tx2.commit()?;
+        let tx = mvcc.begin()?;
+        assert_eq!(tx.get(b"x".to_vec())?, Some(b"0".to_vec()));
+        assert_eq!(tx.get(b"y".to_vec())?, Some(b"0".to_vec()));
+        tx.set(b"x".to_vec(), b"1".to_vec())?;
+        tx.set(b"y".to_vec(), b"1".to_vec())?;
+        tx.commit()?;
+
+        // 可串行化时后提交的事务失败，并且它的写入已经回滚
+        let (tx1, tx2) = (begin(true)?, begin(true)?);
+        skew(&tx1, &tx2)?;
+        tx1.commit()?;
+        assert_eq!(tx2.commit(), Err(RSDBError::SerializationFailure));
+        let tx = mvcc.begin()?;
+        assert!(!tx.state.active_versions.contains(&tx2.version()));
+        assert_eq!(tx.get(b"x".to_vec())?, Some(b"0".to_vec()));
+        assert_eq!(tx.get(b"y".to_vec())?, Some(b"1".to_vec()));
+        tx.commit()?;
+
+        // 扫描过的前缀中新增的 key 同样会导致失败
+        let tx1 = begin(true)?;
+        assert_eq!(tx1.scan_prefix(b"k".to_vec())?, vec![]);
+        let tx2 = mvcc.begin()?;
+        tx2.set(b"k1".to_vec(), b"1".to_vec())?;
+        tx2.commit()?;
+        tx1.set(b"z".to_vec(), b"1".to_vec())?;
+        assert_eq!(tx1.commit(), Err(RSDBError::SerializationFailure));
+
+        // 读取的数据没有被其他事务修改，或者修改的事务没有提交，都可以提交
+        let tx1 = begin(true)?;
+        let tx2 = begin(true)?;
+        tx1.get(b"x".to_vec())?;
+        tx2.set(b"x".to_vec(), b"2".to_vec())?;
+        let tx3 = mvcc.begin()?;
+        tx3.set(b"y".to_vec(), b"2".to_vec())?;
+        tx3.commit()?;
+        tx1.set(b"z".to_vec(), b"2".to_vec())?;
+        tx1.commit()?;
+        tx2.rollback()?;
+        assert_eq!(mvcc.begin()?.get(b"z".to_vec())?, Some(b"2".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_skew() -> RSDBResult<()> {
+        write_skew(MemoryEngine::new())?;
+
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        write_skew(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    fn status(eng: impl Engine) -> RSDBResult<()> {
+        let mvcc = Mvcc::new(eng);
+        let status = mvcc.status()?;
+        assert_eq!(
+            (status.active_txns, status.next_version, status.total_keys),
+            (0, 0, 0)
+        );
+        assert_eq!(status.total_versions, 0);
+        assert_eq!(status.engine.keys, 0);
+
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key1".to_vec(), b"val2".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val3".to_vec())?;
+        let tx1 = mvcc.begin()?;
+        tx1.delete(b"key3".to_vec())?;
+        tx1.fetch_add_unversioned(b"seq".to_vec(), 1)?;
+        let status = tx.status()?;
+        assert_eq!(status.active_txns, 2);
+        assert_eq!(status.next_version, 2);
+        assert_eq!((status.total_keys, status.total_versions), (3, 3));
+        // NextVersion、2 个 TxnActive、3 个 TxnWrite、3 个 Version、1 个 Unversioned
+        assert_eq!(status.engine.keys, 10);
+
+        tx.commit()?;
+        tx1.rollback()?;
+        let tx2 = mvcc.begin_read_only()?;
+        assert_eq!(tx2.status()?.active_txns, 0);
+        let tx3 = mvcc.begin()?;
+        tx3.set(b"key1".to_vec(), b"val4".to_vec())?;
+        tx3.commit()?;
+        let status = mvcc.status()?;
+        assert_eq!(status.active_txns, 0);
+        assert_eq!(status.next_version, 3);
+        // 旧版本不会被清理，key1 有两个版本
+        assert_eq!((status.total_keys, status.total_versions), (2, 3));
+        assert_eq!(status.engine.keys, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_status() -> RSDBResult<()> {
+        status(MemoryEngine::new())?;
+
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        status(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    fn gc(eng: impl Engine) -> RSDBResult<()> {
+        let mvcc = Mvcc::new(eng);
+        let keys = (0..10)
+            .map(|i| format!("k{}", i).into_bytes())
+            .collect::<Vec<_>>();
+        let tx = mvcc.begin()?;
+        for key in &keys {
+            tx.set(key.clone(), b"v0".to_vec())?;
+        }
+        tx.commit()?;
+        let tx = mvcc.begin()?;
+        for key in &keys[5..] {
+            tx.set(key.clone(), b"v1".to_vec())?;
+        }
+        tx.commit()?;
+
+        // 删除一半的 key 之前开启的只读事务仍然能读到它们
+        let reader = mvcc.begin_read_only()?;
+        let tx = mvcc.begin()?;
+        for key in &keys[..5] {
+            tx.delete(key.clone())?;
+        }
+        tx.commit()?;
+        let engine_keys = |mvcc: &Mvcc<_>| -> RSDBResult<u64> { Ok(mvcc.status()?.engine.keys) };
+        let before = engine_keys(&mvcc)?;
+        // 只能删除 k5 到 k9 被覆盖的第一个版本
+        assert_eq!(mvcc.gc()?, 5);
+        // 清理后保存了 GcHorizon
+        assert_eq!(engine_keys(&mvcc)?, before - 5 + 1);
+        assert_eq!(reader.scan_prefix(b"k".to_vec())?.len(), 10);
+        assert_eq!(reader.get(b"k0".to_vec())?, Some(b"v0".to_vec()));
+        assert_eq!(reader.get(b"k9".to_vec())?, Some(b"v1".to_vec()));
+        // 被清理的历史版本不能再查询
+        let as_of = |version| {
+            mvcc.begin_with(TransactionOptions {
+                as_of: Some(version),
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            as_of(1).err(),
+            Some(RSDBError::Internal(
+                "version 1 has been garbage collected, oldest version is 2".to_string()
+            ))
+        );
+        assert_eq!(as_of(2)?.get(b"k0".to_vec())?, Some(b"v0".to_vec()));
+
+        // 只读事务结束后，删除标记以及之前的版本都被删除
+        drop(reader);
+        assert_eq!(mvcc.gc()?, 10);
+        assert_eq!(engine_keys(&mvcc)?, before - 15 + 1);
+        assert_eq!(mvcc.status()?.total_keys, 5);
+        assert_eq!(mvcc.status()?.total_versions, 5);
+        let tx = mvcc.begin()?;
+        assert_eq!(tx.get(b"k0".to_vec())?, None);
+        let scan = tx.scan_prefix(b"k".to_vec())?;
+        assert_eq!(
+            scan.iter().map(|r| r.key.clone()).collect::<Vec<_>>(),
+            keys[5..].to_vec()
+        );
+        assert_eq!(tx.scan(b"k".to_vec(), true)?.count(), 5);
+        // 没有任何版本的 key 可以重新写入
+        tx.set(b"k0".to_vec(), b"v2".to_vec())?;
+        tx.commit()?;
+
+        // 读写事务的快照同样会被保留，再次清理不会删除任何版本
+        let old = mvcc.begin()?;
+        let tx = mvcc.begin()?;
+        tx.delete(b"k5".to_vec())?;
+        tx.delete(b"k0".to_vec())?;
+        tx.commit()?;
+        assert_eq!(mvcc.gc()?, 0);
+        assert_eq!(old.get(b"k5".to_vec())?, Some(b"v1".to_vec()));
+        assert_eq!(old.scan_prefix(b"k".to_vec())?.len(), 6);
+        old.commit()?;
+        assert_eq!(mvcc.gc()?, 4);
+        assert_eq!(mvcc.begin()?.scan_prefix(b"k".to_vec())?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc() -> RSDBResult<()> {
+        gc(MemoryEngine::new())?;
+
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        gc(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_orphaned_transactions() -> RSDBResult<()> {
+        let p = tempfile::tempdir()?.keep().join("rsdb-log");
+        let orphan = {
+            let mvcc = Mvcc::new(DiskEngine::new(p.clone())?);
+            let tx = mvcc.begin()?;
+            tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+            tx.commit()?;
+
+            // 模拟进程退出：事务既没有提交也没有回滚
+            let tx1 = mvcc.begin()?;
+            tx1.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+            tx1.set(b"key2".to_vec(), b"val2".to_vec())?;
+            tx1.version()
+        };
+
+        let mut eng = DiskEngine::new(p.clone())?;
+        let count = |eng: &mut DiskEngine, prefix: Vec<u8>| -> RSDBResult<usize> {
+            let mut iter = eng.scan_prefix(prefix);
+            let mut n = 0;
+            while iter.next().transpose()?.is_some() {
+                n += 1;
+            }
+            Ok(n)
+        };
+        assert_eq!(count(&mut eng, MvccKeyPrefix::TxnActive.encode()?)?, 1);
+        assert_eq!(
+            count(&mut eng, MvccKeyPrefix::TxnWrite(orphan).encode()?)?,
+            2
+        );
+
+        // 第一次开启事务时回滚遗留的事务，它的写入不可见，也不会与新的事务冲突
+        let mvcc = Mvcc::new(eng);
+        let tx2 = mvcc.begin()?;
+        assert!(!tx2.state.active_versions.contains(&orphan));
+        assert_eq!(tx2.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx2.get(b"key2".to_vec())?, None);
+        tx2.set(b"key2".to_vec(), b"val2-2".to_vec())?;
+        tx2.commit()?;
+        {
+            let mut eng = mvcc.engine.lock()?;
+            assert_eq!(count(&mut eng, MvccKeyPrefix::TxnActive.encode()?)?, 0);
+            assert_eq!(
+                count(&mut eng, MvccKeyPrefix::TxnWrite(orphan).encode()?)?,
+                0
+            );
+            for key in [b"key1".to_vec(), b"key2".to_vec()] {
+                assert_eq!(eng.get(MvccKey::Version(key, orphan).encode()?)?, None);
+            }
+        }
+
+        // 之后开启的事务不会再次清理，不影响正在进行的事务
+        let tx3 = mvcc.begin()?;
+        tx3.set(b"key3".to_vec(), b"val3".to_vec())?;
+        let tx4 = mvcc.begin()?;
+        assert!(tx4.state.active_versions.contains(&tx3.version()));
+        tx3.commit()?;
+        assert_eq!(mvcc.begin()?.get(b"key3".to_vec())?, Some(b"val3".to_vec()));
+        drop(mvcc);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 记录 flush 调用次数的存储引擎
+    struct FlushEngine {
+        inner: MemoryEngine,
+        durability: Durability,
+        flushes: Arc<AtomicUsize>,
+    }
+
+    impl Engine for FlushEngine {
+        type EngineIterator<'a> = <MemoryEngine as Engine>::EngineIterator<'a>;
+
+        fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> RSDBResult<()> {
+            self.inner.set(key, value)
+        }
+
+        fn get(&mut self, key: Vec<u8>) -> RSDBResult<Option<Vec<u8>>> {
+            self.inner.get(key)
+        }
+
+        fn delete(&mut self, key: Vec<u8>) -> RSDBResult<()> {
+            self.inner.delete(key)
+        }
+
+        fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+            self.inner.scan(range)
+        }
+
+        fn status(&mut self) -> RSDBResult<EngineStatus> {
+            self.inner.status()
+        }
+
+        fn flush(&mut self) -> RSDBResult<()> {
+            self.flushes.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn durability(&self) -> Durability {
+            self.durability
+        }
+    }
+
+    #[test]
+    fn test_flush_on_commit() -> RSDBResult<()> {
+        for durability in [
+            Durability::Always,
+            Durability::Interval(100),
+            Durability::Never,
+        ] {
+            let flushes = Arc::new(AtomicUsize::new(0));
+            let mvcc = Mvcc::new(FlushEngine {
+                inner: MemoryEngine::new(),
+                durability,
+                flushes: flushes.clone(),
+            });
+            let tx = mvcc.begin()?;
+            tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+            tx.commit()?;
+            // 回滚和只读事务没有需要持久化的写入
+            let tx = mvcc.begin()?;
+            tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+            tx.rollback()?;
+            let tx = mvcc.begin_read_only()?;
+            assert_eq!(tx.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+            tx.commit()?;
+
+            let expected = match durability {
+                Durability::Always => 1,
+                _ => 0,
+            };
+            assert_eq!(flushes.load(Ordering::SeqCst), expected, "{:?}", durability);
+        }
+        Ok(())
+    }
 }